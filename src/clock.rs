@@ -0,0 +1,56 @@
+use chrono::{DateTime, Local};
+#[cfg(test)]
+use std::{cell::Cell, rc::Rc};
+
+/// Source of the wall clock time of the daemon, so time based behavior can be tested without
+/// waiting
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// The time of the system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock that only moves when told to, clones share the time
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockClock(Rc<Cell<DateTime<Local>>>);
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self(Rc::new(Cell::new(now)))
+    }
+
+    pub fn advance(&self, duration: chrono::TimeDelta) {
+        self.0.set(self.0.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Local> {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let start = Local::now();
+        let clock = MockClock::new(start);
+        let shared = clock.clone();
+        assert_eq!(clock.now(), start);
+        shared.advance(chrono::TimeDelta::minutes(5));
+        assert_eq!(clock.now() - start, chrono::TimeDelta::minutes(5));
+    }
+}
@@ -0,0 +1,279 @@
+use crate::{Error, MacAddr};
+use anyhow::{Context, Result, bail};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::mpsc::{Receiver, Sender, channel},
+    thread,
+    time::Duration,
+};
+
+/// The commands accepted over the control socket
+const COMMANDS: &[&str] = &[
+    "status",
+    "set",
+    "wake",
+    "history",
+    "events",
+    "pause",
+    "resume",
+    "rebaseline",
+    "mute",
+    "unmute",
+];
+
+/// A command sent to the daemon over the control socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Print the status table, or the devices as JSON like the `/api/devices` endpoint
+    Status { json: bool },
+    /// Force the connection state of a device
+    Set { mac: MacAddr, online: bool },
+    /// Send a wake-on-LAN packet to a device, by name or MAC address
+    Wake { target: String },
+    /// Print the detection window and base rate of all devices, or of one by name or MAC address
+    History { target: Option<String> },
+    /// Print the most recent connection changes, newest first
+    Events { count: Option<usize> },
+    /// Stop sending notifications for maintenance, for a duration or until resumed
+    Pause { duration: Option<Duration> },
+    /// Send notifications again after a pause
+    Resume,
+    /// Forget the scan history of all devices and take their state from the most recent scan,
+    /// without notifying about the changes, e.g. after reconfiguring the network
+    Rebaseline,
+    /// Stop sending notifications for one device, for a duration or until unmuted
+    Mute {
+        mac: MacAddr,
+        duration: Option<Duration>,
+    },
+    /// Send notifications for a muted device again
+    Unmute { mac: MacAddr },
+}
+
+impl Command {
+    pub fn is_command(word: &str) -> bool {
+        COMMANDS.contains(&word)
+    }
+
+    pub(crate) fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["status"] => Ok(Command::Status { json: false }),
+            ["status", "--json"] => Ok(Command::Status { json: true }),
+            ["set", mac, state] => {
+                let online = match *state {
+                    "online" => true,
+                    "offline" => false,
+                    _ => bail!("Invalid state '{state}', expected 'online' or 'offline'"),
+                };
+                Ok(Command::Set {
+                    mac: mac.parse()?,
+                    online,
+                })
+            }
+            ["wake", target @ ..] if !target.is_empty() => Ok(Command::Wake {
+                target: target.join(" "),
+            }),
+            ["history"] => Ok(Command::History { target: None }),
+            ["history", target @ ..] => Ok(Command::History {
+                target: Some(target.join(" ")),
+            }),
+            ["events"] => Ok(Command::Events { count: None }),
+            ["events", count] => Ok(Command::Events {
+                count: Some(
+                    count
+                        .parse()
+                        .with_context(|| format!("Invalid event count '{count}'"))?,
+                ),
+            }),
+            ["pause"] => Ok(Command::Pause { duration: None }),
+            ["pause", duration] => Ok(Command::Pause {
+                duration: Some(duration_from_str(duration)?),
+            }),
+            ["resume"] => Ok(Command::Resume),
+            ["rebaseline"] => Ok(Command::Rebaseline),
+            ["mute", mac] => Ok(Command::Mute {
+                mac: mac.parse()?,
+                duration: None,
+            }),
+            ["mute", mac, duration] => Ok(Command::Mute {
+                mac: mac.parse()?,
+                duration: Some(duration_from_str(duration)?),
+            }),
+            ["unmute", mac] => Ok(Command::Unmute { mac: mac.parse()? }),
+            _ => bail!(
+                "Invalid command '{line}', expected one of: {}",
+                COMMANDS.join(", ")
+            ),
+        }
+    }
+}
+
+/// Parses a duration in seconds, or with an `s`, `m` or `h` suffix
+pub fn parse_duration(duration: &str) -> Result<Duration, Error> {
+    duration_from_str(duration).map_err(Error::Input)
+}
+
+fn duration_from_str(duration: &str) -> Result<Duration> {
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{duration}'"))?;
+    let secs = match unit {
+        "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => bail!("Invalid duration '{duration}', expected e.g. 30s, 10m or 2h"),
+    };
+    let secs = secs.with_context(|| format!("Duration '{duration}' is too long"))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// A command together with the channel to send the response to
+pub struct Request {
+    pub command: Command,
+    pub reply: Sender<String>,
+}
+
+/// Listens on the control socket in a background thread, forwarding received commands
+pub fn listen(path: &Path) -> Result<Receiver<Request>, Error> {
+    let listener = bind(path).map_err(Error::Service)?;
+    log::info!("listening for commands on '{}'", path.display());
+
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .context("Failed to accept connection")
+                .and_then(|stream| handle_connection(stream, &sender));
+            if let Err(e) = result {
+                log::warn!("Control connection failed: {:#}", e);
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    // remove the socket of a previous run
+    if path.exists() {
+        fs::remove_file(path).context("Failed to remove old control socket")?;
+    }
+    UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket '{}'", path.display()))
+}
+
+fn handle_connection(mut stream: UnixStream, sender: &Sender<Request>) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response = match Command::parse(&line) {
+        Ok(command) => {
+            log::debug!("Received control command {:?}", command);
+            let (reply, response) = channel();
+            sender.send(Request { command, reply })?;
+            response.recv().context("Daemon did not respond")?
+        }
+        Err(e) => format!("Error: {e:#}"),
+    };
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// Sends a command to a running daemon and returns its response
+pub fn send(path: &Path, command: &str) -> Result<String, Error> {
+    request(path, command).map_err(Error::Service)
+}
+
+fn request(path: &Path, command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to control socket '{}'", path.display()))?;
+    writeln!(stream, "{command}")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            Command::parse("status\n").unwrap(),
+            Command::Status { json: false }
+        );
+        assert_eq!(
+            Command::parse("status --json").unwrap(),
+            Command::Status { json: true }
+        );
+        assert!(Command::parse("status --yaml").is_err());
+        assert_eq!(
+            Command::parse("set aa:bb:cc:dd:ee:01 offline").unwrap(),
+            Command::Set {
+                mac: MacAddr::new("aa:bb:cc:dd:ee:01"),
+                online: false
+            }
+        );
+        assert!(Command::parse("set aa:bb:cc:dd:ee:01 maybe").is_err());
+        assert!(Command::parse("set nonsense online").is_err());
+        assert_eq!(
+            Command::parse("wake Living room TV").unwrap(),
+            Command::Wake {
+                target: "Living room TV".to_string()
+            }
+        );
+        assert!(Command::parse("wake").is_err());
+        assert_eq!(
+            Command::parse("history").unwrap(),
+            Command::History { target: None }
+        );
+        assert_eq!(
+            Command::parse("history aa:bb:cc:dd:ee:01").unwrap(),
+            Command::History {
+                target: Some("aa:bb:cc:dd:ee:01".to_string())
+            }
+        );
+        assert_eq!(
+            Command::parse("events 5").unwrap(),
+            Command::Events { count: Some(5) }
+        );
+        assert!(Command::parse("events many").is_err());
+        assert_eq!(
+            Command::parse("pause 30m").unwrap(),
+            Command::Pause {
+                duration: Some(Duration::from_secs(1800))
+            }
+        );
+        assert_eq!(
+            Command::parse("pause").unwrap(),
+            Command::Pause { duration: None }
+        );
+        assert!(Command::parse("pause soon").is_err());
+        assert!(Command::parse("pause 18446744073709551615h").is_err());
+        assert_eq!(Command::parse("resume").unwrap(), Command::Resume);
+        assert_eq!(Command::parse("rebaseline").unwrap(), Command::Rebaseline);
+        assert_eq!(
+            Command::parse("mute aa:bb:cc:dd:ee:01 2h").unwrap(),
+            Command::Mute {
+                mac: MacAddr::new("aa:bb:cc:dd:ee:01"),
+                duration: Some(Duration::from_secs(7200))
+            }
+        );
+        assert_eq!(
+            Command::parse("unmute aa:bb:cc:dd:ee:01").unwrap(),
+            Command::Unmute {
+                mac: MacAddr::new("aa:bb:cc:dd:ee:01")
+            }
+        );
+        assert!(Command::parse("mute").is_err());
+        assert!(Command::parse("reboot").is_err());
+    }
+}
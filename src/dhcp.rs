@@ -0,0 +1,61 @@
+use crate::MacAddr;
+use anyhow::{Context, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Hostnames by MAC address, from a dnsmasq leases file
+#[derive(Debug, Default)]
+pub struct Leases {
+    hostnames: HashMap<MacAddr, String>,
+}
+
+impl Leases {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read DHCP leases file '{}'", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parses the `expiry mac ip hostname clientid` lines, skipping leases without a hostname,
+    /// which dnsmasq writes as `*`
+    pub fn parse(contents: &str) -> Self {
+        let hostnames = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                fields.next()?.parse::<u64>().ok()?;
+                let mac = fields.next()?.parse().ok()?;
+                let _ip = fields.next()?;
+                let hostname = fields.next()?;
+                (hostname != "*").then(|| (mac, hostname.to_string()))
+            })
+            .collect();
+        Self { hostnames }
+    }
+
+    pub fn get(&self, mac: &MacAddr) -> Option<&str> {
+        self.hostnames.get(mac).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let leases = Leases::parse(
+            "1717000000 aa:bb:cc:dd:ee:01 192.168.1.10 phone 01:aa:bb:cc:dd:ee:01\n\
+             1717000100 aa:bb:cc:dd:ee:02 192.168.1.11 * *\n\
+             0 AA:BB:CC:DD:EE:03 192.168.1.12 nas *\n\
+             duid 00:01:00:01:2c:1f:0a:3b:aa:bb:cc:dd:ee:04\n\
+             1717000200 fe80::1 2001:db8::1 laptop 00:02\n",
+        );
+        assert_eq!(leases.hostnames.len(), 2);
+        assert_eq!(
+            leases.get(&MacAddr::new("aa:bb:cc:dd:ee:01")),
+            Some("phone")
+        );
+        assert_eq!(leases.get(&MacAddr::new("aa:bb:cc:dd:ee:02")), None);
+        assert_eq!(leases.get(&MacAddr::new("aa:bb:cc:dd:ee:03")), Some("nas"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// Error of the library API, telling apart which part of lanotify failed
+///
+/// Only the kind can be matched on. Each kind wraps the underlying error with its context, which
+/// is shown unchanged, and `source` returns its causes.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or parsing the config failed
+    Config(anyhow::Error),
+    /// Setting up the scanner or scanning failed
+    Scan(anyhow::Error),
+    /// Setting up a notifier or sending a notification failed
+    Notify(anyhow::Error),
+    /// Restoring the persisted device state failed
+    State(anyhow::Error),
+    /// The control socket, metrics listener or logger failed
+    Service(anyhow::Error),
+    /// An argument such as a duration is invalid
+    Input(anyhow::Error),
+}
+
+impl Error {
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Error::Config(e)
+            | Error::Scan(e)
+            | Error::Notify(e)
+            | Error::State(e)
+            | Error::Service(e)
+            | Error::Input(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // forwarded with the flags, so `{:#}` includes the causes
+        fmt::Display::fmt(self.inner(), f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, scan::ScannerConfig};
+    use anyhow::Context;
+
+    #[test]
+    fn test_kinds() {
+        assert!(matches!(
+            Config::parse("scan_interval = \"often\""),
+            Err(Error::Config(_))
+        ));
+        let scanner = ScannerConfig::Command {
+            command: Vec::new(),
+            format: Default::default(),
+        };
+        assert!(matches!(scanner.build(), Err(Error::Scan(_))));
+        assert!(matches!(
+            crate::control::parse_duration("soon"),
+            Err(Error::Input(_))
+        ));
+    }
+
+    #[test]
+    fn test_transparent() {
+        let e = Error::Scan(
+            Err::<(), _>(anyhow::anyhow!("Connection refused"))
+                .context("Failed to scan")
+                .unwrap_err(),
+        );
+        assert_eq!(e.to_string(), "Failed to scan");
+        assert_eq!(format!("{e:#}"), "Failed to scan: Connection refused");
+        let e = anyhow::Error::new(e).context("Scan 3 failed");
+        assert_eq!(
+            format!("{e:#}"),
+            "Scan 3 failed: Failed to scan: Connection refused"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use flate2::{Compression, write::GzEncoder};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Appends lines to a file, rotating it once it grows beyond a maximum size
+///
+/// Rotated files are renamed with a timestamp suffix, e.g. `events.log.20240501-120000`, and
+/// optionally gzipped. Only the newest `keep` rotated files are kept.
+pub struct EventLog {
+    path: PathBuf,
+    max_size: Option<u64>,
+    keep: usize,
+    compress: bool,
+}
+
+impl EventLog {
+    pub fn new(path: PathBuf, max_size: Option<u64>, keep: usize, compress: bool) -> Self {
+        Self {
+            path,
+            max_size,
+            keep,
+            compress,
+        }
+    }
+
+    pub fn append(&mut self, line: &str, now: DateTime<Local>) -> Result<()> {
+        if let Some(max_size) = self.max_size
+            && fs::metadata(&self.path).is_ok_and(|m| m.len() >= max_size)
+        {
+            self.rotate(now)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open event log '{}'", self.path.display()))?;
+        writeln!(file, "{line}")
+            .with_context(|| format!("Failed to write event log '{}'", self.path.display()))
+    }
+
+    fn rotate(&self, now: DateTime<Local>) -> Result<()> {
+        let mut rotated = self.rotated_path(&now.format("%Y%m%d-%H%M%S").to_string());
+        // several rotations within a second
+        let mut n = 1;
+        while rotated.exists() || gz_path(&rotated).exists() {
+            rotated = self.rotated_path(&format!("{}-{n}", now.format("%Y%m%d-%H%M%S")));
+            n += 1;
+        }
+        fs::rename(&self.path, &rotated)
+            .with_context(|| format!("Failed to rotate event log '{}'", self.path.display()))?;
+        log::info!("Rotated event log to '{}'", rotated.display());
+        if self.compress {
+            compress(&rotated).with_context(|| {
+                format!(
+                    "Failed to compress rotated event log '{}'",
+                    rotated.display()
+                )
+            })?;
+        }
+        self.prune()
+    }
+
+    fn rotated_path(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    /// Removes the oldest rotated files beyond `keep`
+    fn prune(&self) -> Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut prefix = self.path.file_name().unwrap_or_default().to_os_string();
+        prefix.push(".");
+        let prefix = prefix.to_string_lossy().into_owned();
+        let mut rotated = Vec::new();
+        for entry in fs::read_dir(dir).context("Failed to list rotated event logs")? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                rotated.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.keep);
+        for (_, path) in rotated.into_iter().take(excess) {
+            fs::remove_file(&path).with_context(|| {
+                format!("Failed to remove rotated event log '{}'", path.display())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Replaces a file with a gzipped copy
+fn compress(path: &Path) -> Result<()> {
+    let gz = gz_path(path);
+    let mut encoder = GzEncoder::new(File::create(&gz)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("lanotify-event-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.log");
+        let mut log = EventLog::new(path.clone(), Some(10), 2, true);
+        let now = Local::now();
+        for i in 0..4 {
+            log.append(&format!("event {i} line"), now).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "event 3 line\n");
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| *p != path)
+            .collect();
+        rotated.sort();
+        assert_eq!(rotated.len(), 2);
+        assert!(rotated.iter().all(|p| p.extension().unwrap() == "gz"));
+        let mut contents = String::new();
+        GzDecoder::new(File::open(&rotated[1]).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "event 2 line\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{Device, MacAddr};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{net::IpAddr, time::Duration};
+
+/// Another lanotify instance to merge the devices of
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Peer {
+    /// Shown as the node of the devices of this peer
+    pub name: String,
+    /// URL of the `/api/devices` endpoint of the peer
+    pub url: String,
+}
+
+/// A device as served by the `/api/devices` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiDevice {
+    pub mac: MacAddr,
+    pub ip: IpAddr,
+    pub vendor: String,
+    pub hostname: Option<String>,
+    pub name: Option<String>,
+    pub connected: bool,
+    /// Confidence from 0 to 1 that the device is connected, derived from its history
+    #[serde(default)]
+    pub confidence: f64,
+    /// Fraction of the scans of the last `uptime_days` the device was seen in, `None` before its
+    /// first scan
+    #[serde(default)]
+    pub uptime: Option<f64>,
+    pub last_seen: DateTime<Local>,
+    /// Whether the device was seen in each of the recent scans, newest first
+    #[serde(default)]
+    pub history: Vec<bool>,
+}
+
+/// Fetches the devices a peer considers connected
+pub fn fetch(peer: &Peer, timeout: Duration) -> Result<Vec<Device>> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .new_agent();
+    let body = agent
+        .get(&peer.url)
+        .call()
+        .and_then(|mut resp| resp.body_mut().read_to_string())
+        .with_context(|| format!("Failed to fetch devices from peer '{}'", peer.name))?;
+    let devices: Vec<ApiDevice> = serde_json::from_str(&body)
+        .with_context(|| format!("Invalid devices from peer '{}'", peer.name))?;
+    Ok(devices
+        .into_iter()
+        .filter(|d| d.connected)
+        .map(|d| Device {
+            mac: d.mac,
+            ip: d.ip,
+            vendor: d.vendor,
+            hostname: d.hostname,
+            latency: None,
+            interface: None,
+            rssi: None,
+            node: Some(peer.name.clone()),
+            found_as: Vec::new(),
+        })
+        .collect())
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result, bail};
+use std::{
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt, fs::OpenOptionsExt},
+    path::PathBuf,
+};
+
+/// Writes lines to a named pipe for a local process to read, without ever waiting for it
+///
+/// Lines are dropped while no reader has the pipe open or the pipe is full, and if they are longer
+/// than `PIPE_BUF`, as only shorter writes can't be interleaved with those of other writers.
+pub struct Fifo {
+    path: PathBuf,
+    // opened once a reader is connected, until it goes away
+    file: Option<File>,
+}
+
+impl Fifo {
+    /// Creates the named pipe at `path` if it doesn't exist yet
+    pub fn new(path: PathBuf) -> Result<Self> {
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {}
+            Ok(_) => bail!("'{}' is not a named pipe", path.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let c_path = CString::new(path.as_os_str().as_bytes())
+                    .with_context(|| format!("Invalid path '{}'", path.display()))?;
+                // SAFETY: the path is a valid NUL terminated string
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                    return Err(io::Error::last_os_error())
+                        .with_context(|| format!("Failed to create '{}'", path.display()));
+                }
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to access '{}'", path.display()));
+            }
+        }
+        Ok(Self { path, file: None })
+    }
+
+    /// Writes a line if a reader is connected, returns whether it was written
+    pub fn write(&mut self, line: &str) -> Result<bool> {
+        let line = format!("{line}\n");
+        if line.len() > libc::PIPE_BUF {
+            log::warn!(
+                "Dropping a line of {} bytes for '{}', longer than {} bytes",
+                line.len(),
+                self.path.display(),
+                libc::PIPE_BUF
+            );
+            return Ok(false);
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+            {
+                Ok(file) => self.file.insert(file),
+                // no reader
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => return Ok(false),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to open '{}'", self.path.display()));
+                }
+            },
+        };
+        // lines up to PIPE_BUF bytes are written entirely or not at all
+        match file.write(line.as_bytes()) {
+            Ok(n) if n == line.len() => Ok(true),
+            Ok(n) => bail!(
+                "Wrote only {n} of {} bytes to '{}'",
+                line.len(),
+                self.path.display()
+            ),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                log::debug!(
+                    "'{}' is full, dropping '{}'",
+                    self.path.display(),
+                    line.trim_end()
+                );
+                Ok(false)
+            }
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                self.file = None;
+                Ok(false)
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to write '{}'", self.path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_fifo() {
+        let path = std::env::temp_dir().join(format!("lanotify-test-{}.fifo", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut fifo = Fifo::new(path.clone()).unwrap();
+        assert!(fs::metadata(&path).unwrap().file_type().is_fifo());
+        // nobody is reading yet
+        assert!(!fifo.write("lost").unwrap());
+
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        assert!(fifo.write("{\"connected\":true}").unwrap());
+        assert!(fifo.write("{\"connected\":false}").unwrap());
+        let mut read = [0; 128];
+        let n = reader.read(&mut read).unwrap();
+        assert_eq!(&read[..n], b"{\"connected\":true}\n{\"connected\":false}\n");
+        // too long to be written at once, so it is dropped instead of possibly being torn
+        assert!(!fifo.write(&"x".repeat(libc::PIPE_BUF)).unwrap());
+        assert!(reader.read(&mut read).is_err());
+
+        // a full pipe drops lines instead of blocking
+        let line = "x".repeat(1000);
+        let mut written = 0;
+        while fifo.write(&line).unwrap() {
+            written += 1;
+        }
+        assert!(written > 0);
+
+        // the reader went away
+        drop(reader);
+        assert!(!fifo.write("lost").unwrap());
+        assert!(!fifo.write("lost").unwrap());
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "").unwrap();
+        assert!(Fifo::new(path.clone()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{VecDeque, vec_deque};
+
+/// Ring buffer of the `N` most recent samples, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct History<T, const N: usize> {
+    samples: VecDeque<T>,
+}
+
+impl<T, const N: usize> History<T, N> {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(N),
+        }
+    }
+
+    /// Creates a history from samples ordered newest first, keeping at most `N`
+    #[cfg(test)]
+    pub fn from(samples: Vec<T>) -> Self {
+        let mut samples = VecDeque::from(samples);
+        samples.truncate(N);
+        Self { samples }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Adds the newest sample, dropping the oldest once full
+    pub fn push(&mut self, sample: T) {
+        self.samples.push_front(sample);
+        self.samples.truncate(N);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn newest(&self) -> Option<&T> {
+        self.samples.front()
+    }
+
+    /// Iterates over the samples, newest first
+    pub fn iter(&self) -> vec_deque::Iter<'_, T> {
+        self.samples.iter()
+    }
+}
+
+impl<T, const N: usize> Default for History<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a History<T, N> {
+    type Item = &'a T;
+    type IntoIter = vec_deque::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Ring buffer of the `N` most recent boolean samples, newest first, packed in bits
+///
+/// Takes an eighth of the memory of a `History<bool, N>` and (de)serializes the same way.
+#[derive(Debug, Clone)]
+pub struct PackedHistory<const N: usize> {
+    words: Vec<u64>,
+    // bit index of the newest sample
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> PackedHistory<N> {
+    pub fn new() -> Self {
+        Self {
+            words: vec![0; N.div_ceil(64)],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Creates a history from samples ordered newest first, keeping at most `N`
+    pub fn from(mut samples: Vec<bool>) -> Self {
+        let mut history = Self::new();
+        samples.truncate(N);
+        for sample in samples.into_iter().rev() {
+            history.push(sample);
+        }
+        history
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds the newest sample, overwriting the oldest once full
+    pub fn push(&mut self, sample: bool) {
+        if N == 0 {
+            return;
+        }
+        self.head = (self.head + N - 1) % N;
+        let (word, bit) = (self.head / 64, self.head % 64);
+        self.words[word] = self.words[word] & !(1 << bit) | (sample as u64) << bit;
+        self.len = (self.len + 1).min(N);
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn newest(&self) -> Option<&bool> {
+        (self.len > 0).then(|| self.get(0))
+    }
+
+    /// Iterates over the samples, newest first
+    pub fn iter(&self) -> impl Iterator<Item = &bool> {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    // The `i`-th newest sample, as a reference to a constant since there is no `bool` to point to
+    fn get(&self, i: usize) -> &'static bool {
+        let index = (self.head + i) % N;
+        if self.words[index / 64] >> (index % 64) & 1 == 1 {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+impl<const N: usize> Default for PackedHistory<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Serialize for PackedHistory<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for PackedHistory<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_drops_oldest() {
+        let mut history: History<Option<f64>, 3> = History::new();
+        for sample in [Some(1.0), None, Some(3.0), Some(4.0)] {
+            history.push(sample);
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.newest(), Some(&Some(4.0)));
+        assert_eq!(
+            history.iter().copied().collect::<Vec<_>>(),
+            [Some(4.0), Some(3.0), None]
+        );
+        assert_eq!(serde_json::to_string(&history).unwrap(), "[4.0,3.0,null]");
+    }
+
+    #[test]
+    fn test_packed_wraps_around() {
+        let mut history: PackedHistory<70> = PackedHistory::new();
+        let samples: Vec<bool> = (0..150).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+        for &sample in &samples {
+            history.push(sample);
+        }
+        assert_eq!(history.len(), 70);
+        assert_eq!(history.words.len(), 2);
+        let newest: Vec<bool> = samples.iter().rev().take(70).copied().collect();
+        assert_eq!(history.iter().copied().collect::<Vec<_>>(), newest);
+
+        let json = serde_json::to_string(&history).unwrap();
+        let restored: PackedHistory<70> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.iter().copied().collect::<Vec<_>>(), newest);
+        let plain: History<bool, 70> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&plain).unwrap(), json);
+
+        history.clear();
+        assert_eq!(history.newest(), None);
+        history.push(false);
+        assert_eq!(history.iter().collect::<Vec<_>>(), [&false]);
+    }
+}
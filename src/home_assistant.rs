@@ -0,0 +1,88 @@
+use crate::hook;
+use chrono::{DateTime, Local};
+use serde_json::json;
+use std::net::IpAddr;
+
+/// A configured device as set in Home Assistant
+pub struct Presence<'a> {
+    pub name: &'a str,
+    pub mac: &'a str,
+    pub ip: IpAddr,
+    pub last_seen: DateTime<Local>,
+    pub connected: bool,
+}
+
+/// Entity id of the device tracker of a device, e.g. `device_tracker.living_room_tv` for
+/// `Living Room TV`
+pub fn entity_id(name: &str) -> String {
+    let mut id = String::from("device_tracker.");
+    // Home Assistant only allows lowercase letters, digits and single underscores
+    let mut underscore = true;
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            id.push(c);
+            underscore = false;
+        } else if !underscore {
+            id.push('_');
+            underscore = true;
+        }
+    }
+    id.trim_end_matches('_').to_string()
+}
+
+/// Body of a `POST /api/states/<entity_id>` request
+pub fn body(device: &Presence) -> String {
+    json!({
+        "state": if device.connected { "home" } else { "not_home" },
+        "attributes": {
+            "friendly_name": device.name,
+            "source_type": "router",
+            "ip": device.ip,
+            "mac": device.mac,
+            "last_seen": device.last_seen.to_rfc3339(),
+        },
+    })
+    .to_string()
+}
+
+/// Request that sets the state of the device tracker, to send with [`hook::Hooks`]
+pub fn update(base_url: &str, token: &str, device: &Presence) -> hook::Request {
+    hook::Request {
+        url: format!(
+            "{}/api/states/{}",
+            base_url.trim_end_matches('/'),
+            entity_id(device.name)
+        ),
+        authorization: Some(format!("Bearer {token}")),
+        body: body(device),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entity_id() {
+        assert_eq!(entity_id("Phone"), "device_tracker.phone");
+        assert_eq!(entity_id("Living Room TV"), "device_tracker.living_room_tv");
+        assert_eq!(entity_id("Jan's  laptop!"), "device_tracker.jan_s_laptop");
+    }
+
+    #[test]
+    fn test_body() {
+        let last_seen = Local::now();
+        let body: serde_json::Value = serde_json::from_str(&body(&Presence {
+            name: "Phone",
+            mac: "aa:bb:cc:dd:ee:01",
+            ip: "192.168.1.10".parse().unwrap(),
+            last_seen,
+            connected: false,
+        }))
+        .unwrap();
+        assert_eq!(body["state"], "not_home");
+        assert_eq!(body["attributes"]["ip"], "192.168.1.10");
+        assert_eq!(body["attributes"]["mac"], "aa:bb:cc:dd:ee:01");
+        assert_eq!(body["attributes"]["last_seen"], last_seen.to_rfc3339());
+    }
+}
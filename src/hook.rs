@@ -0,0 +1,102 @@
+use std::{
+    borrow::Cow,
+    sync::{
+        OnceLock,
+        mpsc::{self, SyncSender, TrySendError},
+    },
+    thread,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// requests waiting for the worker, beyond which new ones are dropped
+const QUEUE_SIZE: usize = 64;
+
+/// A JSON body to POST to a URL
+pub struct Request {
+    pub url: String,
+    /// Value of the `Authorization` header, if any
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+/// Sends the requests of hooks and integrations one at a time from a background thread, so a
+/// slow or failing endpoint doesn't delay scanning, failures are only logged
+///
+/// The thread is started on the first request and ends when this is dropped.
+#[derive(Default)]
+pub struct Hooks {
+    sender: OnceLock<SyncSender<Request>>,
+}
+
+impl Hooks {
+    /// Queues a request, dropping it if too many are waiting already
+    pub fn call(&self, request: Request) {
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+            thread::spawn(move || {
+                let agent = ureq::Agent::config_builder()
+                    .timeout_global(Some(TIMEOUT))
+                    .build()
+                    .new_agent();
+                for request in receiver {
+                    send(&agent, &request);
+                }
+            });
+            sender
+        });
+        match sender.try_send(request) {
+            Ok(()) => {}
+            Err(TrySendError::Full(request)) => {
+                log::warn!("Too many pending hooks, dropping '{}'", request.url);
+            }
+            Err(TrySendError::Disconnected(request)) => {
+                log::error!("Hook thread stopped, dropping '{}'", request.url);
+            }
+        }
+    }
+}
+
+fn send(agent: &ureq::Agent, request: &Request) {
+    let mut builder = agent
+        .post(&request.url)
+        .header("Content-Type", "application/json");
+    if let Some(authorization) = &request.authorization {
+        builder = builder.header("Authorization", authorization);
+    }
+    match builder.send(&request.body) {
+        Ok(_) => log::debug!("Called hook '{}'", request.url),
+        Err(e) => log::warn!("Failed to call hook '{}': {e}", request.url),
+    }
+}
+
+/// Percent-encodes a value for use in a URL, keeping only the unreserved characters
+pub fn encode(value: &str) -> Cow<'_, str> {
+    let unreserved = |b: u8| b.is_ascii_alphanumeric() || b"-._~".contains(&b);
+    if value.bytes().all(unreserved) {
+        return Cow::Borrowed(value);
+    }
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for b in value.bytes() {
+        if unreserved(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("192.168.1.2"), "192.168.1.2");
+        assert_eq!(encode("Jan's car"), "Jan%27s%20car");
+        assert_eq!(encode("a&b=c/d"), "a%26b%3Dc%2Fd");
+        assert_eq!(encode("aa:bb"), "aa%3Abb");
+        assert_eq!(encode("Gerät"), "Ger%C3%A4t");
+    }
+}
@@ -0,0 +1,178 @@
+use serde::Deserialize;
+
+/// Language of the bundled phrases of connection notifications
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+    Nl,
+}
+
+/// Phrases of connection notifications, overriding those of the configured language
+///
+/// The `{name}` and `{status}` placeholders are filled in everywhere, `{ip}` and `{mac}` in the
+/// body, `{count}` in the batch title and `{absence}` in the title of a device back after a long
+/// absence. The titles of devices going offline by their activity class only have `{name}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Messages {
+    connected: Option<String>,
+    disconnected: Option<String>,
+    title: Option<String>,
+    body: Option<String>,
+    batch_title: Option<String>,
+    back_title: Option<String>,
+    sleeping_title: Option<String>,
+    intermittent_title: Option<String>,
+    always_on_title: Option<String>,
+}
+
+/// The phrases to build connection notifications with
+#[derive(Debug, PartialEq, Eq)]
+pub struct Phrases<'a> {
+    pub connected: &'a str,
+    pub disconnected: &'a str,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub batch_title: &'a str,
+    pub back_title: &'a str,
+    pub sleeping_title: &'a str,
+    pub intermittent_title: &'a str,
+    pub always_on_title: &'a str,
+}
+
+impl Language {
+    fn phrases(self) -> Phrases<'static> {
+        match self {
+            Language::En => Phrases {
+                connected: "connected",
+                disconnected: "disconnected",
+                title: "Device {name} {status}",
+                body: "Device {name} with IP {ip} and MAC {mac} is {status}",
+                batch_title: "{count} devices {status}",
+                back_title: "Device {name} is back after {absence}",
+                sleeping_title: "Sleeping device {name} went quiet",
+                intermittent_title: "Intermittent device {name} disconnected",
+                always_on_title: "Always-on device {name} went offline",
+            },
+            Language::De => Phrases {
+                connected: "verbunden",
+                disconnected: "getrennt",
+                title: "Gerät {name} {status}",
+                body: "Gerät {name} mit IP {ip} und MAC {mac} ist {status}",
+                batch_title: "{count} Geräte {status}",
+                back_title: "Gerät {name} ist nach {absence} zurück",
+                sleeping_title: "Schlafendes Gerät {name} ist verstummt",
+                intermittent_title: "Zeitweise aktives Gerät {name} getrennt",
+                always_on_title: "Dauerhaft aktives Gerät {name} ist offline",
+            },
+            Language::Nl => Phrases {
+                connected: "verbonden",
+                disconnected: "verbroken",
+                title: "Apparaat {name} {status}",
+                body: "Apparaat {name} met IP {ip} en MAC {mac} is {status}",
+                batch_title: "{count} apparaten {status}",
+                back_title: "Apparaat {name} is terug na {absence}",
+                sleeping_title: "Slapend apparaat {name} is stil geworden",
+                intermittent_title: "Af en toe actief apparaat {name} verbroken",
+                always_on_title: "Altijd actief apparaat {name} is offline",
+            },
+        }
+    }
+}
+
+impl Messages {
+    pub fn phrases(&self, language: Language) -> Phrases<'_> {
+        let bundled = language.phrases();
+        Phrases {
+            connected: self.connected.as_deref().unwrap_or(bundled.connected),
+            disconnected: self.disconnected.as_deref().unwrap_or(bundled.disconnected),
+            title: self.title.as_deref().unwrap_or(bundled.title),
+            body: self.body.as_deref().unwrap_or(bundled.body),
+            batch_title: self.batch_title.as_deref().unwrap_or(bundled.batch_title),
+            back_title: self.back_title.as_deref().unwrap_or(bundled.back_title),
+            sleeping_title: self
+                .sleeping_title
+                .as_deref()
+                .unwrap_or(bundled.sleeping_title),
+            intermittent_title: self
+                .intermittent_title
+                .as_deref()
+                .unwrap_or(bundled.intermittent_title),
+            always_on_title: self
+                .always_on_title
+                .as_deref()
+                .unwrap_or(bundled.always_on_title),
+        }
+    }
+}
+
+impl Phrases<'_> {
+    pub fn status(&self, connected: bool) -> &str {
+        if connected {
+            self.connected
+        } else {
+            self.disconnected
+        }
+    }
+}
+
+/// Fills in the `{key}` placeholders of a phrase in a single pass, so placeholders within the
+/// values are kept as they are
+///
+/// Unknown placeholders are left in place.
+pub fn fill(phrase: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(phrase.len());
+    let mut rest = phrase;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let key = &rest[1..end];
+            let (_, value) = values.iter().find(|(k, _)| *k == key)?;
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phrases() {
+        let messages: Messages =
+            toml::from_str("disconnected = \"weg\"\ntitle = \"{name}: {status}\"").unwrap();
+        let phrases = messages.phrases(Language::De);
+        assert_eq!(phrases.status(true), "verbunden");
+        assert_eq!(phrases.status(false), "weg");
+        assert_eq!(phrases.title, "{name}: {status}");
+        assert_eq!(phrases.batch_title, "{count} Geräte {status}");
+        assert_eq!(
+            Messages::default().phrases(Language::En),
+            Language::En.phrases()
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        let values = [("name", "{status} lamp"), ("status", "on")];
+        assert_eq!(fill("{name} is {status}", &values), "{status} lamp is on");
+        assert_eq!(fill("{name} {ip} {", &values), "{status} lamp {ip} {");
+        assert_eq!(fill("{{status}}", &values), "{on}");
+    }
+}
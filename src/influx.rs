@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::{collections::VecDeque, time::Duration};
+
+// lines kept while the database is unavailable, about a day of scans of 10 devices every 10s
+const MAX_BUFFERED_LINES: usize = 100_000;
+
+/// Writes points in the InfluxDB line protocol, buffering them while the database is unavailable
+pub struct Sink {
+    url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+    buffer: VecDeque<String>,
+}
+
+impl Sink {
+    /// `url` is the write endpoint including the database or bucket, e.g.
+    /// `http://influx:8086/api/v2/write?bucket=lanotify&precision=ns`
+    pub fn new(url: String, token: Option<String>) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build()
+            .new_agent();
+        Self {
+            url,
+            token,
+            agent,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.buffer.len() >= MAX_BUFFERED_LINES {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(line);
+    }
+
+    #[cfg(test)]
+    pub fn buffered(&self) -> &VecDeque<String> {
+        &self.buffer
+    }
+
+    /// Writes all buffered points, keeping them to retry with the next flush on failure
+    pub fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let body = self
+            .buffer
+            .iter()
+            .fold(String::new(), |body, line| body + line + "\n");
+        let mut request = self.agent.post(&self.url);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+        request.send(&body).with_context(|| {
+            format!(
+                "Failed to write {} points to InfluxDB, retrying later",
+                self.buffer.len()
+            )
+        })?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Formats a point in the line protocol with boolean fields
+pub fn line(
+    measurement: &str,
+    tags: &[(&str, &str)],
+    fields: &[(&str, bool)],
+    time: DateTime<Local>,
+) -> String {
+    let mut line = escape(measurement, &[',', ' ']);
+    for (key, value) in tags {
+        // empty tag values are not allowed
+        if !value.is_empty() {
+            line += &format!(
+                ",{}={}",
+                escape(key, TAG_SPECIAL),
+                escape(value, TAG_SPECIAL)
+            );
+        }
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", escape(key, TAG_SPECIAL), value))
+        .collect();
+    line += &format!(
+        " {} {}",
+        fields.join(","),
+        time.timestamp_nanos_opt().unwrap_or_default()
+    );
+    line
+}
+
+const TAG_SPECIAL: &[char] = &[',', '=', ' '];
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_line() {
+        let time = chrono::Utc
+            .timestamp_opt(1_714_550_400, 0)
+            .unwrap()
+            .with_timezone(&Local);
+        assert_eq!(
+            line(
+                "presence",
+                &[
+                    ("mac", "aa:bb:cc:dd:ee:01"),
+                    ("name", "Bob's laptop, old"),
+                    ("type", "")
+                ],
+                &[("connected", true), ("seen", false)],
+                time
+            ),
+            "presence,mac=aa:bb:cc:dd:ee:01,name=Bob's\\ laptop\\,\\ old connected=true,seen=false \
+             1714550400000000000"
+        );
+    }
+
+    #[test]
+    fn test_buffer_on_failure() {
+        let mut sink = Sink::new("http://127.0.0.1:1/write".to_string(), None);
+        sink.push("a value=true 1".to_string());
+        assert!(sink.flush().is_err());
+        sink.push("a value=false 2".to_string());
+        assert_eq!(sink.buffer.len(), 2);
+    }
+}
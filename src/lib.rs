@@ -0,0 +1,9484 @@
+//! Tracks which devices are connected to the local network from periodic scans
+//!
+//! [`Daemon`] runs the scan loop of the `lanotify` binary, [`Tracker`] tracks devices from scans
+//! of another source.
+
+mod clock;
+pub mod control;
+mod dhcp;
+mod error;
+mod event_log;
+mod federation;
+mod fifo;
+mod history;
+mod home_assistant;
+mod hook;
+mod i18n;
+mod influx;
+mod mdns;
+pub mod metrics;
+mod mqtt;
+pub mod notify;
+mod oui;
+mod probe;
+mod rolling;
+pub mod scan;
+mod substitute;
+pub mod syslog;
+mod systemd;
+mod table;
+#[cfg(test)]
+mod test_http;
+mod tracker;
+mod wol;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use clock::{Clock, SystemClock};
+use control::Command;
+use history::{History, PackedHistory};
+use hook::Hooks;
+use notify::{
+    Action, Message, NotifierConfig, NotifierSource, Notifiers, Overflow, Priority, Queue,
+    RateLimitAction, RateLimiter, Transition,
+};
+use regex::Regex;
+use scan::{Scanner, ScannerConfig};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque, hash_map::Entry},
+    fmt::{Display, Write},
+    fs,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+use table::{Cell, Color, Table};
+
+pub use error::Error;
+pub use tracker::Tracker;
+
+const HISTORY_SIZE: usize = 30;
+const OFFLINE_THRESHOLD: usize = 10;
+const RECENT_WINDOW: usize = 5;
+// the long history is kept in buckets of this many scans, e.g. an hour with 10s scans
+const LONG_BUCKET_SCANS: u32 = 360;
+// a device is considered steady when seen in this fraction of the long history
+const DEGRADING_MIN_AVAILABILITY: f64 = 0.9;
+// a steady device is degrading when it misses at least this fraction of the detection window..
+const DEGRADING_MIN_MISS_RATE: f64 = 0.2;
+// ..and this many times as many scans as usual
+const DEGRADING_FACTOR: f64 = 3.0;
+// minimum number of connected devices to detect failed scans
+const SCAN_FAILURE_MIN_DEVICES: usize = 3;
+const LONG_HISTORY_BUCKETS: usize = 24;
+// weight of each older scan relative to the next in the recency part of the confidence
+const CONFIDENCE_DECAY: f64 = 0.8;
+// difference between the wall clock and monotonic time between scans considered a clock jump
+const CLOCK_JUMP_THRESHOLD: Duration = Duration::from_secs(60);
+// number of connection changes kept for the `events` command
+const RECENT_EVENTS: usize = 100;
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const SYS_CLASS_NET: &str = "/sys/class/net";
+const PROC_NET_ROUTE: &str = "/proc/net/route";
+
+/// Set by SIGHUP to reload the config before the next scan
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    RELOAD.store(true, Ordering::Relaxed);
+}
+
+/// Makes a running [`Daemon`] reload its config file on SIGHUP
+pub fn reload_on_sighup() {
+    // SAFETY: the handler only stores to an atomic
+    unsafe { libc::signal(libc::SIGHUP, on_sighup as *const () as libc::sighandler_t) };
+}
+
+/// Set by SIGTERM or SIGINT to stop before the next scan
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Interval at which waits check whether to stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+extern "C" fn on_stop(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Makes a running [`Daemon`] send its queued notifications and exit on SIGTERM or SIGINT
+pub fn stop_on_sigterm() {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only stores to an atomic
+        unsafe { libc::signal(signal, on_stop as *const () as libc::sighandler_t) };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String")]
+pub struct MacAddr(String);
+
+impl MacAddr {
+    #[cfg(test)]
+    fn new(mac: &str) -> Self {
+        mac.parse().expect("Invalid MAC address")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The manufacturer part of the address, e.g. `aa:bb:cc`
+    pub fn oui(&self) -> &str {
+        self.0.get(..8).unwrap_or(&self.0)
+    }
+
+    fn octets(&self) -> Result<[u8; 6]> {
+        let mut octets = [0; 6];
+        let mut parts = self.0.split(':');
+        for octet in &mut octets {
+            let part = parts.next().context("MAC address too short")?;
+            *octet = u8::from_str_radix(part, 16)
+                .with_context(|| format!("Invalid MAC address '{}'", self.0))?;
+        }
+        Ok(octets)
+    }
+}
+
+impl std::str::FromStr for MacAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(mac: &str) -> Result<Self> {
+        let is_valid = mac.len() == 17
+            && mac.split(':').count() == 6
+            && mac
+                .split(':')
+                .all(|b| b.len() == 2 && b.chars().all(|c| c.is_ascii_hexdigit()));
+        if !is_valid {
+            bail!("Invalid MAC address '{mac}'");
+        }
+        // scanners report MAC addresses in lowercase
+        Ok(Self(mac.to_ascii_lowercase()))
+    }
+}
+
+// deserializing goes through `from_str`, so MAC addresses from the config and state are checked
+// and lowercased too
+impl TryFrom<String> for MacAddr {
+    type Error = anyhow::Error;
+
+    fn try_from(mac: String) -> Result<Self> {
+        mac.parse()
+    }
+}
+
+/// An IPv4 network in CIDR notation, e.g. `192.168.1.0/24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Subnet {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Subnet {
+    fn mask(&self) -> u32 {
+        u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0)
+    }
+
+    fn contains(&self, ip: Ipv4Addr) -> bool {
+        u32::from(ip) & self.mask() == u32::from(self.network) & self.mask()
+    }
+}
+
+impl std::str::FromStr for Subnet {
+    type Err = anyhow::Error;
+
+    fn from_str(subnet: &str) -> Result<Self> {
+        let (network, prefix) = subnet
+            .split_once('/')
+            .with_context(|| format!("Subnet '{subnet}' is missing the prefix length"))?;
+        let network = network
+            .parse()
+            .with_context(|| format!("Invalid subnet address '{network}'"))?;
+        let prefix = prefix
+            .parse()
+            .ok()
+            .filter(|prefix| *prefix <= 32)
+            .with_context(|| format!("Invalid subnet prefix length '{prefix}'"))?;
+        Ok(Self { network, prefix })
+    }
+}
+
+/// A device found by a scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub mac: MacAddr,
+    pub ip: IpAddr,
+    pub vendor: String,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Response time to the scan, if reported by the scanner
+    #[serde(default)]
+    pub latency: Option<Duration>,
+    /// Network interface the device was found on, if reported by the scanner
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// Signal strength in dBm, if reported by the scanner
+    #[serde(default)]
+    pub rssi: Option<i32>,
+    /// Name of the peer the device was found by, `None` if found by this instance
+    #[serde(default)]
+    pub node: Option<String>,
+    /// Addresses a device with several MAC addresses was found with, empty if only with `mac`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub found_as: Vec<MacAddr>,
+}
+
+impl Device {
+    /// Updates the device with a newer scan of it, keeping the fields the scan got wrong
+    ///
+    /// The vendor is kept when the new one is empty or unknown, the IP address when the new one
+    /// is not a usable address, and the hostname when the lookup didn't answer this time.
+    fn merge(&mut self, new: Device) {
+        let Device {
+            mac: _,
+            ip,
+            vendor,
+            hostname,
+            latency,
+            interface,
+            rssi,
+            node,
+            found_as,
+        } = new;
+        if has_vendor(&vendor) {
+            self.vendor = vendor;
+        }
+        if !ip.is_unspecified() && !matches!(ip, IpAddr::V4(ip) if ip.is_broadcast()) {
+            self.ip = ip;
+        }
+        if hostname.is_some() {
+            self.hostname = hostname;
+        }
+        self.latency = latency;
+        self.interface = interface;
+        self.rssi = rssi;
+        self.node = node;
+        self.found_as = found_as;
+    }
+}
+
+/// Whether a scan reported an actual vendor, arp-scan reports `(Unknown)` for unregistered OUIs
+fn has_vendor(vendor: &str) -> bool {
+    let vendor = vendor.trim();
+    !vendor.is_empty() && !vendor.starts_with("(Unknown")
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Config {
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    pub scan_interval: Duration,
+    /// Maximum random delay added to each wait between scans, to desynchronize instances
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    scan_jitter: Duration,
+    /// Time in seconds after which a hanging scan command is killed and the scan counts as failed
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    pub scan_hard_timeout: Duration,
+    #[serde_as(as = "HashMap<_, serde_with::FromInto<DeviceEntry>>")]
+    devices: HashMap<MacAddr, DeviceConfig>,
+    /// Devices defined as `[[device]]` entries, merged into `devices` when loading
+    #[serde(rename = "device")]
+    device_list: Vec<DeviceConfig>,
+    /// What happens to a device that is no longer in `devices` when the config is reloaded
+    removed_devices: RemovedDevices,
+    ntfy_url: String,
+    /// PEM file with the CA certificates to trust for `ntfy_url`, instead of the system ones
+    ntfy_ca_cert: Option<PathBuf>,
+    /// Accept any TLS certificate for `ntfy_url`, only meant for testing
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks: anyone on the network
+    /// path can impersonate the server and read or alter notifications, including any credentials
+    /// sent with them. Prefer `ntfy_ca_cert` for servers with an internal CA.
+    ntfy_insecure_skip_verify: bool,
+    /// Notifiers to send notifications with, defaults to ntfy using `ntfy_url`
+    #[serde(rename = "notifier")]
+    notifiers: Vec<NotifierConfig>,
+    /// Notification settings shared by the devices that reference them with `tier`, by name
+    tiers: HashMap<String, Tier>,
+    /// URL to open when clicking a notification about a device, see [`expand`] for placeholders
+    ntfy_click: Option<String>,
+    /// URL to open when clicking a notification about an unknown device, e.g. the page of the
+    /// router to block it, see [`expand`] for placeholders
+    ///
+    /// It takes precedence over `ntfy_click` for unknown devices.
+    investigate_url: Option<String>,
+    /// Buttons on notifications about a device, see [`expand`] for placeholders
+    #[serde(rename = "ntfy_action")]
+    ntfy_actions: Vec<Action>,
+    /// Notification bodies for connection changes, see [`expand`] for placeholders
+    message_templates: MessageTemplates,
+    /// Language of the status words and default titles and bodies of connection notifications
+    language: i18n::Language,
+    /// Overrides of the phrases of `language`
+    messages: i18n::Messages,
+    /// Maximum number of notifications waiting to be sent
+    notification_queue_size: usize,
+    /// What to do with new notifications when the queue is full
+    notification_overflow: Overflow,
+    /// Retries of a failed notification, after 1s doubled on every retry
+    notify_retries: u32,
+    /// Consecutive failed notifications after which a notifier isn't tried until
+    /// `notify_circuit_cooldown` passed, notifications are held in the meantime, never if 0
+    notify_failure_threshold: u32,
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    notify_circuit_cooldown: Duration,
+    /// Maximum number of notifications to send per minute, across all devices
+    notify_rate_limit: Option<u32>,
+    /// What to do with notifications over `notify_rate_limit`
+    notify_rate_limit_action: RateLimitAction,
+    /// Suppress repeated (dis)connected notifications of a device within this window, off if 0
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    notify_dedup_window: Duration,
+    /// Drop a (dis)connected notification that is still waiting to be sent together with the
+    /// opposite one when a device changes back, e.g. while a notifier is slow
+    coalesce_reconnects: bool,
+    notify_unknown: bool,
+    /// Say in disconnect notifications whether adaptive detection considers the device sleeping,
+    /// intermittent or always-on, e.g. "Sleeping device X went quiet"
+    describe_activity: bool,
+    /// Ignore devices that are not in `devices` entirely, they are not tracked, shown or counted
+    only_known: bool,
+    /// Regexes matched against device names, matching devices don't send notifications
+    ///
+    /// A pattern matches anywhere in the name, anchor it with `^` and `$` to match the whole name.
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    mute_patterns: Vec<Regex>,
+    /// Notify when a usually steady device starts missing more scans than usual
+    notify_degrading: bool,
+    /// Notify once when a device that is still considered connected hasn't been seen for this
+    /// long, in seconds, e.g. when the detection keeps a device connected longer than expected
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    staleness_warn: Option<Duration>,
+    /// Notify once that a device is unstable when its connection state changes this many times
+    /// within `flap_window`, instead of about every change until it has been stable for the
+    /// window
+    flap_threshold: Option<usize>,
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    flap_window: Duration,
+    /// Treat unknown devices as known, named by their vendor, after their first notification
+    auto_register_unknown: bool,
+    /// Which connection changes to notify about for devices without their own `notify_on`
+    notify_on: NotifyOn,
+    pub log_level: log::LevelFilter,
+    /// Where to write the log and status table to
+    pub log_target: LogTarget,
+    notify_vendor_change: bool,
+    /// Notify the first time a device of a manufacturer not seen before joins, by the OUI of its
+    /// MAC address
+    notify_new_vendor: bool,
+    /// Subnets known devices are expected in, e.g. `192.168.1.0/24`, to notify when one reports
+    /// an IP outside of all of them
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    subnets: Vec<Subnet>,
+    batch_notifications: bool,
+    batch_threshold: usize,
+    detection_mode: DetectionMode,
+    /// How devices are shown and notified about until adaptive detection has enough scans
+    warmup: Warmup,
+    /// Weight the base rate of adaptive detection towards recent scans using this decay factor
+    base_rate_decay: Option<f64>,
+    /// Base rate below which a device counts as quiet, e.g. `0.3` for devices that sleep most of
+    /// the time, so its connects and disconnects are handled by `quiet_action`
+    ///
+    /// Devices count as quiet once they have as many scans as their `offline_threshold`.
+    quiet_base_rate: Option<f64>,
+    /// What happens to connection notifications of quiet devices
+    quiet_action: QuietAction,
+    /// Consecutive missed scans after which a device is offline in simple detection mode
+    offline_after_misses: usize,
+    /// Consecutive hits after which a device is online in simple detection mode
+    online_after_hits: usize,
+    /// Number of recent scans a device has to be seen in one of in window detection mode
+    presence_window: usize,
+    /// Number of scans whose devices are combined into the initial state on startup
+    startup_grace: usize,
+    /// Time to wait before the first scan, e.g. for the network to come up after boot
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    startup_delay: Duration,
+    /// How long a failing initial scan is retried every `scan_interval` before giving up
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    startup_timeout: Duration,
+    /// How long queued notifications are still sent for when stopping
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    shutdown_timeout: Duration,
+    /// Number of scan samples a new device needs before its first notification is sent
+    min_samples_before_notify: usize,
+    /// Number of scans a new device has to be seen in before it is notified about, so devices
+    /// that pass by for a single scan don't alert
+    new_device_settle: usize,
+    /// Time a new device has to be present without missing a scan before it is notified about
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    new_device_min_uptime: Option<Duration>,
+    table_columns: Vec<Column>,
+    /// Order of the devices in the status table
+    table_sort: TableSort,
+    /// Groups the devices in the status table under a header per type, room or status
+    table_group_by: TableGroupBy,
+    /// Spacing of the scans in the history column, `time` remembers when each scan happened
+    history_spacing: HistorySpacing,
+    /// Format of times in the status table and notifications, a chrono format string or `relative`
+    time_format: TimeFormat,
+    /// Show `now` as the last seen time of connected devices, so only the last seen times of
+    /// disconnected devices stand out
+    compact_last_seen: bool,
+    table_width: Option<usize>,
+    pub scanner: ScannerConfig,
+    /// Only scan the last known IPs of the configured devices, falling back to a full scan every
+    /// `full_scan_every` scans to find new devices and changed IPs
+    ///
+    /// Devices that aren't configured keep their state between full scans. Only supported by the
+    /// arp-scan scanner.
+    targeted_scan: bool,
+    /// Number of scans between full scans with `targeted_scan`
+    full_scan_every: usize,
+    /// Exit with an error after this many scans failed in a row, e.g. to be restarted by systemd
+    max_scan_failures: Option<usize>,
+    /// Notify once this many scans failed in a row, and again when scanning works again
+    notify_scan_failures: Option<usize>,
+    /// Notify when an interface to scan goes down and when it is up again
+    ///
+    /// Scans are skipped while an interface is down, in any case. Without configured
+    /// `interfaces` the interface of the default route is checked.
+    notify_interface_down: bool,
+    /// Look up hostnames of devices using mDNS
+    mdns_lookup: bool,
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    mdns_timeout: Duration,
+    /// How long to wait for the TCP probes of devices with a `probe_port`
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    probe_timeout: Duration,
+    /// Fraction of the connected devices that has to vanish in one scan to consider it failed
+    ///
+    /// Such scans are ignored, up to a few in a row in case the devices are really gone.
+    scan_failure_threshold: f64,
+    /// IP of the default gateway, such as the router
+    ///
+    /// While it is missing from the scans this host is considered to have lost the network, e.g.
+    /// during a reboot of the router, and scans are ignored until the gateway is back.
+    ///
+    /// Defaults to the gateway of the default route in `/proc/net/route`, once it was in a scan.
+    gateway: Option<Ipv4Addr>,
+    /// Port to probe the gateway on when it is missing from a scan, such as 53 or 80
+    gateway_probe_port: Option<u16>,
+    /// Ignore devices with a weaker signal than this many dBm, e.g. of neighbours
+    ///
+    /// Devices the scanner reports no signal strength for are never ignored.
+    min_rssi: Option<i32>,
+    /// Maximum number of devices to keep the state of, for large networks on constrained hardware
+    ///
+    /// Beyond it the unknown devices seen least recently are forgotten, configured devices are
+    /// always kept. A forgotten device that shows up again counts as new.
+    max_tracked_devices: Option<usize>,
+    /// IEEE `oui.txt` to look up vendors in, overriding the vendors reported by the scanner
+    ///
+    /// Devices with a MAC prefix that isn't listed keep the vendor of the scan.
+    oui_file: Option<PathBuf>,
+    /// Vendors loaded from `oui_file`
+    #[serde(skip)]
+    oui: Option<Arc<oui::Vendors>>,
+    /// dnsmasq leases file to name devices that aren't configured by their DHCP hostname
+    dhcp_leases_file: Option<PathBuf>,
+    /// Hostnames loaded from `dhcp_leases_file`
+    #[serde(skip)]
+    leases: Option<Arc<dhcp::Leases>>,
+    /// Notify when more than this many devices are connected
+    max_devices: Option<usize>,
+    /// Devices of people, to notify when someone comes home or everyone has left
+    presence_group: Vec<MacAddr>,
+    /// How long the whole presence group has to be absent before everyone is considered gone
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    presence_debounce: Duration,
+    /// URL to POST to repeatedly while anyone of the presence group is home, so home automation
+    /// can re-assert the presence after it restarted
+    presence_heartbeat_url: Option<String>,
+    /// Time in seconds between the calls of `presence_heartbeat_url`
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    presence_heartbeat_interval: Duration,
+    /// Body of the `presence_heartbeat_url` calls with the `{names}` and `{count}` placeholders,
+    /// a JSON object with the names of those present by default
+    presence_heartbeat_body: Option<String>,
+    /// Show and notify about this host and the default gateway, which are left out of the scans
+    /// unless they are configured devices
+    include_local: bool,
+    /// Notify with high priority when a device returns after being disconnected this long
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    long_absence: Option<Duration>,
+    /// Notify when fewer than this many devices are connected
+    min_devices: Option<usize>,
+    /// Time of day to send a daily digest of the device states
+    digest_time: Option<NaiveTime>,
+    /// Send a summary of the devices online after the initial scan
+    notify_on_startup: bool,
+    /// Send a summary of the changes of the configured devices while the daemon was down, and of
+    /// the notifications it still held back, when it starts from the state file
+    notify_catch_up: bool,
+    /// Broadcast address to send wake-on-LAN packets to
+    wol_broadcast: Ipv4Addr,
+    /// Unix socket to accept control commands on
+    pub control_socket: Option<PathBuf>,
+    /// File to persist the device state to, so it survives restarts
+    state_file: Option<PathBuf>,
+    /// Days the scans of every device are counted for its uptime percentage, kept in the state
+    /// file, `0` to not count them
+    uptime_days: usize,
+    /// Address to serve Prometheus metrics and the devices as JSON on `/api/devices`
+    pub metrics_address: Option<SocketAddr>,
+    /// Serve a status page of the devices on `/dashboard` of the `metrics_address`
+    pub dashboard: bool,
+    /// InfluxDB write endpoint to store the presence of every device on every scan in, see
+    /// [`influx::Sink::new`]
+    influx_url: Option<String>,
+    /// API token for `influx_url`
+    influx_token: Option<String>,
+    /// File to append the connection changes to, one JSON object per line
+    event_log: Option<PathBuf>,
+    /// Size in bytes beyond which the event log is rotated
+    event_log_max_size: Option<u64>,
+    /// Number of rotated event logs to keep
+    event_log_keep: usize,
+    /// Gzip rotated event logs
+    event_log_compress: bool,
+    /// Named pipe to write the connection changes to like the event log, for a local process to
+    /// react to, created if it doesn't exist
+    ///
+    /// Changes are dropped while no process reads the pipe.
+    event_fifo: Option<PathBuf>,
+    /// MQTT broker to publish the presence of the configured devices to, as Home Assistant
+    /// device trackers
+    mqtt_host: Option<String>,
+    mqtt_port: u16,
+    mqtt_username: Option<String>,
+    mqtt_password: Option<String>,
+    /// Topic prefix of Home Assistant MQTT discovery
+    mqtt_discovery_prefix: String,
+    /// Prefix of the state topics, `<prefix>/<mac>/state` with `home` or `not_home`
+    mqtt_topic_prefix: String,
+    /// Base URL of Home Assistant, e.g. `http://homeassistant.local:8123`, to set the
+    /// `device_tracker.<name>` entities of the configured devices in on every connection change
+    /// through its REST API
+    home_assistant_url: Option<String>,
+    /// Long-lived access token for `home_assistant_url`
+    home_assistant_token: Option<String>,
+    /// Other instances to merge the connected devices of, identified by their node name
+    #[serde(rename = "peer")]
+    peers: Vec<federation::Peer>,
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    peer_timeout: Duration,
+    /// URL to request after every successful scan, such as a healthchecks.io check, to be
+    /// alerted by an external system when lanotify stops or its scans fail
+    healthcheck_url: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scan_interval: Duration::from_secs(10),
+            scan_jitter: Duration::ZERO,
+            scan_hard_timeout: scan::DEFAULT_TIMEOUT,
+            devices: HashMap::new(),
+            removed_devices: RemovedDevices::Keep,
+            device_list: Vec::new(),
+            ntfy_url: "http://localhost:8080/notify".to_string(),
+            ntfy_ca_cert: None,
+            ntfy_insecure_skip_verify: false,
+            notifiers: Vec::new(),
+            tiers: HashMap::new(),
+            ntfy_click: None,
+            investigate_url: None,
+            ntfy_actions: Vec::new(),
+            message_templates: MessageTemplates::default(),
+            language: i18n::Language::default(),
+            messages: i18n::Messages::default(),
+            notification_queue_size: 100,
+            notification_overflow: Overflow::default(),
+            notify_retries: 0,
+            notify_failure_threshold: 5,
+            notify_circuit_cooldown: Duration::from_secs(60),
+            notify_rate_limit: None,
+            notify_rate_limit_action: RateLimitAction::default(),
+            notify_dedup_window: Duration::ZERO,
+            notify_unknown: true,
+            describe_activity: false,
+            only_known: false,
+            mute_patterns: Vec::new(),
+            auto_register_unknown: false,
+            notify_degrading: false,
+            staleness_warn: None,
+            notify_on: NotifyOn::default(),
+            log_level: log::LevelFilter::Info,
+            log_target: LogTarget::default(),
+            notify_vendor_change: false,
+            notify_new_vendor: false,
+            subnets: Vec::new(),
+            batch_notifications: false,
+            batch_threshold: 3,
+            coalesce_reconnects: false,
+            detection_mode: DetectionMode::default(),
+            warmup: Warmup::default(),
+            base_rate_decay: None,
+            quiet_base_rate: None,
+            quiet_action: QuietAction::default(),
+            offline_after_misses: 3,
+            online_after_hits: 1,
+            presence_window: 3,
+            min_samples_before_notify: 0,
+            new_device_settle: 1,
+            new_device_min_uptime: None,
+            startup_grace: 1,
+            startup_delay: Duration::ZERO,
+            startup_timeout: Duration::from_secs(60),
+            shutdown_timeout: Duration::from_secs(10),
+            table_columns: vec![
+                Column::Status,
+                Column::History,
+                Column::LastSeen,
+                Column::Since,
+                Column::Mac,
+                Column::Ip,
+                Column::Name,
+            ],
+            table_sort: TableSort::default(),
+            table_group_by: TableGroupBy::default(),
+            history_spacing: HistorySpacing::default(),
+            time_format: TimeFormat::default(),
+            compact_last_seen: false,
+            table_width: None,
+            scanner: ScannerConfig::default(),
+            targeted_scan: false,
+            full_scan_every: 10,
+            max_scan_failures: None,
+            notify_scan_failures: None,
+            notify_interface_down: false,
+            mdns_lookup: false,
+            mdns_timeout: Duration::from_millis(500),
+            probe_timeout: Duration::from_millis(500),
+            scan_failure_threshold: 0.9,
+            gateway: None,
+            gateway_probe_port: None,
+            min_rssi: None,
+            max_tracked_devices: None,
+            oui_file: None,
+            oui: None,
+            dhcp_leases_file: None,
+            leases: None,
+            max_devices: None,
+            presence_group: Vec::new(),
+            presence_debounce: Duration::from_secs(600),
+            presence_heartbeat_url: None,
+            presence_heartbeat_interval: Duration::from_secs(300),
+            presence_heartbeat_body: None,
+            include_local: false,
+            flap_threshold: None,
+            flap_window: Duration::from_secs(3600),
+            long_absence: None,
+            min_devices: None,
+            digest_time: None,
+            notify_on_startup: false,
+            notify_catch_up: false,
+            wol_broadcast: Ipv4Addr::BROADCAST,
+            control_socket: None,
+            state_file: None,
+            uptime_days: 30,
+            metrics_address: None,
+            dashboard: false,
+            influx_url: None,
+            influx_token: None,
+            event_log: None,
+            event_log_max_size: None,
+            event_log_keep: 5,
+            event_log_compress: false,
+            event_fifo: None,
+            mqtt_host: None,
+            mqtt_port: 1883,
+            mqtt_username: None,
+            mqtt_password: None,
+            mqtt_discovery_prefix: "homeassistant".to_string(),
+            mqtt_topic_prefix: "lanotify".to_string(),
+            home_assistant_url: None,
+            home_assistant_token: None,
+            peers: Vec::new(),
+            peer_timeout: Duration::from_millis(2000),
+            healthcheck_url: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn notifier_configs(&self) -> Vec<NotifierConfig> {
+        if self.notifiers.is_empty() {
+            vec![NotifierConfig::Ntfy {
+                name: None,
+                url: self.ntfy_url.clone(),
+                ca_cert: self.ntfy_ca_cert.clone(),
+                insecure_skip_verify: self.ntfy_insecure_skip_verify,
+            }]
+        } else {
+            self.notifiers.clone()
+        }
+    }
+
+    /// Configured name of a device
+    pub fn name(&self, mac: &MacAddr) -> Option<&String> {
+        self.devices.get(mac).map(|d| &d.name)
+    }
+
+    /// Whether notifications about a device are muted by `mute_patterns`
+    fn is_muted(&self, device: &Device) -> bool {
+        let name = self
+            .name(&device.mac)
+            .or(device.hostname.as_ref())
+            .unwrap_or(&device.vendor);
+        self.mute_patterns.iter().any(|p| p.is_match(name))
+    }
+
+    /// Whether a known device has an IPv4 address outside of `subnets`, if any are configured
+    fn is_outside_subnets(&self, device: &Device) -> bool {
+        !self.subnets.is_empty()
+            && self.devices.contains_key(&device.mac)
+            && !device.ip.is_unspecified()
+            && matches!(device.ip, IpAddr::V4(ip) if !self.subnets.iter().any(|subnet| subnet.contains(ip)))
+    }
+
+    fn offline_budget(&self, mac: &MacAddr) -> Option<Duration> {
+        self.devices.get(mac).and_then(|d| d.offline_budget)
+    }
+
+    fn is_critical(&self, mac: &MacAddr) -> bool {
+        self.devices.get(mac).is_some_and(|d| d.critical)
+    }
+
+    fn disconnect_grace(&self, mac: &MacAddr) -> Option<Duration> {
+        self.devices.get(mac).and_then(|d| d.disconnect_grace)
+    }
+
+    fn notify_on(&self, mac: &MacAddr) -> NotifyOn {
+        self.devices
+            .get(mac)
+            .and_then(|d| d.notify_on)
+            .unwrap_or(self.notify_on)
+    }
+
+    fn count_limit(&self, count: usize) -> CountLimit {
+        match (self.max_devices, self.min_devices) {
+            (Some(max), _) if count > max => CountLimit::Above(max),
+            (_, Some(min)) if count < min => CountLimit::Below(min),
+            _ => CountLimit::Within,
+        }
+    }
+
+    /// Detection mode of a device, its own or the global one
+    fn detection_mode(&self, mac: &MacAddr) -> DetectionMode {
+        self.devices
+            .get(mac)
+            .and_then(|d| d.detection_mode)
+            .unwrap_or(self.detection_mode)
+    }
+
+    /// Whether adaptive detection is still gathering its first scans of a device while `warmup`
+    /// is `unknown`
+    fn is_warming_up(&self, mac: &MacAddr, history: &ScanHistory) -> bool {
+        self.warmup == Warmup::Unknown
+            && self.detection_mode(mac) == DetectionMode::Adaptive
+            && history.scans() < self.thresholds(mac).offline
+    }
+
+    /// Thresholds of adaptive detection for a device, overridden by its config
+    fn thresholds(&self, mac: &MacAddr) -> Thresholds {
+        let device = self.devices.get(mac);
+        let default = Thresholds::default();
+        Thresholds {
+            offline: device
+                .and_then(|d| d.offline_threshold)
+                .unwrap_or(default.offline),
+            recent: device
+                .and_then(|d| d.recent_window)
+                .unwrap_or(default.recent),
+        }
+    }
+
+    /// Base rate and classification used by adaptive detection, `None` without enough data
+    fn activity(&self, mac: &MacAddr, history: &ScanHistory) -> Option<(f64, Activity)> {
+        if self.detection_mode(mac) != DetectionMode::Adaptive
+            || history.len() < self.thresholds(mac).offline
+        {
+            return None;
+        }
+        Some(history.activity(self.base_rate_decay))
+    }
+
+    /// Whether the device is seen in fewer scans than `quiet_base_rate`
+    fn is_quiet(&self, mac: &MacAddr, history: &ScanHistory) -> bool {
+        self.quiet_base_rate.is_some_and(|threshold| {
+            history.len() >= self.thresholds(mac).offline
+                && history.activity(self.base_rate_decay).0 < threshold
+        })
+    }
+
+    /// Determines whether a device is connected using its detection mode
+    fn is_connected(&self, mac: &MacAddr, history: &ScanHistory, is_connected: bool) -> bool {
+        match self.detection_mode(mac) {
+            DetectionMode::Adaptive => {
+                let (base_rate, _) = history.activity(self.base_rate_decay);
+                history
+                    .log
+                    .is_connected_with_rate(is_connected, base_rate, self.thresholds(mac))
+            }
+            DetectionMode::Simple => history.is_connected_simple(
+                is_connected,
+                self.offline_after_misses,
+                self.online_after_hits,
+            ),
+            DetectionMode::Raw => history.log.newest().copied().unwrap_or(is_connected),
+            DetectionMode::Window => {
+                let window = self.presence_window.max(1);
+                // until the window is filled, the scans before count as the current state
+                history.log.iter().take(window).any(|v| *v)
+                    || (history.len() < window && is_connected)
+            }
+        }
+    }
+
+    /// Loads the config as JSON if the file has a `.json` extension, otherwise as TOML
+    ///
+    /// `${VAR}` references in strings are replaced by environment variables.
+    ///
+    /// If `path` is a directory, all `*.toml` files in it are merged, see [`Config::load_dir`].
+    /// If it is `-`, TOML is read from stdin.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::read(path, None).map_err(Error::Config)
+    }
+
+    /// Loads a config with the settings of a `[profiles.<name>]` section applied on top of the
+    /// others, `default_profile` if `profile` is `None`
+    ///
+    /// Tables such as `devices` are merged, other values are replaced. Profiles are only
+    /// supported in TOML configs.
+    pub fn load_profile(path: &Path, profile: Option<&str>) -> Result<Self, Error> {
+        Self::read(path, profile).map_err(Error::Config)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        Self::parse_toml(contents, None).map_err(Error::Config)
+    }
+
+    /// Merges all `*.toml` files in a directory in alphabetical order
+    ///
+    /// Devices are combined, other fields are taken from the last file that sets them.
+    pub fn load_dir(dir: &Path) -> Result<Self, Error> {
+        Self::read_dir(dir, None).map_err(Error::Config)
+    }
+
+    fn read(path: &Path, profile: Option<&str>) -> Result<Self> {
+        if path == Path::new("-") {
+            let contents = std::io::read_to_string(std::io::stdin())
+                .context("Failed to read config from stdin")?;
+            return Self::parse_toml(&contents, profile);
+        }
+        if path.is_dir() {
+            return Self::read_dir(path, profile);
+        }
+        let contents = fs::read_to_string(path).context("Failed to read config file")?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") if profile.is_some() => {
+                bail!("Profiles are only supported in TOML configs")
+            }
+            Some("json") => Self::parse_json(&contents),
+            _ => Self::parse_toml(&contents, profile),
+        }
+    }
+
+    fn parse_toml(contents: &str, profile: Option<&str>) -> Result<Self> {
+        let mut table: toml::Table =
+            toml::from_str(contents).context("Failed to parse config file as TOML")?;
+        apply_profile(&mut table, profile)?;
+        let mut value = toml::Value::Table(table);
+        substitute::substitute_toml(&mut value)?;
+        substitute::read_secret_files_toml(&mut value)?;
+        let mut config: Config = value
+            .try_into()
+            .context("Failed to parse config file as TOML")?;
+        config.merge_device_list()?;
+        config.validate()?;
+        config.load_files()?;
+        Ok(config)
+    }
+
+    fn read_dir(dir: &Path, profile: Option<&str>) -> Result<Self> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).context("Failed to read config directory")? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "toml") && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        if paths.is_empty() {
+            bail!("No *.toml files in config directory '{}'", dir.display());
+        }
+        let mut fragments = Vec::new();
+        for path in paths {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+            let fragment: toml::Table = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file '{}'", path.display()))?;
+            fragments.push((path.display().to_string(), fragment));
+        }
+        let mut merged = merge_fragments(fragments);
+        apply_profile(&mut merged, profile)?;
+        let mut value = toml::Value::Table(merged);
+        substitute::substitute_toml(&mut value)?;
+        substitute::read_secret_files_toml(&mut value)?;
+        let mut config: Config = value
+            .try_into()
+            .context("Failed to parse merged config files")?;
+        config.merge_device_list()?;
+        config.validate()?;
+        config.load_files()?;
+        Ok(config)
+    }
+
+    fn parse_json(contents: &str) -> Result<Self> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(contents).context("Failed to parse config file as JSON")?;
+        substitute::substitute_json(&mut value)?;
+        substitute::read_secret_files_json(&mut value)?;
+        let mut config: Config =
+            serde_json::from_value(value).context("Failed to parse config file as JSON")?;
+        config.merge_device_list()?;
+        config.validate()?;
+        config.load_files()?;
+        Ok(config)
+    }
+
+    /// Moves the `[[device]]` entries into the `devices` map
+    fn merge_device_list(&mut self) -> Result<()> {
+        for (mac, device) in &self.devices {
+            if device.mac.as_ref().is_some_and(|m| m != mac) {
+                bail!(
+                    "Device '{}' has a mac that differs from its key",
+                    device.name
+                );
+            }
+        }
+        for device in std::mem::take(&mut self.device_list) {
+            let mac = device
+                .mac
+                .clone()
+                .or_else(|| device.macs.first().cloned())
+                .with_context(|| format!("Device '{}' is missing a mac", device.name))?;
+            if self.devices.contains_key(&mac) {
+                bail!("Device {} is defined more than once", mac.0);
+            }
+            self.devices.insert(mac, device);
+        }
+        let mut aliases = HashSet::new();
+        for (mac, device) in &self.devices {
+            for alias in device.macs.iter().filter(|m| *m != mac) {
+                if self.devices.contains_key(alias) || !aliases.insert(alias) {
+                    bail!("Device {} is defined more than once", alias.0);
+                }
+            }
+        }
+        for device in self.devices.values_mut() {
+            let Some(name) = &device.tier else {
+                continue;
+            };
+            let tier = self
+                .tiers
+                .get(name)
+                .with_context(|| format!("Device '{}' has unknown tier '{}'", device.name, name))?;
+            device.priority = device.priority.or(tier.priority);
+            if device.tags.is_empty() {
+                device.tags = tier.tags.clone();
+            }
+            if device.ntfy_url.is_none() {
+                device.ntfy_url = tier.ntfy_url.clone();
+            }
+        }
+        for device in self.devices.values_mut() {
+            let Some(interval) = device.reminder_interval else {
+                continue;
+            };
+            if !device.escalation.is_empty() {
+                bail!(
+                    "Device '{}' has both an escalation and a reminder_interval",
+                    device.name
+                );
+            }
+            let base = device.priority.unwrap_or_default();
+            let mut priorities: Vec<Priority> = [
+                Priority::Low,
+                Priority::Default,
+                Priority::High,
+                Priority::Urgent,
+            ]
+            .into_iter()
+            .filter(|p| *p > base)
+            .collect();
+            if priorities.is_empty() {
+                priorities.push(Priority::Urgent);
+            }
+            device.escalation = (1..)
+                .zip(priorities)
+                .map(|(n, priority)| EscalationStep {
+                    after: interval * n,
+                    notifier: None,
+                    priority,
+                })
+                .collect();
+        }
+        for device in self.devices.values() {
+            let missing = match device.match_by {
+                MatchBy::Mac => None,
+                MatchBy::Ip => device.ip.is_none().then_some("ip"),
+                MatchBy::IpVendor => {
+                    (device.ip.is_none() || device.vendor.is_none()).then_some("ip and vendor")
+                }
+            };
+            if let Some(missing) = missing {
+                bail!(
+                    "Device '{}' is missing the {} to match by",
+                    device.name,
+                    missing
+                );
+            }
+            let notifiers = self.notifier_configs();
+            for name in device.escalation.iter().filter_map(|s| s.notifier.as_ref()) {
+                if !notifiers.iter().any(|n| n.name() == Some(name)) {
+                    bail!(
+                        "Device '{}' escalates to unknown notifier '{}'",
+                        device.name,
+                        name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks what deserializing doesn't, naming the offending field: URLs, durations that have
+    /// to be positive and thresholds that have to be in order
+    fn validate(&self) -> Result<()> {
+        validate_url("ntfy_url", &self.ntfy_url)?;
+        let urls = [
+            ("presence_heartbeat_url", &self.presence_heartbeat_url),
+            ("influx_url", &self.influx_url),
+            ("healthcheck_url", &self.healthcheck_url),
+            ("home_assistant_url", &self.home_assistant_url),
+        ];
+        for (field, url) in urls {
+            if let Some(url) = url {
+                validate_url(field, url)?;
+            }
+        }
+        if self.home_assistant_url.is_some() && self.home_assistant_token.is_none() {
+            bail!("`home_assistant_url` requires `home_assistant_token`");
+        }
+        // MQTT only sends a password along with a user name
+        if self.mqtt_password.is_some() && self.mqtt_username.is_none() {
+            bail!("`mqtt_password` requires `mqtt_username`");
+        }
+        if let Some(url) = &self.investigate_url {
+            validate_template_url("investigate_url", url)?;
+        }
+        for notifier in &self.notifiers {
+            let (field, url) = match notifier {
+                NotifierConfig::Ntfy { url, .. } => ("url", url),
+                NotifierConfig::Telegram { api_url, .. } => ("api_url", api_url),
+            };
+            validate_url(field, url).with_context(|| format!("Invalid notifier {notifier}"))?;
+        }
+        for device in self.devices.values() {
+            let context = || format!("Invalid device '{}'", device.name);
+            if let Some(url) = &device.ntfy_url {
+                validate_url("ntfy_url", url).with_context(context)?;
+            }
+            let hooks = [
+                ("on_connect_url", &device.on_connect_url),
+                ("on_disconnect_url", &device.on_disconnect_url),
+            ];
+            for (field, url) in hooks {
+                if let Some(url) = url {
+                    validate_template_url(field, url).with_context(context)?;
+                }
+            }
+            let scans = [
+                ("offline_threshold", device.offline_threshold),
+                ("recent_window", device.recent_window),
+            ];
+            for (field, n) in scans {
+                match n {
+                    Some(0) => bail!("{}: `{field}` has to be at least 1", context()),
+                    Some(n) if n > HISTORY_SIZE => bail!(
+                        "{}: `{field}` ({n}) can't be above the {HISTORY_SIZE} scans kept per device",
+                        context()
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut durations = vec![
+            ("scan_interval", self.scan_interval),
+            ("scan_hard_timeout", self.scan_hard_timeout),
+        ];
+        if self.flap_threshold.is_some() {
+            durations.push(("flap_window", self.flap_window));
+        }
+        if self.presence_heartbeat_url.is_some() {
+            durations.push((
+                "presence_heartbeat_interval",
+                self.presence_heartbeat_interval,
+            ));
+        }
+        if self.mdns_lookup {
+            durations.push(("mdns_timeout", self.mdns_timeout));
+        }
+        if !self.peers.is_empty() {
+            durations.push(("peer_timeout", self.peer_timeout));
+        }
+        if let Some((field, _)) = durations.iter().find(|(_, d)| d.is_zero()) {
+            bail!("`{field}` has to be positive");
+        }
+
+        if let (Some(min), Some(max)) = (self.min_devices, self.max_devices)
+            && min > max
+        {
+            bail!("`min_devices` ({min}) is above `max_devices` ({max})");
+        }
+        if !(0.0..=1.0).contains(&self.scan_failure_threshold) {
+            bail!(
+                "`scan_failure_threshold` ({}) has to be between 0 and 1",
+                self.scan_failure_threshold
+            );
+        }
+        if let Some(decay) = self.base_rate_decay
+            && !(decay > 0.0 && decay < 1.0)
+        {
+            bail!("`base_rate_decay` ({decay}) has to be between 0 and 1");
+        }
+        if let Some(threshold) = self.quiet_base_rate
+            && !(0.0..=1.0).contains(&threshold)
+        {
+            bail!("`quiet_base_rate` ({threshold}) has to be between 0 and 1");
+        }
+        let counts = [
+            ("offline_after_misses", self.offline_after_misses),
+            ("online_after_hits", self.online_after_hits),
+            ("presence_window", self.presence_window),
+            ("full_scan_every", self.full_scan_every),
+        ];
+        if let Some((field, _)) = counts.iter().find(|(_, n)| *n == 0) {
+            bail!("`{field}` has to be at least 1");
+        }
+        if self.notify_rate_limit == Some(0) {
+            bail!("`notify_rate_limit` has to be at least 1");
+        }
+        // the history of a device is never longer, so more scans would never be reached
+        let scans = [
+            ("offline_after_misses", self.offline_after_misses),
+            ("online_after_hits", self.online_after_hits),
+            ("presence_window", self.presence_window),
+        ];
+        if let Some((field, n)) = scans.iter().find(|(_, n)| *n > HISTORY_SIZE) {
+            bail!("`{field}` ({n}) can't be above the {HISTORY_SIZE} scans kept per device");
+        }
+        Ok(())
+    }
+
+    /// Loads `oui_file` and `dhcp_leases_file`
+    fn load_files(&mut self) -> Result<()> {
+        if let Some(path) = &self.oui_file {
+            self.oui = Some(Arc::new(oui::Vendors::load(path)?));
+        }
+        if let Some(path) = &self.dhcp_leases_file {
+            self.leases = Some(Arc::new(dhcp::Leases::load(path)?));
+        }
+        Ok(())
+    }
+
+    /// Hostname of a device in `dhcp_leases_file`
+    fn lease_hostname(&self, mac: &MacAddr) -> Option<&str> {
+        self.leases.as_ref()?.get(mac)
+    }
+
+    /// Drops the devices ignored by `min_rssi`, looks up their vendors in `oui_file` and their
+    /// hostnames in `dhcp_leases_file` and identifies the remaining ones
+    fn filter_scan(&self, devices: &mut Vec<Device>) {
+        if let Some(min_rssi) = self.min_rssi {
+            devices.retain(|d| match d.rssi {
+                Some(rssi) if rssi < min_rssi => {
+                    log::debug!("Ignoring {} with a signal of {} dBm", d.mac.0, rssi);
+                    false
+                }
+                _ => true,
+            });
+        }
+        if let Some(vendors) = &self.oui {
+            for device in devices.iter_mut() {
+                if let Some(vendor) = vendors.get(&device.mac) {
+                    device.vendor = vendor.to_string();
+                }
+            }
+        }
+        for device in devices.iter_mut().filter(|d| d.hostname.is_none()) {
+            device.hostname = self.lease_hostname(&device.mac).map(str::to_string);
+        }
+        self.identify(devices);
+        if self.only_known {
+            devices.retain(|d| self.devices.contains_key(&d.mac));
+        }
+    }
+
+    /// Replaces the MAC address of devices matched by IP or by one of their other MAC addresses
+    /// with the one of their config entry
+    fn identify(&self, devices: &mut Vec<Device>) {
+        for device in devices.iter_mut() {
+            let Some((mac, _)) = self.devices.iter().find(|(_, config)| {
+                let vendor_matches = config.vendor.as_ref() == Some(&device.vendor);
+                let ip_matches = config.ip == Some(device.ip)
+                    && match config.match_by {
+                        MatchBy::Mac => false,
+                        MatchBy::Ip => true,
+                        MatchBy::IpVendor => vendor_matches,
+                    };
+                ip_matches || config.macs.contains(&device.mac)
+            }) else {
+                continue;
+            };
+            if device.mac != *mac {
+                log::debug!(
+                    "Device with MAC {} and IP {} matched as {}",
+                    device.mac.0,
+                    device.ip,
+                    mac.0
+                );
+                let found_as = std::mem::replace(&mut device.mac, mac.clone());
+                device.found_as.push(found_as);
+            }
+        }
+        // a device that is seen with several of its addresses at once is only tracked once
+        let mut tracked: Vec<Device> = Vec::with_capacity(devices.len());
+        let mut index: HashMap<MacAddr, usize> = HashMap::new();
+        for device in devices.drain(..) {
+            match index.entry(device.mac.clone()) {
+                Entry::Occupied(e) => {
+                    let kept = &mut tracked[*e.get()];
+                    if kept.found_as.is_empty() {
+                        kept.found_as.push(kept.mac.clone());
+                    }
+                    if device.found_as.is_empty() {
+                        kept.found_as.push(device.mac);
+                    } else {
+                        kept.found_as.extend(device.found_as);
+                    }
+                }
+                Entry::Vacant(e) => {
+                    e.insert(tracked.len());
+                    tracked.push(device);
+                }
+            }
+        }
+        *devices = tracked;
+    }
+
+    /// All MAC addresses of a device with several, `None` for other devices
+    fn member_macs(&self, mac: &MacAddr) -> Option<Vec<&MacAddr>> {
+        let (mac, config) = self
+            .devices
+            .get_key_value(mac)
+            .filter(|(_, c)| !c.macs.is_empty())?;
+        let mut members = vec![mac];
+        members.extend(config.macs.iter().filter(|m| *m != mac));
+        Some(members)
+    }
+}
+
+/// Applies the `[profiles.<name>]` section of the selected profile, or of `default_profile`,
+/// on top of the other settings and removes the profiles
+fn apply_profile(table: &mut toml::Table, profile: Option<&str>) -> Result<()> {
+    let profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => bail!("profiles must be a table of profiles"),
+        None => toml::Table::new(),
+    };
+    let default = match table.remove("default_profile") {
+        Some(toml::Value::String(name)) => Some(name),
+        Some(_) => bail!("default_profile must be a string"),
+        None => None,
+    };
+    let Some(name) = profile.map(str::to_string).or(default) else {
+        return Ok(());
+    };
+    match profiles.get(&name) {
+        Some(toml::Value::Table(overrides)) => {
+            log::debug!("Using config profile '{name}'");
+            merge_table(table, overrides.clone());
+            Ok(())
+        }
+        Some(_) => bail!("Profile '{name}' must be a table"),
+        None => bail!("Unknown profile '{name}'"),
+    }
+}
+
+/// Merges tables recursively, other values of `overrides` replace those in `base`
+fn merge_table(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        if let toml::Value::Table(overrides) = &value
+            && let Some(toml::Value::Table(base)) = base.get_mut(&key)
+        {
+            merge_table(base, overrides.clone());
+            continue;
+        }
+        base.insert(key, value);
+    }
+}
+
+/// Merges parsed config files key by key, later files winning, and warns about devices defined in
+/// more than one file
+fn merge_fragments(fragments: Vec<(String, toml::Table)>) -> toml::Table {
+    let mut merged = toml::Table::new();
+    let mut devices = toml::Table::new();
+    // file each device was defined in
+    let mut sources: HashMap<String, String> = HashMap::new();
+    let mut notifiers = Vec::new();
+    let mut device_list = Vec::new();
+    for (source, mut fragment) in fragments {
+        let mut entries: Vec<(String, toml::Value)> = Vec::new();
+        if let Some(toml::Value::Table(table)) = fragment.remove("devices") {
+            entries.extend(table);
+        }
+        if let Some(toml::Value::Array(list)) = fragment.remove("device") {
+            for entry in list {
+                match entry.get("mac").and_then(|m| m.as_str()) {
+                    Some(mac) => entries.push((mac.to_string(), entry)),
+                    // reported as missing a mac when the config is parsed
+                    None => device_list.push(entry),
+                }
+            }
+        }
+        for (mac, entry) in entries {
+            if let Some(previous) = sources.insert(mac.clone(), source.clone()) {
+                log::warn!("Device {mac} in '{source}' overrides the one in '{previous}'");
+            }
+            devices.insert(mac, entry);
+        }
+        if let Some(toml::Value::Array(list)) = fragment.remove("notifier") {
+            notifiers.extend(list);
+        }
+        merge_table(&mut merged, fragment);
+    }
+    merged.insert("devices".to_string(), toml::Value::Table(devices));
+    if !device_list.is_empty() {
+        merged.insert("device".to_string(), toml::Value::Array(device_list));
+    }
+    if !notifiers.is_empty() {
+        merged.insert("notifier".to_string(), toml::Value::Array(notifiers));
+    }
+    merged
+}
+
+/// Destination of the log messages and status table
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogTarget {
+    /// The log to stderr and the status table to stdout
+    #[default]
+    Stdout,
+    /// The local syslog daemon, with the daemon facility
+    Syslog,
+}
+
+/// How the connection state of a device is derived from its scan history
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum DetectionMode {
+    /// Statistical detection based on the base rate of the device, see [`ScanHistory::is_connected`]
+    #[default]
+    Adaptive,
+    /// Fixed thresholds of consecutive misses and hits
+    Simple,
+    /// Connected if and only if seen in the most recent scan, without any smoothing
+    Raw,
+    /// Connected if seen in any of the last `presence_window` scans
+    Window,
+}
+
+/// How a device is treated until adaptive detection has the scans it needs to decide
+///
+/// Until then the device keeps the state it was found in, connected for a device found by a scan.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Warmup {
+    /// Show the device as connected and notify about new devices right away
+    #[default]
+    Connected,
+    /// Show the state as unknown and hold back the notification about a new device until the
+    /// warm-up is over, only sending it if the device is still connected
+    Unknown,
+}
+
+/// Which connection changes of a device to notify about
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum NotifyOn {
+    Connect,
+    Disconnect,
+    #[default]
+    Both,
+}
+
+impl NotifyOn {
+    fn allows(&self, is_connected: bool) -> bool {
+        match self {
+            NotifyOn::Connect => is_connected,
+            NotifyOn::Disconnect => !is_connected,
+            NotifyOn::Both => true,
+        }
+    }
+}
+
+/// A notification sent when a device has been disconnected for some time
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EscalationStep {
+    /// Time since the device disconnected, in seconds
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    after: Duration,
+    /// Name of the notifier to send with, all notifiers if not set
+    notifier: Option<String>,
+    #[serde(default)]
+    priority: Priority,
+}
+
+/// Notification settings shared by several devices, e.g. an urgent `security` tier
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tier {
+    priority: Option<Priority>,
+    #[serde(default)]
+    tags: Vec<String>,
+    ntfy_url: Option<String>,
+}
+
+/// How a known device is recognized in scans
+///
+/// Devices matched by IP are tracked under the MAC address of their config entry, whatever MAC
+/// address they are seen with.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum MatchBy {
+    #[default]
+    Mac,
+    Ip,
+    IpVendor,
+}
+
+/// Configuration of a known device
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceConfig {
+    /// MAC address of the device, only used in `[[device]]` entries
+    mac: Option<MacAddr>,
+    /// Other MAC addresses of the device, e.g. of its wifi and ethernet interfaces
+    ///
+    /// The device is tracked as one and is connected when any of its addresses is seen. Without a
+    /// `mac` the first one identifies the device.
+    #[serde(default)]
+    macs: Vec<MacAddr>,
+    name: String,
+    /// Shown in front of the name, e.g. an emoji, defaults to the icon of the type
+    icon: Option<String>,
+    #[serde(rename = "type", default)]
+    device_type: DeviceType,
+    /// Room the device is in, e.g. `Living Room`, to group the status table by
+    room: Option<String>,
+    /// Name of the entry in `tiers` to take the priority, tags and ntfy URL from, unless they are
+    /// set for the device
+    tier: Option<String>,
+    /// Priority of notifications about this device
+    priority: Option<Priority>,
+    /// Overrides the global `ntfy_url` for notifications about this device
+    ///
+    /// With several `notifiers` it applies to the first ntfy one, or the one an escalation step
+    /// sends with.
+    ntfy_url: Option<String>,
+    /// Overrides the global `notify_on` for this device
+    notify_on: Option<NotifyOn>,
+    /// Overrides the global `detection_mode` for this device
+    detection_mode: Option<DetectionMode>,
+    /// Scans needed before adaptive detection decides, and after which an intermittent device
+    /// without sightings is offline
+    offline_threshold: Option<usize>,
+    /// Scans in which a sighting counts as recent for adaptive detection
+    recent_window: Option<usize>,
+    /// ntfy tags of notifications about this device, defaults to a tag for the connection state
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Reminders with increasing urgency while the device stays disconnected
+    #[serde(default)]
+    escalation: Vec<EscalationStep>,
+    /// Time in seconds between reminders while the device stays disconnected, each one priority
+    /// higher than the last up to `urgent`, as a shorthand for `escalation`
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    #[serde(default)]
+    reminder_interval: Option<Duration>,
+    /// How to recognize the device, for devices that randomize their MAC address
+    #[serde(default)]
+    match_by: MatchBy,
+    /// Fixed IP address of the device, required when matching by IP
+    ip: Option<IpAddr>,
+    /// Vendor of the device, required when matching by IP and vendor
+    vendor: Option<String>,
+    /// TCP port the device keeps open, to count it present when a scan misses it but it accepts
+    /// a connection, e.g. 9100 for printers
+    probe_port: Option<u16>,
+    /// Notifications about this device bypass `notify_rate_limit`, they are never dropped or
+    /// deferred
+    ///
+    /// It only lifts the rate limit: `notify_on`, `mute_patterns` and `notify_dedup_window` still
+    /// decide whether a notification is sent at all.
+    #[serde(default)]
+    critical: bool,
+    /// Time in seconds to hold back the disconnect notification, e.g. for a phone that sleeps
+    ///
+    /// When the device reconnects within it neither the disconnect nor the connect is notified.
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    #[serde(default)]
+    disconnect_grace: Option<Duration>,
+    /// Time in seconds after a notification about the device connecting or disconnecting in which
+    /// further ones are suppressed, e.g. for smart bulbs that reconnect several times an hour
+    ///
+    /// The connection state is still updated.
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    #[serde(default)]
+    notify_cooldown: Option<Duration>,
+    /// Time in seconds the device may be offline per day, to notify once a day when it was
+    /// offline for longer, e.g. for a NAS that should always be on
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    #[serde(default)]
+    offline_budget: Option<Duration>,
+    /// URL to POST the event to when the device connects, e.g. a Home Assistant webhook, see
+    /// [`expand`] for placeholders
+    ///
+    /// The body is the JSON object of the event as written to the event log. It is called on
+    /// every change of the connection state, regardless of whether it is notified.
+    on_connect_url: Option<String>,
+    /// URL to POST the event to when the device disconnects, like `on_connect_url`
+    on_disconnect_url: Option<String>,
+    /// Free text for your own reference, e.g. `borrowed from work`, shown in the `note` column and
+    /// below the body of notifications
+    note: Option<String>,
+}
+
+impl DeviceConfig {
+    #[cfg(test)]
+    fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Name including the icon, if any
+    fn display_name(&self) -> String {
+        match self.icon.as_deref().or(self.device_type.icon()) {
+            Some(icon) => format!("{} {}", icon, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Templates for the body of connection notifications, the default body is used when unset
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct MessageTemplates {
+    known_connected: Option<String>,
+    known_disconnected: Option<String>,
+    unknown_connected: Option<String>,
+    unknown_disconnected: Option<String>,
+}
+
+impl MessageTemplates {
+    fn get(&self, known: bool, connected: bool) -> Option<&String> {
+        match (known, connected) {
+            (true, true) => self.known_connected.as_ref(),
+            (true, false) => self.known_disconnected.as_ref(),
+            (false, true) => self.unknown_connected.as_ref(),
+            (false, false) => self.unknown_disconnected.as_ref(),
+        }
+    }
+}
+
+/// Kind of a known device, which determines its default icon and groups the status table
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum DeviceType {
+    Phone,
+    Laptop,
+    Server,
+    Iot,
+    #[default]
+    Unknown,
+}
+
+impl DeviceType {
+    fn icon(&self) -> Option<&'static str> {
+        match self {
+            DeviceType::Phone => Some("📱"),
+            DeviceType::Laptop => Some("💻"),
+            DeviceType::Server => Some("🖥️"),
+            DeviceType::Iot => Some("💡"),
+            DeviceType::Unknown => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            DeviceType::Phone => "Phone",
+            DeviceType::Laptop => "Laptop",
+            DeviceType::Server => "Server",
+            DeviceType::Iot => "IoT",
+            DeviceType::Unknown => "Unknown",
+        }
+    }
+}
+
+/// A device in the config, either just its name or a table with more options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DeviceEntry {
+    Name(String),
+    Full(Box<DeviceConfig>),
+}
+
+impl From<DeviceEntry> for DeviceConfig {
+    fn from(entry: DeviceEntry) -> Self {
+        match entry {
+            DeviceEntry::Name(name) => Self {
+                name,
+                ..Default::default()
+            },
+            DeviceEntry::Full(config) => *config,
+        }
+    }
+}
+
+/// Order of the devices in the status table, devices that are equal are sorted by name
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum TableSort {
+    /// Known devices grouped by type and by name, then unknown devices
+    #[default]
+    Name,
+    /// Most recently seen first
+    LastSeen,
+    /// Disconnected devices first, to surface problems
+    Status,
+    /// Connected the longest first, then disconnected devices
+    Uptime,
+    Ip,
+}
+
+/// What to group the devices in the status table by, each group is headed by its name
+///
+/// Groups keep the order of `table_sort` within them. Unknown devices and devices without a room
+/// are grouped under `Unknown`, which comes last.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum TableGroupBy {
+    #[default]
+    None,
+    Type,
+    Room,
+    /// Connected devices, then disconnected devices
+    Status,
+}
+
+/// How the scans in the history column of the status table are spaced
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum HistorySpacing {
+    /// One character per scan
+    #[default]
+    Scans,
+    /// By the time between the scans, with a blank for every `scan_interval` without a scan
+    Time,
+}
+
+/// What happens to connection notifications of devices below `quiet_base_rate`
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum QuietAction {
+    /// Don't notify, the connection state and event log are still updated
+    #[default]
+    Suppress,
+    /// Notify with low priority
+    Low,
+}
+
+/// What happens to a device that was removed from `devices` when the config is reloaded
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RemovedDevices {
+    /// Keep tracking it with the name and settings it had, until the daemon restarts
+    #[default]
+    Keep,
+    /// Forget its state, the next scan it is found in treats it as a device seen for the first
+    /// time
+    Drop,
+    /// Track it as an unknown device from now on, notifying about it like about a new device if
+    /// it is connected
+    Unknown,
+}
+
+/// A column of the status table
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Column {
+    Status,
+    Name,
+    History,
+    LastSeen,
+    Mac,
+    Ip,
+    Vendor,
+    Hostname,
+    Latency,
+    Interface,
+    /// The peer a device was found by, empty for devices found by this instance
+    Node,
+    Uptime,
+    /// Time since the last connect or disconnect, e.g. `offline for 2h0m`
+    Since,
+    /// Percentage of scans the device was seen in over the long history
+    Availability,
+    /// The long history downsampled
+    LongHistory,
+    /// Base rate and classification of adaptive detection, e.g. `ALW 0.93`
+    Detection,
+    /// The note of a known device
+    Note,
+    /// Confidence that the device is connected, see [`ScanHistory::confidence`]
+    Confidence,
+    /// Percentage of scans the device was seen in over the last `uptime_days`
+    UptimePercent,
+}
+
+impl Column {
+    fn cell(&self, state: &DeviceState, settings: &Config, now: DateTime<Local>) -> Cell {
+        let config = settings.devices.get(&state.device.mac);
+        let time_format = &settings.time_format;
+        let warming_up = settings.is_warming_up(&state.device.mac, &state.ping_history);
+        let status_color = if warming_up {
+            Color::Yellow
+        } else if state.is_connected {
+            Color::Green
+        } else {
+            Color::Red
+        };
+        let text = match self {
+            Column::Status if warming_up => "❔".to_string(),
+            Column::Status => if state.is_connected { "✅" } else { "❌" }.to_string(),
+            Column::Name => {
+                let name = match (config, &state.device.hostname) {
+                    (Some(config), _) => config.display_name(),
+                    (None, Some(hostname)) => {
+                        format!("Unknown: {} ({})", hostname, state.device.vendor)
+                    }
+                    (None, None) => format!("Unknown: {}", state.device.vendor),
+                };
+                if state.is_muted(now) {
+                    name + " (muted)"
+                } else {
+                    name
+                }
+            }
+            Column::History => match settings.history_spacing {
+                HistorySpacing::Scans => state.ping_history.to_string(),
+                HistorySpacing::Time => state.ping_history.spaced(settings.scan_interval),
+            },
+            Column::LongHistory => format!("{:#}", state.ping_history),
+            Column::Availability => match state.ping_history.availability() {
+                Some(availability) => format!("{:.0}%", availability * 100.0),
+                None => "-".to_string(),
+            },
+            Column::LastSeen if settings.compact_last_seen && state.is_connected => {
+                "now".to_string()
+            }
+            Column::LastSeen => time_format.format(state.last_seen, now),
+            Column::Mac => state.device.mac.0.clone(),
+            Column::Ip => state.device.ip.to_string(),
+            Column::Vendor => state.device.vendor.clone(),
+            Column::Hostname => state.device.hostname.clone().unwrap_or_default(),
+            Column::Interface => state.device.interface.clone().unwrap_or_default(),
+            Column::Node => state.device.node.clone().unwrap_or_default(),
+            Column::Latency => match state.device.latency {
+                Some(latency) => format!("{:.1}ms", latency.as_secs_f64() * 1000.0),
+                None => "-".to_string(),
+            },
+            Column::Uptime => {
+                if state.is_connected {
+                    format_duration(now - state.last_change)
+                } else {
+                    "-".to_string()
+                }
+            }
+            Column::Since => state
+                .since(now, time_format)
+                .unwrap_or_else(|| "-".to_string()),
+            Column::Detection => match settings.activity(&state.device.mac, &state.ping_history) {
+                Some((base_rate, activity)) => format!("{} {:.2}", activity.tag(), base_rate),
+                None => "-".to_string(),
+            },
+            Column::Note => config
+                .and_then(|config| config.note.clone())
+                .unwrap_or_default(),
+            Column::Confidence => format!("{:.2}", state.ping_history.confidence()),
+            Column::UptimePercent => match state.uptime.ratio() {
+                Some(ratio) => format!("{:.0}%", ratio * 100.0),
+                None => "-".to_string(),
+            },
+        };
+        match self {
+            Column::Status | Column::History | Column::LongHistory => {
+                Cell::colored(text, status_color)
+            }
+            Column::Name if config.is_some() => Cell::colored(text, status_color),
+            Column::Name | Column::Vendor if config.is_none() => Cell::colored(text, Color::Yellow),
+            _ => Cell::from(text),
+        }
+    }
+}
+
+/// Fills in the `{mac}`, `{oui}`, `{ip}`, `{vendor}`, `{hostname}` and `{name}` placeholders of a
+/// template for a device
+fn expand(template: &str, device: &Device, name: &str) -> String {
+    template
+        .replace("{mac}", &device.mac.0)
+        .replace("{oui}", device.mac.oui())
+        .replace("{ip}", &device.ip.to_string())
+        .replace("{vendor}", &device.vendor)
+        .replace("{hostname}", device.hostname.as_deref().unwrap_or("-"))
+        .replace("{name}", name)
+}
+
+/// Like [`expand`] for a URL, with the values percent-encoded
+fn expand_url(template: &str, device: &Device, name: &str) -> String {
+    template
+        .replace("{mac}", &hook::encode(&device.mac.0))
+        .replace("{oui}", &hook::encode(device.mac.oui()))
+        .replace("{ip}", &hook::encode(&device.ip.to_string()))
+        .replace("{vendor}", &hook::encode(&device.vendor))
+        .replace(
+            "{hostname}",
+            &hook::encode(device.hostname.as_deref().unwrap_or("-")),
+        )
+        .replace("{name}", &hook::encode(name))
+}
+
+/// Checks that a URL has an http(s) scheme and a host
+fn validate_url(field: &str, url: &str) -> Result<()> {
+    let uri: ureq::http::Uri = url
+        .parse()
+        .with_context(|| format!("Invalid `{field}` '{url}'"))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+        bail!("Invalid `{field}` '{url}', expected an http(s) URL");
+    }
+    Ok(())
+}
+
+/// Checks a URL with [`expand`] placeholders like [`validate_url`]
+fn validate_template_url(field: &str, url: &str) -> Result<()> {
+    let example = ["{mac}", "{oui}", "{ip}", "{vendor}", "{hostname}", "{name}"]
+        .iter()
+        .fold(url.to_string(), |url, placeholder| {
+            url.replace(placeholder, "x")
+        });
+    if validate_url(field, &example).is_err() {
+        bail!("Invalid `{field}` '{url}', expected an http(s) URL");
+    }
+    Ok(())
+}
+
+/// Default ntfy tag of a connection notification, a green or red circle
+fn status_tag(is_connected: bool) -> String {
+    if is_connected {
+        "green_circle".to_string()
+    } else {
+        "red_circle".to_string()
+    }
+}
+
+/// Describes the state before the last transition, e.g. `, was offline for 2h0m`
+fn previous_state(state: &DeviceState, time_format: &TimeFormat) -> String {
+    if let Some(absence) = state.absence() {
+        return format!(", was offline for {}", format_duration(absence));
+    }
+    match (
+        state.is_connected,
+        state.last_connect,
+        state.last_disconnect,
+    ) {
+        (false, Some(connect), Some(disconnect)) if connect <= disconnect => match time_format {
+            TimeFormat::Relative => {
+                format!(", was online for {}", format_duration(disconnect - connect))
+            }
+            TimeFormat::Custom(format) => {
+                format!(", was online since {}", connect.format(format))
+            }
+        },
+        _ => String::new(),
+    }
+}
+
+/// How points in time are shown in the status table and notifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TimeFormat {
+    /// Time relative to now, e.g. `3m ago`
+    Relative,
+    /// A chrono format string
+    Custom(String),
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat::Custom("%Y-%m-%d %H:%M:%S".to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeFormat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let format = String::deserialize(deserializer)?;
+        if format == "relative" {
+            return Ok(TimeFormat::Relative);
+        }
+        // invalid format strings would fail when formatting
+        chrono::format::StrftimeItems::new(&format)
+            .parse()
+            .map_err(|e| {
+                serde::de::Error::custom(format!("invalid time format '{format}': {e}"))
+            })?;
+        Ok(TimeFormat::Custom(format))
+    }
+}
+
+impl TimeFormat {
+    fn format(&self, time: DateTime<Local>, now: DateTime<Local>) -> String {
+        match self {
+            TimeFormat::Relative => format!("{} ago", humanize(now - time)),
+            TimeFormat::Custom(format) => time.format(format).to_string(),
+        }
+    }
+}
+
+/// Random duration up to `max`
+fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // the hasher is randomly seeded, which is random enough to spread scans
+    let random = std::hash::RandomState::new().build_hasher().finish();
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Formats a duration in its largest unit, e.g. `2d`, `3h` or `45s`
+fn humanize(duration: chrono::TimeDelta) -> String {
+    let secs = duration.num_seconds().max(0);
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Formats a duration compactly, e.g. `2d3h`, `1h20m` or `45s`
+fn format_duration(duration: chrono::TimeDelta) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{mins}m")
+    } else if mins > 0 {
+        format!("{mins}m{}s", secs % 60)
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Something that happened on the network that may be notified about
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected(MacAddr),
+    Disconnected(MacAddr),
+    /// A usually steady device started missing scans
+    Degrading(MacAddr),
+    /// A device that is still considered connected hasn't been seen for `staleness_warn`
+    Stale(MacAddr),
+    /// A device changed its connection state too often within the flap window
+    Unstable {
+        mac: MacAddr,
+        changes: usize,
+    },
+    /// A flapping device has not changed its connection state for the flap window
+    Stabilized(MacAddr),
+    /// A device has been disconnected for the time of an escalation step
+    Escalation {
+        mac: MacAddr,
+        step: usize,
+    },
+    /// A known device reported a different vendor, which may indicate MAC spoofing
+    VendorChanged {
+        mac: MacAddr,
+        old_vendor: String,
+    },
+    /// The first device of a manufacturer not seen before joined, see `notify_new_vendor`
+    NewVendor(MacAddr),
+    /// A device was offline for longer than its `offline_budget` today
+    OfflineBudget(MacAddr),
+    /// A known device reported an IP outside of the configured subnets, which may indicate ARP
+    /// spoofing or a rogue DHCP server
+    UnexpectedIp {
+        mac: MacAddr,
+        ip: IpAddr,
+    },
+    /// The number of connected devices went outside of the configured bounds
+    DeviceCount {
+        count: usize,
+        limit: CountLimit,
+    },
+    /// Most connected devices were missing from a scan, which was ignored as a likely failure
+    ScanFailure {
+        vanished: usize,
+        connected: usize,
+    },
+    /// The gateway is reachable again after the scans were ignored for a while
+    NetworkRestored {
+        lost_for: chrono::TimeDelta,
+    },
+    /// Scans failed `notify_scan_failures` times in a row
+    ScanBroken {
+        failures: usize,
+        error: String,
+    },
+    /// Scanning works again after failing `notify_scan_failures` or more times in a row
+    ScanRecovered {
+        failures: usize,
+    },
+    /// An interface to scan went down, so scans are skipped
+    InterfaceDown {
+        interface: String,
+    },
+    /// The interfaces to scan are all up again
+    InterfaceUp {
+        interface: String,
+    },
+    /// Someone of the presence group arrived while nobody was home, or everyone left
+    Presence {
+        present: bool,
+    },
+}
+
+impl Event {
+    /// The device the event is about, if it is about a single one
+    fn mac(&self) -> Option<&MacAddr> {
+        match self {
+            Event::Connected(mac)
+            | Event::Disconnected(mac)
+            | Event::Degrading(mac)
+            | Event::Stale(mac)
+            | Event::Unstable { mac, .. }
+            | Event::Stabilized(mac)
+            | Event::Escalation { mac, .. }
+            | Event::VendorChanged { mac, .. }
+            | Event::NewVendor(mac)
+            | Event::OfflineBudget(mac)
+            | Event::UnexpectedIp { mac, .. } => Some(mac),
+            Event::DeviceCount { .. }
+            | Event::ScanFailure { .. }
+            | Event::NetworkRestored { .. }
+            | Event::ScanBroken { .. }
+            | Event::ScanRecovered { .. }
+            | Event::InterfaceDown { .. }
+            | Event::InterfaceUp { .. }
+            | Event::Presence { .. } => None,
+        }
+    }
+}
+
+/// Which bound the number of connected devices is beyond, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountLimit {
+    Within,
+    Above(usize),
+    Below(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeviceState {
+    device: Device,
+    last_seen: DateTime<Local>,
+    // time of the last change of `is_connected`
+    last_change: DateTime<Local>,
+    #[serde(default)]
+    last_connect: Option<DateTime<Local>>,
+    #[serde(default)]
+    last_disconnect: Option<DateTime<Local>>,
+    is_connected: bool,
+    ping_history: ScanHistory,
+    // whether the initial connected notification has been emitted (or skipped)
+    #[serde(default)]
+    announced: bool,
+    // whether the device was unknown and registered by `auto_register_unknown`
+    #[serde(default)]
+    registered: bool,
+    // whether the device misses more scans than usual, see `ScanHistory::is_degrading`
+    #[serde(default)]
+    degrading: bool,
+    // whether the device is connected but wasn't seen for `Config::staleness_warn`
+    #[serde(default)]
+    stale: bool,
+    // number of escalation steps reached since the device disconnected
+    #[serde(default)]
+    escalation_step: usize,
+    // times of the most recent connection changes within the flap window, newest first
+    #[serde(default)]
+    changes: VecDeque<DateTime<Local>>,
+    // whether the device changes state too often, see `Config::flap_threshold`
+    #[serde(default)]
+    flapping: bool,
+    // since when a device that is not announced yet has been seen in every scan
+    #[serde(default)]
+    present_since: Option<DateTime<Local>>,
+    // when a disconnect held back by `disconnect_grace` happened
+    #[serde(default)]
+    pending_disconnect: Option<DateTime<Local>>,
+    // history and state per address of a device with several MAC addresses
+    #[serde(default)]
+    members: BTreeMap<MacAddr, Member>,
+    // when a connection change was last notified, for `notify_cooldown`
+    #[serde(default)]
+    last_notified: Option<DateTime<Local>>,
+    // notifications for the device held by the `mute` command
+    #[serde(default)]
+    muted: Option<Pause>,
+    #[serde(default)]
+    uptime: Uptime,
+    #[serde(default)]
+    downtime: Downtime,
+}
+
+/// One of the MAC addresses of a device with several, e.g. its wifi or ethernet interface
+///
+/// Each address is detected on its own, the device is connected when any of them is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Member {
+    history: ScanHistory,
+    is_connected: bool,
+}
+
+// detection window of the most recent scans, bit-packed to save memory with many devices
+type Window = PackedHistory<HISTORY_SIZE>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScanHistory {
+    log: Window,
+    // long horizon history in buckets, the front bucket is being filled
+    #[serde(default)]
+    long: VecDeque<Bucket>,
+    // times of the newest samples of `log`, only recorded for `HistorySpacing::Time`
+    #[serde(default, skip_serializing_if = "History::is_empty")]
+    times: History<DateTime<Local>, HISTORY_SIZE>,
+}
+
+/// Number of scans a device was seen in out of a number of scans
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Bucket {
+    hits: u32,
+    scans: u32,
+}
+
+/// Time a device was offline on a day, for `offline_budget`
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Downtime {
+    date: Option<NaiveDate>,
+    // offline time of `date` up to the last reconnect
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    offline: Duration,
+    // day the budget was last exceeded on, to notify once a day
+    alerted: Option<NaiveDate>,
+}
+
+/// Scans a device was seen in per day and since it was first seen, for its uptime over more days
+/// than the long history covers
+///
+/// Scans are counted rather than the time present, so a changed `scan_interval` doesn't skew it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Uptime {
+    // newest day first
+    days: VecDeque<(NaiveDate, Bucket)>,
+    hits: u64,
+    scans: u64,
+}
+
+impl Uptime {
+    /// Counts a scan on `date`, keeping the counts of the last `days` days
+    fn record(&mut self, seen: bool, date: NaiveDate, days: usize) {
+        if days == 0 {
+            return;
+        }
+        // a clock set back keeps counting on the newest day
+        if self.days.front().is_none_or(|(newest, _)| *newest < date) {
+            self.days.push_front((date, Bucket::default()));
+            self.days.truncate(days);
+        }
+        let (_, bucket) = self.days.front_mut().unwrap();
+        bucket.scans += 1;
+        bucket.hits += seen as u32;
+        self.scans += 1;
+        self.hits += seen as u64;
+    }
+
+    /// Fraction of the scans of the kept days the device was seen in
+    fn ratio(&self) -> Option<f64> {
+        let (hits, scans) = self
+            .days
+            .iter()
+            .fold((0, 0), |(h, s), (_, b)| (h + b.hits, s + b.scans));
+        (scans > 0).then(|| hits as f64 / scans as f64)
+    }
+}
+
+impl ScanHistory {
+    fn new() -> Self {
+        Self {
+            log: Window::new(),
+            long: VecDeque::new(),
+            times: History::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn from(array: Vec<bool>) -> Self {
+        assert_eq!(array.len(), HISTORY_SIZE);
+        Self {
+            log: Window::from(array),
+            long: VecDeque::new(),
+            times: History::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.log.len()
+    }
+
+    #[cfg(test)]
+    fn is_connected(&self, is_connected: bool) -> bool {
+        self.log.is_connected(is_connected)
+    }
+
+    fn base_rate(&self) -> f64 {
+        self.log.base_rate()
+    }
+
+    fn weighted_base_rate(&self, decay: f64) -> f64 {
+        self.log.weighted_base_rate(decay)
+    }
+
+    fn is_connected_simple(&self, is_connected: bool, misses: usize, hits: usize) -> bool {
+        self.log.is_connected_simple(is_connected, misses, hits)
+    }
+
+    // Number of scans in the long history, unlike the detection window not cleared by a reset
+    fn scans(&self) -> usize {
+        self.long.iter().map(|b| b.scans as usize).sum()
+    }
+
+    // Number of scans in the long history the device was seen in
+    fn hits(&self) -> usize {
+        self.long.iter().map(|b| b.hits as usize).sum()
+    }
+
+    fn update(&mut self, state: bool) {
+        self.log.push(state);
+
+        if self
+            .long
+            .front()
+            .is_none_or(|b| b.scans >= LONG_BUCKET_SCANS)
+        {
+            self.long.push_front(Bucket::default());
+            self.long.truncate(LONG_HISTORY_BUCKETS);
+        }
+        let bucket = self.long.front_mut().unwrap();
+        bucket.scans += 1;
+        bucket.hits += state as u32;
+    }
+
+    // Adds a sample of a scan at a time, which is only remembered if given
+    fn update_at(&mut self, state: bool, time: Option<DateTime<Local>>) {
+        self.update(state);
+        match time {
+            Some(time) => self.times.push(time),
+            None => self.times.clear(),
+        }
+    }
+
+    // Clears the detection window, keeping the long history
+    fn reset(&mut self) {
+        self.log.clear();
+        self.times.clear();
+    }
+
+    // Sparkline of the detection window with a blank for every `interval` without a scan,
+    // samples without a time are spaced evenly
+    fn spaced(&self, interval: Duration) -> String {
+        let mut sparkline = String::new();
+        let times = self.times.iter().map(Some).chain(std::iter::repeat(None));
+        let mut newer: Option<&DateTime<Local>> = None;
+        for (seen, time) in self.log.iter().zip(times) {
+            if let (Some(newer), Some(time)) = (newer, time) {
+                let intervals = (*newer - *time).as_seconds_f64() / interval.as_secs_f64();
+                let missed = (intervals.round() as usize).saturating_sub(1);
+                sparkline.extend(std::iter::repeat_n(' ', missed));
+            }
+            sparkline.push(if *seen { 'O' } else { '-' });
+            newer = time;
+        }
+        let mut sparkline: String = sparkline.chars().take(HISTORY_SIZE).collect();
+        let padding = HISTORY_SIZE - sparkline.chars().count();
+        sparkline.extend(std::iter::repeat_n('.', padding));
+        sparkline
+    }
+
+    /// Confidence from 0 to 1 that the device is connected, the base rate of the detection
+    /// window blended with a base rate weighted towards the recent scans
+    ///
+    /// Without any scans it is 1, as devices start out connected when they are found.
+    fn confidence(&self) -> f64 {
+        if self.len() == 0 {
+            return 1.0;
+        }
+        (self.base_rate() + self.weighted_base_rate(CONFIDENCE_DECAY)) / 2.0
+    }
+
+    /// Base rate and classification that adaptive detection decides on, with the base rate
+    /// weighted towards recent scans if `decay` is set
+    fn activity(&self, decay: Option<f64>) -> (f64, Activity) {
+        let base_rate = match decay {
+            Some(decay) => self.weighted_base_rate(decay),
+            None => self.base_rate(),
+        };
+        (base_rate, Activity::classify(base_rate))
+    }
+
+    /// Fraction of scans in the long history in which the device was seen
+    fn availability(&self) -> Option<f64> {
+        let (hits, scans) = self
+            .long
+            .iter()
+            .fold((0, 0), |(h, s), b| (h + b.hits, s + b.scans));
+        (scans > 0).then(|| hits as f64 / scans as f64)
+    }
+
+    // Whether a device that is usually steady misses notably more scans in the detection window
+    // than over the long history, an early sign of connection trouble
+    fn is_degrading(&self) -> bool {
+        let scans: u32 = self.long.iter().map(|b| b.scans).sum();
+        if self.len() < HISTORY_SIZE || scans < LONG_BUCKET_SCANS {
+            return false;
+        }
+        let Some(availability) = self.availability() else {
+            return false;
+        };
+        let usual_miss_rate = 1.0 - availability;
+        let recent_miss_rate = 1.0 - self.base_rate();
+        availability >= DEGRADING_MIN_AVAILABILITY
+            && recent_miss_rate >= DEGRADING_MIN_MISS_RATE
+            && recent_miss_rate >= usual_miss_rate * DEGRADING_FACTOR
+    }
+}
+
+/// Classification of a device by its base rate, which selects the adaptive detection rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Activity {
+    Sleeping,
+    Intermittent,
+    AlwaysOn,
+}
+
+impl Activity {
+    fn classify(base_rate: f64) -> Self {
+        if base_rate <= 0.3 {
+            Activity::Sleeping
+        } else if base_rate <= 0.7 {
+            Activity::Intermittent
+        } else {
+            Activity::AlwaysOn
+        }
+    }
+
+    /// Title phrase of the notification of a device of this class going offline
+    fn disconnect_title<'a>(&self, phrases: &i18n::Phrases<'a>) -> &'a str {
+        match self {
+            Activity::Sleeping => phrases.sleeping_title,
+            Activity::Intermittent => phrases.intermittent_title,
+            Activity::AlwaysOn => phrases.always_on_title,
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Activity::Sleeping => "SLP",
+            Activity::Intermittent => "INT",
+            Activity::AlwaysOn => "ALW",
+        }
+    }
+}
+
+/// Numbers of scans that adaptive detection bases its decisions on
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+    /// Scans needed before deciding, and without a sighting after which an intermittent device
+    /// is offline
+    offline: usize,
+    /// Scans in which a sighting counts as recent
+    recent: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            offline: OFFLINE_THRESHOLD,
+            recent: RECENT_WINDOW,
+        }
+    }
+}
+
+/// Presence specific logic on the detection window, also on an unpacked `History<bool, N>`
+trait Presence {
+    fn len(&self) -> usize;
+
+    fn newest(&self) -> Option<&bool>;
+
+    fn iter(&self) -> impl Iterator<Item = &bool>;
+
+    // Determines statistically if the device is likely to be connected or disconnected
+    // Takes in the current connection state
+    #[cfg(test)]
+    fn is_connected(&self, is_connected: bool) -> bool {
+        self.is_connected_with_rate(is_connected, self.base_rate(), Thresholds::default())
+    }
+
+    // Fraction of scans in which the device was seen
+    fn base_rate(&self) -> f64 {
+        rolling::rate(self.iter().copied()).unwrap_or(f64::NAN)
+    }
+
+    // Exponentially weighted base rate, each older scan has `decay` times the weight of the next
+    fn weighted_base_rate(&self, decay: f64) -> f64 {
+        rolling::weighted_rate(self.iter().copied(), decay).unwrap_or(f64::NAN)
+    }
+
+    fn is_connected_with_rate(
+        &self,
+        is_connected: bool,
+        base_rate: f64,
+        thresholds: Thresholds,
+    ) -> bool {
+        let Thresholds { offline, recent } = thresholds;
+        if self.len() < offline {
+            // Insufficient data
+            return is_connected;
+        }
+
+        let last_ping = self.iter().position(|v| *v).unwrap_or(HISTORY_SIZE);
+        match Activity::classify(base_rate) {
+            // Devices that are sleeping a lot, or a device that has just gone offline!
+            Activity::Sleeping => {
+                if last_ping >= HISTORY_SIZE {
+                    false
+                } else if last_ping < recent {
+                    true
+                } else {
+                    is_connected
+                }
+            }
+            Activity::Intermittent => {
+                if is_connected && last_ping > offline {
+                    false
+                } else if !is_connected && last_ping < recent {
+                    true
+                } else {
+                    is_connected
+                }
+            }
+            Activity::AlwaysOn => {
+                if *self.newest().unwrap() {
+                    true
+                } else if is_connected && last_ping > recent {
+                    false
+                } else {
+                    is_connected
+                }
+            }
+        }
+    }
+
+    // Offline after `misses` consecutive missed scans, online after `hits` consecutive hits
+    fn is_connected_simple(&self, is_connected: bool, misses: usize, hits: usize) -> bool {
+        let Some(&latest) = self.newest() else {
+            return is_connected;
+        };
+        let streak = self.iter().take_while(|v| **v == latest).count();
+        if latest && streak >= hits {
+            true
+        } else if !latest && streak >= misses {
+            false
+        } else {
+            is_connected
+        }
+    }
+}
+
+impl Presence for History<bool, HISTORY_SIZE> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn newest(&self) -> Option<&bool> {
+        self.newest()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &bool> {
+        self.iter()
+    }
+}
+
+impl Presence for PackedHistory<HISTORY_SIZE> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn newest(&self) -> Option<&bool> {
+        self.newest()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &bool> {
+        self.iter()
+    }
+}
+
+// Shows the detection window, or the long history downsampled to one character per bucket with the
+// alternate flag (`{:#}`)
+impl Display for ScanHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            for bucket in &self.long {
+                f.write_char(match bucket.hits {
+                    0 => '-',
+                    hits if hits == bucket.scans => 'O',
+                    _ => 'o',
+                })?;
+            }
+            for _ in 0..(LONG_HISTORY_BUCKETS - self.long.len()) {
+                f.write_char('.')?;
+            }
+            return Ok(());
+        }
+        self.log.fmt(f)
+    }
+}
+
+// Shows the samples as a sparkline padded to the full window
+impl<const N: usize> Display for History<bool, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_sparkline(f, self.iter(), N - self.len())
+    }
+}
+
+impl<const N: usize> Display for PackedHistory<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_sparkline(f, self.iter(), N - self.len())
+    }
+}
+
+fn write_sparkline<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    samples: impl Iterator<Item = &'a bool>,
+    padding: usize,
+) -> std::fmt::Result {
+    for act in samples {
+        f.write_char(if *act { 'O' } else { '-' })?;
+    }
+    for _ in 0..padding {
+        f.write_char('.')?;
+    }
+    Ok(())
+}
+
+impl DeviceState {
+    /// Creates the state of a device with the given initial connection state and history
+    fn new(
+        device: Device,
+        is_connected: bool,
+        ping_history: ScanHistory,
+        now: DateTime<Local>,
+    ) -> Self {
+        DeviceState {
+            device,
+            last_seen: now,
+            last_change: now,
+            last_connect: is_connected.then_some(now),
+            last_disconnect: (!is_connected).then_some(now),
+            is_connected,
+            ping_history,
+            announced: true,
+            registered: false,
+            degrading: false,
+            stale: false,
+            escalation_step: 0,
+            changes: VecDeque::new(),
+            flapping: false,
+            present_since: None,
+            pending_disconnect: None,
+            members: BTreeMap::new(),
+            last_notified: None,
+            muted: None,
+            uptime: Uptime::default(),
+            downtime: Downtime::default(),
+        }
+    }
+
+    fn is_muted(&self, now: DateTime<Local>) -> bool {
+        self.muted.is_some_and(|mute| mute.is_active(now))
+    }
+
+    /// Drops the connection changes older than `window`, returns `Some(true)` when the device
+    /// starts flapping and `Some(false)` when it has not changed state for the window since
+    fn check_flapping(
+        &mut self,
+        now: DateTime<Local>,
+        threshold: usize,
+        window: Duration,
+    ) -> Option<bool> {
+        let window = chrono::TimeDelta::from_std(window).unwrap_or(chrono::TimeDelta::MAX);
+        while self.changes.back().is_some_and(|time| now - *time > window) {
+            self.changes.pop_back();
+        }
+        self.changes.truncate(threshold);
+        let flapping = if self.flapping {
+            !self.changes.is_empty()
+        } else {
+            self.changes.len() >= threshold
+        };
+        (flapping != self.flapping).then(|| {
+            self.flapping = flapping;
+            flapping
+        })
+    }
+
+    fn set_connected(&mut self, is_connected: bool, now: DateTime<Local>) {
+        if is_connected && !self.is_connected {
+            self.downtime.offline = self.offline_today(now);
+            self.downtime.date = Some(now.date_naive());
+        }
+        self.is_connected = is_connected;
+        self.last_change = now;
+        if is_connected {
+            self.last_connect = Some(now);
+        } else {
+            self.last_disconnect = Some(now);
+        }
+    }
+
+    /// Time the device was offline on the day of `now`, including the current disconnect
+    fn offline_today(&self, now: DateTime<Local>) -> Duration {
+        let earlier = match self.downtime.date {
+            Some(date) if date == now.date_naive() => self.downtime.offline,
+            _ => Duration::ZERO,
+        };
+        let midnight = now
+            .date_naive()
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap_or(now);
+        let current = if self.is_connected {
+            Duration::ZERO
+        } else {
+            (now - self.last_change.max(midnight))
+                .to_std()
+                .unwrap_or_default()
+        };
+        earlier + current
+    }
+
+    /// How long the device was disconnected before it connected again, if it is connected
+    fn absence(&self) -> Option<chrono::TimeDelta> {
+        match (self.is_connected, self.last_connect, self.last_disconnect) {
+            (true, Some(connect), Some(disconnect)) if disconnect <= connect => {
+                Some(connect - disconnect)
+            }
+            _ => None,
+        }
+    }
+
+    /// Describes the current connection state, e.g. `online since 09:00` or `offline for 2h0m`
+    fn since(&self, now: DateTime<Local>, time_format: &TimeFormat) -> Option<String> {
+        if self.is_connected {
+            let time = self.last_connect?;
+            Some(match time_format {
+                TimeFormat::Relative => format!("online for {}", format_duration(now - time)),
+                TimeFormat::Custom(format) => format!("online since {}", time.format(format)),
+            })
+        } else {
+            let time = self.last_disconnect?;
+            Some(format!("offline for {}", format_duration(now - time)))
+        }
+    }
+}
+
+/// Changes in the connection states between two status logs
+#[derive(Debug, Default, PartialEq, Eq)]
+struct StateDelta {
+    new: usize,
+    connected: usize,
+    disconnected: usize,
+    // evicted by `max_tracked_devices` or dropped when removed from the config
+    removed: usize,
+}
+
+impl StateDelta {
+    fn new(prev: &HashMap<MacAddr, bool>, current: &HashMap<MacAddr, bool>) -> Self {
+        let mut delta = Self::default();
+        for (mac, is_connected) in current {
+            match prev.get(mac) {
+                None => delta.new += 1,
+                Some(false) if *is_connected => delta.connected += 1,
+                Some(true) if !is_connected => delta.disconnected += 1,
+                _ => {}
+            }
+        }
+        delta.removed = prev.keys().filter(|mac| !current.contains_key(mac)).count();
+        delta
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for StateDelta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            (self.new, "+", "new"),
+            (self.connected, "+", "connected"),
+            (self.disconnected, "-", "disconnected"),
+            (self.removed, "-", "removed"),
+        ]
+        .into_iter()
+        .filter(|(count, _, _)| *count > 0)
+        .map(|(count, sign, label)| format!("{sign}{count} {label}"))
+        .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Scans the network at the scan interval, notifying about the changes
+pub struct Daemon {
+    config: Config,
+    scanner: Box<dyn Scanner>,
+    // source of the current time, replaced by a mock in tests
+    clock: Box<dyn Clock>,
+    state: HashMap<MacAddr, DeviceState>,
+    last_scan_start: Instant,
+    // wall clock time at `last_scan_start`, to detect clock jumps
+    last_scan_time: DateTime<Local>,
+    last_scan_duration: Duration,
+    last_digest: Option<NaiveDate>,
+    // this host and its default route, detected when running
+    local: scan::LocalAddrs,
+    // whether the gateway of the default route was in a scan, before which it isn't missed
+    gateway_seen: bool,
+    /// Use colors in the status table
+    pub color: bool,
+    /// Print the status table even if nothing changed
+    pub verbose: bool,
+    /// Clear the screen and redraw the status table after every scan
+    pub watch: bool,
+    // number of scans since starting
+    scans: u64,
+    // connection states at the last time the status was logged
+    last_logged: Option<HashMap<MacAddr, bool>>,
+    pub control: Option<Receiver<control::Request>>,
+    // number of devices discovered since the last digest
+    new_devices: usize,
+    count_limit: CountLimit,
+    // number of consecutive scans ignored as likely failures
+    failed_scans: usize,
+    // number of consecutive scans that returned an error
+    scan_failures: usize,
+    // devices probed by the last scan if it was targeted, `None` after a full scan
+    targeted: Option<HashSet<MacAddr>>,
+    // interface to scan that was down at the last check
+    interface_down: Option<String>,
+    // when the gateway went missing from the scans
+    network_lost: Option<DateTime<Local>>,
+    // whether anyone of the presence group is home, `None` until the first scan
+    presence: Option<bool>,
+    // when the whole presence group was first seen absent, for the departure debounce
+    absent_since: Option<DateTime<Local>>,
+    // when `presence_heartbeat_url` was last called, `None` while nobody is home
+    last_heartbeat: Option<DateTime<Local>>,
+    // when the restored state was saved, until the catch-up summary of the first scan
+    down_since: Option<DateTime<Local>>,
+    // titles of the notifications held back by the rate limit before the restart
+    missed: Vec<String>,
+    // OUIs of the devices with a vendor seen so far, for `notify_new_vendor`
+    seen_ouis: BTreeSet<String>,
+    notifiers: Notifiers,
+    // sends the device hooks, Home Assistant updates and presence heartbeats
+    hooks: Hooks,
+    /// Notifications waiting to be sent by the notifiers
+    queue: Queue,
+    rate_limiter: Option<RateLimiter>,
+    /// Notifications held back by the rate limit, to send once it allows
+    deferred: VecDeque<Message>,
+    // number of notifications dropped by the rate limit since the last one sent
+    rate_limited: usize,
+    // maintenance mode set by the `pause` command
+    paused: Option<Pause>,
+    /// Path to reload the config from on SIGHUP
+    pub config_path: Option<PathBuf>,
+    /// Profile to apply when reloading the config, see [`Config::load_profile`]
+    pub config_profile: Option<String>,
+    pub metrics: Option<metrics::Exporter>,
+    influx: Option<influx::Sink>,
+    event_log: Option<event_log::EventLog>,
+    event_fifo: Option<fifo::Fifo>,
+    mqtt: Option<mqtt::Publisher>,
+    // number of connects (`true`) and disconnects (`false`) per device
+    transitions: HashMap<(MacAddr, bool), u64>,
+    // when a device was last notified as connected (`true`) or disconnected (`false`)
+    last_notified: HashMap<(MacAddr, bool), Instant>,
+    /// Most recent connection changes, newest first
+    recent_events: History<RecentEvent, RECENT_EVENTS>,
+    /// systemd to notify of the readiness and status, when run as a `Type=notify` service
+    systemd: Option<systemd::Notify>,
+}
+
+/// Maintenance mode, during which the state is kept up to date but no notifications are sent,
+/// for all devices or a muted one
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Pause {
+    UntilResumed,
+    Until(DateTime<Local>),
+}
+
+impl Pause {
+    /// `None` if the end of `duration` is out of range
+    fn from_duration(duration: Option<Duration>, now: DateTime<Local>) -> Option<Self> {
+        let Some(duration) = duration else {
+            return Some(Pause::UntilResumed);
+        };
+        let duration = chrono::TimeDelta::from_std(duration).ok()?;
+        now.checked_add_signed(duration).map(Pause::Until)
+    }
+
+    fn is_active(&self, now: DateTime<Local>) -> bool {
+        match self {
+            Pause::UntilResumed => true,
+            Pause::Until(until) => now < *until,
+        }
+    }
+}
+
+/// A connection change of a device, as shown by the `events` command and written to the event log
+#[derive(Debug, Clone, Serialize)]
+struct RecentEvent {
+    time: DateTime<Local>,
+    mac: MacAddr,
+    name: Option<String>,
+    connected: bool,
+}
+
+/// Calls the `on_connect_url` or `on_disconnect_url` of a device for a connection change and
+/// updates its device tracker in Home Assistant
+fn call_hook(config: &Config, hooks: &Hooks, event: &RecentEvent, state: &DeviceState) {
+    let Some(device_config) = config.devices.get(&event.mac) else {
+        return;
+    };
+    if let (Some(url), Some(token)) = (&config.home_assistant_url, &config.home_assistant_token) {
+        hooks.call(home_assistant::update(
+            url,
+            token,
+            &home_assistant::Presence {
+                name: &device_config.name,
+                mac: event.mac.as_str(),
+                ip: state.device.ip,
+                last_seen: state.last_seen,
+                connected: event.connected,
+            },
+        ));
+    }
+    let url = if event.connected {
+        &device_config.on_connect_url
+    } else {
+        &device_config.on_disconnect_url
+    };
+    let Some(url) = url else {
+        return;
+    };
+    match serde_json::to_string(event) {
+        Ok(body) => hooks.call(hook::Request {
+            url: expand_url(url, &state.device, &device_config.name),
+            authorization: None,
+            body,
+        }),
+        Err(e) => log::error!("Failed to serialize event: {}", e),
+    }
+}
+
+/// File kept next to the state file, e.g. `state.json.pending` for the `pending` suffix
+fn sidecar_path(state_file: &Path, suffix: &str) -> PathBuf {
+    let mut path = state_file.as_os_str().to_os_string();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Appends a connection change to the event log and writes it to the event FIFO, if enabled
+fn log_event(
+    event_log: &mut Option<event_log::EventLog>,
+    event_fifo: &mut Option<fifo::Fifo>,
+    event: &RecentEvent,
+) {
+    if event_log.is_none() && event_fifo.is_none() {
+        return;
+    }
+    let line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(e) => {
+            log::error!("Failed to serialize event: {}", e);
+            return;
+        }
+    };
+    if let Some(event_log) = event_log
+        && let Err(e) = event_log.append(&line, event.time)
+    {
+        log::error!("{:#}", e);
+    }
+    if let Some(fifo) = event_fifo
+        && let Err(e) = fifo.write(&line)
+    {
+        log::error!("{:#}", e);
+    }
+}
+
+impl Daemon {
+    pub fn new(config: Config, mut scanner: Box<dyn Scanner>) -> Self {
+        scanner.set_timeout(config.scan_hard_timeout);
+        let clock: Box<dyn Clock> = Box::new(SystemClock);
+        // don't send a digest right away when started after the digest time
+        let now = clock.now();
+        let last_digest = config
+            .digest_time
+            .filter(|time| now.time() >= *time)
+            .map(|_| now.date_naive());
+        let notifiers = config
+            .notifier_configs()
+            .into_iter()
+            .filter_map(|c| match c.build() {
+                Ok(notifier) => Some((NotifierSource::Config(c), notifier.into())),
+                Err(e) => {
+                    log::error!("Failed to set up notifier {}: {:#}", c, e);
+                    None
+                }
+            })
+            .collect();
+        let notifiers = Arc::new(Mutex::new(notifiers));
+        let queue = Queue::new(
+            notifiers.clone(),
+            config.notification_queue_size,
+            config.notification_overflow,
+            notify::RetryPolicy {
+                retries: config.notify_retries,
+                failure_threshold: config.notify_failure_threshold,
+                cooldown: config.notify_circuit_cooldown,
+                ..Default::default()
+            },
+        );
+        let rate_limiter = config.notify_rate_limit.map(RateLimiter::new);
+        let influx = config
+            .influx_url
+            .clone()
+            .map(|url| influx::Sink::new(url, config.influx_token.clone()));
+        let event_log = config.event_log.clone().map(|path| {
+            event_log::EventLog::new(
+                path,
+                config.event_log_max_size,
+                config.event_log_keep,
+                config.event_log_compress,
+            )
+        });
+        let event_fifo = config
+            .event_fifo
+            .clone()
+            .and_then(|path| match fifo::Fifo::new(path) {
+                Ok(fifo) => Some(fifo),
+                Err(e) => {
+                    log::error!("Failed to set up event FIFO: {:#}", e);
+                    None
+                }
+            });
+        let mqtt = config.mqtt_host.clone().map(|host| {
+            mqtt::Publisher::new(mqtt::MqttConfig {
+                host,
+                port: config.mqtt_port,
+                username: config.mqtt_username.clone(),
+                password: config.mqtt_password.clone(),
+                discovery_prefix: config.mqtt_discovery_prefix.clone(),
+                topic_prefix: config.mqtt_topic_prefix.clone(),
+            })
+        });
+        Self {
+            config,
+            scanner,
+            clock,
+            state: HashMap::new(),
+            last_scan_start: Instant::now(),
+            last_scan_time: now,
+            last_scan_duration: Duration::ZERO,
+            last_digest,
+            local: scan::LocalAddrs::default(),
+            gateway_seen: false,
+            color: false,
+            verbose: false,
+            watch: false,
+            scans: 0,
+            last_logged: None,
+            control: None,
+            new_devices: 0,
+            count_limit: CountLimit::Within,
+            failed_scans: 0,
+            scan_failures: 0,
+            targeted: None,
+            interface_down: None,
+            network_lost: None,
+            presence: None,
+            absent_since: None,
+            last_heartbeat: None,
+            down_since: None,
+            missed: Vec::new(),
+            seen_ouis: BTreeSet::new(),
+            notifiers,
+            hooks: Hooks::default(),
+            queue,
+            rate_limiter,
+            deferred: VecDeque::new(),
+            rate_limited: 0,
+            paused: None,
+            config_path: None,
+            config_profile: None,
+            metrics: None,
+            influx,
+            event_log,
+            event_fifo,
+            mqtt,
+            transitions: HashMap::new(),
+            last_notified: HashMap::new(),
+            recent_events: History::new(),
+            systemd: systemd::Notify::from_env(),
+        }
+    }
+
+    /// Reloads the notifiers, mute patterns, DHCP leases and devices from the config file,
+    /// keeping the scan state, returns the events of devices that became unknown
+    ///
+    /// Devices registered by `auto_register_unknown` are kept, what happens to other devices
+    /// that were removed from the config depends on `removed_devices`.
+    fn reload(&mut self) -> Result<Vec<Event>> {
+        let Some(path) = &self.config_path else {
+            return Ok(Vec::new());
+        };
+        let config = Config::load_profile(path, self.config_profile.as_deref())?;
+        log::info!("reloaded config from '{}'", path.display());
+        self.config.ntfy_url = config.ntfy_url.clone();
+        self.config.ntfy_ca_cert = config.ntfy_ca_cert.clone();
+        self.config.ntfy_insecure_skip_verify = config.ntfy_insecure_skip_verify;
+        self.config.notifiers = config.notifiers.clone();
+        self.config.mute_patterns = config.mute_patterns.clone();
+        self.config.dhcp_leases_file = config.dhcp_leases_file.clone();
+        self.config.leases = config.leases.clone();
+        self.set_notifiers(config.notifier_configs());
+        self.config.removed_devices = config.removed_devices;
+        Ok(self.reload_devices(config.devices))
+    }
+
+    fn reload_devices(&mut self, mut devices: HashMap<MacAddr, DeviceConfig>) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (mac, config) in std::mem::take(&mut self.config.devices) {
+            if devices.contains_key(&mac) {
+                continue;
+            }
+            let Some(state) = self.state.get(&mac) else {
+                continue;
+            };
+            if state.registered {
+                devices.insert(mac, config);
+                continue;
+            }
+            match self.config.removed_devices {
+                RemovedDevices::Keep => {
+                    log::info!(
+                        "Device '{}' was removed from the config, keeping it until restart",
+                        config.name
+                    );
+                    devices.insert(mac, config);
+                }
+                RemovedDevices::Drop => {
+                    log::info!(
+                        "Device '{}' was removed from the config, forgetting it",
+                        config.name
+                    );
+                    self.state.remove(&mac);
+                }
+                RemovedDevices::Unknown => {
+                    log::info!(
+                        "Device '{}' was removed from the config, it is unknown from now on",
+                        config.name
+                    );
+                    if state.is_connected {
+                        events.push(Event::Connected(mac));
+                    }
+                }
+            }
+        }
+        self.config.devices = devices;
+        events
+    }
+
+    /// Replaces the notifiers, keeping the ones that did not change
+    fn set_notifiers(&mut self, configs: Vec<NotifierConfig>) {
+        let mut notifiers = self.notifiers.lock().unwrap();
+        // notifiers added with `add_notifier` are not part of the config
+        let (custom, mut old): (Vec<_>, Vec<_>) = std::mem::take(&mut *notifiers)
+            .into_iter()
+            .partition(|(source, _)| matches!(source, NotifierSource::Custom { .. }));
+        notifiers.extend(custom);
+        for config in configs {
+            match old
+                .iter()
+                .position(|(source, _)| *source == NotifierSource::Config(config.clone()))
+            {
+                Some(i) => notifiers.push(old.swap_remove(i)),
+                None => match config.build() {
+                    Ok(notifier) => {
+                        log::info!("added notifier {}", config);
+                        notifiers.push((NotifierSource::Config(config), notifier.into()));
+                    }
+                    Err(e) => log::error!("Failed to set up notifier {}: {:#}", config, e),
+                },
+            }
+        }
+        for (config, _) in old {
+            log::info!("removed notifier {}", config);
+        }
+    }
+
+    /// Adds a notifier that is not part of the config, kept when the config is reloaded
+    ///
+    /// It only receives the messages meant for a specific notifier if `name` matches, like the
+    /// configured notifiers.
+    pub fn add_notifier(&mut self, name: Option<String>, notifier: Box<dyn notify::Notifier>) {
+        self.notifiers
+            .lock()
+            .unwrap()
+            .push((NotifierSource::Custom { name }, notifier.into()));
+    }
+
+    /// Processes the devices of a scan of another source and notifies about the changes
+    ///
+    /// Like with [`Tracker::feed_scan`], the devices of the first scan are considered connected
+    /// without any notifications. Queued notifications are sent at the latest when the daemon is
+    /// dropped.
+    pub fn feed_scan(&mut self, devices: Vec<Device>) {
+        let events = self.track_scan(devices);
+        self.send_notifications(events);
+    }
+
+    /// Updates the state with a scan of another source, returns the events, none for the first
+    fn track_scan(&mut self, mut devices: Vec<Device>) -> Vec<Event> {
+        self.config.filter_scan(&mut devices);
+        self.scans += 1;
+        if self.scans == 1 {
+            self.init_state(devices);
+            return Vec::new();
+        }
+        self.update_state(devices)
+    }
+
+    /// Runs the scan loop, or a single scan if `once` is set
+    ///
+    /// Queued notifications are sent before returning, for at most `shutdown_timeout`.
+    pub fn run(&mut self, once: bool) -> Result<(), Error> {
+        let result = self.scan_loop(once);
+        self.shutdown();
+        result
+    }
+
+    fn scan_loop(&mut self, once: bool) -> Result<(), Error> {
+        self.local = scan::LocalAddrs::detect(Path::new(SYS_CLASS_NET), Path::new(PROC_NET_ROUTE));
+        if !self.config.include_local {
+            log::debug!(
+                "Leaving out this host {:?} and the gateway {:?}",
+                self.local.macs,
+                self.local.gateway
+            );
+        }
+        if !self.config.startup_delay.is_zero() {
+            log::info!(
+                "Waiting {:?} before the first scan",
+                self.config.startup_delay
+            );
+            self.wait(self.config.startup_delay);
+        }
+        if self.restore_state().map_err(Error::State)? {
+            log::info!("Ready, monitoring {} devices", self.state.len());
+            self.notify_systemd(true);
+            if let Some(message) = self.startup_message() {
+                self.notify(message);
+            }
+        } else {
+            let (scans, timeout) = if once {
+                (1, Duration::ZERO)
+            } else {
+                (self.config.startup_grace, self.config.startup_timeout)
+            };
+            let Some(devices) = self.initial_scan(scans, timeout)? else {
+                return Ok(());
+            };
+            self.init_state(devices);
+            log::info!("Ready, monitoring {} devices", self.state.len());
+            self.notify_systemd(true);
+            if let Some(message) = self.startup_message() {
+                self.notify(message);
+            }
+            self.log_state();
+            self.save_state();
+            self.export_metrics();
+            self.publish_presence();
+            self.ping_healthcheck();
+            if once {
+                return Ok(());
+            }
+        }
+        loop {
+            if STOP.load(Ordering::Relaxed) {
+                log::info!("Stopping");
+                return Ok(());
+            }
+            if RELOAD.swap(false, Ordering::Relaxed) {
+                match self.reload() {
+                    Ok(events) => self.send_notifications(events),
+                    Err(e) => log::error!("Failed to reload config: {:#}", e),
+                }
+            }
+            // a scan of a down interface would make every device on it seem gone
+            let events = self.check_interfaces(Path::new(SYS_CLASS_NET));
+            self.send_notifications(events);
+            if self.interface_down.is_some() {
+                self.notify_systemd(false);
+                if once {
+                    return Ok(());
+                }
+                self.last_scan_start = Instant::now();
+                self.wait_next_scan();
+                continue;
+            }
+            let devices = match self.scan() {
+                Ok(Some(devices)) => devices,
+                Ok(None) => break,
+                // e.g. an unreachable SSH host, which may work again on the next scan
+                Err(e) if !once => {
+                    let event = self.update_scan_health(Some(&e));
+                    let scan_failures = self.scan_failures;
+                    if self
+                        .config
+                        .max_scan_failures
+                        .is_some_and(|max| scan_failures >= max)
+                    {
+                        self.notify(Message {
+                            title: "lanotify stopped".to_string(),
+                            body: format!("{scan_failures} scans failed in a row: {e:#}"),
+                            priority: Priority::High,
+                            ..Default::default()
+                        });
+                        return Err(Error::Scan(
+                            anyhow::Error::new(e)
+                                .context(format!("{scan_failures} scans failed in a row")),
+                        ));
+                    }
+                    log::warn!("Skipping failed scan: {:#}", e);
+                    self.send_notifications(event.into_iter().collect());
+                    self.notify_systemd(false);
+                    self.wait_next_scan();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(message) = self.catch_up(&devices, self.clock.now()) {
+                self.notify(message);
+            }
+            let mut transitions: Vec<Event> = self.update_scan_health(None).into_iter().collect();
+            transitions.extend(self.update_state(devices));
+            self.send_notifications(transitions);
+            if let Some(message) = self.digest(self.clock.now()) {
+                self.notify(message);
+            }
+            self.log_state();
+            self.save_state();
+            self.export_metrics();
+            self.write_points();
+            self.publish_presence();
+            self.ping_healthcheck();
+            self.notify_systemd(false);
+            if once {
+                return Ok(());
+            }
+
+            self.wait_next_scan();
+        }
+        log::info!("Scanner exhausted, stopping");
+        Ok(())
+    }
+
+    /// Checks whether the interfaces to scan are up, returns the events when one goes down or all
+    /// are up again, if `notify_interface_down` is set
+    ///
+    /// Without configured interfaces the one of the default route is checked.
+    fn check_interfaces(&mut self, root: &Path) -> Vec<Event> {
+        let mut interfaces = self.config.scanner.interfaces();
+        if interfaces.is_empty() {
+            interfaces = self.local.interface.as_slice();
+        }
+        let down = interfaces
+            .iter()
+            .find(|interface| !scan::is_interface_up(root, interface))
+            .cloned();
+        if down == self.interface_down {
+            return Vec::new();
+        }
+        let event = match (
+            down.clone(),
+            std::mem::replace(&mut self.interface_down, down),
+        ) {
+            (Some(interface), _) => {
+                log::warn!("Interface {interface} is down, skipping scans until it is up");
+                Event::InterfaceDown { interface }
+            }
+            (None, Some(interface)) => {
+                log::info!("Interface {interface} is up again, resuming scans");
+                Event::InterfaceUp { interface }
+            }
+            (None, None) => return Vec::new(),
+        };
+        if self.config.notify_interface_down {
+            vec![event]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Counts the scans that failed in a row, returns an event when scanning breaks or recovers
+    /// according to `notify_scan_failures`
+    fn update_scan_health(&mut self, error: Option<&Error>) -> Option<Event> {
+        let Some(error) = error else {
+            let failures = std::mem::take(&mut self.scan_failures);
+            return (failures >= self.config.notify_scan_failures?)
+                .then_some(Event::ScanRecovered { failures });
+        };
+        self.scan_failures += 1;
+        (self.config.notify_scan_failures? == self.scan_failures).then(|| Event::ScanBroken {
+            failures: self.scan_failures,
+            error: format!("{error:#}"),
+        })
+    }
+
+    fn wait_next_scan(&mut self) {
+        // account for the time spent scanning to keep a steady cadence
+        let wait = self
+            .config
+            .scan_interval
+            .saturating_sub(self.last_scan_start.elapsed())
+            + jitter(self.config.scan_jitter);
+        log::debug!("Waiting {:?} until next scan...", wait);
+        self.wait(wait);
+    }
+
+    /// Scans `scans` times at the scan interval, returning the union of the devices seen
+    ///
+    /// A failing first scan is retried until `timeout` passed.
+    fn initial_scan(
+        &mut self,
+        scans: usize,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Device>>, Error> {
+        // e.g. started before the interface is up, which shouldn't stop the daemon right away
+        let deadline = Instant::now() + timeout;
+        let first = loop {
+            match self.scan() {
+                Ok(first) => break first,
+                Err(e) if Instant::now() < deadline => {
+                    log::warn!("Initial scan failed, retrying: {:#}", e);
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    self.wait(self.config.scan_interval.min(remaining));
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let Some(mut devices) = first else {
+            return Ok(None);
+        };
+        for i in 1..scans {
+            self.wait(
+                self.config
+                    .scan_interval
+                    .saturating_sub(self.last_scan_start.elapsed()),
+            );
+            let Some(scan) = self.scan()? else {
+                break;
+            };
+            let known = devices.len();
+            for device in scan {
+                match devices.iter_mut().find(|d| d.mac == device.mac) {
+                    Some(existing) => *existing = device,
+                    None => devices.push(device),
+                }
+            }
+            log::info!(
+                "startup scan {}/{}: {} devices, {} new",
+                i + 1,
+                scans,
+                devices.len(),
+                devices.len() - known
+            );
+        }
+        Ok(Some(devices))
+    }
+
+    /// Waits for the given duration while handling control commands and keeping the systemd
+    /// watchdog alive
+    fn wait(&mut self, duration: Duration) {
+        let control = self.control.take();
+        let deadline = Instant::now() + duration;
+        // at half the watchdog interval, so a keep-alive arrives in time even if one is late
+        let keep_alive = self
+            .systemd
+            .as_ref()
+            .and_then(|systemd| systemd.watchdog)
+            .map(|watchdog| watchdog / 2);
+        let mut next_keep_alive = Instant::now();
+        while !STOP.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            let mut timeout = deadline.saturating_duration_since(now);
+            if timeout.is_zero() {
+                break;
+            }
+            if let Some(interval) = keep_alive
+                && let Some(systemd) = &self.systemd
+            {
+                if now >= next_keep_alive {
+                    systemd.send("WATCHDOG=1\n");
+                    next_keep_alive = now + interval;
+                }
+                timeout = timeout.min(next_keep_alive - now);
+            }
+            let timeout = timeout.min(STOP_POLL_INTERVAL);
+            let Some(control) = &control else {
+                sleep(timeout);
+                continue;
+            };
+            match control.recv_timeout(timeout) {
+                Ok(request) => {
+                    let response = self.handle_command(request.command);
+                    let _ = request.reply.send(response);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => sleep(timeout),
+            }
+        }
+        self.control = control;
+    }
+
+    /// Sends the notifications still deferred by the rate limit and waits for the queue to be
+    /// sent, for at most `shutdown_timeout`
+    fn shutdown(&mut self) {
+        self.send_deferred();
+        if !self.deferred.is_empty() {
+            let titles: Vec<&str> = self.deferred.iter().map(|m| m.title.as_str()).collect();
+            log::warn!(
+                "Dropping {} notifications deferred by the rate limit: {}",
+                titles.len(),
+                titles.join(", ")
+            );
+            self.deferred.clear();
+        }
+        self.queue.close(self.config.shutdown_timeout);
+    }
+
+    fn handle_command(&mut self, command: Command) -> String {
+        match command {
+            Command::Status { json: true } => {
+                match serde_json::to_string_pretty(&self.api_devices()) {
+                    Ok(json) => json + "\n",
+                    Err(e) => format!("Error: failed to serialize devices: {e}\n"),
+                }
+            }
+            Command::Status { json: false } => {
+                let mut response = format!("Status of {} devices:\n", self.state.len());
+                for line in self.status_table(false) {
+                    response.push_str(&line);
+                    response.push('\n');
+                }
+                response
+            }
+            Command::Set { mac, online } => {
+                let Some(state) = self.state.get_mut(&mac) else {
+                    return format!("Error: unknown device {}\n", mac.0);
+                };
+                log::info!(
+                    "Manually marking {} as {}",
+                    mac.0,
+                    if online { "online" } else { "offline" }
+                );
+                state.set_connected(online, self.clock.now());
+                state.ping_history.reset();
+                self.save_state();
+                "ok\n".to_string()
+            }
+            Command::Wake { target } => {
+                let Some(mac) = self.find_device(&target) else {
+                    return format!("Error: unknown device '{target}'\n");
+                };
+                match wol::wake(&mac, self.config.wol_broadcast) {
+                    Ok(()) => "ok\n".to_string(),
+                    Err(e) => format!("Error: {e:#}\n"),
+                }
+            }
+            Command::Pause { duration } => {
+                let Some(pause) = Pause::from_duration(duration, self.clock.now()) else {
+                    return "Error: the pause is too long\n".to_string();
+                };
+                self.paused = Some(pause);
+                let description = self.pause_description().unwrap_or_default();
+                log::info!("{description}");
+                description + "\n"
+            }
+            Command::Resume => {
+                let was_paused = self.is_paused(self.clock.now());
+                self.paused = None;
+                if was_paused {
+                    log::info!("Maintenance mode ended, notifications resumed");
+                    "Notifications resumed\n".to_string()
+                } else {
+                    "Notifications were not paused\n".to_string()
+                }
+            }
+            Command::Mute { mac, duration } => {
+                let Some(state) = self.state.get_mut(&mac) else {
+                    return format!("Error: unknown device {}\n", mac.0);
+                };
+                let Some(mute) = Pause::from_duration(duration, self.clock.now()) else {
+                    return "Error: the mute is too long\n".to_string();
+                };
+                state.muted = Some(mute);
+                let response = match mute {
+                    Pause::UntilResumed => format!("Muted {} until unmuted", mac.0),
+                    Pause::Until(until) => {
+                        format!("Muted {} until {}", mac.0, until.format("%H:%M:%S"))
+                    }
+                };
+                log::info!("{response}");
+                self.save_state();
+                response + "\n"
+            }
+            Command::Unmute { mac } => {
+                let Some(state) = self.state.get_mut(&mac) else {
+                    return format!("Error: unknown device {}\n", mac.0);
+                };
+                let was_muted = state.is_muted(self.clock.now());
+                state.muted = None;
+                self.save_state();
+                if was_muted {
+                    log::info!("Unmuted {}", mac.0);
+                    format!("Unmuted {}\n", mac.0)
+                } else {
+                    format!("{} was not muted\n", mac.0)
+                }
+            }
+            Command::Rebaseline => {
+                let now = self.clock.now();
+                let mut changed = 0;
+                for state in self.state.values_mut() {
+                    if let Some(&seen) = state.ping_history.log.newest()
+                        && seen != state.is_connected
+                    {
+                        state.set_connected(seen, now);
+                        changed += 1;
+                    }
+                    state.ping_history = ScanHistory::new();
+                    state.members.clear();
+                    state.announced = true;
+                    state.present_since = None;
+                    state.pending_disconnect = None;
+                    state.degrading = false;
+                    state.stale = false;
+                    state.flapping = false;
+                    state.changes.clear();
+                }
+                let response = format!(
+                    "Cleared the history of {} devices, {} changed state",
+                    self.state.len(),
+                    changed
+                );
+                log::info!("{response}");
+                self.save_state();
+                response + "\n"
+            }
+            Command::Events { count } => {
+                let now = self.clock.now();
+                let mut table = Table::new();
+                for event in self
+                    .recent_events
+                    .iter()
+                    .take(count.unwrap_or(RECENT_EVENTS))
+                {
+                    table.push_row(vec![
+                        Cell::from(self.config.time_format.format(event.time, now)),
+                        Cell::from(
+                            if event.connected {
+                                "connected"
+                            } else {
+                                "disconnected"
+                            }
+                            .to_string(),
+                        ),
+                        Cell::from(event.name.clone().unwrap_or_else(|| "-".to_string())),
+                        Cell::from(event.mac.0.clone()),
+                    ]);
+                }
+                let mut response = String::new();
+                for line in table.render(None, false) {
+                    response.push_str(&line);
+                    response.push('\n');
+                }
+                response
+            }
+            Command::History { target } => {
+                let mac = match target {
+                    Some(target) => match self.find_device(&target) {
+                        Some(mac) if self.state.contains_key(&mac) => Some(mac),
+                        _ => return format!("Error: unknown device '{target}'\n"),
+                    },
+                    None => None,
+                };
+                let columns = [
+                    Column::Name,
+                    Column::Mac,
+                    Column::History,
+                    Column::Detection,
+                ];
+                let mut response = String::new();
+                for line in self.device_table(&columns, mac.as_ref(), TableGroupBy::None, false) {
+                    response.push_str(&line);
+                    response.push('\n');
+                }
+                response
+            }
+        }
+    }
+
+    /// Finds a device by its MAC address or configured name
+    fn find_device(&self, target: &str) -> Option<MacAddr> {
+        if let Ok(mac) = target.parse::<MacAddr>() {
+            return Some(mac);
+        }
+        self.config
+            .devices
+            .iter()
+            .find(|(_, d)| d.name.eq_ignore_ascii_case(target))
+            .map(|(mac, _)| mac.clone())
+    }
+
+    /// Detects jumps of the wall clock since the last scan by comparing it to monotonic time
+    ///
+    /// Timestamps are moved along with a backward jump, which keeps durations such as "online for"
+    /// intact. A forward jump may also be a suspend, in which case the timestamps are right.
+    fn check_clock(&mut self, now: DateTime<Local>) {
+        let Ok(monotonic) = chrono::TimeDelta::from_std(self.last_scan_start.elapsed()) else {
+            return;
+        };
+        let jump = (now - self.last_scan_time) - monotonic;
+        let threshold = chrono::TimeDelta::from_std(CLOCK_JUMP_THRESHOLD).unwrap();
+        if jump > threshold {
+            log::warn!(
+                "Clock jumped forward by {} since the last scan, the system may have been suspended",
+                format_duration(jump)
+            );
+        } else if jump < -threshold {
+            log::warn!(
+                "Clock jumped back by {} since the last scan, adjusting timestamps",
+                format_duration(-jump)
+            );
+            for state in self.state.values_mut() {
+                state.last_seen += jump;
+                state.last_change += jump;
+                state.last_connect = state.last_connect.map(|t| t + jump);
+                state.last_disconnect = state.last_disconnect.map(|t| t + jump);
+            }
+        }
+    }
+
+    /// IPs and MAC addresses of the configured devices to scan when the next scan is targeted,
+    /// and the IP of the gateway to tell whether the network is up
+    fn scan_targets(&self) -> Option<(Vec<IpAddr>, HashSet<MacAddr>)> {
+        if !self.config.targeted_scan
+            || !self.scanner.supports_targets()
+            || self
+                .scans
+                .is_multiple_of(self.config.full_scan_every as u64)
+        {
+            return None;
+        }
+        let mut ips = Vec::new();
+        let mut macs = HashSet::new();
+        for (mac, config) in &self.config.devices {
+            let ip = self.state.get(mac).map(|s| s.device.ip).or(config.ip);
+            if let Some(ip) = ip {
+                ips.push(ip);
+                macs.insert(mac.clone());
+            }
+        }
+        if ips.is_empty() {
+            return None;
+        }
+        if let Some(gateway) = self.config.gateway.or(self.local.gateway)
+            && !ips.contains(&gateway.into())
+        {
+            ips.push(gateway.into());
+        }
+        Some((ips, macs))
+    }
+
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.check_clock(self.clock.now());
+        self.last_scan_start = Instant::now();
+        self.last_scan_time = self.clock.now();
+        let targets = self.scan_targets();
+        let result = match &targets {
+            Some((ips, _)) => self.scanner.scan_targets(ips),
+            None => self.scanner.scan(),
+        };
+        self.targeted = targets.map(|(_, macs)| macs);
+        let Some(mut devices) = result? else {
+            return Ok(None);
+        };
+        self.scans += 1;
+        self.config.filter_scan(&mut devices);
+        if self.config.mdns_lookup
+            && let Err(e) = mdns::resolve_hostnames(&mut devices, self.config.mdns_timeout)
+        {
+            log::warn!("Failed to resolve hostnames: {:#}", e);
+        }
+        for peer in &self.config.peers {
+            match federation::fetch(peer, self.config.peer_timeout) {
+                Ok(remote) => {
+                    // devices seen locally take precedence
+                    let remote: Vec<Device> = remote
+                        .into_iter()
+                        .filter(|r| !devices.iter().any(|d| d.mac == r.mac))
+                        .collect();
+                    devices.extend(remote);
+                }
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+        self.last_scan_duration = self.last_scan_start.elapsed();
+        log::debug!(
+            "Scan found {} devices in {:?}",
+            devices.len(),
+            self.last_scan_duration
+        );
+        if self.last_scan_duration > self.config.scan_interval {
+            log::warn!(
+                "Scan took {:?} which exceeds the scan interval of {:?}",
+                self.last_scan_duration,
+                self.config.scan_interval
+            );
+        }
+        Ok(Some(devices))
+    }
+
+    fn init_state(&mut self, mut devices: Vec<Device>) {
+        self.exclude_local(&mut devices);
+        for device in devices {
+            if has_vendor(&device.vendor) {
+                self.seen_ouis.insert(device.mac.oui().to_string());
+            }
+            // devices found in a scan are connected
+            self.state.insert(
+                device.mac.clone(),
+                DeviceState::new(device, true, ScanHistory::new(), self.clock.now()),
+            );
+        }
+        log::info!("Initilized with {} devices", self.state.len());
+    }
+
+    /// Restores the state from the state file, returns whether any state was restored
+    fn restore_state(&mut self) -> Result<bool> {
+        let Some(path) = &self.config.state_file else {
+            return Ok(false);
+        };
+        if !path.exists() {
+            return Ok(false);
+        }
+        let contents = fs::read(path).context("Failed to read state file")?;
+        // e.g. truncated by a power loss, which shouldn't keep the daemon from starting
+        self.state = match serde_json::from_slice(&contents) {
+            Ok(state) => state,
+            Err(e) => {
+                let mut backup = path.as_os_str().to_os_string();
+                backup.push(".corrupt");
+                log::warn!(
+                    "Failed to parse state file '{}', moving it to '{}' and starting without \
+                     state: {}",
+                    path.display(),
+                    backup.to_string_lossy(),
+                    e
+                );
+                // it is overwritten by the next save then
+                if let Err(e) = fs::rename(path, &backup) {
+                    log::error!("Failed to back up corrupt state file: {}", e);
+                }
+                return Ok(false);
+            }
+        };
+        log::info!(
+            "Restored state of {} devices from '{}'",
+            self.state.len(),
+            path.display()
+        );
+        if self.config.notify_catch_up {
+            self.down_since = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::from);
+            let pending = sidecar_path(path, "pending");
+            if pending.exists() {
+                let contents =
+                    fs::read(&pending).context("Failed to read pending notifications")?;
+                self.missed = serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                    log::warn!("Failed to parse pending notifications: {}", e);
+                    Vec::new()
+                });
+            }
+        }
+        if self.config.notify_new_vendor {
+            let ouis = sidecar_path(path, "ouis");
+            if ouis.exists() {
+                let contents = fs::read(&ouis).context("Failed to read seen manufacturers")?;
+                self.seen_ouis = serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                    log::warn!("Failed to parse seen manufacturers: {}", e);
+                    BTreeSet::new()
+                });
+            }
+        }
+        // e.g. the state of a version that didn't keep track of them yet
+        self.seen_ouis.extend(
+            self.state
+                .values()
+                .filter(|s| has_vendor(&s.device.vendor))
+                .map(|s| s.device.mac.oui().to_string()),
+        );
+        let registered: Vec<MacAddr> = self
+            .state
+            .iter()
+            .filter(|(_, s)| s.registered)
+            .map(|(mac, _)| mac.clone())
+            .collect();
+        for mac in registered {
+            self.register(&mac);
+        }
+        if self.config.only_known {
+            let devices = &self.config.devices;
+            self.state.retain(|mac, _| devices.contains_key(mac));
+        }
+        // saved before they were left out, they would seem disconnected
+        if !self.config.include_local {
+            let (local, devices) = (&self.local, &self.config.devices);
+            self.state
+                .retain(|mac, state| !local.contains(&state.device) || devices.contains_key(mac));
+        }
+        Ok(true)
+    }
+
+    fn save_state(&self) {
+        let Some(path) = &self.config.state_file else {
+            return;
+        };
+        // write to a temporary file first so the state file is never left half-written
+        let tmp_path = path.with_extension("tmp");
+        let result = serde_json::to_string(&self.state)
+            .context("Failed to serialize state")
+            .and_then(|json| fs::write(&tmp_path, json).context("Failed to write state file"))
+            .and_then(|_| fs::rename(&tmp_path, path).context("Failed to replace state file"));
+        if let Err(e) = result {
+            log::error!("Failed to save state to '{}': {:#}", path.display(), e);
+        }
+        if self.config.notify_catch_up {
+            let titles: Vec<&str> = self.deferred.iter().map(|m| m.title.as_str()).collect();
+            let pending = sidecar_path(path, "pending");
+            if let Err(e) = fs::write(&pending, serde_json::json!(titles).to_string()) {
+                log::error!(
+                    "Failed to save pending notifications to '{}': {}",
+                    pending.display(),
+                    e
+                );
+            }
+        }
+        if self.config.notify_new_vendor {
+            let ouis = sidecar_path(path, "ouis");
+            if let Err(e) = fs::write(&ouis, serde_json::json!(self.seen_ouis).to_string()) {
+                log::error!(
+                    "Failed to save seen manufacturers to '{}': {}",
+                    ouis.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Writes the presence points of the scans since the last successful write
+    fn write_points(&mut self) {
+        if let Some(sink) = &mut self.influx
+            && let Err(e) = sink.flush()
+        {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    /// Publishes the presence of the configured devices over MQTT, retrying on the next scan on
+    /// failure
+    fn publish_presence(&mut self) {
+        let Some(publisher) = &mut self.mqtt else {
+            return;
+        };
+        let mut devices: Vec<mqtt::Presence> = self
+            .config
+            .devices
+            .iter()
+            .map(|(mac, config)| mqtt::Presence {
+                mac,
+                name: config.name.clone(),
+                connected: self.state.get(mac).is_some_and(|s| s.is_connected),
+            })
+            .collect();
+        devices.sort_by_key(|d| d.mac);
+        if let Err(e) = publisher.publish(&devices) {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    /// Signals the external healthcheck that a scan cycle completed
+    fn ping_healthcheck(&self) {
+        let Some(url) = &self.config.healthcheck_url else {
+            return;
+        };
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(HEALTHCHECK_TIMEOUT))
+            .build()
+            .new_agent();
+        if let Err(e) = agent.get(url).call() {
+            log::warn!("Failed to ping healthcheck '{}': {}", url, e);
+        }
+    }
+
+    /// Sends systemd the status and a watchdog keep-alive for the scan cycle, and that the daemon
+    /// is ready if `ready`
+    fn notify_systemd(&self, ready: bool) {
+        let Some(systemd) = &self.systemd else {
+            return;
+        };
+        let status = if let Some(interface) = &self.interface_down {
+            format!("Interface {interface} is down")
+        } else if self.scan_failures > 0 {
+            format!("{} scans failed in a row", self.scan_failures)
+        } else {
+            let connected = self.state.values().filter(|s| s.is_connected).count();
+            format!(
+                "Monitoring {} devices, {} connected",
+                self.state.len(),
+                connected
+            )
+        };
+        let mut state = format!("STATUS={status}\n");
+        if ready {
+            state.push_str("READY=1\n");
+        }
+        if systemd.watchdog.is_some() {
+            state.push_str("WATCHDOG=1\n");
+        }
+        systemd.send(&state);
+    }
+
+    fn export_metrics(&self) {
+        if let Some(exporter) = &self.metrics {
+            exporter.update(self.render_metrics());
+            match serde_json::to_string(&self.api_devices()) {
+                Ok(json) => exporter.update_devices(json),
+                Err(e) => log::error!("Failed to serialize devices: {}", e),
+            }
+        }
+    }
+
+    /// Devices found by this instance, devices of peers are left out to avoid loops
+    fn api_devices(&self) -> Vec<federation::ApiDevice> {
+        let mut devices: Vec<federation::ApiDevice> = self
+            .state
+            .values()
+            .filter(|s| s.device.node.is_none())
+            .map(|s| federation::ApiDevice {
+                mac: s.device.mac.clone(),
+                ip: s.device.ip,
+                vendor: s.device.vendor.clone(),
+                hostname: s.device.hostname.clone(),
+                name: self.config.name(&s.device.mac).cloned(),
+                connected: s.is_connected,
+                confidence: s.ping_history.confidence(),
+                uptime: s.uptime.ratio(),
+                last_seen: s.last_seen,
+                history: s.ping_history.log.iter().copied().collect(),
+            })
+            .collect();
+        devices.sort_by(|a, b| a.mac.cmp(&b.mac));
+        devices
+    }
+
+    fn render_metrics(&self) -> String {
+        let mut metrics = metrics::Metrics::new();
+        metrics.family(
+            "lanotify_devices_connected",
+            "gauge",
+            "Number of connected devices",
+        );
+        let connected = self.state.values().filter(|s| s.is_connected).count();
+        metrics.sample("lanotify_devices_connected", &[], connected as f64);
+        metrics.family(
+            "lanotify_device_confidence",
+            "gauge",
+            "Confidence from 0 to 1 that a device is connected",
+        );
+        let mut states: Vec<&DeviceState> = self.state.values().collect();
+        states.sort_by(|a, b| a.device.mac.cmp(&b.device.mac));
+        for state in &states {
+            let mac = &state.device.mac;
+            let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+            metrics.sample(
+                "lanotify_device_confidence",
+                &[("mac", &mac.0), ("name", name)],
+                state.ping_history.confidence(),
+            );
+        }
+        metrics.family(
+            "lanotify_device_latency_seconds",
+            "gauge",
+            "Response time of a device in the last scan, if it answered and the scanner measures it",
+        );
+        for state in &states {
+            let mac = &state.device.mac;
+            let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+            if let Some(latency) = state.device.latency {
+                metrics.sample(
+                    "lanotify_device_latency_seconds",
+                    &[("mac", &mac.0), ("name", name)],
+                    latency.as_secs_f64(),
+                );
+            }
+        }
+        metrics.family(
+            "lanotify_device_uptime_ratio",
+            "gauge",
+            "Fraction of the scans of the last uptime_days a device was seen in",
+        );
+        for state in &states {
+            let mac = &state.device.mac;
+            let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+            if let Some(ratio) = state.uptime.ratio() {
+                metrics.sample(
+                    "lanotify_device_uptime_ratio",
+                    &[("mac", &mac.0), ("name", name)],
+                    ratio,
+                );
+            }
+        }
+        let mut counter = |family: &str, help: &str, count: fn(&Uptime) -> u64| {
+            metrics.family(family, "counter", help);
+            for state in &states {
+                let mac = &state.device.mac;
+                let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+                let count = count(&state.uptime) as f64;
+                metrics.sample(family, &[("mac", &mac.0), ("name", name)], count);
+            }
+        };
+        counter(
+            "lanotify_device_scans_total",
+            "Number of scans since a device was first seen",
+            |uptime| uptime.scans,
+        );
+        counter(
+            "lanotify_device_seen_scans_total",
+            "Number of scans a device was seen in",
+            |uptime| uptime.hits,
+        );
+        metrics.family(
+            "lanotify_scan_duration_seconds",
+            "gauge",
+            "Duration of the last scan",
+        );
+        metrics.sample(
+            "lanotify_scan_duration_seconds",
+            &[],
+            self.last_scan_duration.as_secs_f64(),
+        );
+        metrics.family(
+            "lanotify_transitions_total",
+            "counter",
+            "Number of times a device connected or disconnected",
+        );
+        let mut transitions: Vec<_> = self.transitions.iter().collect();
+        transitions.sort();
+        for ((mac, connected), count) in transitions {
+            let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+            let direction = if *connected { "connect" } else { "disconnect" };
+            metrics.sample(
+                "lanotify_transitions_total",
+                &[("mac", &mac.0), ("name", name), ("direction", direction)],
+                *count as f64,
+            );
+        }
+        metrics.finish()
+    }
+
+    /// Devices missing from a scan that accept a connection on their `probe_port`
+    ///
+    /// The devices are probed at the same time, so together they take at most `probe_timeout`.
+    fn probe_missing(&self, scanned: &[Device]) -> Vec<Device> {
+        let missing: Vec<(&DeviceState, u16)> = self
+            .config
+            .devices
+            .iter()
+            .filter_map(|(mac, config)| Some((self.state.get(mac)?, config.probe_port?)))
+            .filter(|(state, _)| !scanned.iter().any(|d| d.mac == state.device.mac))
+            .collect();
+        let timeout = self.config.probe_timeout;
+        thread::scope(|scope| {
+            let handles: Vec<_> = missing
+                .into_iter()
+                .map(|(state, port)| {
+                    scope.spawn(move || {
+                        probe::tcp(state.device.ip, port, timeout).then_some((state, port))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok().flatten())
+                .map(|(state, port)| {
+                    log::debug!(
+                        "Device {} missing from scan answered on port {}",
+                        state.device.mac.0,
+                        port
+                    );
+                    state.device.clone()
+                })
+                .collect()
+        })
+    }
+
+    /// The configured gateway, or the one of the default route once it was in a scan, as scans
+    /// of another network never contain it
+    fn gateway(&mut self, scanned: &[Device]) -> Option<Ipv4Addr> {
+        if self.config.gateway.is_some() {
+            return self.config.gateway;
+        }
+        let gateway = self.local.gateway?;
+        self.gateway_seen |= scanned.iter().any(|d| d.ip == gateway);
+        self.gateway_seen.then_some(gateway)
+    }
+
+    /// Whether the gateway is in a scan or answers on its probe port
+    fn is_gateway_reachable(&self, gateway: Ipv4Addr, scanned: &[Device]) -> bool {
+        scanned.iter().any(|d| d.ip == gateway)
+            || self
+                .config
+                .gateway_probe_port
+                .is_some_and(|port| probe::tcp(gateway.into(), port, self.config.probe_timeout))
+    }
+
+    /// Drops this host and the gateway from a scan, unless they are configured devices or
+    /// `include_local` is set
+    fn exclude_local(&self, devices: &mut Vec<Device>) {
+        if self.config.include_local {
+            return;
+        }
+        devices.retain(|d| !self.local.contains(d) || self.config.devices.contains_key(&d.mac));
+    }
+
+    /// Updates the state with a new scan, returns the detected (dis)connections
+    fn update_state(&mut self, mut new_devices: Vec<Device>) -> Vec<Event> {
+        let mut notifications = Vec::new();
+        // without the network every device seems gone, keep the states from before the outage
+        if let Some(gateway) = self.gateway(&new_devices) {
+            let reachable = self.is_gateway_reachable(gateway, &new_devices);
+            match self.network_lost {
+                None if !reachable => {
+                    log::warn!(
+                        "Gateway {gateway} is unreachable, ignoring scans until the network is back"
+                    );
+                    self.network_lost = Some(self.clock.now());
+                    return notifications;
+                }
+                Some(_) if !reachable => return notifications,
+                Some(since) => {
+                    let lost_for = self.clock.now() - since;
+                    log::info!(
+                        "Gateway {gateway} is reachable again after {}",
+                        format_duration(lost_for)
+                    );
+                    self.network_lost = None;
+                    notifications.push(Event::NetworkRestored { lost_for });
+                }
+                None => {}
+            }
+        }
+        // after the gateway check, which needs the gateway in the scan
+        self.exclude_local(&mut new_devices);
+        new_devices.extend(self.probe_missing(&new_devices));
+        let scanned: HashSet<MacAddr> = new_devices.iter().map(|d| d.mac.clone()).collect();
+        let today = self.clock.now().date_naive();
+        let scan_time =
+            (self.config.history_spacing == HistorySpacing::Time).then(|| self.clock.now());
+        // addresses of devices with several that were found
+        let found_as: HashSet<MacAddr> = new_devices
+            .iter()
+            .flat_map(|d| {
+                if d.found_as.is_empty() {
+                    std::slice::from_ref(&d.mac)
+                } else {
+                    &d.found_as[..]
+                }
+            })
+            .cloned()
+            .collect();
+
+        // when (nearly) all devices vanish at once the scan itself most likely failed
+        let connected = self.state.values().filter(|s| s.is_connected).count();
+        let vanished = self
+            .state
+            .iter()
+            .filter(|(mac, s)| s.is_connected && !scanned.contains(*mac))
+            .filter(|(mac, _)| self.targeted.as_ref().is_none_or(|t| t.contains(*mac)))
+            .count();
+        let suspicious = connected >= SCAN_FAILURE_MIN_DEVICES
+            && vanished as f64 > connected as f64 * self.config.scan_failure_threshold;
+        if !suspicious {
+            self.failed_scans = 0;
+        } else if self.failed_scans < OFFLINE_THRESHOLD {
+            self.failed_scans += 1;
+            log::warn!(
+                "{vanished} of {connected} connected devices missing from scan, ignoring it as a likely scan failure"
+            );
+            if self.failed_scans == 1 {
+                notifications.push(Event::ScanFailure {
+                    vanished,
+                    connected,
+                });
+            }
+            return notifications;
+        }
+        for device in new_devices {
+            // only when the IP changes, not on every scan of a device that stays outside
+            if self.config.is_outside_subnets(&device)
+                && self
+                    .state
+                    .get(&device.mac)
+                    .is_none_or(|s| s.device.ip != device.ip)
+            {
+                notifications.push(Event::UnexpectedIp {
+                    mac: device.mac.clone(),
+                    ip: device.ip,
+                });
+            }
+            match self.state.entry(device.mac.clone()) {
+                // update status existing device
+                Entry::Occupied(mut e) => {
+                    let state = e.get_mut();
+                    if self.config.notify_vendor_change
+                        && has_vendor(&device.vendor)
+                        && state.device.vendor != device.vendor
+                        && self.config.devices.contains_key(&device.mac)
+                    {
+                        notifications.push(Event::VendorChanged {
+                            mac: device.mac.clone(),
+                            old_vendor: state.device.vendor.clone(),
+                        });
+                    }
+                    if let (Some(old), Some(new)) = (&state.device.interface, &device.interface)
+                        && old != new
+                    {
+                        log::info!(
+                            "Device {} moved from interface {} to {} with IP {}",
+                            device.mac.0,
+                            old,
+                            new,
+                            device.ip
+                        );
+                    }
+                    state.device.merge(device);
+                    state.last_seen = self.clock.now();
+                    state.ping_history.update_at(true, scan_time);
+                    state.uptime.record(true, today, self.config.uptime_days);
+                }
+                // found a new device
+                Entry::Vacant(e) => {
+                    self.new_devices += 1;
+                    if has_vendor(&device.vendor)
+                        && self.seen_ouis.insert(device.mac.oui().to_string())
+                        && self.config.notify_new_vendor
+                    {
+                        notifications.push(Event::NewVendor(device.mac.clone()));
+                    }
+                    let mac = device.mac.clone();
+                    let state = e.insert(DeviceState::new(
+                        device,
+                        true,
+                        ScanHistory::new(),
+                        self.clock.now(),
+                    ));
+                    // counted when it appears, also if it is only announced later
+                    *self.transitions.entry((mac.clone(), true)).or_default() += 1;
+                    let event = RecentEvent {
+                        time: state.last_change,
+                        mac: mac.clone(),
+                        name: self.config.name(&mac).cloned(),
+                        connected: true,
+                    };
+                    log_event(&mut self.event_log, &mut self.event_fifo, &event);
+                    call_hook(&self.config, &self.hooks, &event, state);
+                    self.recent_events.push(event);
+                    if self.config.min_samples_before_notify > 0
+                        || self.config.new_device_settle > 1
+                        || self.config.new_device_min_uptime.is_some()
+                        || self.config.warmup == Warmup::Unknown
+                    {
+                        state.announced = false;
+                    } else if self.config.notify_on(&mac).allows(true) {
+                        notifications.push(Event::Connected(mac));
+                    }
+                }
+            }
+        }
+        self.evict_devices(&notifications);
+        for (mac, state) in self.state.iter_mut() {
+            // left out of a targeted scan, it is kept as it is until the next full scan
+            if !scanned.contains(mac) && self.targeted.as_ref().is_some_and(|t| !t.contains(mac)) {
+                continue;
+            }
+            // if the device was not found in the new scan, update its log to disconnected
+            if !scanned.contains(mac) {
+                state.ping_history.update_at(false, scan_time);
+                state.uptime.record(false, today, self.config.uptime_days);
+                state.device.latency = None;
+            }
+            let is_connected = match self.config.member_macs(mac) {
+                Some(members) => {
+                    // found by another address, e.g. matched by IP, counts as the main one
+                    let found_otherwise = scanned.contains(mac)
+                        && !members.iter().any(|member| found_as.contains(*member));
+                    let mut any_connected = false;
+                    for member_mac in members {
+                        let member =
+                            state
+                                .members
+                                .entry(member_mac.clone())
+                                .or_insert_with(|| Member {
+                                    history: ScanHistory::new(),
+                                    is_connected: state.is_connected,
+                                });
+                        member.history.update(
+                            found_as.contains(member_mac) || (found_otherwise && member_mac == mac),
+                        );
+                        member.is_connected =
+                            self.config
+                                .is_connected(mac, &member.history, member.is_connected);
+                        any_connected |= member.is_connected;
+                    }
+                    any_connected
+                }
+                None => self
+                    .config
+                    .is_connected(mac, &state.ping_history, state.is_connected),
+            };
+            let previous = state.is_connected;
+            if is_connected != previous {
+                state.set_connected(is_connected, self.clock.now());
+                *self
+                    .transitions
+                    .entry((mac.clone(), is_connected))
+                    .or_default() += 1;
+                let event = RecentEvent {
+                    time: state.last_change,
+                    mac: mac.clone(),
+                    name: self.config.name(mac).cloned(),
+                    connected: is_connected,
+                };
+                log_event(&mut self.event_log, &mut self.event_fifo, &event);
+                call_hook(&self.config, &self.hooks, &event, state);
+                self.recent_events.push(event);
+                state.changes.push_front(state.last_change);
+            }
+            if let Some(threshold) = self.config.flap_threshold
+                && let Some(flapping) =
+                    state.check_flapping(self.clock.now(), threshold, self.config.flap_window)
+                && state.announced
+            {
+                notifications.push(if flapping {
+                    Event::Unstable {
+                        mac: mac.clone(),
+                        changes: state.changes.len(),
+                    }
+                } else {
+                    Event::Stabilized(mac.clone())
+                });
+            }
+            let returned = is_connected && state.pending_disconnect.take().is_some();
+            if returned {
+                log::debug!("Device {} reconnected within its disconnect grace", mac.0);
+            }
+            let suppressed = self.config.quiet_action == QuietAction::Suppress
+                && self.config.is_quiet(mac, &state.ping_history);
+            if is_connected != previous && suppressed {
+                log::debug!("Not notifying about quiet device {}", mac.0);
+            }
+            // changes of flapping devices are only notified about as being unstable
+            if is_connected != previous
+                && !returned
+                && state.announced
+                && !state.flapping
+                && !suppressed
+                && self.config.notify_on(mac).allows(is_connected)
+            {
+                if !is_connected && self.config.disconnect_grace(mac).is_some() {
+                    state.pending_disconnect = Some(state.last_change);
+                } else {
+                    notifications.push(if is_connected {
+                        Event::Connected(mac.clone())
+                    } else {
+                        Event::Disconnected(mac.clone())
+                    });
+                }
+            }
+            if let Some(since) = state.pending_disconnect
+                && self.config.disconnect_grace(mac).is_none_or(|grace| {
+                    (self.clock.now() - since).to_std().unwrap_or_default() >= grace
+                })
+            {
+                state.pending_disconnect = None;
+                notifications.push(Event::Disconnected(mac.clone()));
+            }
+            // only notify when a device starts degrading, not on every scan while it is
+            let degrading = state.is_connected && state.ping_history.is_degrading();
+            if degrading != state.degrading {
+                state.degrading = degrading;
+                if degrading && state.announced && self.config.notify_degrading {
+                    notifications.push(Event::Degrading(mac.clone()));
+                }
+            }
+            if let Some(threshold) = self.config.staleness_warn {
+                let stale = state.is_connected
+                    && (self.clock.now() - state.last_seen)
+                        .to_std()
+                        .is_ok_and(|age| age >= threshold);
+                if stale != state.stale {
+                    state.stale = stale;
+                    if stale && state.announced {
+                        notifications.push(Event::Stale(mac.clone()));
+                    }
+                }
+            }
+            let now = self.clock.now();
+            if let Some(budget) = self.config.offline_budget(mac)
+                && state.downtime.alerted != Some(now.date_naive())
+                && state.offline_today(now) > budget
+            {
+                state.downtime.alerted = Some(now.date_naive());
+                if state.announced {
+                    notifications.push(Event::OfflineBudget(mac.clone()));
+                }
+            }
+            if state.announced || !scanned.contains(mac) {
+                state.present_since = None;
+            } else {
+                state.present_since.get_or_insert(state.last_seen);
+            }
+            if !state.announced
+                && state.ping_history.len() >= self.config.min_samples_before_notify
+                && self.config.new_device_min_uptime.is_none_or(|min_uptime| {
+                    state.present_since.is_some_and(|since| {
+                        (self.clock.now() - since).to_std().unwrap_or_default() >= min_uptime
+                    })
+                })
+                // the scan a device was first seen in is not part of its history
+                && state.ping_history.hits() + 1 >= self.config.new_device_settle
+                && !self.config.is_warming_up(mac, &state.ping_history)
+            {
+                // only announce devices that are still around once there is enough data
+                state.announced = true;
+                if state.is_connected && self.config.notify_on(mac).allows(true) {
+                    notifications.push(Event::Connected(mac.clone()));
+                }
+            }
+            if state.is_connected {
+                state.escalation_step = 0;
+            } else if let (Some(config), Some(since)) =
+                (self.config.devices.get(mac), state.last_disconnect)
+            {
+                // only the last step reached is sent when several are reached at once
+                let down = (self.clock.now() - since).to_std().unwrap_or_default();
+                let reached = config
+                    .escalation
+                    .iter()
+                    .take_while(|step| down >= step.after)
+                    .count();
+                if reached > state.escalation_step {
+                    state.escalation_step = reached;
+                    notifications.push(Event::Escalation {
+                        mac: mac.clone(),
+                        step: reached - 1,
+                    });
+                }
+            }
+        }
+
+        // only notify when crossing a bound, not on every scan beyond it
+        let count = self.state.values().filter(|s| s.is_connected).count();
+        let limit = self.config.count_limit(count);
+        if limit != self.count_limit {
+            self.count_limit = limit;
+            if limit != CountLimit::Within {
+                notifications.push(Event::DeviceCount { count, limit });
+            }
+        }
+        if let Some(sink) = &mut self.influx {
+            let now = self.clock.now();
+            for (mac, state) in &self.state {
+                let name = self.config.name(mac).map(String::as_str).unwrap_or("");
+                sink.push(influx::line(
+                    "presence",
+                    &[("mac", &mac.0), ("name", name)],
+                    &[
+                        ("connected", state.is_connected),
+                        ("seen", scanned.contains(mac)),
+                    ],
+                    now,
+                ));
+            }
+        }
+        notifications.extend(self.update_presence(self.clock.now()));
+        if let Some(url) = self.config.presence_heartbeat_url.clone()
+            && let Some(body) = self.presence_heartbeat(self.clock.now())
+        {
+            self.hooks.call(hook::Request {
+                url,
+                authorization: None,
+                body,
+            });
+        }
+        notifications
+    }
+
+    /// Forgets the unknown devices seen least recently beyond `max_tracked_devices`, except the
+    /// ones with `pending` events
+    fn evict_devices(&mut self, pending: &[Event]) {
+        let Some(max) = self.config.max_tracked_devices else {
+            return;
+        };
+        let excess = self.state.len().saturating_sub(max);
+        if excess == 0 {
+            return;
+        }
+        let mut unknown: Vec<(DateTime<Local>, MacAddr)> = self
+            .state
+            .iter()
+            .filter(|(mac, _)| !self.config.devices.contains_key(*mac))
+            .filter(|(mac, _)| !pending.iter().any(|e| e.mac() == Some(*mac)))
+            .map(|(mac, state)| (state.last_seen, mac.clone()))
+            .collect();
+        unknown.sort();
+        for (last_seen, mac) in unknown.into_iter().take(excess) {
+            log::debug!("Forgetting device {} last seen {}", mac.0, last_seen);
+            self.state.remove(&mac);
+            for connected in [true, false] {
+                self.transitions.remove(&(mac.clone(), connected));
+                self.last_notified.remove(&(mac.clone(), connected));
+            }
+            if let Some(last_logged) = &mut self.last_logged {
+                last_logged.remove(&mac);
+            }
+        }
+    }
+
+    /// Tracks whether anyone of the presence group is home
+    ///
+    /// Arrivals are reported right away, but everyone has to be absent for `presence_debounce`
+    /// before a departure is reported, to tolerate phones sleeping.
+    fn update_presence(&mut self, now: DateTime<Local>) -> Option<Event> {
+        if self.config.presence_group.is_empty() {
+            return None;
+        }
+        let present = self
+            .config
+            .presence_group
+            .iter()
+            .any(|mac| self.state.get(mac).is_some_and(|s| s.is_connected));
+        let Some(was_present) = self.presence else {
+            self.presence = Some(present);
+            return None;
+        };
+        if present {
+            self.absent_since = None;
+        }
+        if present == was_present {
+            return None;
+        }
+        if !present {
+            let since = *self.absent_since.get_or_insert(now);
+            if now - since < chrono::TimeDelta::from_std(self.config.presence_debounce).ok()? {
+                return None;
+            }
+            self.absent_since = None;
+        }
+        self.presence = Some(present);
+        Some(Event::Presence { present })
+    }
+
+    /// Body of the `presence_heartbeat_url` call when it is due, at most every
+    /// `presence_heartbeat_interval` while anyone of the presence group is home
+    fn presence_heartbeat(&mut self, now: DateTime<Local>) -> Option<String> {
+        let names = self.present_names();
+        if names.is_empty() {
+            self.last_heartbeat = None;
+            return None;
+        }
+        let interval = chrono::TimeDelta::from_std(self.config.presence_heartbeat_interval).ok()?;
+        if self
+            .last_heartbeat
+            .is_some_and(|last| now - last < interval)
+        {
+            return None;
+        }
+        self.last_heartbeat = Some(now);
+        Some(match &self.config.presence_heartbeat_body {
+            Some(template) => template
+                .replace("{names}", &names.join(", "))
+                .replace("{count}", &names.len().to_string()),
+            None => serde_json::json!({ "present": names }).to_string(),
+        })
+    }
+
+    /// Display names of the connected devices of the presence group
+    fn present_names(&self) -> Vec<String> {
+        self.config
+            .presence_group
+            .iter()
+            .filter_map(|mac| self.state.get(mac))
+            .filter(|s| s.is_connected)
+            .map(|s| self.display_name(&s.device))
+            .collect()
+    }
+
+    fn send_notifications(&mut self, mut events: Vec<Event>) {
+        self.send_deferred();
+        self.dedup(&mut events, Instant::now());
+        self.cooldown(&mut events, self.clock.now());
+        for message in self.messages(&events) {
+            self.notify(message);
+        }
+        if self.config.auto_register_unknown && self.config.notify_unknown {
+            for event in &events {
+                if let Event::Connected(mac) | Event::Disconnected(mac) = event {
+                    self.register(mac);
+                }
+            }
+        }
+    }
+
+    /// Drops (dis)connections already notified within `notify_dedup_window`
+    fn dedup(&mut self, events: &mut Vec<Event>, now: Instant) {
+        let window = self.config.notify_dedup_window;
+        if window.is_zero() {
+            return;
+        }
+        events.retain(|event| {
+            let key = match event {
+                Event::Connected(mac) => (mac.clone(), true),
+                Event::Disconnected(mac) => (mac.clone(), false),
+                _ => return true,
+            };
+            match self.last_notified.entry(key) {
+                Entry::Occupied(e) if now.duration_since(*e.get()) < window => {
+                    log::debug!("Suppressing duplicate notification for {}", e.key().0.0);
+                    false
+                }
+                e => {
+                    e.insert_entry(now);
+                    true
+                }
+            }
+        });
+    }
+
+    /// Drops (dis)connections of devices notified about within their `notify_cooldown`, and
+    /// starts the cooldown of those that are notified
+    fn cooldown(&mut self, events: &mut Vec<Event>, now: DateTime<Local>) {
+        events.retain(|event| {
+            let (Event::Connected(mac) | Event::Disconnected(mac)) = event else {
+                return true;
+            };
+            let Some(cooldown) = self.config.devices.get(mac).and_then(|c| c.notify_cooldown)
+            else {
+                return true;
+            };
+            let cooling_down = self
+                .state
+                .get(mac)
+                .and_then(|s| s.last_notified)
+                .is_some_and(|last| {
+                    (now - last)
+                        .to_std()
+                        .is_ok_and(|elapsed| elapsed < cooldown)
+                });
+            if cooling_down {
+                log::debug!("Suppressing notification for {} in its cooldown", mac.0);
+            }
+            !cooling_down
+        });
+        for event in events.iter() {
+            if let Event::Connected(mac) | Event::Disconnected(mac) = event
+                && self.message(event).is_some()
+                && let Some(state) = self.state.get_mut(mac)
+            {
+                state.last_notified = Some(now);
+            }
+        }
+    }
+
+    /// Adds an unknown device to the known devices, using its vendor as the name
+    fn register(&mut self, mac: &MacAddr) {
+        let Some(state) = self.state.get_mut(mac) else {
+            return;
+        };
+        if self.config.devices.contains_key(mac) {
+            return;
+        }
+        log::info!(
+            "Registering unknown device {} as '{}'",
+            mac.0,
+            state.device.vendor
+        );
+        state.registered = true;
+        self.config.devices.insert(
+            mac.clone(),
+            DeviceConfig {
+                name: state.device.vendor.clone(),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn log_state(&mut self) {
+        if self.watch {
+            // clear the screen and move the cursor home
+            print!("\x1b[2J\x1b[H");
+            println!("{}", self.watch_header());
+            for line in self.status_table(self.color) {
+                println!("{line}");
+            }
+            return;
+        }
+        let current: HashMap<MacAddr, bool> = self
+            .state
+            .iter()
+            .map(|(mac, s)| (mac.clone(), s.is_connected))
+            .collect();
+        let delta = self
+            .last_logged
+            .as_ref()
+            .map(|prev| StateDelta::new(prev, &current));
+        self.last_logged = Some(current);
+        match delta {
+            Some(delta) if delta.is_empty() && !self.verbose => {
+                log::debug!("No changes in the status of {} devices", self.state.len());
+                return;
+            }
+            Some(delta) if !delta.is_empty() => self.output(&format!(
+                "Status of {} devices ({delta}):",
+                self.state.len()
+            )),
+            _ => self.output(&format!("Status of {} devices:", self.state.len())),
+        }
+        for line in self.status_table(self.color) {
+            self.output(&line);
+        }
+    }
+
+    /// Prints a line of the status, or logs it when logging to syslog
+    fn output(&self, line: &str) {
+        match self.config.log_target {
+            LogTarget::Stdout => println!("{line}"),
+            LogTarget::Syslog => log::info!("{line}"),
+        }
+    }
+
+    fn watch_header(&self) -> String {
+        let next = self
+            .config
+            .scan_interval
+            .saturating_sub(self.last_scan_start.elapsed());
+        format!(
+            "Status of {} devices, scan {}, next scan in {}s",
+            self.state.len(),
+            self.scans,
+            next.as_secs_f64().round()
+        )
+    }
+
+    fn status_table(&self, color: bool) -> Vec<String> {
+        let mut lines: Vec<String> = self.pause_description().into_iter().collect();
+        lines.extend(self.device_table(
+            &self.config.table_columns,
+            None,
+            self.config.table_group_by,
+            color,
+        ));
+        lines
+    }
+
+    fn is_paused(&self, now: DateTime<Local>) -> bool {
+        self.paused.is_some_and(|pause| pause.is_active(now))
+    }
+
+    /// Describes the maintenance mode, if active
+    fn pause_description(&self) -> Option<String> {
+        match self.paused? {
+            _ if !self.is_paused(self.clock.now()) => None,
+            Pause::UntilResumed => {
+                Some("Maintenance mode: notifications paused until resumed".to_string())
+            }
+            Pause::Until(until) => Some(format!(
+                "Maintenance mode: notifications paused until {}",
+                until.format("%H:%M:%S")
+            )),
+        }
+    }
+
+    /// Renders the given columns for all devices, or only for `mac`
+    fn device_table(
+        &self,
+        columns: &[Column],
+        mac: Option<&MacAddr>,
+        group_by: TableGroupBy,
+        color: bool,
+    ) -> Vec<String> {
+        let mut states: Vec<&DeviceState> = self
+            .state
+            .values()
+            .filter(|s| mac.is_none_or(|mac| s.device.mac == *mac))
+            .collect();
+        // known devices grouped by type first, then unknown devices
+        states.sort_by_key(|s| {
+            let config = self.config.devices.get(&s.device.mac);
+            (
+                config.is_none(),
+                config.map(|c| c.device_type),
+                config.map(|c| &c.name),
+            )
+        });
+        // the sort is stable, so devices that are equal stay sorted by name
+        match self.config.table_sort {
+            TableSort::Name => {}
+            TableSort::LastSeen => states.sort_by_key(|s| std::cmp::Reverse(s.last_seen)),
+            TableSort::Status => states.sort_by_key(|s| s.is_connected),
+            TableSort::Uptime => {
+                states.sort_by_key(|s| (!s.is_connected, s.is_connected.then_some(s.last_change)))
+            }
+            TableSort::Ip => states.sort_by_key(|s| s.device.ip),
+        }
+        // a group of `None` is headed `Unknown`
+        let group = |s: &DeviceState| {
+            let config = self.config.devices.get(&s.device.mac);
+            match group_by {
+                TableGroupBy::None => None,
+                TableGroupBy::Type => config
+                    .map(|c| c.device_type)
+                    .filter(|t| *t != DeviceType::Unknown)
+                    .map(|t| t.label().to_string()),
+                TableGroupBy::Room => config.and_then(|c| c.room.clone()),
+                TableGroupBy::Status => Some(
+                    if s.is_connected {
+                        "Connected"
+                    } else {
+                        "Disconnected"
+                    }
+                    .to_string(),
+                ),
+            }
+        };
+        // stable again, so the order within a group is kept
+        match group_by {
+            TableGroupBy::None => {}
+            TableGroupBy::Type => states.sort_by_key(|s| {
+                self.config
+                    .devices
+                    .get(&s.device.mac)
+                    .map_or(DeviceType::Unknown, |c| c.device_type)
+            }),
+            TableGroupBy::Room => states.sort_by_key(|s| {
+                let room = group(s);
+                (room.is_none(), room)
+            }),
+            TableGroupBy::Status => states.sort_by_key(|s| !s.is_connected),
+        }
+        let now = self.clock.now();
+        let mut table = Table::new();
+        for state in &states {
+            table.push_row(
+                columns
+                    .iter()
+                    .map(|c| c.cell(state, &self.config, now))
+                    .collect(),
+            );
+        }
+        let lines = table.render(self.config.table_width, color);
+        if group_by == TableGroupBy::None {
+            return lines;
+        }
+        // rendered as one table so the columns line up across groups
+        let mut grouped = Vec::new();
+        let mut current = None;
+        for (state, line) in states.into_iter().zip(lines) {
+            let group = Some(group(state));
+            if group != current {
+                if current.is_some() {
+                    grouped.push(String::new());
+                }
+                let name = group.as_ref().and_then(|g| g.as_deref());
+                grouped.push(format!("{}:", name.unwrap_or("Unknown")));
+                current = group;
+            }
+            grouped.push(line);
+        }
+        grouped
+    }
+
+    fn notify(&mut self, message: Message) {
+        if self.is_paused(self.clock.now()) {
+            log::info!("[paused] {} {}", message.title, message.body);
+            return;
+        }
+        if self.config.coalesce_reconnects
+            && let Some(transition) = &message.transition
+        {
+            let reverted =
+                |m: &Message| m.transition.as_ref().is_some_and(|t| transition.reverts(t));
+            let cancelled = match self.deferred.iter().position(reverted) {
+                Some(i) => self.deferred.remove(i).is_some(),
+                None => self.queue.cancel(transition),
+            };
+            if cancelled {
+                log::info!(
+                    "[coalesced] {} changed back before it was notified, dropping both",
+                    transition.mac
+                );
+                return;
+            }
+        }
+        log::info!("[notify] {} {}", message.title, message.body);
+        // critical messages bypass the rate limit and are neither counted nor deferred
+        let Some(limiter) = self.rate_limiter.as_mut().filter(|_| !message.critical) else {
+            self.queue.push(message);
+            return;
+        };
+        match self.config.notify_rate_limit_action {
+            RateLimitAction::Drop if limiter.try_acquire(Instant::now()) => {
+                if self.rate_limited > 0 {
+                    log::warn!(
+                        "Dropped {} notifications over the rate limit",
+                        self.rate_limited
+                    );
+                    self.rate_limited = 0;
+                }
+                self.queue.push(message);
+            }
+            RateLimitAction::Drop => {
+                log::debug!("Rate limit reached, dropping '{}'", message.title);
+                self.rate_limited += 1;
+            }
+            RateLimitAction::Defer => {
+                if self.deferred.len() >= self.config.notification_queue_size.max(1)
+                    && let Some(dropped) = self.deferred.pop_front()
+                {
+                    log::warn!(
+                        "Too many deferred notifications, dropping '{}'",
+                        dropped.title
+                    );
+                }
+                self.deferred.push_back(message);
+                self.send_deferred();
+            }
+        }
+    }
+
+    /// Sends deferred notifications as far as the rate limit allows
+    fn send_deferred(&mut self) {
+        let Some(limiter) = &mut self.rate_limiter else {
+            return;
+        };
+        while !self.deferred.is_empty() && limiter.try_acquire(Instant::now()) {
+            if let Some(message) = self.deferred.pop_front() {
+                self.queue.push(message);
+            }
+        }
+    }
+
+    /// Formats the messages for the events of a single scan, batching (dis)connections if enabled
+    fn messages(&self, events: &[Event]) -> Vec<Message> {
+        let mut messages = Vec::new();
+        // (dis)connections grouped by status and destination
+        let mut groups: Vec<(bool, Vec<(&Device, Message)>)> = Vec::new();
+        for event in events {
+            let Some(message) = self.message(event) else {
+                continue;
+            };
+            let (status, mac) = match event {
+                Event::Connected(mac) => (true, mac),
+                Event::Disconnected(mac) => (false, mac),
+                _ => {
+                    messages.push(message);
+                    continue;
+                }
+            };
+            let device = &self.state[mac].device;
+            match groups
+                .iter_mut()
+                .find(|(s, group)| *s == status && group[0].1.ntfy_url == message.ntfy_url)
+            {
+                Some((_, group)) => group.push((device, message)),
+                None => groups.push((status, vec![(device, message)])),
+            }
+        }
+        let phrases = self.config.messages.phrases(self.config.language);
+        for (connected, group) in groups {
+            if self.config.batch_notifications && group.len() > self.config.batch_threshold {
+                let names: Vec<String> = group.iter().map(|(d, _)| self.display_name(d)).collect();
+                let priority = group
+                    .iter()
+                    .map(|(_, m)| m.priority)
+                    .max()
+                    .unwrap_or_default();
+                let title = i18n::fill(
+                    phrases.batch_title,
+                    &[
+                        ("count", &group.len().to_string()),
+                        ("status", phrases.status(connected)),
+                    ],
+                );
+                messages.push(Message {
+                    body: format!("{}: {}", title, names.join(", ")),
+                    title,
+                    priority,
+                    ntfy_url: group[0].1.ntfy_url.clone(),
+                    tags: vec![status_tag(connected)],
+                    critical: group.iter().any(|(_, m)| m.critical),
+                    ..Default::default()
+                });
+            } else {
+                messages.extend(group.into_iter().map(|(_, m)| m));
+            }
+        }
+        messages
+    }
+
+    /// Returns the digest message once the digest time has been crossed on a new day
+    fn digest(&mut self, now: DateTime<Local>) -> Option<Message> {
+        let digest_time = self.config.digest_time?;
+        let today = now.date_naive();
+        if now.time() < digest_time || self.last_digest == Some(today) {
+            return None;
+        }
+        self.last_digest = Some(today);
+        let online = self.state.values().filter(|s| s.is_connected).count();
+        let offline = self.state.len() - online;
+        let new_devices = std::mem::take(&mut self.new_devices);
+        Some(Message {
+            title: "Daily digest".to_string(),
+            body: format!(
+                "Devices online: {online}, offline: {offline}, new today: {new_devices}."
+            ),
+            priority: Priority::Low,
+            ..Default::default()
+        })
+    }
+
+    /// Summary of the devices online after the initial scan or the restored state, if
+    /// `notify_on_startup` is set
+    fn startup_message(&self) -> Option<Message> {
+        if !self.config.notify_on_startup {
+            return None;
+        }
+        let online: Vec<&DeviceState> = self.state.values().filter(|s| s.is_connected).collect();
+        let mut known: Vec<String> = online
+            .iter()
+            .filter_map(|s| self.config.devices.get(&s.device.mac))
+            .map(|config| config.display_name())
+            .collect();
+        known.sort();
+        let unknown = online.len() - known.len();
+        let mut body = if known.is_empty() {
+            "No known devices online".to_string()
+        } else {
+            format!("Known devices online: {}", known.join(", "))
+        };
+        if unknown > 0 {
+            body += &format!(", and {unknown} unknown");
+        }
+        Some(Message {
+            title: format!("Monitoring started: {} devices online", online.len()),
+            body: body + ".",
+            priority: Priority::Low,
+            ..Default::default()
+        })
+    }
+
+    /// Summary of the changes of the configured devices since the restored state was saved, if
+    /// `notify_catch_up` is set, marks them so the first scan doesn't notify about them again
+    ///
+    /// Waits for a scan in which the gateway is reachable, if one is configured.
+    fn catch_up(&mut self, devices: &[Device], now: DateTime<Local>) -> Option<Message> {
+        let since = self.down_since?;
+        // without the network every device would seem to have left while lanotify was down
+        if let Some(gateway) = self.gateway(devices)
+            && !self.is_gateway_reachable(gateway, devices)
+        {
+            return None;
+        }
+        self.down_since = None;
+        let scanned: HashSet<&MacAddr> = devices.iter().map(|d| &d.mac).collect();
+        let mut changes = Vec::new();
+        for (mac, state) in self.state.iter_mut() {
+            let Some(config) = self.config.devices.get(mac) else {
+                continue;
+            };
+            // left out of a targeted scan, its state is unknown
+            if self.targeted.as_ref().is_some_and(|t| !t.contains(mac)) {
+                continue;
+            }
+            let found = scanned.contains(mac);
+            if found == state.is_connected {
+                continue;
+            }
+            state.set_connected(found, now);
+            // the detection starts over like for a new device
+            state.ping_history = ScanHistory::new();
+            state.members.clear();
+            let event = RecentEvent {
+                time: now,
+                mac: mac.clone(),
+                name: Some(config.name.clone()),
+                connected: found,
+            };
+            log_event(&mut self.event_log, &mut self.event_fifo, &event);
+            call_hook(&self.config, &self.hooks, &event, state);
+            self.recent_events.push(event);
+            let status = if found { "connected" } else { "disconnected" };
+            changes.push(format!("{} {}", config.display_name(), status));
+        }
+        let missed = std::mem::take(&mut self.missed);
+        if changes.is_empty() && missed.is_empty() {
+            return None;
+        }
+        changes.sort();
+        let mut body = format!("Since {}: ", since.format("%Y-%m-%d %H:%M"));
+        if changes.is_empty() {
+            body += "no changes of known devices.";
+        } else {
+            body += &format!("{}.", changes.join(", "));
+        }
+        if !missed.is_empty() {
+            body += &format!(" Not sent before the restart: {}.", missed.join("; "));
+        }
+        Some(Message {
+            title: "While lanotify was down".to_string(),
+            body,
+            ..Default::default()
+        })
+    }
+
+    fn display_name(&self, device: &Device) -> String {
+        match (self.config.devices.get(&device.mac), &device.hostname) {
+            (Some(config), _) => config.display_name(),
+            (None, _) if let Some(hostname) = self.config.lease_hostname(&device.mac) => {
+                hostname.to_string()
+            }
+            (None, Some(hostname)) => format!("Unknown {} ({})", hostname, device.vendor),
+            (None, None) => format!("Unknown {}", device.vendor),
+        }
+    }
+
+    /// Formats the notification message for an event, `None` if it should not be sent
+    fn message(&self, event: &Event) -> Option<Message> {
+        if let Some(mac) = event.mac()
+            && let Some(state) = self.state.get(mac)
+            && (self.config.is_muted(&state.device) || state.is_muted(self.clock.now()))
+        {
+            log::debug!("Not notifying about muted device {}", mac.0);
+            return None;
+        }
+        match event {
+            Event::Connected(mac) => self.connection_message(mac, true),
+            Event::Disconnected(mac) => self.connection_message(mac, false),
+            Event::Degrading(mac) => {
+                let state = self.state.get(mac)?;
+                if !self.config.notify_unknown && !self.config.devices.contains_key(mac) {
+                    return None;
+                }
+                let display_name = self.display_name(&state.device);
+                let missed = state.ping_history.log.iter().filter(|v| !**v).count();
+                Some(Message {
+                    title: format!("Device {display_name} is unreliable"),
+                    body: format!(
+                        "Device {} with IP {} missed {} of the last {} scans, it is usually seen in {:.0}% of scans",
+                        display_name,
+                        state.device.ip,
+                        missed,
+                        state.ping_history.len(),
+                        state.ping_history.availability().unwrap_or_default() * 100.0
+                    ),
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::OfflineBudget(mac) => {
+                let state = self.state.get(mac)?;
+                let display_name = self.display_name(&state.device);
+                let offline = state.offline_today(self.clock.now());
+                let to_delta = |d: Duration| chrono::TimeDelta::from_std(d).unwrap_or_default();
+                let budget = self.config.offline_budget(mac)?;
+                Some(Message {
+                    title: format!(
+                        "Device {} offline for {} today",
+                        display_name,
+                        format_duration(to_delta(offline))
+                    ),
+                    body: format!(
+                        "Device {} was offline for {} today, over its budget of {}",
+                        display_name,
+                        format_duration(to_delta(offline)),
+                        format_duration(to_delta(budget))
+                    ),
+                    priority: Priority::High,
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::Stale(mac) => {
+                let state = self.state.get(mac)?;
+                if !self.config.notify_unknown && !self.config.devices.contains_key(mac) {
+                    return None;
+                }
+                let display_name = self.display_name(&state.device);
+                let unseen = format_duration(self.clock.now() - state.last_seen);
+                Some(Message {
+                    title: format!("Device {display_name} not seen for {unseen}"),
+                    body: format!(
+                        "Device {} with IP {} has not been seen for {} but is still considered connected",
+                        display_name, state.device.ip, unseen
+                    ),
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::Unstable { mac, changes } => {
+                let state = self.state.get(mac)?;
+                if !self.config.notify_unknown && !self.config.devices.contains_key(mac) {
+                    return None;
+                }
+                let display_name = self.display_name(&state.device);
+                Some(Message {
+                    title: format!("Device {display_name} is unstable"),
+                    body: format!(
+                        "Device {} with IP {} changed its connection state {} times within {}, notifications about it are paused until it is stable",
+                        display_name,
+                        state.device.ip,
+                        changes,
+                        format_duration(
+                            chrono::TimeDelta::from_std(self.config.flap_window)
+                                .unwrap_or_default()
+                        )
+                    ),
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::Stabilized(mac) => {
+                let state = self.state.get(mac)?;
+                if !self.config.notify_unknown && !self.config.devices.contains_key(mac) {
+                    return None;
+                }
+                let display_name = self.display_name(&state.device);
+                Some(Message {
+                    title: format!("Device {display_name} has stabilized"),
+                    body: format!(
+                        "Device {} with IP {} is stable again and {}",
+                        display_name,
+                        state.device.ip,
+                        if state.is_connected {
+                            "connected"
+                        } else {
+                            "disconnected"
+                        }
+                    ),
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::Escalation { mac, step } => {
+                let state = self.state.get(mac)?;
+                let step = self.config.devices.get(mac)?.escalation.get(*step)?;
+                let display_name = self.display_name(&state.device);
+                let down = state
+                    .last_disconnect
+                    .map(|since| format_duration(self.clock.now() - since))
+                    .unwrap_or_default();
+                Some(Message {
+                    title: format!("Device {display_name} is still disconnected"),
+                    body: format!(
+                        "Device {} with IP {} and MAC {} has been disconnected for {}",
+                        display_name, state.device.ip, state.device.mac.0, down
+                    ),
+                    priority: step.priority,
+                    ntfy_url: self.ntfy_url(mac),
+                    notifier: step.notifier.clone(),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::VendorChanged { mac, old_vendor } => {
+                let device = &self.state.get(mac)?.device;
+                let display_name = self.display_name(device);
+                Some(Message {
+                    title: format!("Vendor of {display_name} changed"),
+                    body: format!(
+                        "Device {} with IP {} and MAC {} changed vendor from '{}' to '{}', this may indicate MAC spoofing",
+                        display_name, device.ip, device.mac.0, old_vendor, device.vendor
+                    ),
+                    priority: Priority::High,
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::NewVendor(mac) => {
+                let device = &self.state.get(mac)?.device;
+                let display_name = self.display_name(device);
+                Some(Message {
+                    title: format!("New manufacturer {} on the network", device.vendor),
+                    body: format!(
+                        "Device {} with IP {} and MAC {} is the first device by {} (OUI {}) seen on the network",
+                        display_name,
+                        device.ip,
+                        device.mac.0,
+                        device.vendor,
+                        mac.oui()
+                    ),
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::UnexpectedIp { mac, ip } => {
+                let device = &self.state.get(mac)?.device;
+                let display_name = self.display_name(device);
+                Some(Message {
+                    title: format!("Device {display_name} has an unexpected IP"),
+                    body: format!(
+                        "Device {} with MAC {} reported IP {} outside of the configured subnets, this may indicate ARP spoofing or a rogue DHCP server",
+                        display_name, device.mac.0, ip
+                    ),
+                    priority: Priority::High,
+                    ntfy_url: self.ntfy_url(mac),
+                    critical: self.config.is_critical(mac),
+                    ..Default::default()
+                })
+            }
+            Event::DeviceCount { count, limit } => {
+                let (title, body) = match limit {
+                    CountLimit::Above(max) => (
+                        "Too many devices connected",
+                        format!("{count} devices are connected, more than the maximum of {max}"),
+                    ),
+                    CountLimit::Below(min) => (
+                        "Too few devices connected",
+                        format!("{count} devices are connected, less than the minimum of {min}"),
+                    ),
+                    CountLimit::Within => return None,
+                };
+                Some(Message {
+                    title: title.to_string(),
+                    body,
+                    priority: Priority::High,
+                    ..Default::default()
+                })
+            }
+            Event::ScanFailure {
+                vanished,
+                connected,
+            } => Some(Message {
+                title: "Scan failed".to_string(),
+                body: format!(
+                    "{vanished} of {connected} connected devices disappeared at once, the network scan most likely failed"
+                ),
+                priority: Priority::High,
+                ..Default::default()
+            }),
+            Event::ScanBroken { failures, error } => Some(Message {
+                title: "Scanning failed".to_string(),
+                body: format!("{failures} scans failed in a row: {error}"),
+                priority: Priority::High,
+                ..Default::default()
+            }),
+            Event::InterfaceDown { interface } => Some(Message {
+                title: format!("Interface {interface} is down"),
+                body: format!(
+                    "The scanned interface {interface} is down, scans are skipped until it is up again"
+                ),
+                priority: Priority::High,
+                ..Default::default()
+            }),
+            Event::InterfaceUp { interface } => Some(Message {
+                title: format!("Interface {interface} is up"),
+                body: format!("The scanned interface {interface} is up again, scanning resumed"),
+                ..Default::default()
+            }),
+            Event::ScanRecovered { failures } => Some(Message {
+                title: "Scanning recovered".to_string(),
+                body: format!("Scanning works again after {failures} failed scans"),
+                ..Default::default()
+            }),
+            Event::NetworkRestored { lost_for } => Some(Message {
+                title: "Network restored".to_string(),
+                body: format!(
+                    "The gateway was unreachable for {}, the scans in the meantime were ignored",
+                    format_duration(*lost_for)
+                ),
+                priority: Priority::Low,
+                ..Default::default()
+            }),
+            Event::Presence { present } => {
+                let (title, body) = if *present {
+                    let names = self.present_names();
+                    ("Someone is home", format!("Present: {}", names.join(", ")))
+                } else {
+                    (
+                        "Everyone left",
+                        "Nobody of the presence group is home".to_string(),
+                    )
+                };
+                Some(Message {
+                    title: title.to_string(),
+                    body,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    /// Overridden ntfy URL for notifications about a device
+    fn ntfy_url(&self, mac: &MacAddr) -> Option<String> {
+        self.config
+            .devices
+            .get(mac)
+            .and_then(|d| d.ntfy_url.clone())
+    }
+
+    fn connection_message(&self, mac: &MacAddr, connected: bool) -> Option<Message> {
+        let state = self.state.get(mac)?;
+        let device = &state.device;
+        let config = self.config.devices.get(mac);
+        if !self.config.notify_unknown && config.is_none() {
+            log::info!(
+                "Unknown device {} with IP {} and MAC {} is {}",
+                device.vendor,
+                device.ip,
+                device.mac.0,
+                if connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                }
+            );
+            return None;
+        }
+        let phrases = self.config.messages.phrases(self.config.language);
+        let status = phrases.status(connected);
+
+        let display_name = self.display_name(device);
+        let mut priority = match config {
+            Some(config) => config.priority.unwrap_or_default(),
+            None => Priority::High,
+        };
+        if self.config.quiet_action == QuietAction::Low
+            && self.config.is_quiet(mac, &state.ping_history)
+        {
+            priority = Priority::Low;
+        }
+        let mut title = i18n::fill(
+            phrases.title,
+            &[("name", &display_name), ("status", status)],
+        );
+        if let Some(absence) = state.absence()
+            && let Some(long_absence) = self.config.long_absence
+            && absence
+                .to_std()
+                .is_ok_and(|absence| absence >= long_absence)
+        {
+            priority = priority.max(Priority::High);
+            title = i18n::fill(
+                phrases.back_title,
+                &[
+                    ("name", &display_name),
+                    ("absence", &format_duration(absence)),
+                ],
+            );
+        }
+        let activity = self
+            .config
+            .activity(mac, &state.ping_history)
+            .filter(|_| self.config.describe_activity && !connected);
+        if let Some((_, activity)) = activity {
+            title = i18n::fill(
+                activity.disconnect_title(&phrases),
+                &[("name", &display_name)],
+            );
+        }
+        let template = self
+            .config
+            .message_templates
+            .get(config.is_some(), connected);
+        let mut body = match template {
+            Some(template) => expand(template, device, &display_name),
+            None => format!(
+                "{}{}{}",
+                i18n::fill(
+                    phrases.body,
+                    &[
+                        ("name", &display_name),
+                        ("ip", &device.ip.to_string()),
+                        ("mac", &device.mac.0),
+                        ("status", status),
+                    ]
+                ),
+                device
+                    .node
+                    .as_ref()
+                    .map(|node| format!(" on {node}"))
+                    .unwrap_or_default(),
+                previous_state(state, &self.config.time_format)
+            ),
+        };
+        if let Some((base_rate, _)) = activity {
+            body.push_str(&format!(
+                "\nSeen in {:.0}% of the recent scans",
+                base_rate * 100.0
+            ));
+        }
+        if let Some(note) = config.and_then(|config| config.note.as_ref()) {
+            body.push_str(&format!("\nNote: {note}"));
+        }
+        let click = match config {
+            Some(_) => self.config.ntfy_click.as_ref(),
+            None => self
+                .config
+                .investigate_url
+                .as_ref()
+                .or(self.config.ntfy_click.as_ref()),
+        };
+        Some(Message {
+            title,
+            body,
+            priority,
+            ntfy_url: self.ntfy_url(mac),
+            notifier: None,
+            click: click.map(|click| expand(click, device, &display_name)),
+            actions: self
+                .config
+                .ntfy_actions
+                .iter()
+                .map(|action| Action {
+                    url: expand(&action.url, device, &display_name),
+                    label: expand(&action.label, device, &display_name),
+                    ..action.clone()
+                })
+                .collect(),
+            tags: match config {
+                Some(config) if !config.tags.is_empty() => config.tags.clone(),
+                _ => vec![status_tag(connected)],
+            },
+            critical: config.is_some_and(|config| config.critical),
+            transition: Some(Transition {
+                mac: mac.0.clone(),
+                connected,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+
+    fn test_daemon(config: Config) -> Daemon {
+        Daemon::new(config, Box::new(scan::FixtureScanner::parse("").unwrap()))
+    }
+
+    #[test]
+    fn test_connected_always_on() {
+        let mut history = ScanHistory::from(vec![true; HISTORY_SIZE]);
+        let mut is_connected = true;
+
+        // for a short while it stays connected
+        for _ in 0..RECENT_WINDOW {
+            history.update(false);
+            is_connected = history.is_connected(is_connected);
+            assert!(is_connected);
+        }
+
+        // stay disconnected
+        for _ in 0..HISTORY_SIZE {
+            history.update(false);
+            is_connected = history.is_connected(is_connected);
+            assert!(!is_connected);
+        }
+    }
+
+    #[test]
+    fn test_connected_always_on_temporary_offline() {
+        let mut history = ScanHistory::from(vec![true; HISTORY_SIZE]);
+
+        // 3x scan misses
+        for _ in 0..3 {
+            history.update(false);
+        }
+
+        let mut is_connected = true;
+
+        // stay connected
+        for _ in 0..HISTORY_SIZE {
+            is_connected = history.is_connected(is_connected);
+            assert!(is_connected);
+            history.update(true);
+        }
+    }
+
+    #[test]
+    fn test_connected_sleeping() {
+        let mut history = ScanHistory::new();
+        for i in 0..HISTORY_SIZE {
+            history.update(i % 10 == 0); // 10% activity
+        }
+
+        let mut is_connected = true;
+        is_connected = history.is_connected(is_connected);
+        assert!(is_connected);
+
+        // stays connected the entire time
+        for _ in 0..HISTORY_SIZE {
+            history.update(false);
+            assert!(is_connected);
+        }
+
+        is_connected = history.is_connected(is_connected);
+        assert!(!is_connected);
+    }
+
+    #[test]
+    fn test_connected_intermittent() {
+        let mut history = ScanHistory::new();
+        let mut is_connected = true;
+        for i in 0..HISTORY_SIZE {
+            is_connected = history.is_connected(is_connected);
+            assert!(is_connected);
+            history.update(i % 2 == 0); // 50% activity
+        }
+        for _ in 0..OFFLINE_THRESHOLD {
+            history.update(false);
+        }
+        for _ in 0..HISTORY_SIZE {
+            is_connected = history.is_connected(is_connected);
+            assert!(!is_connected);
+            history.update(false);
+        }
+    }
+
+    #[test]
+    fn test_connected_new_sleeping() {
+        let mut is_connected = true;
+        let mut history = ScanHistory::new();
+        for i in 0..(HISTORY_SIZE * 2) {
+            is_connected = history.is_connected(is_connected);
+            assert!(is_connected);
+            history.update(i % 20 == 0); // very low activity
+        }
+    }
+
+    #[test]
+    fn test_connected_intervals() {
+        // on and off in intervals
+        for x in 1..RECENT_WINDOW {
+            let mut is_connected = true;
+            let mut history = ScanHistory::new();
+            for y in 0..HISTORY_SIZE {
+                for z in 0..OFFLINE_THRESHOLD {
+                    // offset of z
+                    for _ in 0..z {
+                        history.update(false);
+                    }
+                    // x times on
+                    for _ in 0..x {
+                        is_connected = history.is_connected(is_connected);
+                        assert!(is_connected, "x={x}, y={y}, z={z}, {history}");
+                        history.update(true);
+                    }
+                    // x times off
+                    for _ in 0..x {
+                        is_connected = history.is_connected(is_connected);
+                        assert!(is_connected, "x={x}, y={y}, z={z} {history}");
+                        history.update(false);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_daemon_fixture_scenario() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let mut fixture = format!("{a}---\n{a}{b}");
+        for _ in 0..HISTORY_SIZE {
+            fixture.push_str(&format!("---\n{a}"));
+        }
+        let scanner = scan::FixtureScanner::parse(&fixture).unwrap();
+        let mut daemon = Daemon::new(Config::default(), Box::new(scanner));
+
+        let devices = daemon.scanner.scan().unwrap().unwrap();
+        daemon.init_state(devices);
+
+        let mut transitions = Vec::new();
+        while let Some(devices) = daemon.scanner.scan().unwrap() {
+            for event in daemon.update_state(devices) {
+                match event {
+                    Event::Connected(mac) => transitions.push((mac.0, true)),
+                    Event::Disconnected(mac) => transitions.push((mac.0, false)),
+                    _ => {}
+                }
+            }
+        }
+        let b_mac = "aa:bb:cc:dd:ee:02".to_string();
+        assert_eq!(transitions, vec![(b_mac.clone(), true), (b_mac, false)]);
+    }
+
+    #[test]
+    fn test_vendor_change() {
+        let fixture = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                       ---\n\
+                       192.168.1.2\taa:bb:cc:dd:ee:01\tVendor B\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let config = Config {
+            notify_vendor_change: true,
+            devices: HashMap::from([(mac, DeviceConfig::named("Phone"))]),
+            ..Default::default()
+        };
+        let mut scanner = scan::FixtureScanner::parse(fixture).unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scanner.scan().unwrap().unwrap());
+        let events = daemon.update_state(scanner.scan().unwrap().unwrap());
+        assert_eq!(events.len(), 1);
+        let message = daemon.message(&events[0]).unwrap();
+        assert_eq!(message.title, "Vendor of Phone changed");
+        assert!(message.body.contains("'Vendor A' to 'Vendor B'"));
+    }
+
+    #[test]
+    fn test_unexpected_ip() {
+        let subnet: Subnet = "192.168.1.0/24".parse().unwrap();
+        assert!(subnet.contains("192.168.1.200".parse().unwrap()));
+        assert!(!subnet.contains("192.168.2.1".parse().unwrap()));
+        let all: Subnet = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains("10.0.0.1".parse().unwrap()));
+        assert!("192.168.1.0".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/33".parse::<Subnet>().is_err());
+
+        let mut daemon = test_daemon(
+            Config::parse(
+                "subnets = [\"192.168.1.0/24\", \"10.0.0.0/8\"]\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        let scan = |ip: &str| {
+            scan::parse_scan(&format!(
+                "{ip}\taa:bb:cc:dd:ee:01\tVendor A\n192.168.5.3\taa:bb:cc:dd:ee:02\tVendor B\n"
+            ))
+        };
+        daemon.init_state(scan("192.168.1.2"));
+        assert!(daemon.update_state(scan("10.1.2.3")).is_empty());
+        let events = daemon.update_state(scan("172.16.0.5"));
+        assert!(
+            matches!(&events[..], [Event::UnexpectedIp { ip, .. }] if ip.to_string() == "172.16.0.5")
+        );
+        let message = daemon.message(&events[0]).unwrap();
+        assert_eq!(message.priority, Priority::High);
+        assert!(message.body.contains("172.16.0.5"));
+        // not repeated while the device keeps the IP
+        assert!(daemon.update_state(scan("172.16.0.5")).is_empty());
+    }
+
+    #[test]
+    fn test_merge_device() {
+        let fixture = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                       ---\n\
+                       0.0.0.0\taa:bb:cc:dd:ee:01\t\n\
+                       ---\n\
+                       192.168.1.3\taa:bb:cc:dd:ee:01\t(Unknown)\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let config = Config {
+            notify_vendor_change: true,
+            devices: HashMap::from([(mac.clone(), DeviceConfig::named("Phone"))]),
+            ..Default::default()
+        };
+        let mut scanner = scan::FixtureScanner::parse(fixture).unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scanner.scan().unwrap().unwrap());
+        // an empty vendor keeps the previous one without a vendor change
+        assert!(
+            daemon
+                .update_state(scanner.scan().unwrap().unwrap())
+                .is_empty()
+        );
+        let device = &daemon.state[&mac].device;
+        assert_eq!(device.vendor, "Vendor A");
+        assert_eq!(device.ip, Ipv4Addr::new(192, 168, 1, 2));
+
+        assert!(
+            daemon
+                .update_state(scanner.scan().unwrap().unwrap())
+                .is_empty()
+        );
+        let device = &daemon.state[&mac].device;
+        assert_eq!(device.vendor, "Vendor A");
+        assert_eq!(device.ip, Ipv4Addr::new(192, 168, 1, 3));
+    }
+
+    #[test]
+    fn test_probe_port() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = Config::parse(&format!(
+            "[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Printer\"\nprobe_port = {port}\n"
+        ))
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan("127.0.0.1\taa:bb:cc:dd:ee:01\tVendor A\n"));
+        for _ in 0..HISTORY_SIZE {
+            assert!(daemon.update_state(Vec::new()).is_empty());
+        }
+        assert!(daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:01")].is_connected);
+
+        drop(listener);
+        let events: Vec<Event> = (0..HISTORY_SIZE)
+            .flat_map(|_| daemon.update_state(Vec::new()))
+            .collect();
+        assert!(matches!(events[..], [Event::Disconnected(_)]));
+    }
+
+    #[test]
+    fn test_healthcheck() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let n = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&request[..n]).into_owned()
+        });
+        let config = Config::parse(&format!(
+            "healthcheck_url = \"http://127.0.0.1:{port}/ping/lan\""
+        ))
+        .unwrap();
+        let scanner =
+            scan::FixtureScanner::parse("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n").unwrap();
+        let mut daemon = Daemon::new(config, Box::new(scanner));
+        daemon.run(true).unwrap();
+        assert!(server.join().unwrap().starts_with("GET /ping/lan HTTP/1.1"));
+    }
+
+    #[test]
+    fn test_recorded_notifications() {
+        let config = Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap();
+        let mut daemon = test_daemon(config);
+        let recorder = notify::Recorder::new();
+        daemon.add_notifier(None, Box::new(recorder.clone()));
+        daemon.feed_scan(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        for _ in 0..HISTORY_SIZE {
+            daemon.feed_scan(Vec::new());
+        }
+        // kept on reload
+        daemon.set_notifiers(Vec::new());
+        drop(daemon);
+        let messages = recorder.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].title, "Device Laptop disconnected");
+        assert!(
+            messages[0]
+                .body
+                .starts_with("Device Laptop with IP 192.168.1.2")
+        );
+    }
+
+    #[test]
+    fn test_coalesce_reconnects() {
+        let config = Config::parse(
+            "detection_mode = \"raw\"\ncoalesce_reconnects = true\n[devices]\n\
+             \"aa:bb:cc:dd:ee:01\" = \"Phone\"\n\"aa:bb:cc:dd:ee:02\" = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        // keeps the worker sending the first message
+        let stalled = notify::Stalled::default();
+        daemon.add_notifier(None, Box::new(stalled.clone()));
+        let recorder = notify::Recorder::new();
+        daemon.add_notifier(None, Box::new(recorder.clone()));
+        let phone = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let laptop = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        daemon.feed_scan(scan::parse_scan(&format!("{phone}{laptop}")));
+
+        let mut first = daemon.messages(&[Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02"))]);
+        first[0].title = "First".to_string();
+        first[0].transition = None;
+        daemon.notify(first.remove(0));
+        // the phone flaps before its disconnect is sent
+        daemon.feed_scan(scan::parse_scan(laptop));
+        daemon.feed_scan(scan::parse_scan(&format!("{phone}{laptop}")));
+        // the laptop really leaves
+        daemon.feed_scan(scan::parse_scan(phone));
+        stalled.release();
+        drop(daemon);
+        let titles: Vec<String> = recorder.messages().into_iter().map(|m| m.title).collect();
+        assert_eq!(titles, ["First", "Device Laptop disconnected"]);
+    }
+
+    #[test]
+    fn test_batch_notifications() {
+        let config = Config {
+            batch_notifications: true,
+            batch_threshold: 2,
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config);
+        let device = |i: u8| Device {
+            mac: MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}")),
+            ip: Ipv4Addr::new(192, 168, 1, i).into(),
+            vendor: format!("Vendor {i}"),
+            hostname: None,
+            latency: None,
+            interface: None,
+            rssi: None,
+            node: None,
+            found_as: Vec::new(),
+        };
+        daemon.init_state((1..=4).map(device).collect());
+        let events = vec![
+            Event::Disconnected(device(1).mac),
+            Event::Disconnected(device(2).mac),
+            Event::Disconnected(device(3).mac),
+            Event::Connected(device(4).mac),
+        ];
+        let messages = daemon.messages(&events);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].title, "3 devices disconnected");
+        assert_eq!(messages[1].title, "Device Unknown Vendor 4 connected");
+        assert_eq!(
+            messages[0].body,
+            "3 devices disconnected: Unknown Vendor 1, Unknown Vendor 2, Unknown Vendor 3"
+        );
+    }
+
+    #[test]
+    fn test_state_persistence() {
+        let path = std::env::temp_dir().join(format!("lanotify-test-{}.json", std::process::id()));
+        let config = Config {
+            state_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config.clone());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let offline = scan::parse_scan("192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n")[0].clone();
+        daemon.state.insert(
+            offline.mac.clone(),
+            DeviceState::new(
+                offline,
+                false,
+                ScanHistory::from(vec![false; HISTORY_SIZE]),
+                Local::now(),
+            ),
+        );
+        daemon.save_state();
+
+        let mut restored = test_daemon(config);
+        assert!(restored.restore_state().unwrap());
+        fs::remove_file(&path).unwrap();
+        let state = &restored.state[&MacAddr::new("aa:bb:cc:dd:ee:01")];
+        assert_eq!(state.device.vendor, "Vendor A");
+        assert!(state.is_connected);
+        let state = &restored.state[&MacAddr::new("aa:bb:cc:dd:ee:02")];
+        assert!(!state.is_connected);
+        assert!(state.last_connect.is_none());
+        // a device that was offline at shutdown stays offline without notifications
+        let events = restored.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_state_file() {
+        let path =
+            std::env::temp_dir().join(format!("lanotify-corrupt-{}.json", std::process::id()));
+        let backup = path.with_extension("json.corrupt");
+        fs::write(&path, b"{\"aa:bb:cc:dd:ee:01\": {\"device\": \xff").unwrap();
+        let mut daemon = test_daemon(Config {
+            state_file: Some(path.clone()),
+            ..Default::default()
+        });
+        assert!(!daemon.restore_state().unwrap());
+        assert!(daemon.state.is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read(&backup).unwrap(),
+            b"{\"aa:bb:cc:dd:ee:01\": {\"device\": \xff"
+        );
+        fs::remove_file(&backup).unwrap();
+
+        // the backup can't be written, which doesn't keep it from starting
+        fs::write(&path, b"{").unwrap();
+        fs::create_dir_all(backup.join("occupied")).unwrap();
+        assert!(!daemon.restore_state().unwrap());
+        assert!(daemon.state.is_empty());
+        fs::remove_dir_all(&backup).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let path =
+            std::env::temp_dir().join(format!("lanotify-catch-up-{}.json", std::process::id()));
+        let mut config = Config::parse(
+            "notify_catch_up = true\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n",
+        )
+        .unwrap();
+        config.state_file = Some(path.clone());
+        let laptop = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let phone = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let mut daemon = test_daemon(config.clone());
+        daemon.init_state(scan::parse_scan(laptop));
+        let offline = scan::parse_scan(phone)[0].clone();
+        daemon.state.insert(
+            offline.mac.clone(),
+            DeviceState::new(
+                offline,
+                false,
+                ScanHistory::from(vec![false; HISTORY_SIZE]),
+                Local::now(),
+            ),
+        );
+        let mut held = daemon.message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01")));
+        held.as_mut().unwrap().title = "Held back".to_string();
+        daemon.deferred.extend(held);
+        daemon.save_state();
+
+        let mut restored = test_daemon(config);
+        assert!(restored.restore_state().unwrap());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(sidecar_path(&path, "pending")).unwrap();
+        let since = restored.down_since.unwrap();
+        let message = restored
+            .catch_up(&scan::parse_scan(phone), Local::now())
+            .unwrap();
+        assert_eq!(message.title, "While lanotify was down");
+        assert!(
+            message.body.ends_with(
+                ": Laptop disconnected, Phone connected. Not sent before the restart: Held back."
+            ),
+            "{}",
+            message.body
+        );
+        assert_eq!(restored.recent_events.iter().count(), 2);
+        // the changes are not notified again by the scans after
+        for _ in 0..HISTORY_SIZE {
+            assert!(restored.update_state(scan::parse_scan(phone)).is_empty());
+        }
+        assert!(!restored.state[&MacAddr::new("aa:bb:cc:dd:ee:01")].is_connected);
+        assert!(restored.state[&MacAddr::new("aa:bb:cc:dd:ee:02")].is_connected);
+        // nothing to catch up on
+        restored.down_since = Some(since);
+        assert!(
+            restored
+                .catch_up(&scan::parse_scan(phone), Local::now())
+                .is_none()
+        );
+        assert!(restored.down_since.is_none());
+
+        // a scan without the gateway doesn't mean the devices left
+        restored.config.gateway = Some(Ipv4Addr::new(192, 168, 1, 1));
+        restored.down_since = Some(since);
+        assert!(restored.catch_up(&[], Local::now()).is_none());
+        assert!(restored.state[&MacAddr::new("aa:bb:cc:dd:ee:02")].is_connected);
+        assert_eq!(restored.down_since, Some(since));
+        let gateway = "192.168.1.1\taa:bb:cc:dd:ee:ff\tRouter\n";
+        let message = restored
+            .catch_up(&scan::parse_scan(gateway), Local::now())
+            .unwrap();
+        assert!(message.body.ends_with(": Phone disconnected."));
+    }
+
+    #[test]
+    fn test_new_vendor() {
+        let mut daemon = test_daemon(Config::parse("notify_new_vendor = true").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let new_vendor = |events: &[Event]| {
+            events
+                .iter()
+                .filter(|e| matches!(e, Event::NewVendor(_)))
+                .count()
+        };
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor A\n\
+             192.168.1.4\t12:34:56:78:9a:01\t(Unknown: locally administered)\n",
+        ));
+        assert_eq!(new_vendor(&events), 0);
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.5\t00:11:22:33:44:01\tVendor B\n",
+        ));
+        assert!(
+            matches!(&events[..], [Event::NewVendor(m), ..] if *m == MacAddr::new("00:11:22:33:44:01"))
+        );
+        let message = daemon.message(&events[0]).unwrap();
+        assert_eq!(message.title, "New manufacturer Vendor B on the network");
+        assert!(message.body.contains("(OUI 00:11:22)"));
+        // only the first device of a manufacturer
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.6\t00:11:22:33:44:02\tVendor B\n",
+        ));
+        assert_eq!(new_vendor(&events), 0);
+    }
+
+    #[test]
+    fn test_flapping() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let mut daemon = test_daemon(
+            Config::parse(
+                "detection_mode = \"simple\"\noffline_after_misses = 1\nonline_after_hits = 1\n\
+                 flap_threshold = 3\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(a));
+        let mut events = Vec::new();
+        for i in 0..6 {
+            let scan = if i % 2 == 0 { "" } else { a };
+            events.extend(daemon.update_state(scan::parse_scan(scan)));
+        }
+        assert!(matches!(
+            &events[..],
+            [
+                Event::Disconnected(_),
+                Event::Connected(_),
+                Event::Unstable { changes: 3, .. }
+            ]
+        ));
+        let message = daemon.message(&events[2]).unwrap();
+        assert_eq!(message.title, "Device Laptop is unstable");
+
+        // stable once the window has passed without changes
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        let state = daemon
+            .state
+            .get_mut(&MacAddr::new("aa:bb:cc:dd:ee:01"))
+            .unwrap();
+        for change in &mut state.changes {
+            *change -= chrono::TimeDelta::hours(2);
+        }
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert!(matches!(&events[..], [Event::Stabilized(_)]));
+        let message = daemon.message(&events[0]).unwrap();
+        assert!(message.body.ends_with("is stable again and connected"));
+    }
+
+    #[test]
+    fn test_min_samples_before_notify() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let config = Config {
+            min_samples_before_notify: 3,
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config);
+        daemon.init_state(Vec::new());
+
+        for _ in 0..3 {
+            let events = daemon.update_state(scan::parse_scan(a));
+            assert!(events.is_empty());
+        }
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Event::Connected(_)));
+    }
+
+    #[test]
+    fn test_new_device_settle() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let mut daemon = test_daemon(Config::parse("new_device_settle = 3").unwrap());
+        daemon.init_state(Vec::new());
+
+        // b passes by for a single scan
+        assert!(
+            daemon
+                .update_state(scan::parse_scan(&format!("{a}{b}")))
+                .is_empty()
+        );
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert!(matches!(&events[..], [Event::Connected(mac)] if mac.0 == "aa:bb:cc:dd:ee:01"));
+        let events: Vec<Event> = (0..HISTORY_SIZE)
+            .flat_map(|_| daemon.update_state(scan::parse_scan(a)))
+            .collect();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_new_device_min_uptime() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut daemon = test_daemon(Config::parse("new_device_min_uptime = 600").unwrap());
+        daemon.init_state(Vec::new());
+        let go_back = |daemon: &mut Daemon| {
+            let state = daemon.state.get_mut(&mac).unwrap();
+            state.present_since = state
+                .present_since
+                .map(|t| t - chrono::TimeDelta::minutes(6));
+        };
+
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        go_back(&mut daemon);
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        // a missed scan starts the uptime over
+        assert!(daemon.update_state(Vec::new()).is_empty());
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        go_back(&mut daemon);
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        go_back(&mut daemon);
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert!(matches!(&events[..], [Event::Connected(_)]));
+    }
+
+    #[test]
+    fn test_warmup_unknown() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let config = Config::parse("warmup = \"unknown\"").unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(Vec::new());
+
+        // a device that is only seen once is never notified about
+        let mut events = daemon.update_state(scan::parse_scan(&format!("{a}{b}")));
+        let state = &daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:01")];
+        assert_eq!(
+            Column::Status
+                .cell(state, &daemon.config, Local::now())
+                .text(),
+            "❔"
+        );
+        for _ in 0..OFFLINE_THRESHOLD * 2 {
+            events.extend(daemon.update_state(scan::parse_scan(a)));
+        }
+        assert!(matches!(&events[..], [Event::Connected(mac)] if mac.0 == "aa:bb:cc:dd:ee:01"));
+        let state = &daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:01")];
+        assert_eq!(
+            Column::Status
+                .cell(state, &daemon.config, Local::now())
+                .text(),
+            "✅"
+        );
+        assert!(!daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:02")].is_connected);
+    }
+
+    #[test]
+    fn test_device_config() {
+        let config = Config::parse(
+            r#"
+            [devices]
+            "aa:bb:cc:dd:ee:01" = "Laptop"
+
+            [[device]]
+            mac = "aa:bb:cc:dd:ee:02"
+            name = "Tablet"
+            icon = "📱"
+            priority = "low"
+            ntfy_url = "http://ntfy/family"
+            "#,
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor\n",
+        ));
+        let laptop = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01")))
+            .unwrap();
+        assert_eq!(laptop.title, "Device Laptop connected");
+        assert_eq!(laptop.ntfy_url, None);
+        let tablet = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02")))
+            .unwrap();
+        assert_eq!(tablet.ntfy_url.as_deref(), Some("http://ntfy/family"));
+        assert_eq!(tablet.title, "Device 📱 Tablet connected");
+        assert_eq!(tablet.priority, Priority::Low);
+
+        let duplicate = r#"
+            devices = { "aa:bb:cc:dd:ee:01" = "Laptop" }
+            [[device]]
+            mac = "aa:bb:cc:dd:ee:01"
+            name = "Tablet"
+            "#;
+        assert!(Config::parse(duplicate).is_err());
+        assert!(Config::parse("[[device]]\nname = \"No MAC\"").is_err());
+    }
+
+    #[test]
+    fn test_raw_detection() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut daemon = test_daemon(Config::parse("detection_mode = \"raw\"").unwrap());
+        daemon.init_state(scan::parse_scan(a));
+        let events = daemon.update_state(Vec::new());
+        assert!(matches!(&events[..], [Event::Disconnected(_)]));
+        assert!(!daemon.state[&mac].is_connected);
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert!(matches!(&events[..], [Event::Connected(_)]));
+        assert_eq!(daemon.state[&mac].ping_history.len(), 2);
+    }
+
+    #[test]
+    fn test_window_detection() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut daemon =
+            test_daemon(Config::parse("detection_mode = \"window\"\npresence_window = 3").unwrap());
+        daemon.init_state(scan::parse_scan(a));
+        for _ in 0..2 {
+            assert!(daemon.update_state(Vec::new()).is_empty());
+        }
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+        for _ in 0..2 {
+            assert!(daemon.update_state(Vec::new()).is_empty());
+        }
+        let events = daemon.update_state(Vec::new());
+        assert!(matches!(&events[..], [Event::Disconnected(_)]));
+        let events = daemon.update_state(scan::parse_scan(a));
+        assert!(matches!(&events[..], [Event::Connected(_)]));
+        assert!(daemon.state[&mac].is_connected);
+    }
+
+    #[test]
+    fn test_simple_detection_full_history() {
+        // the most misses that can be configured still disconnect
+        let config = Config::parse(&format!(
+            "detection_mode = \"simple\"\noffline_after_misses = {HISTORY_SIZE}\n"
+        ))
+        .unwrap();
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut samples = vec![true; HISTORY_SIZE];
+        samples[..HISTORY_SIZE - 1].fill(false);
+        assert!(config.is_connected(&mac, &ScanHistory::from(samples), true));
+        let history = ScanHistory::from(vec![false; HISTORY_SIZE]);
+        assert!(!config.is_connected(&mac, &history, true));
+    }
+
+    #[test]
+    fn test_device_detection_overrides() {
+        let config = Config::parse(
+            "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Server\"\nrecent_window = 1\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Tablet\"\n\
+             detection_mode = \"simple\"\n",
+        )
+        .unwrap();
+        let server = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let other = MacAddr::new("aa:bb:cc:dd:ee:03");
+        // an always-on device that missed the last two scans
+        let mut samples = vec![true; HISTORY_SIZE];
+        samples[..2].fill(false);
+        let history = ScanHistory::from(samples);
+        assert!(!config.is_connected(&server, &history, true));
+        assert!(config.is_connected(&other, &history, true));
+
+        let tablet = MacAddr::new("aa:bb:cc:dd:ee:02");
+        assert_eq!(config.detection_mode(&tablet), DetectionMode::Simple);
+        assert!(config.activity(&tablet, &history).is_none());
+        assert!(config.activity(&other, &history).is_some());
+    }
+
+    #[test]
+    fn test_describe_activity() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "describe_activity = true\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Server\"\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let server = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let phone = MacAddr::new("aa:bb:cc:dd:ee:02");
+        let mut history = vec![true; HISTORY_SIZE];
+        history[..RECENT_WINDOW + 1].fill(false);
+        daemon.state.get_mut(&server).unwrap().ping_history = ScanHistory::from(history);
+        let mut history = vec![false; HISTORY_SIZE];
+        history[RECENT_WINDOW + 1] = true;
+        daemon.state.get_mut(&phone).unwrap().ping_history = ScanHistory::from(history);
+
+        let message = daemon.connection_message(&server, false).unwrap();
+        assert_eq!(message.title, "Always-on device Server went offline");
+        assert!(message.body.ends_with("\nSeen in 80% of the recent scans"));
+        let message = daemon.connection_message(&phone, false).unwrap();
+        assert_eq!(message.title, "Sleeping device Phone went quiet");
+        // connections keep the usual title
+        let message = daemon.connection_message(&phone, true).unwrap();
+        assert_eq!(message.title, "Device Phone connected");
+
+        daemon.config.language = i18n::Language::De;
+        daemon.config.messages = toml::from_str("always_on_title = \"{name} ist weg\"").unwrap();
+        let message = daemon.connection_message(&phone, false).unwrap();
+        assert_eq!(message.title, "Schlafendes Gerät Phone ist verstummt");
+        let message = daemon.connection_message(&server, false).unwrap();
+        assert_eq!(message.title, "Server ist weg");
+    }
+
+    #[test]
+    fn test_quiet_devices() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "detection_mode = \"raw\"\nquiet_base_rate = 0.3\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Server\"\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n",
+            )
+            .unwrap(),
+        );
+        let both = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                    192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        daemon.init_state(scan::parse_scan(both));
+        let server = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let phone = MacAddr::new("aa:bb:cc:dd:ee:02");
+        daemon.state.get_mut(&server).unwrap().ping_history =
+            ScanHistory::from(vec![true; HISTORY_SIZE]);
+        let mut history = vec![false; HISTORY_SIZE];
+        history[0] = true;
+        daemon.state.get_mut(&phone).unwrap().ping_history = ScanHistory::from(history);
+
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        assert!(!daemon.state[&phone].is_connected);
+        assert!(events.is_empty());
+        let events = daemon.update_state(scan::parse_scan(both));
+        assert!(daemon.state[&phone].is_connected);
+        assert!(events.is_empty());
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        assert!(matches!(&events[..], [Event::Disconnected(mac)] if mac == &server));
+
+        daemon.config.quiet_action = QuietAction::Low;
+        let message = daemon.connection_message(&phone, true).unwrap();
+        assert_eq!(message.priority, Priority::Low);
+        let message = daemon.connection_message(&server, true).unwrap();
+        assert_eq!(message.priority, Priority::Default);
+    }
+
+    #[test]
+    fn test_escalation() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "[[notifier]]\ntype = \"ntfy\"\nurl = \"http://ntfy/a\"\n\
+                 [[notifier]]\ntype = \"ntfy\"\nname = \"pager\"\nurl = \"http://pager\"\n\
+                 [[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Server\"\n\
+                 escalation = [\n\
+                   { after = 600, priority = \"high\" },\n\
+                   { after = 3600, notifier = \"pager\", priority = \"urgent\" },\n\
+                 ]\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let ago = |minutes| Local::now() - chrono::TimeDelta::minutes(minutes);
+        let escalations = |daemon: &mut Daemon| {
+            daemon
+                .update_state(Vec::new())
+                .into_iter()
+                .filter(|e| matches!(e, Event::Escalation { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        daemon
+            .state
+            .get_mut(&mac)
+            .unwrap()
+            .set_connected(false, ago(5));
+        assert!(escalations(&mut daemon).is_empty());
+        daemon.state.get_mut(&mac).unwrap().last_disconnect = Some(ago(20));
+        let events = escalations(&mut daemon);
+        assert!(matches!(events[..], [Event::Escalation { step: 0, .. }]));
+        let message = daemon.message(&events[0]).unwrap();
+        assert_eq!(message.priority, Priority::High);
+        assert_eq!(message.notifier, None);
+        assert!(escalations(&mut daemon).is_empty());
+
+        daemon.state.get_mut(&mac).unwrap().last_disconnect = Some(ago(120));
+        let events = escalations(&mut daemon);
+        let message = daemon.message(&events[0]).unwrap();
+        assert_eq!(message.title, "Device Server is still disconnected");
+        assert_eq!(message.priority, Priority::Urgent);
+        assert_eq!(message.notifier.as_deref(), Some("pager"));
+
+        // reconnecting resets the chain
+        daemon
+            .state
+            .get_mut(&mac)
+            .unwrap()
+            .set_connected(true, ago(0));
+        daemon.update_state(Vec::new());
+        assert_eq!(daemon.state[&mac].escalation_step, 0);
+
+        assert!(
+            Config::parse(
+                "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Server\"\n\
+                 escalation = [{ after = 600, notifier = \"pager\" }]\n",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reminder_interval() {
+        let config = Config::parse(
+            "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Server\"\nreminder_interval = 600\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Alarm\"\npriority = \"urgent\"\n\
+             reminder_interval = 300\n",
+        )
+        .unwrap();
+        let steps = |i| {
+            let mac = MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+            config.devices[&mac]
+                .escalation
+                .iter()
+                .map(|step| (step.after.as_secs(), step.priority))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(steps(1), [(600, Priority::High), (1200, Priority::Urgent)]);
+        assert_eq!(steps(2), [(300, Priority::Urgent)]);
+
+        assert!(
+            Config::parse(
+                "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Server\"\n\
+                 reminder_interval = 600\nescalation = [{ after = 600 }]\n",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tiers() {
+        let config = Config::parse(
+            "[tiers.security]\npriority = \"urgent\"\ntags = [\"warning\"]\n\
+             ntfy_url = \"https://ntfy.sh/security\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Door\"\ntier = \"security\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Camera\"\ntier = \"security\"\n\
+             tags = [\"camera\"]\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let message = |i| {
+            let mac = MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+            daemon.message(&Event::Disconnected(mac)).unwrap()
+        };
+        let door = message(1);
+        assert_eq!(door.priority, Priority::Urgent);
+        assert_eq!(door.tags, ["warning"]);
+        assert_eq!(door.ntfy_url.as_deref(), Some("https://ntfy.sh/security"));
+        // settings of the device take precedence
+        let camera = message(2);
+        assert_eq!(camera.priority, Priority::Urgent);
+        assert_eq!(camera.tags, ["camera"]);
+
+        assert!(
+            Config::parse(
+                "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Door\"\ntier = \"missing\"\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_device_tags() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Doorbell\"\n\
+                 tags = [\"bell\"]\npriority = \"high\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let tags = |event| daemon.message(&event).unwrap().tags;
+        assert_eq!(
+            tags(Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01"))),
+            ["bell"]
+        );
+        assert_eq!(
+            tags(Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02"))),
+            ["green_circle"]
+        );
+        assert_eq!(
+            tags(Event::Disconnected(MacAddr::new("aa:bb:cc:dd:ee:02"))),
+            ["red_circle"]
+        );
+    }
+
+    #[test]
+    fn test_mute_patterns() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "mute_patterns = [\"^living-room-.*\"]\n\
+                 [devices]\n\
+                 \"aa:bb:cc:dd:ee:01\" = \"living-room-tv\"\n\
+                 \"aa:bb:cc:dd:ee:02\" = \"office-printer\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let tv = MacAddr::new("aa:bb:cc:dd:ee:01");
+        assert!(daemon.message(&Event::Connected(tv.clone())).is_none());
+        assert!(daemon.message(&Event::Degrading(tv)).is_none());
+        let printer = MacAddr::new("aa:bb:cc:dd:ee:02");
+        assert!(daemon.message(&Event::Connected(printer)).is_some());
+
+        assert!(Config::parse("mute_patterns = [\"living-room-(\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let err = |config| format!("{:#}", Config::parse(config).unwrap_err());
+        assert_eq!(
+            err("[devices]\n\"aa:bb:cc:dd:ee\" = \"Laptop\"\n"),
+            "Failed to parse config file as TOML: Invalid MAC address 'aa:bb:cc:dd:ee'\nin `devices`\n"
+        );
+        assert!(err("presence_group = [\"phone\"]\n").contains("Invalid MAC address 'phone'"));
+        assert_eq!(
+            err("ntfy_url = \"localhost:8080\"\n"),
+            "Invalid `ntfy_url` 'localhost:8080', expected an http(s) URL"
+        );
+        assert!(
+            err("healthcheck_url = \"http://exa mple.com\"\n")
+                .starts_with("Invalid `healthcheck_url`")
+        );
+        assert_eq!(
+            err("mqtt_host = \"broker\"\nmqtt_password = \"secret\"\n"),
+            "`mqtt_password` requires `mqtt_username`"
+        );
+        assert_eq!(
+            err("scan_interval = 0\n"),
+            "`scan_interval` has to be positive"
+        );
+        assert_eq!(
+            err("min_devices = 5\nmax_devices = 3\n"),
+            "`min_devices` (5) is above `max_devices` (3)"
+        );
+        assert_eq!(
+            err("scan_failure_threshold = 1.5\n"),
+            "`scan_failure_threshold` (1.5) has to be between 0 and 1"
+        );
+        assert_eq!(
+            err("offline_after_misses = 0\n"),
+            "`offline_after_misses` has to be at least 1"
+        );
+        assert_eq!(
+            err("presence_window = 31\n"),
+            "`presence_window` (31) can't be above the 30 scans kept per device"
+        );
+        assert!(Config::parse("presence_window = 30\n").is_ok());
+        assert_eq!(
+            err("online_after_hits = 31\n"),
+            "`online_after_hits` (31) can't be above the 30 scans kept per device"
+        );
+        assert_eq!(
+            err("[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Laptop\"\noffline_threshold = 0\n"),
+            "Invalid device 'Laptop': `offline_threshold` has to be at least 1"
+        );
+        assert_eq!(
+            err("[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Laptop\"\nrecent_window = 50\n"),
+            "Invalid device 'Laptop': `recent_window` (50) can't be above the 30 scans kept per device"
+        );
+        assert_eq!(
+            err("notify_rate_limit = 0\n"),
+            "`notify_rate_limit` has to be at least 1"
+        );
+        assert!(
+            err("[[notifier]]\ntype = \"ntfy\"\nurl = \"ntfy.sh/lan\"\n")
+                .starts_with("Invalid notifier")
+        );
+        assert_eq!(
+            err(
+                "[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Laptop\"\non_connect_url = \"/arrived\"\n"
+            ),
+            "Invalid device 'Laptop': Invalid `on_connect_url` '/arrived', expected an http(s) URL"
+        );
+        assert_eq!(
+            err("investigate_url = \"router/clients?mac={mac}\"\n"),
+            "Invalid `investigate_url` 'router/clients?mac={mac}', expected an http(s) URL"
+        );
+        assert!(
+            Config::parse(
+                "investigate_url = \"http://router/clients?mac={mac}&ip={ip}\"\n\
+                 [devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Laptop\"\n\
+                 on_connect_url = \"http://{ip}:8080/arrived\"\n"
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            err("offline_after_misses = 40\n"),
+            "`offline_after_misses` (40) can't be above the 30 scans kept per device"
+        );
+        assert!(Config::parse("ntfy_url = \"https://ntfy.sh/lanotify\"\n").is_ok());
+    }
+
+    #[test]
+    fn test_reload_notifiers() {
+        let path = std::env::temp_dir().join(format!("lanotify-test-{}.toml", std::process::id()));
+        let ntfy = |url: &str| {
+            NotifierSource::Config(NotifierConfig::Ntfy {
+                name: None,
+                url: url.to_string(),
+                ca_cert: None,
+                insecure_skip_verify: false,
+            })
+        };
+
+        // falls back to ntfy_url without any [[notifier]] entries
+        fs::write(&path, "ntfy_url = \"http://ntfy/a\"\n").unwrap();
+        let mut daemon = test_daemon(Config::load(&path).unwrap());
+        daemon.config_path = Some(path.clone());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let configs = |d: &Daemon| {
+            d.notifiers
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(c, _)| c.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(configs(&daemon), vec![ntfy("http://ntfy/a")]);
+
+        fs::write(
+            &path,
+            "[[notifier]]\ntype = \"ntfy\"\nurl = \"http://ntfy/a\"\n\n\
+             [[notifier]]\ntype = \"ntfy\"\nurl = \"http://ntfy/b\"\n",
+        )
+        .unwrap();
+        daemon.reload().unwrap();
+        assert_eq!(
+            configs(&daemon),
+            vec![ntfy("http://ntfy/a"), ntfy("http://ntfy/b")]
+        );
+
+        fs::write(
+            &path,
+            "[[notifier]]\ntype = \"ntfy\"\nurl = \"http://ntfy/b\"\n",
+        )
+        .unwrap();
+        daemon.reload().unwrap();
+        assert_eq!(configs(&daemon), vec![ntfy("http://ntfy/b")]);
+        assert!(daemon.config.mute_patterns.is_empty());
+
+        fs::write(&path, "mute_patterns = [\"Vendor\"]\n").unwrap();
+        daemon.reload().unwrap();
+        assert_eq!(daemon.config.mute_patterns.len(), 1);
+        // the scan state is kept
+        assert_eq!(daemon.state.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_removed_devices() {
+        let path =
+            std::env::temp_dir().join(format!("lanotify-removed-{}.toml", std::process::id()));
+        let devices =
+            "[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n";
+        let laptop = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let phone = MacAddr::new("aa:bb:cc:dd:ee:02");
+        let reload = |removed: &str| {
+            fs::write(&path, devices).unwrap();
+            let mut daemon = test_daemon(Config::load(&path).unwrap());
+            daemon.config_path = Some(path.clone());
+            daemon.init_state(scan::parse_scan(
+                "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                 192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+            ));
+            fs::write(
+                &path,
+                format!(
+                    "removed_devices = \"{removed}\"\n\
+                     [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Work laptop\"\n"
+                ),
+            )
+            .unwrap();
+            let events = daemon.reload().unwrap();
+            (daemon, events)
+        };
+
+        let (daemon, events) = reload("keep");
+        assert!(events.is_empty());
+        assert_eq!(daemon.config.name(&laptop).unwrap(), "Work laptop");
+        assert_eq!(daemon.config.name(&phone).unwrap(), "Phone");
+
+        let (daemon, events) = reload("drop");
+        assert!(events.is_empty());
+        assert!(daemon.config.name(&phone).is_none());
+        assert!(!daemon.state.contains_key(&phone));
+
+        let (daemon, events) = reload("unknown");
+        assert!(matches!(&events[..], [Event::Connected(m)] if m == &phone));
+        assert!(daemon.config.name(&phone).is_none());
+        assert!(daemon.state.contains_key(&phone));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_detection_column() {
+        let config = Config::default();
+        let mut state = DeviceState::new(
+            scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n")[0].clone(),
+            true,
+            ScanHistory::new(),
+            Local::now(),
+        );
+        assert_eq!(
+            Column::Detection.cell(&state, &config, Local::now()).text(),
+            "-"
+        );
+        state.ping_history = ScanHistory::from(vec![true; HISTORY_SIZE]);
+        assert_eq!(
+            Column::Detection.cell(&state, &config, Local::now()).text(),
+            "ALW 1.00"
+        );
+        let mut history = vec![false; HISTORY_SIZE];
+        history[..15].fill(true);
+        state.ping_history = ScanHistory::from(history);
+        assert_eq!(
+            Column::Detection.cell(&state, &config, Local::now()).text(),
+            "INT 0.50"
+        );
+        state.ping_history = ScanHistory::from(vec![false; HISTORY_SIZE]);
+        assert_eq!(
+            Column::Detection.cell(&state, &config, Local::now()).text(),
+            "SLP 0.00"
+        );
+    }
+
+    #[test]
+    fn test_degrading() {
+        let mut history = ScanHistory::new();
+        for _ in 0..LONG_BUCKET_SCANS {
+            history.update(true);
+        }
+        assert!(!history.is_degrading());
+        // an occasional miss is fine
+        history.update(false);
+        assert!(!history.is_degrading());
+        for i in 0..HISTORY_SIZE {
+            history.update(i % 3 != 0);
+        }
+        assert!(history.is_degrading());
+
+        // devices that are often away aren't steady to begin with
+        let mut history = ScanHistory::new();
+        for i in 0..LONG_BUCKET_SCANS {
+            history.update(i % 2 == 0);
+        }
+        assert!(!history.is_degrading());
+    }
+
+    #[test]
+    fn test_offline_budget() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "detection_mode = \"raw\"\n\
+                 [devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"NAS\"\noffline_budget = 600\n\
+                 [devices.\"aa:bb:cc:dd:ee:02\"]\nname = \"Phone\"\n",
+            )
+            .unwrap(),
+        );
+        let noon = Local::now()
+            .date_naive()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        let clock = MockClock::new(noon);
+        daemon.clock = Box::new(clock.clone());
+        let both = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                    192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let phone = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        daemon.init_state(scan::parse_scan(both));
+        let scan = |daemon: &mut Daemon, scan: &str, minutes| {
+            clock.advance(chrono::TimeDelta::minutes(minutes));
+            daemon
+                .update_state(scan::parse_scan(scan))
+                .into_iter()
+                .filter(|e| matches!(e, Event::OfflineBudget(_)))
+                .count()
+        };
+
+        // offline for 5 minutes, then for 5 more later on
+        assert_eq!(scan(&mut daemon, phone, 0), 0);
+        assert_eq!(scan(&mut daemon, both, 5), 0);
+        assert_eq!(scan(&mut daemon, phone, 60), 0);
+        assert_eq!(scan(&mut daemon, phone, 5), 0);
+        // over the budget, notified once
+        assert_eq!(scan(&mut daemon, phone, 1), 1);
+        assert_eq!(scan(&mut daemon, phone, 10), 0);
+        let nas = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let message = daemon.message(&Event::OfflineBudget(nas.clone())).unwrap();
+        assert_eq!(message.title, "Device NAS offline for 21m0s today");
+        assert_eq!(
+            message.body,
+            "Device NAS was offline for 21m0s today, over its budget of 10m0s"
+        );
+        // still offline the next day, counted from midnight
+        assert_eq!(scan(&mut daemon, phone, 60 * 24), 1);
+        // not the offline time of yesterday
+        let offline = daemon.state[&nas].offline_today(clock.now());
+        assert!(
+            offline > Duration::from_secs(12 * 3600) && offline < Duration::from_secs(15 * 3600)
+        );
+    }
+
+    #[test]
+    fn test_staleness_warn() {
+        let config =
+            Config::parse("staleness_warn = 300\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Phone\"\n")
+                .unwrap();
+        let mut daemon = test_daemon(config);
+        let scan = scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n");
+        daemon.init_state(scan.clone());
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        daemon.state.get_mut(&mac).unwrap().ping_history =
+            ScanHistory::from(vec![true; HISTORY_SIZE]);
+        assert!(daemon.update_state(Vec::new()).is_empty());
+
+        // the detection still considers it connected
+        daemon.state.get_mut(&mac).unwrap().last_seen -= chrono::TimeDelta::minutes(6);
+        let events = daemon.update_state(Vec::new());
+        assert!(matches!(&events[..], [Event::Stale(m)] if *m == mac));
+        assert!(daemon.state[&mac].is_connected);
+        let message = daemon.message(&events[0]).unwrap();
+        assert!(message.title.starts_with("Device Phone not seen for 6m"));
+        assert!(daemon.update_state(Vec::new()).is_empty());
+
+        // warns again only after it was seen in between
+        assert!(daemon.update_state(scan).is_empty());
+        assert!(!daemon.state[&mac].stale);
+    }
+
+    #[test]
+    fn test_weighted_base_rate() {
+        // flaky in the past, steady now
+        let mut history = ScanHistory::new();
+        for i in 0..HISTORY_SIZE {
+            history.update(i % 3 == 0);
+        }
+        let mut weighted = Vec::new();
+        let mut flat = Vec::new();
+        for _ in 0..RECENT_WINDOW {
+            history.update(true);
+            weighted.push(history.weighted_base_rate(0.8));
+            flat.push(history.base_rate());
+        }
+        // the weighted rate reaches the always-on class much sooner
+        assert!(weighted[RECENT_WINDOW - 1] > 0.7);
+        assert!(flat[RECENT_WINDOW - 1] <= 0.7);
+        assert!(weighted.iter().zip(&flat).all(|(w, f)| w > f));
+        // without decay both are equal
+        assert!((history.weighted_base_rate(1.0) - history.base_rate()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_confidence() {
+        let mut history = ScanHistory::new();
+        assert_eq!(history.confidence(), 1.0);
+        for _ in 0..HISTORY_SIZE {
+            history.update(true);
+        }
+        assert!((history.confidence() - 1.0).abs() < 1e-9);
+        // recent misses weigh more than the share of misses alone
+        history.update(false);
+        history.update(false);
+        let confidence = history.confidence();
+        assert!(confidence < history.base_rate() && confidence > 0.5);
+        for _ in 0..HISTORY_SIZE {
+            history.update(false);
+        }
+        assert!(history.confidence().abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_packed_history() {
+        let mut plain: History<bool, HISTORY_SIZE> = History::new();
+        let mut packed: PackedHistory<HISTORY_SIZE> = PackedHistory::new();
+        let (mut plain_connected, mut packed_connected) = (false, false);
+        for i in 0..(HISTORY_SIZE * 3) {
+            // steady, then intermittent, then gone
+            let seen = i < HISTORY_SIZE || (i < HISTORY_SIZE * 2 && i % 3 == 0);
+            plain.push(seen);
+            packed.push(seen);
+            plain_connected = plain.is_connected(plain_connected);
+            packed_connected = packed.is_connected(packed_connected);
+            assert_eq!(plain_connected, packed_connected, "scan {i}");
+            assert_eq!(
+                plain.is_connected_simple(true, 3, 2),
+                packed.is_connected_simple(true, 3, 2)
+            );
+            assert_eq!(plain.base_rate(), packed.base_rate());
+            assert_eq!(
+                plain.weighted_base_rate(0.8),
+                packed.weighted_base_rate(0.8)
+            );
+            assert_eq!(plain.to_string(), packed.to_string());
+            assert_eq!(
+                serde_json::to_string(&plain).unwrap(),
+                serde_json::to_string(&packed).unwrap()
+            );
+        }
+        assert!(!packed_connected);
+    }
+
+    #[test]
+    fn test_long_history() {
+        let mut history = ScanHistory::new();
+        assert_eq!(history.availability(), None);
+        for i in 0..(LONG_BUCKET_SCANS * 2) {
+            history.update(i < LONG_BUCKET_SCANS);
+        }
+        history.update(true);
+        assert_eq!(history.long.len(), 3);
+        assert_eq!(history.log.len(), HISTORY_SIZE);
+        let availability = history.availability().unwrap();
+        assert!((availability - 0.5).abs() < 0.01);
+        assert!(format!("{history:#}").starts_with("O-O......"));
+
+        for _ in 0..(LONG_BUCKET_SCANS as usize * LONG_HISTORY_BUCKETS) {
+            history.update(false);
+        }
+        assert_eq!(history.long.len(), LONG_HISTORY_BUCKETS);
+        assert_eq!(history.availability(), Some(0.0));
+    }
+
+    #[test]
+    fn test_spaced_history() {
+        let mut history = ScanHistory::new();
+        let start = Local::now();
+        let at = |secs| Some(start + chrono::TimeDelta::seconds(secs));
+        // evenly spaced before the times were recorded
+        history.update_at(true, None);
+        history.update_at(false, None);
+        history.update_at(true, at(0));
+        history.update_at(true, at(10));
+        // two scans were skipped
+        history.update_at(false, at(40));
+        history.update_at(true, at(49));
+        let interval = Duration::from_secs(10);
+        assert!(history.spaced(interval).starts_with("O-  OO-O....."));
+        assert_eq!(history.spaced(interval).len(), HISTORY_SIZE);
+        // without times it is the same as the plain sparkline
+        history.update_at(true, None);
+        assert_eq!(history.spaced(interval), history.to_string());
+        assert!(history.times.is_empty());
+    }
+
+    #[test]
+    fn test_connected_simple() {
+        let mut history = ScanHistory::new();
+        let mut is_connected = true;
+        for _ in 0..2 {
+            history.update(false);
+            is_connected = history.is_connected_simple(is_connected, 3, 2);
+            assert!(is_connected);
+        }
+        history.update(false);
+        is_connected = history.is_connected_simple(is_connected, 3, 2);
+        assert!(!is_connected);
+
+        history.update(true);
+        is_connected = history.is_connected_simple(is_connected, 3, 2);
+        assert!(!is_connected);
+        history.update(true);
+        is_connected = history.is_connected_simple(is_connected, 3, 2);
+        assert!(is_connected);
+    }
+
+    #[test]
+    fn test_window_presence() {
+        let mut window = Window::new();
+        assert_eq!(window.to_string(), ".".repeat(HISTORY_SIZE));
+        for seen in [false, true, true, true] {
+            window.push(seen);
+        }
+        assert_eq!(window.newest(), Some(&true));
+        assert_eq!(window.base_rate(), 0.75);
+        assert!(window.weighted_base_rate(0.5) > window.base_rate());
+        assert!(window.is_connected_simple(false, 3, 1));
+        assert_eq!(
+            window.to_string(),
+            format!("OOO-{}", ".".repeat(HISTORY_SIZE - 4))
+        );
+
+        // the scan history delegates to its detection window
+        let mut history = ScanHistory::new();
+        for seen in [false, true, true, true] {
+            history.update(seen);
+        }
+        assert_eq!(history.base_rate(), window.base_rate());
+        assert_eq!(history.to_string(), window.to_string());
+    }
+
+    #[test]
+    fn test_digest() {
+        let config = Config {
+            digest_time: Some(NaiveTime::from_hms_opt(20, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config);
+        daemon.last_digest = None;
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+
+        let at = |day: u32, hour: u32| {
+            NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        };
+        assert!(daemon.digest(at(1, 19)).is_none());
+        let message = daemon.digest(at(1, 20)).unwrap();
+        assert_eq!(message.body, "Devices online: 2, offline: 0, new today: 1.");
+        assert!(daemon.digest(at(1, 21)).is_none());
+        assert!(daemon.digest(at(2, 8)).is_none());
+        let message = daemon.digest(at(2, 20)).unwrap();
+        assert_eq!(message.body, "Devices online: 2, offline: 0, new today: 0.");
+    }
+
+    #[test]
+    fn test_startup_message() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "notify_on_startup = true\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Phone\"\n\"aa:bb:cc:dd:ee:02\" = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor A\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor B\n\
+             192.168.1.5\taa:bb:cc:dd:ee:04\tVendor B\n",
+        ));
+        let message = daemon.startup_message().unwrap();
+        assert_eq!(message.title, "Monitoring started: 4 devices online");
+        assert_eq!(
+            message.body,
+            "Known devices online: Laptop, Phone, and 2 unknown."
+        );
+
+        daemon.config.notify_on_startup = false;
+        assert!(daemon.startup_message().is_none());
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        let max = Duration::from_secs(2);
+        let samples: Vec<Duration> = (0..20).map(|_| jitter(max)).collect();
+        assert!(samples.iter().all(|j| *j <= max));
+        assert!(samples.iter().any(|j| *j != samples[0]));
+    }
+
+    #[test]
+    fn test_set_command() {
+        let mut daemon = test_daemon(Config::default());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let response = daemon.handle_command(Command::Set {
+            mac: mac.clone(),
+            online: false,
+        });
+        assert_eq!(response, "ok\n");
+        assert!(!daemon.state[&mac].is_connected);
+
+        let unknown = MacAddr::new("aa:bb:cc:dd:ee:02");
+        let response = daemon.handle_command(Command::Set {
+            mac: unknown,
+            online: true,
+        });
+        assert!(response.starts_with("Error"));
+    }
+
+    #[test]
+    fn test_rebaseline_command() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let mut daemon = test_daemon(Config::default());
+        daemon.init_state(scan::parse_scan(&format!("{a}{b}")));
+        for _ in 0..HISTORY_SIZE {
+            daemon.update_state(scan::parse_scan(&format!("{a}{b}")));
+        }
+        // gone after a network change, but not yet disconnected by adaptive detection
+        daemon.update_state(scan::parse_scan(a));
+        let b_mac = MacAddr::new("aa:bb:cc:dd:ee:02");
+        assert!(daemon.state[&b_mac].is_connected);
+
+        assert_eq!(
+            daemon.handle_command(Command::Rebaseline),
+            "Cleared the history of 2 devices, 1 changed state\n"
+        );
+        assert!(!daemon.state[&b_mac].is_connected);
+        assert!(daemon.state.values().all(|s| s.ping_history.len() == 0));
+        assert_eq!(daemon.state[&b_mac].ping_history.scans(), 0);
+        assert!(daemon.update_state(scan::parse_scan(a)).is_empty());
+    }
+
+    #[test]
+    fn test_pause_command() {
+        // the second message within a minute is dropped when notifications are not paused
+        let mut daemon = test_daemon(Config::parse("notify_rate_limit = 1").unwrap());
+        daemon.init_state(Vec::new());
+        let message = Message {
+            title: "Device connected".to_string(),
+            body: String::new(),
+            ..Default::default()
+        };
+        let response = daemon.handle_command(Command::Pause { duration: None });
+        assert_eq!(
+            response,
+            "Maintenance mode: notifications paused until resumed\n"
+        );
+        assert!(daemon.status_table(false)[0].starts_with("Maintenance mode"));
+        daemon.notify(message.clone());
+        daemon.notify(message.clone());
+        assert_eq!(daemon.rate_limited, 0);
+
+        assert_eq!(
+            daemon.handle_command(Command::Resume),
+            "Notifications resumed\n"
+        );
+        assert!(daemon.status_table(false).is_empty());
+        daemon.notify(message.clone());
+        daemon.notify(message);
+        assert_eq!(daemon.rate_limited, 1);
+
+        daemon.handle_command(Command::Pause {
+            duration: Some(Duration::from_secs(60)),
+        });
+        assert!(daemon.is_paused(Local::now()));
+        assert!(!daemon.is_paused(Local::now() + chrono::TimeDelta::minutes(2)));
+
+        // too long to be a point in time, which keeps the previous pause
+        let response = daemon.handle_command(Command::Pause {
+            duration: Some(Duration::from_secs(u64::MAX)),
+        });
+        assert_eq!(response, "Error: the pause is too long\n");
+        assert!(!daemon.is_paused(Local::now() + chrono::TimeDelta::minutes(2)));
+    }
+
+    #[test]
+    fn test_mute_command() {
+        let mut daemon =
+            test_daemon(Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Bulb\"\n").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let response = daemon.handle_command(Command::Mute {
+            mac: mac.clone(),
+            duration: None,
+        });
+        assert_eq!(response, "Muted aa:bb:cc:dd:ee:01 until unmuted\n");
+        assert!(daemon.message(&Event::Disconnected(mac.clone())).is_none());
+        assert!(
+            daemon
+                .device_table(&[Column::Name], None, TableGroupBy::None, false)
+                .iter()
+                .any(|line| line.contains("Bulb (muted)"))
+        );
+        // the state keeps being updated
+        for _ in 0..HISTORY_SIZE {
+            daemon.update_state(Vec::new());
+        }
+        assert!(!daemon.state[&mac].is_connected);
+
+        assert_eq!(
+            daemon.handle_command(Command::Unmute { mac: mac.clone() }),
+            "Unmuted aa:bb:cc:dd:ee:01\n"
+        );
+        assert!(daemon.message(&Event::Disconnected(mac.clone())).is_some());
+
+        daemon.handle_command(Command::Mute {
+            mac: mac.clone(),
+            duration: Some(Duration::from_secs(60)),
+        });
+        let state = &daemon.state[&mac];
+        assert!(state.is_muted(Local::now()));
+        assert!(!state.is_muted(Local::now() + chrono::TimeDelta::minutes(2)));
+        assert!(
+            daemon
+                .handle_command(Command::Unmute {
+                    mac: MacAddr::new("aa:bb:cc:dd:ee:09")
+                })
+                .starts_with("Error: unknown device")
+        );
+    }
+
+    #[test]
+    fn test_history_command() {
+        let mut daemon =
+            test_daemon(Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        daemon.state.get_mut(&mac).unwrap().ping_history =
+            ScanHistory::from(vec![true; HISTORY_SIZE]);
+
+        let response = daemon.handle_command(Command::History {
+            target: Some("laptop".to_string()),
+        });
+        assert_eq!(
+            response,
+            format!(
+                "Laptop  aa:bb:cc:dd:ee:01  {}  ALW 1.00\n",
+                "O".repeat(HISTORY_SIZE)
+            )
+        );
+        let response = daemon.handle_command(Command::History { target: None });
+        assert_eq!(response.lines().count(), 2);
+        let response = daemon.handle_command(Command::History {
+            target: Some("aa:bb:cc:dd:ee:03".to_string()),
+        });
+        assert!(response.starts_with("Error"));
+    }
+
+    #[test]
+    fn test_status_json_command() {
+        let mut daemon =
+            test_daemon(Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let response = daemon.handle_command(Command::Status { json: true });
+        let devices: Vec<federation::ApiDevice> = serde_json::from_str(&response).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name.as_deref(), Some("Laptop"));
+        assert!(devices[1].connected);
+        assert_eq!(
+            response,
+            serde_json::to_string_pretty(&daemon.api_devices()).unwrap() + "\n"
+        );
+    }
+
+    #[test]
+    fn test_events_command() {
+        let mut daemon = test_daemon(Config {
+            time_format: TimeFormat::Relative,
+            ..Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap()
+        });
+        daemon.init_state(Vec::new());
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        for _ in 0..HISTORY_SIZE {
+            daemon.update_state(scan::parse_scan(
+                "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+            ));
+        }
+        let response = daemon.handle_command(Command::Events { count: None });
+        let lines: Vec<&str> = response.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("disconnected  Laptop  aa:bb:cc:dd:ee:01"));
+        assert!(lines[0].starts_with("0s ago"));
+        let response = daemon.handle_command(Command::Events { count: Some(1) });
+        assert_eq!(response.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_targeted_scan() {
+        // answers for the targets that are on the network, records which were scanned
+        struct TargetScanner {
+            devices: Vec<Device>,
+            scans: Arc<Mutex<Vec<Option<Vec<IpAddr>>>>>,
+        }
+
+        impl Scanner for TargetScanner {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                self.scans.lock().unwrap().push(None);
+                Ok(Some(self.devices.clone()))
+            }
+
+            fn supports_targets(&self) -> bool {
+                true
+            }
+
+            fn scan_targets(&mut self, targets: &[IpAddr]) -> Result<Option<Vec<Device>>, Error> {
+                self.scans.lock().unwrap().push(Some(targets.to_vec()));
+                let devices = self.devices.iter().filter(|d| targets.contains(&d.ip));
+                Ok(Some(devices.cloned().collect()))
+            }
+        }
+
+        let scans = Arc::new(Mutex::new(Vec::new()));
+        let scanner = TargetScanner {
+            devices: scan::parse_scan(
+                "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                 192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+            ),
+            scans: scans.clone(),
+        };
+        let config = Config::parse(
+            "targeted_scan = true\nfull_scan_every = 3\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = Daemon::new(config, Box::new(scanner));
+        let devices = daemon.scan().unwrap().unwrap();
+        daemon.init_state(devices);
+        for _ in 0..3 {
+            let devices = daemon.scan().unwrap().unwrap();
+            assert!(daemon.update_state(devices).is_empty());
+        }
+        let target = Some(vec![IpAddr::from([192, 168, 1, 2])]);
+        assert_eq!(*scans.lock().unwrap(), [None, target.clone(), target, None]);
+        // the unknown device kept its state in the targeted scans
+        let unknown = &daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:02")];
+        assert!(unknown.is_connected);
+        assert_eq!(unknown.ping_history.len(), 1);
+        assert_eq!(
+            daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:01")]
+                .ping_history
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_targeted_scan_gateway() {
+        struct TargetScanner(Vec<Device>);
+
+        impl Scanner for TargetScanner {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                Ok(Some(self.0.clone()))
+            }
+
+            fn supports_targets(&self) -> bool {
+                true
+            }
+
+            fn scan_targets(&mut self, targets: &[IpAddr]) -> Result<Option<Vec<Device>>, Error> {
+                let devices = self.0.iter().filter(|d| targets.contains(&d.ip));
+                Ok(Some(devices.cloned().collect()))
+            }
+        }
+
+        let scanner = TargetScanner(scan::parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:ff\tRouter\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let config = Config::parse(
+            "targeted_scan = true\nfull_scan_every = 3\ngateway = \"192.168.1.1\"\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = Daemon::new(config, Box::new(scanner));
+        let devices = daemon.scan().unwrap().unwrap();
+        daemon.init_state(devices);
+        for _ in 0..5 {
+            let devices = daemon.scan().unwrap().unwrap();
+            // the gateway is in the targeted scans, so they don't look like a network outage
+            assert!(daemon.update_state(devices).is_empty());
+            assert!(daemon.network_lost.is_none());
+        }
+    }
+
+    #[test]
+    fn test_skip_failed_scan() {
+        struct FlakyScanner(VecDeque<Result<Option<Vec<Device>>>>);
+
+        impl Scanner for FlakyScanner {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                self.0.pop_front().unwrap_or(Ok(None)).map_err(Error::Scan)
+            }
+        }
+
+        let devices = scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n");
+        let scanner = FlakyScanner(VecDeque::from([
+            Ok(Some(devices.clone())),
+            Err(anyhow::anyhow!("Connection refused")),
+            Ok(Some(devices)),
+        ]));
+        let mut daemon = Daemon::new(
+            Config {
+                scan_interval: Duration::ZERO,
+                ..Default::default()
+            },
+            Box::new(scanner),
+        );
+        daemon.run(false).unwrap();
+        assert_eq!(daemon.scans, 2);
+        assert!(daemon.state[&MacAddr::new("aa:bb:cc:dd:ee:01")].is_connected);
+
+        // a successful scan resets the number of failures
+        let error = || Err(anyhow::anyhow!("Connection refused"));
+        let scanner = FlakyScanner(VecDeque::from([
+            Ok(Some(Vec::new())),
+            error(),
+            Ok(Some(Vec::new())),
+            error(),
+            error(),
+            Ok(Some(Vec::new())),
+        ]));
+        let mut daemon = Daemon::new(
+            Config {
+                scan_interval: Duration::ZERO,
+                max_scan_failures: Some(2),
+                ..Default::default()
+            },
+            Box::new(scanner),
+        );
+        let error = daemon.run(false).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "2 scans failed in a row: Connection refused"
+        );
+        assert_eq!(daemon.scans, 2);
+    }
+
+    #[test]
+    fn test_initial_scan_retry() {
+        struct FlakyScanner(VecDeque<Result<Option<Vec<Device>>>>);
+
+        impl Scanner for FlakyScanner {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                self.0.pop_front().unwrap_or(Ok(None)).map_err(Error::Scan)
+            }
+        }
+
+        let scanner = || {
+            FlakyScanner(VecDeque::from([
+                Err(anyhow::anyhow!("Network is down")),
+                Ok(Some(scan::parse_scan(
+                    "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+                ))),
+            ]))
+        };
+        let config = Config {
+            scan_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut daemon = Daemon::new(config.clone(), Box::new(scanner()));
+        let devices = daemon
+            .initial_scan(1, Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(devices.len(), 1);
+
+        let mut daemon = Daemon::new(config, Box::new(scanner()));
+        assert!(daemon.initial_scan(1, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_scan_health() {
+        let mut daemon = test_daemon(Config::parse("notify_scan_failures = 2").unwrap());
+        let error = Error::Scan(anyhow::anyhow!("Network is down"));
+        assert!(daemon.update_scan_health(Some(&error)).is_none());
+        let event = daemon.update_scan_health(Some(&error)).unwrap();
+        assert!(
+            matches!(&event, Event::ScanBroken { failures: 2, error } if error == "Network is down")
+        );
+        assert_eq!(daemon.message(&event).unwrap().title, "Scanning failed");
+        // only notified once while failing
+        assert!(daemon.update_scan_health(Some(&error)).is_none());
+        assert!(matches!(
+            daemon.update_scan_health(None),
+            Some(Event::ScanRecovered { failures: 3 })
+        ));
+        assert!(daemon.update_scan_health(None).is_none());
+
+        // a single failure neither breaks nor recovers
+        assert!(daemon.update_scan_health(Some(&error)).is_none());
+        assert!(daemon.update_scan_health(None).is_none());
+    }
+
+    #[test]
+    fn test_interface_down() {
+        let root = std::env::temp_dir().join(format!("lanotify-net-lib-{}", std::process::id()));
+        fs::create_dir_all(root.join("eth0")).unwrap();
+        let set = |state: &str| fs::write(root.join("eth0").join("operstate"), state).unwrap();
+        let config = Config::parse(
+            "notify_interface_down = true\n\
+             [scanner]\n\
+             type = \"arp_scan\"\n\
+             interfaces = [\"eth0\"]\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        set("up");
+        assert!(daemon.check_interfaces(&root).is_empty());
+        set("down");
+        let events = daemon.check_interfaces(&root);
+        assert!(matches!(&events[..], [Event::InterfaceDown { interface }] if interface == "eth0"));
+        assert_eq!(daemon.interface_down.as_deref(), Some("eth0"));
+        assert!(daemon.check_interfaces(&root).is_empty());
+        set("up");
+        let events = daemon.check_interfaces(&root);
+        assert!(matches!(&events[..], [Event::InterfaceUp { .. }]));
+        assert!(daemon.interface_down.is_none());
+
+        // the interface of the default route without configured ones
+        let mut daemon = test_daemon(Config::parse("notify_interface_down = true").unwrap());
+        daemon.local.interface = Some("eth0".to_string());
+        set("down");
+        let events = daemon.check_interfaces(&root);
+        assert!(matches!(&events[..], [Event::InterfaceDown { interface }] if interface == "eth0"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_clock_jump() {
+        let mut daemon = test_daemon(Config::default());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let last_seen = daemon.state[&mac].last_seen;
+
+        // a forward jump leaves the timestamps alone
+        daemon.check_clock(daemon.last_scan_time + chrono::TimeDelta::hours(2));
+        assert_eq!(daemon.state[&mac].last_seen, last_seen);
+
+        // the clock was set back an hour
+        daemon.check_clock(daemon.last_scan_time - chrono::TimeDelta::hours(1));
+        let shift = last_seen - daemon.state[&mac].last_seen;
+        assert!((shift - chrono::TimeDelta::hours(1)).num_seconds().abs() <= 1);
+        assert_eq!(
+            daemon.state[&mac].last_connect,
+            Some(daemon.state[&mac].last_seen)
+        );
+    }
+
+    #[test]
+    fn test_transition_timestamps() {
+        let mut state = DeviceState::new(
+            scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n")[0].clone(),
+            true,
+            ScanHistory::new(),
+            Local::now(),
+        );
+        let start = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        let format = TimeFormat::Custom("%H:%M".to_string());
+        state.last_connect = Some(start);
+        assert_eq!(
+            state
+                .since(start + chrono::TimeDelta::hours(1), &format)
+                .as_deref(),
+            Some("online since 09:00")
+        );
+        assert_eq!(
+            state
+                .since(start + chrono::TimeDelta::hours(1), &TimeFormat::Relative)
+                .as_deref(),
+            Some("online for 1h0m")
+        );
+        assert_eq!(previous_state(&state, &format), "");
+
+        state.set_connected(false, start + chrono::TimeDelta::hours(3));
+        assert_eq!(
+            state
+                .since(start + chrono::TimeDelta::hours(5), &format)
+                .as_deref(),
+            Some("offline for 2h0m")
+        );
+        assert_eq!(
+            previous_state(&state, &TimeFormat::default()),
+            ", was online since 2024-05-01 09:00:00"
+        );
+        assert_eq!(
+            previous_state(&state, &TimeFormat::Relative),
+            ", was online for 3h0m"
+        );
+
+        state.set_connected(true, start + chrono::TimeDelta::hours(5));
+        assert_eq!(
+            state.last_disconnect,
+            Some(start + chrono::TimeDelta::hours(3))
+        );
+        assert_eq!(previous_state(&state, &format), ", was offline for 2h0m");
+    }
+
+    #[test]
+    fn test_long_absence() {
+        let config =
+            Config::parse("long_absence = 86400\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n")
+                .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let start = Local::now();
+        let state = daemon.state.get_mut(&mac).unwrap();
+        state.set_connected(false, start);
+        state.set_connected(true, start + chrono::TimeDelta::hours(2));
+        let message = daemon.message(&Event::Connected(mac.clone())).unwrap();
+        assert_eq!(message.title, "Device Laptop connected");
+        assert_eq!(message.priority, Priority::Default);
+
+        let state = daemon.state.get_mut(&mac).unwrap();
+        state.set_connected(false, start + chrono::TimeDelta::hours(3));
+        state.set_connected(true, start + chrono::TimeDelta::days(4));
+        let message = daemon.message(&Event::Connected(mac.clone())).unwrap();
+        assert_eq!(message.title, "Device Laptop is back after 3d21h");
+        assert_eq!(message.priority, Priority::High);
+        assert!(message.body.ends_with("was offline for 3d21h"));
+
+        daemon.config.language = i18n::Language::Nl;
+        let message = daemon.message(&Event::Connected(mac)).unwrap();
+        assert_eq!(message.title, "Apparaat Laptop is terug na 3d21h");
+    }
+
+    #[test]
+    fn test_compact_last_seen() {
+        let mut daemon = test_daemon(Config::parse("compact_last_seen = true").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let state = daemon
+            .state
+            .get_mut(&MacAddr::new("aa:bb:cc:dd:ee:02"))
+            .unwrap();
+        state.is_connected = false;
+        state.last_seen = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        let cell = |mac| {
+            Column::LastSeen
+                .cell(
+                    &daemon.state[&MacAddr::new(mac)],
+                    &daemon.config,
+                    Local::now(),
+                )
+                .text()
+                .to_string()
+        };
+        assert_eq!(cell("aa:bb:cc:dd:ee:01"), "now");
+        assert_eq!(cell("aa:bb:cc:dd:ee:02"), "2024-05-01 09:30:00");
+    }
+
+    #[test]
+    fn test_time_format() {
+        let config = Config::parse("time_format = \"relative\"").unwrap();
+        assert_eq!(config.time_format, TimeFormat::Relative);
+        let now = Local::now();
+        let format = |delta| config.time_format.format(now - delta, now);
+        assert_eq!(format(chrono::TimeDelta::seconds(5)), "5s ago");
+        assert_eq!(format(chrono::TimeDelta::minutes(3)), "3m ago");
+        assert_eq!(format(chrono::TimeDelta::hours(50)), "2d ago");
+
+        let config = Config::parse("time_format = \"%d/%m %H:%M\"").unwrap();
+        let time = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        assert_eq!(config.time_format.format(time, now), "01/05 09:30");
+        assert!(Config::parse("time_format = \"%Q\"").is_err());
+    }
+
+    #[test]
+    fn test_transition_metrics() {
+        let mut daemon =
+            test_daemon(Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        for _ in 0..HISTORY_SIZE {
+            daemon.update_state(Vec::new());
+        }
+        let metrics = daemon.render_metrics();
+        assert!(metrics.contains(
+            "lanotify_transitions_total{mac=\"aa:bb:cc:dd:ee:01\",name=\"Laptop\",direction=\"disconnect\"} 1\n"
+        ));
+        assert!(!metrics.contains("direction=\"connect\""));
+        assert!(metrics.contains("lanotify_devices_connected 0\n"));
+        assert!(
+            metrics.contains(
+                "lanotify_device_confidence{mac=\"aa:bb:cc:dd:ee:01\",name=\"Laptop\"} 0\n"
+            )
+        );
+        assert!(!metrics.contains("lanotify_device_latency_seconds{"));
+
+        // a new device connects once, however many scans it takes to be announced
+        daemon.config.new_device_settle = 2;
+        let phone = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        for _ in 0..3 {
+            daemon.update_state(scan::parse_scan(phone));
+        }
+        assert!(daemon.render_metrics().contains(
+            "lanotify_transitions_total{mac=\"aa:bb:cc:dd:ee:02\",name=\"\",direction=\"connect\"} 1\n"
+        ));
+
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t1.5\n",
+        ));
+        assert!(daemon.render_metrics().contains(
+            "lanotify_device_latency_seconds{mac=\"aa:bb:cc:dd:ee:01\",name=\"Laptop\"} 0.0015\n"
+        ));
+    }
+
+    #[test]
+    fn test_uptime_stats() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "uptime_days = 2\ntable_columns = [\"uptime_percent\"]\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n",
+            )
+            .unwrap(),
+        );
+        let clock = MockClock::new(Local::now());
+        daemon.clock = Box::new(clock.clone());
+        let laptop = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let both = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                    192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let phone = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        daemon.init_state(scan::parse_scan(both));
+        let scans = |daemon: &mut Daemon, scan: &str, count| {
+            for _ in 0..count {
+                daemon.update_state(scan::parse_scan(scan));
+            }
+        };
+        scans(&mut daemon, both, 3);
+        scans(&mut daemon, phone, 1);
+        assert_eq!(daemon.state[&laptop].uptime.ratio(), Some(0.75));
+        clock.advance(chrono::TimeDelta::days(1));
+        scans(&mut daemon, phone, 4);
+        clock.advance(chrono::TimeDelta::days(1));
+        scans(&mut daemon, both, 2);
+
+        // only the last two days count
+        let uptime = &daemon.state[&laptop].uptime;
+        assert_eq!(uptime.days.len(), 2);
+        assert_eq!(uptime.ratio(), Some(2.0 / 6.0));
+        assert_eq!((uptime.hits, uptime.scans), (5, 10));
+        assert_eq!(
+            daemon.device_table(
+                &[Column::UptimePercent],
+                Some(&laptop),
+                TableGroupBy::None,
+                false
+            ),
+            ["33%"]
+        );
+        let api = daemon.api_devices();
+        assert_eq!(api[0].uptime, Some(2.0 / 6.0));
+        let metrics = daemon.render_metrics();
+        assert!(metrics.contains(
+            "lanotify_device_scans_total{mac=\"aa:bb:cc:dd:ee:01\",name=\"Laptop\"} 10\n"
+        ));
+        assert!(metrics.contains(
+            "lanotify_device_seen_scans_total{mac=\"aa:bb:cc:dd:ee:01\",name=\"Laptop\"} 5\n"
+        ));
+
+        // kept in the state file
+        let json = serde_json::to_string(&daemon.state[&laptop]).unwrap();
+        let restored: DeviceState = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.uptime.ratio(), Some(2.0 / 6.0));
+        assert_eq!(restored.uptime.scans, 10);
+    }
+
+    #[test]
+    fn test_notification_dedup() {
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let start = Instant::now();
+        let events = vec![
+            Event::Connected(mac.clone()),
+            Event::Connected(mac.clone()),
+            Event::Disconnected(mac.clone()),
+        ];
+        // off by default
+        let mut daemon = test_daemon(Config::default());
+        let mut kept = events.clone();
+        daemon.dedup(&mut kept, start);
+        assert_eq!(kept.len(), 3);
+
+        let mut daemon = test_daemon(Config::parse("notify_dedup_window = 60").unwrap());
+        let mut events = events;
+        daemon.dedup(&mut events, start);
+        assert_eq!(events.len(), 2);
+
+        let mut events = vec![Event::Connected(mac.clone())];
+        daemon.dedup(&mut events, start + Duration::from_secs(30));
+        assert!(events.is_empty());
+        let mut events = vec![Event::Connected(mac)];
+        daemon.dedup(&mut events, start + Duration::from_secs(61));
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_critical_device() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "notify_rate_limit = 1\nnotify_rate_limit_action = \"defer\"\n\
+                 [[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Smoke alarm\"\ncritical = true\n\
+                 [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let message = |daemon: &Daemon, mac| {
+            daemon
+                .message(&Event::Disconnected(MacAddr::new(mac)))
+                .unwrap()
+        };
+        let laptop = message(&daemon, "aa:bb:cc:dd:ee:02");
+        assert!(!laptop.critical);
+        daemon.notify(laptop.clone());
+        daemon.notify(laptop);
+        assert_eq!(daemon.deferred.len(), 1);
+
+        let alarm = message(&daemon, "aa:bb:cc:dd:ee:01");
+        assert!(alarm.critical);
+        daemon.notify(alarm);
+        assert_eq!(daemon.deferred.len(), 1);
+    }
+
+    #[test]
+    fn test_wait_watchdog() {
+        use std::os::unix::net::UnixDatagram;
+        let path =
+            std::env::temp_dir().join(format!("lanotify-watchdog-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let mut daemon = test_daemon(Config::default());
+        daemon.systemd =
+            Some(systemd::Notify::new(path.as_os_str(), Some(Duration::from_millis(400))).unwrap());
+        // a keep-alive right away and then every 200ms, although the wait is longer than the
+        // watchdog interval
+        daemon.wait(Duration::from_millis(500));
+        let mut buf = [0; 32];
+        let mut keep_alives = 0;
+        while let Ok(n) = listener.recv(&mut buf) {
+            assert_eq!(&buf[..n], b"WATCHDOG=1\n");
+            keep_alives += 1;
+        }
+        assert_eq!(keep_alives, 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_deferred() {
+        let mut daemon = test_daemon(
+            Config::parse("notify_rate_limit = 1\nnotify_rate_limit_action = \"defer\"").unwrap(),
+        );
+        let recorder = notify::Recorder::new();
+        daemon.add_notifier(None, Box::new(recorder.clone()));
+        let message = |title: &str| Message {
+            title: title.to_string(),
+            ..Default::default()
+        };
+        daemon.notify(message("sent"));
+        daemon.notify(message("deferred"));
+        assert_eq!(daemon.deferred.len(), 1);
+        // still over the rate limit, so it is dropped instead of kept from exiting
+        daemon.shutdown();
+        assert!(daemon.deferred.is_empty());
+        let titles: Vec<String> = recorder.messages().into_iter().map(|m| m.title).collect();
+        assert_eq!(titles, ["sent"]);
+    }
+
+    #[test]
+    fn test_event_log() {
+        let path = std::env::temp_dir().join(format!("lanotify-events-{}.log", std::process::id()));
+        let mut daemon = test_daemon(
+            Config::parse(&format!("event_log = {:?}", path.display().to_string())).unwrap(),
+        );
+        daemon.init_state(Vec::new());
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let event: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(event["mac"], "aa:bb:cc:dd:ee:01");
+        assert_eq!(event["connected"], true);
+    }
+
+    #[test]
+    fn test_event_fifo() {
+        use std::{io::Read, os::unix::fs::OpenOptionsExt};
+        let path =
+            std::env::temp_dir().join(format!("lanotify-events-{}.fifo", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut daemon = test_daemon(
+            Config::parse(&format!("event_fifo = {:?}", path.display().to_string())).unwrap(),
+        );
+        daemon.init_state(Vec::new());
+        // not read by anyone, which doesn't stop the daemon
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mut reader = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let mut contents = String::new();
+        let _ = reader.read_to_string(&mut contents);
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(event["mac"], "aa:bb:cc:dd:ee:02");
+        assert_eq!(event["connected"], true);
+    }
+
+    #[test]
+    fn test_influx_points() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "influx_url = \"http://127.0.0.1:1/write\"\n\
+                 [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        daemon.write_points();
+        let lines = daemon.influx.as_ref().unwrap().buffered();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().any(|l| {
+            l.starts_with("presence,mac=aa:bb:cc:dd:ee:01,name=Laptop connected=true,seen=true ")
+        }));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("presence,mac=aa:bb:cc:dd:ee:02 connected=true,seen=false "))
+        );
+    }
+
+    #[test]
+    fn test_notify_on() {
+        let config = Config::parse(
+            "notify_on = \"connect\"\n\
+             [[device]]\n\
+             mac = \"aa:bb:cc:dd:ee:01\"\n\
+             name = \"NAS\"\n\
+             notify_on = \"disconnect\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(Vec::new());
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        // only the guest device is notified about when connecting
+        assert!(
+            matches!(events.as_slice(), [Event::Connected(mac)] if mac.0 == "aa:bb:cc:dd:ee:02")
+        );
+
+        let mut disconnected = Vec::new();
+        for _ in 0..HISTORY_SIZE {
+            disconnected.extend(daemon.update_state(Vec::new()));
+        }
+        assert!(
+            matches!(disconnected.as_slice(), [Event::Disconnected(mac)] if mac.0 == "aa:bb:cc:dd:ee:01")
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let contents = "scan_interval = 30\nntfy_url = \"http://ntfy/base\"\n\
+                        default_profile = \"home\"\n\
+                        [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\
+                        [profiles.home.devices]\n\"aa:bb:cc:dd:ee:02\" = \"Router\"\n\
+                        [profiles.travel]\nscan_interval = 120\nntfy_url = \"http://ntfy/travel\"\n";
+        let home = Config::parse_toml(contents, None).unwrap();
+        assert_eq!(home.scan_interval, Duration::from_secs(30));
+        assert_eq!(home.devices.len(), 2);
+
+        let travel = Config::parse_toml(contents, Some("travel")).unwrap();
+        assert_eq!(travel.scan_interval, Duration::from_secs(120));
+        assert_eq!(travel.ntfy_url, "http://ntfy/travel");
+        assert_eq!(travel.devices.len(), 1);
+
+        let err = Config::parse_toml(contents, Some("office")).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown profile 'office'");
+        // without a default only the base settings apply
+        assert!(Config::parse("[profiles.travel]\nscan_interval = 120\n").is_ok());
+    }
+
+    #[test]
+    fn test_config_dir() {
+        let dir = std::env::temp_dir().join(format!("lanotify-test-{}.d", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("00-base.toml"),
+            "scan_interval = 30\nntfy_url = \"http://ntfy/base\"\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Router\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("10-kitchen.toml"),
+            "ntfy_url = \"http://ntfy/kitchen\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Fridge\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("20-office.toml"),
+            "[devices]\n\"aa:bb:cc:dd:ee:02\" = \"Printer\"\n\"aa:bb:cc:dd:ee:03\" = \"Desktop\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a config").unwrap();
+
+        let config = Config::load(&dir).unwrap();
+        assert_eq!(config.scan_interval, Duration::from_secs(30));
+        assert_eq!(config.ntfy_url, "http://ntfy/kitchen");
+        assert_eq!(config.devices.len(), 3);
+        // the later file wins for conflicting devices
+        assert_eq!(
+            config.name(&MacAddr::new("aa:bb:cc:dd:ee:02")).unwrap(),
+            "Printer"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_dir_tables() {
+        let dir = std::env::temp_dir().join(format!("lanotify-tables-{}.d", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("00-base.toml"),
+            "[messages]\ntitle = \"{name}: {status}\"\nbody = \"base\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("10-body.toml"), "[messages]\nbody = \"{name}\"\n").unwrap();
+
+        let config = Config::load(&dir).unwrap();
+        // the tables are merged per key
+        let phrases = config.messages.phrases(config.language);
+        assert_eq!(phrases.title, "{name}: {status}");
+        assert_eq!(phrases.body, "{name}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_env_substitution() {
+        // SAFETY: no other test reads this variable
+        unsafe { std::env::set_var("LANOTIFY_TEST_TOPIC", "secret-topic") };
+        let config = Config::parse("ntfy_url = \"http://ntfy/${LANOTIFY_TEST_TOPIC}\"").unwrap();
+        assert_eq!(config.ntfy_url, "http://ntfy/secret-topic");
+        let config =
+            Config::parse_json(r#"{ "ntfy_url": "http://ntfy/${LANOTIFY_TEST_TOPIC}" }"#).unwrap();
+        assert_eq!(config.ntfy_url, "http://ntfy/secret-topic");
+
+        let err = Config::parse("ntfy_url = \"${LANOTIFY_TEST_MISSING}\"").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid value for 'ntfy_url': Environment variable 'LANOTIFY_TEST_MISSING' is not set"
+        );
+    }
+
+    #[test]
+    fn test_json_config() {
+        let config = Config::parse_json(
+            r#"{
+                "scan_interval": 30,
+                "ntfy_url": "http://ntfy/home",
+                "devices": { "aa:bb:cc:dd:ee:01": "Laptop" },
+                "device": [{ "mac": "aa:bb:cc:dd:ee:02", "name": "Phone", "notify_on": "connect" }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.ntfy_url, "http://ntfy/home");
+        assert_eq!(config.devices.len(), 2);
+        assert_eq!(
+            config.name(&MacAddr::new("aa:bb:cc:dd:ee:02")).unwrap(),
+            "Phone"
+        );
+
+        let err = Config::parse_json("scan_interval = 30").unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse config file as JSON");
+    }
+
+    #[test]
+    fn test_auto_register_unknown() {
+        let config = Config {
+            auto_register_unknown: true,
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config);
+        daemon.init_state(Vec::new());
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        assert_eq!(daemon.messages(&events)[0].priority, Priority::High);
+        daemon.send_notifications(events);
+        assert!(daemon.state[&mac].registered);
+        assert_eq!(daemon.config.name(&mac).unwrap(), "Vendor A");
+
+        // later transitions are notified as a known device
+        let message = daemon.message(&Event::Disconnected(mac)).unwrap();
+        assert_eq!(message.title, "Device Vendor A disconnected");
+        assert_eq!(message.priority, Priority::Default);
+    }
+
+    #[test]
+    fn test_ntfy_actions() {
+        let config = Config::parse(
+            "ntfy_click = \"http://dashboard/devices/{mac}\"\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Desktop\"\n\
+             [[ntfy_action]]\naction = \"http\"\nlabel = \"Wake {name}\"\n\
+             url = \"http://lanotify/wake/{mac}\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let message = daemon.message(&Event::Disconnected(mac)).unwrap();
+        assert_eq!(
+            message.click.as_deref(),
+            Some("http://dashboard/devices/aa:bb:cc:dd:ee:01")
+        );
+        assert_eq!(message.actions[0].label, "Wake Desktop");
+        assert_eq!(
+            message.actions[0].url,
+            "http://lanotify/wake/aa:bb:cc:dd:ee:01"
+        );
+
+        // not set unless configured
+        let mut daemon = test_daemon(Config::default());
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let message = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01")))
+            .unwrap();
+        assert!(message.click.is_none() && message.actions.is_empty());
+    }
+
+    #[test]
+    fn test_investigate_url() {
+        let config = Config::parse(
+            "notify_unknown = true\n\
+             ntfy_click = \"http://dashboard/devices/{mac}\"\n\
+             investigate_url = \"http://router/clients?mac={mac}&ip={ip}\"\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Desktop\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let click = |daemon: &Daemon, i| {
+            let mac = MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+            daemon.message(&Event::Connected(mac)).unwrap().click
+        };
+        assert_eq!(
+            click(&daemon, 2).as_deref(),
+            Some("http://router/clients?mac=aa:bb:cc:dd:ee:02&ip=192.168.1.3")
+        );
+        // known devices keep `ntfy_click`
+        assert_eq!(
+            click(&daemon, 1).as_deref(),
+            Some("http://dashboard/devices/aa:bb:cc:dd:ee:01")
+        );
+    }
+
+    #[test]
+    fn test_startup_grace() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        let scanner = scan::FixtureScanner::parse(&format!("{a}---\n{b}---\n{a}{b}")).unwrap();
+        let config = Config {
+            scan_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut daemon = Daemon::new(config, Box::new(scanner));
+        let devices = daemon.initial_scan(2, Duration::ZERO).unwrap().unwrap();
+        assert_eq!(devices.len(), 2);
+        daemon.init_state(devices);
+        // the slow device is part of the baseline, so it isn't announced as new
+        let devices = daemon.scanner.scan().unwrap().unwrap();
+        assert!(daemon.update_state(devices).is_empty());
+    }
+
+    #[test]
+    fn test_presence_group() {
+        let config = Config::parse(
+            "presence_group = [\"aa:bb:cc:dd:ee:01\", \"aa:bb:cc:dd:ee:02\"]\n\
+             presence_debounce = 600\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Alice's phone\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let now = Local::now();
+        let minutes = |m| now + chrono::TimeDelta::minutes(m);
+        assert!(daemon.update_presence(now).is_none());
+
+        let set = |daemon: &mut Daemon, i: u8, online: bool| {
+            let mac = MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+            daemon.state.get_mut(&mac).unwrap().is_connected = online;
+        };
+        // one person leaving doesn't change anything
+        set(&mut daemon, 2, false);
+        assert!(daemon.update_presence(minutes(1)).is_none());
+
+        // everyone has to be gone for the debounce time
+        set(&mut daemon, 1, false);
+        assert!(daemon.update_presence(minutes(2)).is_none());
+        assert!(daemon.update_presence(minutes(11)).is_none());
+        let event = daemon.update_presence(minutes(12));
+        assert!(matches!(event, Some(Event::Presence { present: false })));
+        assert!(daemon.update_presence(minutes(13)).is_none());
+
+        // arriving is reported right away
+        set(&mut daemon, 1, true);
+        let event = daemon.update_presence(minutes(14)).unwrap();
+        let message = daemon.message(&event).unwrap();
+        assert_eq!(message.title, "Someone is home");
+        assert_eq!(message.body, "Present: Alice's phone");
+
+        // a phone sleeping briefly is tolerated
+        set(&mut daemon, 1, false);
+        assert!(daemon.update_presence(minutes(15)).is_none());
+        set(&mut daemon, 1, true);
+        assert!(daemon.update_presence(minutes(20)).is_none());
+        set(&mut daemon, 1, false);
+        assert!(daemon.update_presence(minutes(29)).is_none());
+    }
+
+    #[test]
+    fn test_presence_heartbeat() {
+        let config = Config::parse(
+            "presence_group = [\"aa:bb:cc:dd:ee:01\", \"aa:bb:cc:dd:ee:02\"]\n\
+             presence_heartbeat_interval = 300\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Alice's phone\"\n\"aa:bb:cc:dd:ee:02\" = \"Bob's phone\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let now = Local::now();
+        let minutes = |m| now + chrono::TimeDelta::minutes(m);
+        assert_eq!(
+            daemon.presence_heartbeat(now).as_deref(),
+            Some("{\"present\":[\"Alice's phone\"]}")
+        );
+        assert!(daemon.presence_heartbeat(minutes(4)).is_none());
+        assert!(daemon.presence_heartbeat(minutes(5)).is_some());
+
+        daemon.config.presence_heartbeat_body = Some("{count}: {names}".to_string());
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        daemon.state.get_mut(&mac).unwrap().is_connected = false;
+        assert!(daemon.presence_heartbeat(minutes(10)).is_none());
+        // the next arrival is reported right away
+        daemon.state.get_mut(&mac).unwrap().is_connected = true;
+        assert_eq!(
+            daemon.presence_heartbeat(minutes(11)).as_deref(),
+            Some("1: Alice's phone")
+        );
+    }
+
+    #[test]
+    fn test_device_hooks() {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = Config::parse(&format!(
+            "[devices.\"aa:bb:cc:dd:ee:01\"]\n\
+             name = \"Jan's car\"\n\
+             on_connect_url = \"http://127.0.0.1:{port}/arrived?ip={{ip}}&name={{name}}\"\n\
+             on_disconnect_url = \"http://127.0.0.1:{port}/left\"\n"
+        ))
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(Vec::new());
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let (mut stream, _) = listener.accept().unwrap();
+        let (request, event) = test_http::read_json_request(&mut stream);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        assert!(request.starts_with("POST /arrived?ip=192.168.1.2&name=Jan%27s%20car HTTP/1.1"));
+        assert_eq!(event["name"], "Jan's car");
+        assert_eq!(event["connected"], true);
+    }
+
+    #[test]
+    fn test_max_tracked_devices() {
+        let config = Config::parse(
+            "max_tracked_devices = 2\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let mac = |i| MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+        // the configured device is kept although it was seen the longest ago
+        daemon.state.get_mut(&mac(1)).unwrap().last_seen -= chrono::TimeDelta::hours(1);
+        daemon.transitions.insert((mac(2), true), 1);
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        ));
+        let mut macs: Vec<&MacAddr> = daemon.state.keys().collect();
+        macs.sort();
+        assert_eq!(macs, [&mac(1), &mac(3)]);
+        assert!(!daemon.transitions.keys().any(|(m, _)| *m == mac(2)));
+    }
+
+    #[test]
+    fn test_max_tracked_devices_pending() {
+        let config = Config::parse(
+            "max_tracked_devices = 1\nnotify_unknown = true\n\
+             [devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:03");
+        // over the limit, but the new device is kept until its connection is notified
+        let events = daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        ));
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, Event::Connected(m) if *m == mac))
+        );
+        assert!(daemon.message(&Event::Connected(mac.clone())).is_some());
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+        assert!(!daemon.state.contains_key(&mac));
+    }
+
+    #[test]
+    fn test_disconnect_grace() {
+        let config = Config::parse(
+            "[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Phone\"\ndisconnect_grace = 120\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        let clock = MockClock::new(Local::now());
+        daemon.clock = Box::new(clock.clone());
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let scan = scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n");
+        daemon.init_state(scan.clone());
+        let disconnect = |daemon: &mut Daemon| {
+            (0..HISTORY_SIZE)
+                .flat_map(|_| daemon.update_state(Vec::new()))
+                .collect::<Vec<Event>>()
+        };
+
+        // back within the grace period, nothing is notified
+        assert!(disconnect(&mut daemon).is_empty());
+        assert!(!daemon.state[&mac].is_connected);
+        assert!(daemon.state[&mac].pending_disconnect.is_some());
+        assert!(daemon.update_state(scan.clone()).is_empty());
+        assert!(daemon.state[&mac].is_connected);
+        assert!(daemon.state[&mac].pending_disconnect.is_none());
+
+        // still gone after the grace period
+        assert!(disconnect(&mut daemon).is_empty());
+        clock.advance(chrono::TimeDelta::seconds(119));
+        assert!(daemon.update_state(Vec::new()).is_empty());
+        clock.advance(chrono::TimeDelta::seconds(1));
+        let events = daemon.update_state(Vec::new());
+        assert!(matches!(&events[..], [Event::Disconnected(m)] if *m == mac));
+        assert!(daemon.update_state(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_notify_cooldown() {
+        let config = Config::parse(
+            "[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Bulb\"\nnotify_cooldown = 600\n\
+             [devices.\"aa:bb:cc:dd:ee:02\"]\nname = \"Laptop\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let bulb = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let laptop = MacAddr::new("aa:bb:cc:dd:ee:02");
+        let now = Local::now();
+        let cooldown = |daemon: &mut Daemon, mut events: Vec<Event>, minutes| {
+            daemon.cooldown(&mut events, now + chrono::TimeDelta::minutes(minutes));
+            events
+        };
+
+        let events = cooldown(&mut daemon, vec![Event::Disconnected(bulb.clone())], 0);
+        assert_eq!(events.len(), 1);
+        // only the device with a cooldown is suppressed
+        let events = vec![
+            Event::Connected(bulb.clone()),
+            Event::Connected(laptop.clone()),
+        ];
+        let events = cooldown(&mut daemon, events, 5);
+        assert!(matches!(&events[..], [Event::Connected(m)] if *m == laptop));
+        // suppressed notifications don't extend the cooldown
+        let events = cooldown(&mut daemon, vec![Event::Disconnected(bulb)], 10);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_exclude_local() {
+        let config = Config::parse("[devices]\n\"aa:bb:cc:dd:ee:03\" = \"Router\"\n").unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.local = scan::LocalAddrs {
+            macs: HashSet::from([MacAddr::new("aa:bb:cc:dd:ee:01")]),
+            gateway: Some(Ipv4Addr::new(192, 168, 1, 1)),
+            interface: None,
+        };
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tThis host\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        // a configured gateway is kept
+        daemon.update_state(scan::parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:03\tRouter\n\
+             192.168.1.1\taa:bb:cc:dd:ee:04\tOther router\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\tThis host\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let mut macs: Vec<&str> = daemon.state.keys().map(MacAddr::as_str).collect();
+        macs.sort();
+        assert_eq!(macs, ["aa:bb:cc:dd:ee:02", "aa:bb:cc:dd:ee:03"]);
+    }
+
+    #[test]
+    fn test_network_lost() {
+        let config = Config::parse("gateway = \"192.168.1.1\"").unwrap();
+        let mut daemon = test_daemon(config);
+        let scan = scan::parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:01\tRouter\n\
+             192.168.1.2\taa:bb:cc:dd:ee:02\tVendor B\n",
+        );
+        daemon.init_state(scan.clone());
+        // the router reboots, then only the gateway is missing from a scan
+        for _ in 0..HISTORY_SIZE {
+            assert!(daemon.update_state(Vec::new()).is_empty());
+        }
+        assert!(daemon.update_state(scan[1..].to_vec()).is_empty());
+        assert!(daemon.network_lost.is_some());
+
+        let events = daemon.update_state(scan.clone());
+        assert!(matches!(events[..], [Event::NetworkRestored { .. }]));
+        assert!(daemon.network_lost.is_none());
+        assert!(daemon.state.values().all(|s| s.is_connected));
+        assert!(daemon.message(&events[0]).is_some());
+    }
+
+    #[test]
+    fn test_network_lost_default_route() {
+        let mut daemon = test_daemon(Config::default());
+        daemon.local.gateway = Some(Ipv4Addr::new(192, 168, 1, 1));
+        let scan = scan::parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:01\tRouter\n\
+             192.168.1.2\taa:bb:cc:dd:ee:02\tVendor B\n",
+        );
+        daemon.init_state(scan[1..].to_vec());
+        // e.g. a remote scanner of another network, which never finds the gateway
+        daemon.update_state(scan[1..].to_vec());
+        assert!(daemon.network_lost.is_none());
+
+        daemon.update_state(scan.clone());
+        daemon.update_state(scan[1..].to_vec());
+        assert!(daemon.network_lost.is_some());
+        let events = daemon.update_state(scan);
+        assert!(matches!(events[..], [Event::NetworkRestored { .. }]));
+    }
+
+    #[test]
+    fn test_scan_failure() {
+        let mut daemon = test_daemon(Config::default());
+        let scan = scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        );
+        daemon.init_state(scan.clone());
+        for _ in 0..HISTORY_SIZE {
+            daemon.update_state(scan.clone());
+        }
+
+        let events = daemon.update_state(Vec::new());
+        assert!(matches!(
+            events.as_slice(),
+            [Event::ScanFailure {
+                vanished: 3,
+                connected: 3
+            }]
+        ));
+        assert_eq!(
+            daemon
+                .state
+                .values()
+                .next()
+                .unwrap()
+                .ping_history
+                .to_string(),
+            "O".repeat(HISTORY_SIZE)
+        );
+        // only notified once
+        assert!(daemon.update_state(Vec::new()).is_empty());
+
+        // devices that are really gone eventually disconnect
+        let mut disconnected = 0;
+        for _ in 0..HISTORY_SIZE {
+            disconnected += daemon
+                .update_state(Vec::new())
+                .iter()
+                .filter(|e| matches!(e, Event::Disconnected(_)))
+                .count();
+        }
+        assert_eq!(disconnected, 3);
+    }
+
+    #[test]
+    fn test_device_type() {
+        let config = Config::parse(
+            "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Zed's phone\"\ntype = \"phone\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Bulb\"\ntype = \"iot\"\nicon = \"🔆\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:03\"\nname = \"Alice's phone\"\ntype = \"phone\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:04\"\nname = \"Printer\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(Config {
+            table_columns: vec![Column::Name],
+            ..config
+        });
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n\
+             192.168.1.5\taa:bb:cc:dd:ee:04\tVendor D\n\
+             192.168.1.6\taa:bb:cc:dd:ee:05\tVendor E\n",
+        ));
+        assert_eq!(
+            daemon.status_table(false),
+            vec![
+                "📱 Alice's phone",
+                "📱 Zed's phone",
+                "🔆 Bulb",
+                "Printer",
+                "Unknown: Vendor E"
+            ]
+        );
+        let message = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01")))
+            .unwrap();
+        assert_eq!(message.title, "Device 📱 Zed's phone connected");
+    }
+
+    #[test]
+    fn test_match_by_ip() {
+        let config = Config::parse(
+            "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Bulb\"\n\
+             match_by = \"ip\"\nip = \"192.168.1.50\"\n\
+             [[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\nname = \"Plug\"\n\
+             match_by = \"ip_vendor\"\nip = \"192.168.1.51\"\nvendor = \"Shelly\"\n",
+        )
+        .unwrap();
+        let mut devices = scan::parse_scan(
+            "192.168.1.50\t12:34:56:78:9a:01\t(Unknown: locally administered)\n\
+             192.168.1.51\t12:34:56:78:9a:02\tShelly\n\
+             192.168.1.52\t12:34:56:78:9a:03\tShelly\n",
+        );
+        config.identify(&mut devices);
+        let macs: Vec<&str> = devices.iter().map(|d| d.mac.0.as_str()).collect();
+        assert_eq!(
+            macs,
+            [
+                "aa:bb:cc:dd:ee:01",
+                "aa:bb:cc:dd:ee:02",
+                "12:34:56:78:9a:03"
+            ]
+        );
+
+        let mut devices = scan::parse_scan("192.168.1.51\t12:34:56:78:9a:02\tOther\n");
+        config.identify(&mut devices);
+        assert_eq!(devices[0].mac, MacAddr::new("12:34:56:78:9a:02"));
+
+        let error = Config::parse(
+            "[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"Bulb\"\nmatch_by = \"ip\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Device 'Bulb' is missing the ip to match by"
+        );
+    }
+
+    #[test]
+    fn test_device_macs() {
+        let config = Config::parse(
+            "[[device]]\nname = \"Laptop\"\nmacs = [\"aa:bb:cc:dd:ee:01\", \"aa:bb:cc:dd:ee:02\"]\n",
+        )
+        .unwrap();
+        let laptop = MacAddr::new("aa:bb:cc:dd:ee:01");
+        assert!(config.devices.contains_key(&laptop));
+        let mut daemon = test_daemon(Config {
+            table_columns: vec![Column::Name, Column::Ip],
+            ..config
+        });
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        ));
+
+        // only the ethernet address responds
+        let mut devices = scan::parse_scan("192.168.1.3\taa:bb:cc:dd:ee:02\tVendor A\n");
+        daemon.config.identify(&mut devices);
+        daemon.update_state(devices);
+        // both respond
+        let mut devices = scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor A\n",
+        );
+        daemon.config.identify(&mut devices);
+        assert_eq!(devices.len(), 1);
+        daemon.update_state(devices);
+        assert_eq!(daemon.state.len(), 1);
+        assert_eq!(daemon.state[&laptop].ping_history.len(), 2);
+        assert_eq!(daemon.status_table(false), vec!["Laptop  192.168.1.2"]);
+
+        assert!(
+            Config::parse(
+                "[devices]\n\"aa:bb:cc:dd:ee:02\" = \"Phone\"\n\
+                 [[device]]\nname = \"Laptop\"\nmacs = [\"aa:bb:cc:dd:ee:01\", \"aa:bb:cc:dd:ee:02\"]\n",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_device_macs_alternating() {
+        let config = Config::parse(
+            "[[device]]\nname = \"Laptop\"\nmacs = [\"aa:bb:cc:dd:ee:01\", \"aa:bb:cc:dd:ee:02\"]\n",
+        )
+        .unwrap();
+        let laptop = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let wifi = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let ethernet = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor A\n";
+        let mut daemon = test_daemon(config);
+        let feed = |daemon: &mut Daemon, output: &str| {
+            let mut devices = scan::parse_scan(output);
+            daemon.config.filter_scan(&mut devices);
+            daemon.update_state(devices)
+        };
+        let mut both = scan::parse_scan(&format!("{wifi}{ethernet}"));
+        daemon.config.filter_scan(&mut both);
+        assert_eq!(both.len(), 1);
+        assert_eq!(
+            both[0].found_as,
+            [laptop.clone(), MacAddr::new("aa:bb:cc:dd:ee:02")]
+        );
+        daemon.init_state(both);
+
+        // switching between its interfaces every scan
+        for i in 0..HISTORY_SIZE * 2 {
+            let events = feed(&mut daemon, if i % 2 == 0 { ethernet } else { wifi });
+            assert!(events.is_empty(), "scan {i}: {events:?}");
+        }
+        let state = &daemon.state[&laptop];
+        assert!(state.is_connected);
+        assert_eq!(state.members.len(), 2);
+        assert!(state.members.values().all(|m| m.history.base_rate() == 0.5));
+
+        let events: Vec<Event> = (0..HISTORY_SIZE)
+            .flat_map(|_| feed(&mut daemon, ""))
+            .collect();
+        assert!(matches!(&events[..], [Event::Disconnected(_)]));
+        let events: Vec<Event> = (0..RECENT_WINDOW)
+            .flat_map(|_| feed(&mut daemon, ethernet))
+            .collect();
+        assert!(matches!(&events[..], [Event::Connected(_)]));
+    }
+
+    #[test]
+    fn test_watch_header() {
+        let mut daemon = Daemon::new(
+            Config {
+                scan_interval: Duration::from_secs(30),
+                ..Default::default()
+            },
+            Box::new(scan::FixtureScanner::parse("---\n").unwrap()),
+        );
+        daemon.scan().unwrap();
+        daemon.scan().unwrap();
+        assert_eq!(
+            daemon.watch_header(),
+            "Status of 0 devices, scan 2, next scan in 30s"
+        );
+    }
+
+    #[test]
+    fn test_language() {
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut daemon = test_daemon(
+            Config::parse(
+                "language = \"nl\"\n\
+                 [messages]\n\
+                 disconnected = \"weg\"\n\
+                 [devices]\n\
+                 \"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(a));
+        let message = daemon.message(&Event::Connected(mac.clone())).unwrap();
+        assert_eq!(message.title, "Apparaat Laptop verbonden");
+        assert!(message.body.starts_with(
+            "Apparaat Laptop met IP 192.168.1.2 en MAC aa:bb:cc:dd:ee:01 is verbonden"
+        ));
+        let message = daemon.message(&Event::Disconnected(mac)).unwrap();
+        assert_eq!(message.title, "Apparaat Laptop weg");
+    }
+
+    #[test]
+    fn test_message_templates() {
+        let mut daemon = test_daemon(
+            Config::parse(
+                "[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\
+                 [message_templates]\n\
+                 known_disconnected = \"{name} left\"\n\
+                 unknown_connected = \"OUI {oui} ({vendor}) at {ip}\"\n",
+            )
+            .unwrap(),
+        );
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        let body = |event| daemon.message(&event).unwrap().body;
+        assert_eq!(
+            body(Event::Disconnected(MacAddr::new("aa:bb:cc:dd:ee:01"))),
+            "Laptop left"
+        );
+        assert!(
+            body(Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:01"))).starts_with("Device Laptop")
+        );
+        assert_eq!(
+            body(Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02"))),
+            "OUI aa:bb:cc (Vendor B) at 192.168.1.3"
+        );
+    }
+
+    #[test]
+    fn test_only_known() {
+        let config =
+            Config::parse("only_known = true\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n")
+                .unwrap();
+        let mut daemon = test_daemon(config);
+        let recorder = notify::Recorder::new();
+        daemon.add_notifier(None, Box::new(recorder.clone()));
+        daemon.feed_scan(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        daemon.feed_scan(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        ));
+        assert_eq!(
+            daemon.state.keys().collect::<Vec<_>>(),
+            [&MacAddr::new("aa:bb:cc:dd:ee:01")]
+        );
+        drop(daemon);
+        assert!(recorder.messages().is_empty());
+    }
+
+    #[test]
+    fn test_table_sort() {
+        let config = Config::parse(
+            "table_columns = [\"ip\"]\n[devices]\n\"aa:bb:cc:dd:ee:01\" = \"B\"\n\
+             \"aa:bb:cc:dd:ee:02\" = \"A\"\n\"aa:bb:cc:dd:ee:03\" = \"C\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.30\taa:bb:cc:dd:ee:01\tVendor\n\
+             192.168.1.4\taa:bb:cc:dd:ee:02\tVendor\n\
+             192.168.1.100\taa:bb:cc:dd:ee:03\tVendor\n",
+        ));
+        let now = Local::now();
+        let mac = |i| MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+        daemon.state.get_mut(&mac(1)).unwrap().last_change = now - chrono::TimeDelta::hours(1);
+        daemon.state.get_mut(&mac(2)).unwrap().last_seen = now - chrono::TimeDelta::minutes(2);
+        let state = daemon.state.get_mut(&mac(3)).unwrap();
+        state.last_seen = now - chrono::TimeDelta::minutes(1);
+        state.set_connected(false, now);
+
+        let mut sorted = |sort| {
+            daemon.config.table_sort = sort;
+            daemon
+                .status_table(false)
+                .into_iter()
+                .map(|line| line.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sorted(TableSort::Name),
+            ["192.168.1.4", "192.168.1.30", "192.168.1.100"]
+        );
+        assert_eq!(
+            sorted(TableSort::Status),
+            ["192.168.1.100", "192.168.1.4", "192.168.1.30"]
+        );
+        assert_eq!(
+            sorted(TableSort::Uptime),
+            ["192.168.1.30", "192.168.1.4", "192.168.1.100"]
+        );
+        assert_eq!(
+            sorted(TableSort::Ip),
+            ["192.168.1.4", "192.168.1.30", "192.168.1.100"]
+        );
+        assert_eq!(
+            sorted(TableSort::LastSeen),
+            ["192.168.1.30", "192.168.1.100", "192.168.1.4"]
+        );
+    }
+
+    #[test]
+    fn test_table_group_by() {
+        let config = Config::parse(
+            "table_columns = [\"ip\"]\n[[device]]\nmac = \"aa:bb:cc:dd:ee:01\"\nname = \"TV\"\n\
+             room = \"Living Room\"\ntype = \"iot\"\n[[device]]\nmac = \"aa:bb:cc:dd:ee:02\"\n\
+             name = \"Laptop\"\nroom = \"Office\"\ntype = \"laptop\"\n[[device]]\n\
+             mac = \"aa:bb:cc:dd:ee:03\"\nname = \"Lamp\"\nroom = \"Living Room\"\ntype = \"iot\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(config);
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:01\tVendor\n\
+             192.168.1.2\taa:bb:cc:dd:ee:02\tVendor\n\
+             192.168.1.3\taa:bb:cc:dd:ee:03\tVendor\n\
+             192.168.1.4\taa:bb:cc:dd:ee:04\tVendor\n",
+        ));
+        daemon
+            .state
+            .get_mut(&MacAddr::new("aa:bb:cc:dd:ee:02"))
+            .unwrap()
+            .set_connected(false, Local::now());
+
+        let mut grouped = |group_by| {
+            daemon.config.table_group_by = group_by;
+            daemon
+                .status_table(false)
+                .into_iter()
+                .map(|line| line.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            grouped(TableGroupBy::Room),
+            [
+                "Living Room:",
+                "192.168.1.3",
+                "192.168.1.1",
+                "",
+                "Office:",
+                "192.168.1.2",
+                "",
+                "Unknown:",
+                "192.168.1.4"
+            ]
+        );
+        assert_eq!(
+            grouped(TableGroupBy::Type),
+            [
+                "Laptop:",
+                "192.168.1.2",
+                "",
+                "IoT:",
+                "192.168.1.3",
+                "192.168.1.1",
+                "",
+                "Unknown:",
+                "192.168.1.4"
+            ]
+        );
+        assert_eq!(
+            grouped(TableGroupBy::Status),
+            [
+                "Connected:",
+                "192.168.1.3",
+                "192.168.1.1",
+                "192.168.1.4",
+                "",
+                "Disconnected:",
+                "192.168.1.2"
+            ]
+        );
+        assert_eq!(grouped(TableGroupBy::None).len(), 4);
+    }
+
+    #[test]
+    fn test_device_note() {
+        let config = Config::parse(
+            "[devices.\"aa:bb:cc:dd:ee:01\"]\nname = \"Laptop\"\nnote = \"borrowed from work\"\n",
+        )
+        .unwrap();
+        let mut daemon = test_daemon(Config {
+            table_columns: vec![Column::Mac, Column::Note],
+            ..config
+        });
+        daemon.init_state(scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        ));
+        assert_eq!(
+            daemon.status_table(false),
+            vec![
+                "aa:bb:cc:dd:ee:01  borrowed from work",
+                "aa:bb:cc:dd:ee:02  ",
+            ]
+        );
+        let message = daemon
+            .message(&Event::Disconnected(MacAddr::new("aa:bb:cc:dd:ee:01")))
+            .unwrap();
+        assert!(message.body.ends_with("\nNote: borrowed from work"));
+        let message = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02")))
+            .unwrap();
+        assert!(!message.body.contains("Note"));
+    }
+
+    #[test]
+    fn test_federation() {
+        let config = Config::parse(
+            "[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n\
+             [[peer]]\nname = \"attic\"\nurl = \"http://10.0.0.2:9100/api/devices\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.peers[0].name, "attic");
+        let mut daemon = test_daemon(Config {
+            table_columns: vec![Column::Mac, Column::Node],
+            ..config
+        });
+        let mut devices = scan::parse_scan(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n",
+        );
+        devices[1].node = Some("attic".to_string());
+        daemon.init_state(devices);
+
+        let api = daemon.api_devices();
+        assert_eq!(api.len(), 1);
+        assert_eq!(api[0].name.as_deref(), Some("Laptop"));
+        let json = serde_json::to_string(&api).unwrap();
+        let parsed: Vec<federation::ApiDevice> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0].mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert!(parsed[0].connected);
+        assert!(parsed[0].history.is_empty());
+
+        assert_eq!(
+            daemon.status_table(false),
+            vec!["aa:bb:cc:dd:ee:01  ", "aa:bb:cc:dd:ee:02  attic"]
+        );
+        let message = daemon
+            .message(&Event::Connected(MacAddr::new("aa:bb:cc:dd:ee:02")))
+            .unwrap();
+        assert!(message.body.ends_with("is connected on attic"));
+    }
+
+    #[test]
+    fn test_state_delta() {
+        let mac = |i: u8| MacAddr::new(&format!("aa:bb:cc:dd:ee:0{i}"));
+        let prev = HashMap::from([(mac(1), true), (mac(2), true), (mac(3), false)]);
+        let current = HashMap::from([
+            (mac(1), true),
+            (mac(2), false),
+            (mac(3), false),
+            (mac(4), true),
+        ]);
+        let delta = StateDelta::new(&prev, &current);
+        assert_eq!(delta.to_string(), "+1 new, -1 disconnected");
+        assert!(StateDelta::new(&current, &current).is_empty());
+
+        // a removed device is a change on its own
+        let current = HashMap::from([(mac(1), true), (mac(2), true)]);
+        assert_eq!(StateDelta::new(&prev, &current).to_string(), "-1 removed");
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture bench_`
+    #[test]
+    #[ignore]
+    fn bench_large_network() {
+        // a /22 with every address in use
+        let scan: Vec<Device> = (0..1024u32)
+            .map(|i| Device {
+                mac: MacAddr::new(&format!("aa:bb:cc:dd:{:02x}:{:02x}", i >> 8, i & 0xff)),
+                ip: Ipv4Addr::from(0xC0A8_0000 + i).into(),
+                vendor: format!("Vendor {i}"),
+                hostname: None,
+                latency: None,
+                interface: None,
+                rssi: None,
+                node: None,
+                found_as: Vec::new(),
+            })
+            .collect();
+        let mut daemon = test_daemon(Config::default());
+        daemon.init_state(scan.clone());
+
+        let iterations = 100;
+        let start = Instant::now();
+        for i in 0..iterations {
+            // drop a different part of the network every scan
+            let devices = scan
+                .iter()
+                .filter(|d| !matches!(d.ip, IpAddr::V4(ip) if ip.octets()[3] == i))
+                .cloned();
+            daemon.update_state(devices.collect());
+        }
+        println!(
+            "update_state: {:?}/scan",
+            start.elapsed() / iterations as u32
+        );
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            daemon.status_table(false);
+        }
+        println!(
+            "status_table: {:?}/scan",
+            start.elapsed() / iterations as u32
+        );
+    }
+
+    #[test]
+    fn test_min_rssi() {
+        let scanner = scan::FixtureScanner::parse(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t\twlan0\t-60\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\t\twlan0\t-85\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        )
+        .unwrap();
+        let config = Config::parse("min_rssi = -70").unwrap();
+        let mut daemon = Daemon::new(config, Box::new(scanner));
+        let macs: Vec<String> = daemon
+            .scan()
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.mac.0)
+            .collect();
+        assert_eq!(macs, ["aa:bb:cc:dd:ee:01", "aa:bb:cc:dd:ee:03"]);
+    }
+
+    #[test]
+    fn test_mac_addr() {
+        let mac: MacAddr = "AA:bb:CC:dd:EE:01".parse().unwrap();
+        assert_eq!(mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert_eq!(mac.oui(), "aa:bb:cc");
+        assert!("aa:bb:cc:dd:ee:0g".parse::<MacAddr>().is_err());
+    }
+
+    #[test]
+    fn test_uppercase_mac_config() {
+        let config = Config::parse(
+            "presence_group = [\"AA:BB:CC:DD:EE:01\"]\n\
+             [devices.\"AA:BB:CC:DD:EE:01\"]\n\
+             name = \"Phone\"\n\
+             macs = [\"AA:BB:CC:DD:EE:02\"]\n",
+        )
+        .unwrap();
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        assert_eq!(config.name(&mac).map(String::as_str), Some("Phone"));
+        assert_eq!(config.presence_group, [MacAddr::new("aa:bb:cc:dd:ee:01")]);
+        assert_eq!(
+            config.devices[&mac].macs,
+            [MacAddr::new("aa:bb:cc:dd:ee:02")]
+        );
+        let state: HashMap<MacAddr, bool> =
+            serde_json::from_str(r#"{ "AA:BB:CC:DD:EE:01": true }"#).unwrap();
+        assert_eq!(state.get(&mac), Some(&true));
+    }
+
+    #[test]
+    fn test_oui_file() {
+        let path = std::env::temp_dir().join(format!("lanotify-oui-{}.txt", std::process::id()));
+        fs::write(&path, "AA-BB-CC   (hex)\t\tAcme Corporation\n").unwrap();
+        let scanner = scan::FixtureScanner::parse(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\t(Unknown)\n\
+             192.168.1.3\t11:22:33:44:55:66\tVendor B\n",
+        )
+        .unwrap();
+        let config = Config::parse(&format!("oui_file = {:?}", path.display().to_string()));
+        fs::remove_file(&path).unwrap();
+        let mut daemon = Daemon::new(config.unwrap(), Box::new(scanner));
+        let vendors: Vec<String> = daemon
+            .scan()
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.vendor)
+            .collect();
+        assert_eq!(vendors, ["Acme Corporation", "Vendor B"]);
+
+        assert!(matches!(
+            Config::parse("oui_file = \"/nonexistent/oui.txt\""),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_dhcp_leases_file() {
+        let path = std::env::temp_dir().join(format!("lanotify-leases-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "1717000000 aa:bb:cc:dd:ee:01 192.168.1.2 phone *\n\
+             1717000000 aa:bb:cc:dd:ee:02 192.168.1.3 tv *\n",
+        )
+        .unwrap();
+        let config = Config::parse(&format!(
+            "dhcp_leases_file = {:?}\n[devices]\n\"aa:bb:cc:dd:ee:02\" = \"Television\"\n",
+            path.display().to_string()
+        ));
+        fs::remove_file(&path).unwrap();
+        let scanner = scan::FixtureScanner::parse(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n\
+             192.168.1.4\taa:bb:cc:dd:ee:03\tVendor C\n",
+        )
+        .unwrap();
+        let mut daemon = Daemon::new(config.unwrap(), Box::new(scanner));
+        let devices = daemon.scan().unwrap().unwrap();
+        assert_eq!(devices[0].hostname.as_deref(), Some("phone"));
+        let names: Vec<String> = devices.iter().map(|d| daemon.display_name(d)).collect();
+        assert_eq!(names, ["phone", "Television", "Unknown Vendor C"]);
+    }
+
+    #[test]
+    fn test_device_count_limits() {
+        let config = Config {
+            max_devices: Some(1),
+            ..Default::default()
+        };
+        let mut daemon = test_daemon(config);
+        let a = "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n";
+        let b = "192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n";
+        daemon.init_state(scan::parse_scan(a));
+
+        let count_events = |events: Vec<Event>| {
+            events
+                .into_iter()
+                .filter(|e| matches!(e, Event::DeviceCount { .. }))
+                .count()
+        };
+        let events = daemon.update_state(scan::parse_scan(&format!("{a}{b}")));
+        assert_eq!(count_events(events), 1);
+        // stays above, doesn't fire again
+        let events = daemon.update_state(scan::parse_scan(&format!("{a}{b}")));
+        assert_eq!(count_events(events), 0);
+        assert_eq!(daemon.count_limit, CountLimit::Above(1));
+    }
+
+    #[test]
+    fn test_regression() {
+        let patterns = &[
+            "-OO----O-O-OO-OOO-O-OOOOOOOO--",
+            "--O--OOOOOOOOOOOOOOOOOOOOOOOOO",
+            "-OOOO--O-OO----OOO--O--OOOOOOO",
+        ];
+        let mut history = ScanHistory::new();
+        let mut is_connected = true;
+        for p in patterns {
+            for (i, c) in p.chars().rev().enumerate() {
+                match c {
+                    'O' => {
+                        history.update(true);
+                    }
+                    '-' => {
+                        history.update(false);
+                    }
+                    _ => panic!(),
+                }
+                is_connected = history.is_connected(is_connected);
+                assert!(is_connected, "{history} i={i}");
+            }
+        }
+    }
+}
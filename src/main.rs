@@ -1,516 +1,322 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local};
-use serde::Deserialize;
-use serde_with::serde_as;
+use anyhow::{Context, Result, bail};
+use lanotify::{
+    Config, Daemon, LogTarget, control,
+    control::{Command, parse_duration},
+    metrics,
+    notify::Message,
+    scan::{self, ScannerConfig},
+    syslog,
+};
 use std::{
-    collections::{HashMap, VecDeque, hash_map::Entry},
-    fmt::{Display, Write},
     fs,
-    net::Ipv4Addr,
+    io::{self, BufRead, IsTerminal, Write},
     path::{Path, PathBuf},
-    process::Command,
-    thread::sleep,
     time::Duration,
 };
 
-const HISTORY_SIZE: usize = 30;
-const OFFLINE_THRESHOLD: usize = 10;
-const RECENT_WINDOW: usize = 5;
-
 fn main() -> Result<()> {
-    env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
-        .parse_default_env()
-        .init();
-
-    let config_path = if let Some(arg) = std::env::args().skip(1).next() {
-        PathBuf::from(arg)
-    } else {
-        PathBuf::from("config.toml")
-    };
-    log::info!("loading config from '{}'", config_path.display());
-
-    let config = Config::load(&config_path).context("Failed to load config file")?;
-
-    Daemon::new(config).run()?;
-
-    Ok(())
-}
-
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
-#[serde(transparent)]
-struct MacAddr(String);
-
-impl MacAddr {
-    fn new(mac: &str) -> Self {
-        if mac.len() != 17 {
-            panic!("Invalid MAC address length");
-        }
-        Self(mac.to_string())
+    let args = Args::parse()?;
+    if args.init {
+        return init(&args.config_path);
     }
-}
-
-#[derive(Debug, Clone)]
-struct Device {
-    mac: MacAddr,
-    ip: Ipv4Addr,
-    vendor: String,
-}
-
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-#[serde(deny_unknown_fields, default)]
-struct Config {
-    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
-    scan_interval: Duration,
-    devices: HashMap<MacAddr, String>,
-    ntfy_url: String,
-    notify_unknown: bool,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            scan_interval: Duration::from_secs(10),
-            devices: HashMap::new(),
-            ntfy_url: "http://localhost:8080/notify".to_string(),
-            notify_unknown: true,
-        }
-    }
-}
-
-impl Config {
-    fn load(path: &Path) -> Result<Self> {
-        let contents = fs::read_to_string(path).context("Failed to read config file")?;
-        let config: Config = toml::from_str(&contents).context("Failed to parse config file")?;
-        Ok(config)
-    }
-}
-
-#[derive(Debug, Clone)]
-struct DeviceState {
-    device: Device,
-    last_seen: DateTime<Local>,
-    is_connected: bool,
-    ping_history: ScanHistory,
-}
-
-#[derive(Debug, Clone)]
-struct ScanHistory {
-    log: VecDeque<bool>,
-}
-
-impl ScanHistory {
-    fn new() -> Self {
-        Self {
-            log: VecDeque::new(),
-        }
-    }
-
-    #[cfg(test)]
-    fn from(array: Vec<bool>) -> Self {
-        assert_eq!(array.len(), HISTORY_SIZE);
-        Self {
-            log: VecDeque::from(array),
-        }
-    }
-
-    fn update(&mut self, state: bool) {
-        self.log.push_front(state);
-        if self.log.len() > HISTORY_SIZE {
-            self.log.pop_back();
-        }
-    }
-
-    // Determines statistically if the device is likely to be connected or disconnected
-    // Takes in the current connection state
-    fn is_connected(&self, is_connected: bool) -> bool {
-        if self.log.len() < OFFLINE_THRESHOLD {
-            // Insufficient data
-            return is_connected;
-        }
-
-        let last_ping = self.log.iter().position(|v| *v).unwrap_or(HISTORY_SIZE);
-        let base_rate =
-            self.log.iter().map(|v| *v as u64).sum::<u64>() as f64 / self.log.len() as f64;
-        if base_rate <= 0.3 {
-            // Devices that are sleeping a lot, or a device that has just gone offline!
-            if last_ping >= HISTORY_SIZE {
-                false
-            } else if last_ping < RECENT_WINDOW {
-                true
-            } else {
-                is_connected
+    // the config is loaded before the logger is initialized since it sets the log level
+    let config = Config::load_profile(&args.config_path, args.profile.as_deref())
+        .context("Failed to load config file")?;
+
+    let cli_level = args.log_level(config.log_level);
+    match config.log_target {
+        LogTarget::Stdout => {
+            let mut builder = env_logger::builder();
+            builder.filter_level(config.log_level).parse_default_env();
+            // `-v` wins over RUST_LOG
+            if let Some(level) = cli_level {
+                builder.filter_level(level);
             }
-        } else if base_rate <= 0.7 {
-            // Intermittent devices
-            if is_connected && last_ping > OFFLINE_THRESHOLD {
-                false
-            } else if !is_connected && last_ping < RECENT_WINDOW {
-                true
-            } else {
-                is_connected
+            builder.init();
+        }
+        LogTarget::Syslog => {
+            let mut builder = env_filter::Builder::new();
+            builder.filter_level(config.log_level);
+            if let Ok(filters) = std::env::var("RUST_LOG") {
+                builder.parse(&filters);
             }
-        } else {
-            // Always-on devices devices
-            if *self.log.front().unwrap() {
-                true
-            } else if is_connected && last_ping > RECENT_WINDOW {
-                false
-            } else {
-                is_connected
+            // `-v` wins over RUST_LOG
+            if let Some(level) = cli_level {
+                builder.filter_level(level);
             }
+            syslog::init(builder.build())?;
         }
     }
-}
-
-impl Display for ScanHistory {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for act in &self.log {
-            f.write_char(if *act { 'O' } else { '-' })?;
-        }
-        for _ in 0..(HISTORY_SIZE - self.log.len()) {
-            f.write_char('.')?;
-        }
-        Ok(())
+    if args.config_from_stdin() {
+        log::info!("loaded config from stdin");
+    } else {
+        log::info!("loaded config from '{}'", args.config_path.display());
     }
-}
-
-impl DeviceState {
-    fn new(device: Device) -> Self {
-        DeviceState {
-            device,
-            last_seen: Local::now(),
-            is_connected: true, // assume connected at first
-            ping_history: ScanHistory::new(),
-        }
+    if let Some(profile) = &args.profile {
+        log::info!("using config profile '{profile}'");
     }
-}
 
-impl Display for DeviceState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.is_connected {
-            write!(f, "✅")?;
-        } else {
-            write!(f, "❌")?;
-        };
-        write!(
-            f,
-            "  {}  {}  {}  {:15}",
-            self.ping_history,
-            self.last_seen.format("%Y-%m-%d %H:%M:%S"),
-            self.device.mac.0,
-            self.device.ip,
-        )?;
-        Ok(())
+    if args.test_notify {
+        return test_notify(&config);
     }
-}
 
-struct Daemon {
-    config: Config,
-    state: HashMap<MacAddr, DeviceState>,
-}
-
-impl Daemon {
-    fn new(config: Config) -> Self {
-        Self {
-            config,
-            state: HashMap::new(),
-        }
+    if !args.command.is_empty() {
+        let socket = config
+            .control_socket
+            .as_ref()
+            .context("No control_socket configured")?;
+        print!("{}", control::send(socket, &args.command.join(" "))?);
+        return Ok(());
     }
 
-    fn run(&mut self) -> Result<()> {
-        let devices = arp_scan()?; // initial scan
-        self.init_state(devices);
-        self.log_state();
-        loop {
-            let devices = arp_scan()?;
-
-            self.update_state(devices);
-            self.log_state();
-
-            log::debug!("Waiting {:?} until next scan...", self.config.scan_interval);
-            sleep(self.config.scan_interval);
-        }
+    if let Some((path, duration)) = &args.capture {
+        let mut scanner = config.scanner.build()?;
+        scanner.set_timeout(config.scan_hard_timeout);
+        let scans = scan::capture(scanner.as_mut(), path, *duration, config.scan_interval)?;
+        println!("Captured {scans} scans to '{}'", path.display());
+        return Ok(());
     }
 
-    fn init_state(&mut self, devices: Vec<Device>) {
-        for device in devices {
-            self.state
-                .insert(device.mac.clone(), DeviceState::new(device));
-        }
-        log::info!("Initilized with {} devices", self.state.len());
+    let scanner = config.scanner.build()?;
+    // fail early on invalid notifiers, the daemon only logs them so reloads can't stop it
+    for notifier in config.notifier_configs() {
+        notifier
+            .build()
+            .with_context(|| format!("Invalid notifier {notifier}"))?;
     }
-
-    fn update_state(&mut self, new_devices: Vec<Device>) {
-        let mut notifications = Vec::new();
-        for device in &new_devices {
-            match self.state.entry(device.mac.clone()) {
-                // update status existing device
-                Entry::Occupied(mut e) => {
-                    let state = e.get_mut();
-                    state.device = device.clone();
-                    state.last_seen = Local::now();
-                    state.ping_history.update(true);
-                }
-                // found a new device
-                Entry::Vacant(e) => {
-                    e.insert(DeviceState::new(device.clone()));
-                    notifications.push((device.clone(), true));
-                }
-            }
-        }
-        for state in self.state.values_mut() {
-            // if the device was not found in the new scan, update its log to disconnected
-            if !new_devices.iter().any(|d| d.mac == state.device.mac) {
-                state.ping_history.update(false);
-            }
-            if state.ping_history.is_connected(state.is_connected) {
-                if !state.is_connected {
-                    state.is_connected = true;
-                    notifications.push((state.device.clone(), true));
-                }
-            } else {
-                if state.is_connected {
-                    state.is_connected = false;
-                    notifications.push((state.device.clone(), false));
-                }
-            }
-        }
-        for (device, state) in notifications {
-            if let Err(e) = self.notify(&device, state) {
-                log::error!("Failed to send notification: {}", e);
-            }
-        }
+    let control = config
+        .control_socket
+        .as_deref()
+        .map(control::listen)
+        .transpose()?;
+    let metrics = config
+        .metrics_address
+        .map(|address| metrics::Exporter::listen(address, config.dashboard))
+        .transpose()?;
+    let color = !args.no_color
+        && std::env::var_os("NO_COLOR").is_none()
+        && std::io::stdout().is_terminal()
+        && config.log_target == LogTarget::Stdout;
+    let mut daemon = Daemon::new(config, scanner);
+    // stdin can't be read again to reload
+    if !args.config_from_stdin() {
+        daemon.config_path = Some(args.config_path);
     }
+    daemon.config_profile = args.profile;
+    lanotify::reload_on_sighup();
+    lanotify::stop_on_sigterm();
+    daemon.verbose = args.verbose;
+    daemon.watch = args.watch;
+    daemon.color = color;
+    daemon.control = control;
+    daemon.metrics = metrics;
+    daemon.run(args.once)?;
+
+    Ok(())
+}
 
-    fn log_state(&self) {
-        let mut mapping: Vec<(MacAddr, DeviceState)> = self.state.clone().into_iter().collect();
-        mapping.sort_by_key(|(_, s)| {
-            let name = self.config.devices.get(&s.device.mac);
-            (name.is_none(), name.cloned())
-        });
-        println!("Status of {} devices:", mapping.len());
-        for (_, state) in mapping {
-            print!("{state}  ");
-            if let Some(name) = self.config.devices.get(&state.device.mac) {
-                print!("{name}");
-            } else {
-                print!("Unknown: {}", state.device.vendor);
+/// Sends a test notification through each configured notifier, reporting the results
+fn test_notify(config: &Config) -> Result<()> {
+    let message = Message {
+        title: "lanotify test".to_string(),
+        body: "Test notification from lanotify".to_string(),
+        ..Default::default()
+    };
+    let configs = config.notifier_configs();
+    let mut failed = 0;
+    for notifier_config in &configs {
+        match notifier_config.build().and_then(|n| n.send(&message)) {
+            Ok(response) => println!("{notifier_config}: ok ({response})"),
+            Err(e) => {
+                println!("{notifier_config}: failed: {e:#}");
+                failed += 1;
             }
-            println!();
         }
     }
-
-    fn notify(&self, device: &Device, state: bool) -> Result<()> {
-        let status = if state { "connected" } else { "disconnected" };
-        if !self.config.notify_unknown && self.config.devices.get(&device.mac).is_none() {
-            log::info!(
-                "Unknown device {} with IP {} and MAC {} is {}",
-                device.vendor,
-                device.ip,
-                device.mac.0,
-                status
-            );
-            return Ok(());
-        }
-
-        let name = self.config.devices.get(&device.mac);
-        let priority = if name.is_some() { "default" } else { "high" };
-        let display_name = name
-            .map(|d| d.to_string())
-            .unwrap_or(format!("Unknown {}", &device.vendor));
-        let title = format!("Device {} {}", display_name, status);
-        let body = format!(
-            "Device {} with IP {} and MAC {} is {}",
-            display_name, device.ip, device.mac.0, status
-        );
-        log::info!("[notify] {title} {body}");
-        let resp = ureq::post(&self.config.ntfy_url)
-            .header("Title", &title)
-            .header("X-Priority", priority)
-            .send(body)?;
-        println!("Notification sent: {} {:?}", resp.status(), resp.body());
-        Ok(())
+    if failed > 0 {
+        bail!("{failed} of {} notifiers failed", configs.len());
     }
+    Ok(())
 }
 
-fn arp_scan() -> Result<Vec<Device>> {
-    log::debug!("starting network scan");
-    let output = Command::new("arp-scan")
-        .args([
-            "--localnet",
-            "--plain",
-            "--format=${ip}\\t${mac}\\t${vendor}",
-        ])
-        .output()
-        .context("Failed to execute 'arp-scan' command")?;
-
-    let devices = String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .map(|line| {
-            let mut fields = line.split('\t');
-            let ip = fields.next().context("missing IP address")?;
-            let mac = fields.next().context("missing MAC address")?;
-            let vendor = fields.next().context("missing vendor")?;
-
-            Ok(Device {
-                mac: MacAddr::new(mac),
-                ip: ip.parse::<Ipv4Addr>().context("invalid IP address")?,
-                vendor: vendor.to_string(),
-            })
-        })
-        .collect::<Result<Vec<_>>>()?;
-
-    Ok(devices)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_connected_always_on() {
-        let mut history = ScanHistory::from(vec![true; HISTORY_SIZE]);
-        let mut is_connected = true;
-
-        // for a short while it stays connected
-        for _ in 0..RECENT_WINDOW {
-            history.update(false);
-            is_connected = history.is_connected(is_connected);
-            assert!(is_connected);
-        }
-
-        // stay disconnected
-        for _ in 0..HISTORY_SIZE {
-            history.update(false);
-            is_connected = history.is_connected(is_connected);
-            assert!(!is_connected);
-        }
+/// Scans the network once and asks which devices to track and where to notify, writing a starter
+/// config
+fn init(path: &Path) -> Result<()> {
+    if path.exists() {
+        bail!(
+            "'{}' already exists, pass another path with --config",
+            path.display()
+        );
     }
-
-    #[test]
-    fn test_connected_always_on_temporary_offline() {
-        let mut history = ScanHistory::from(vec![true; HISTORY_SIZE]);
-
-        // 3x scan misses
-        for _ in 0..3 {
-            history.update(false);
+    let mut config = toml::Table::new();
+    let mut scanner = match ScannerConfig::default().build() {
+        Ok(scanner) => scanner,
+        Err(e) => {
+            println!("Can't use arp-scan ({e:#}), reading the neighbour table instead");
+            let mut scanner_config = toml::Table::new();
+            scanner_config.insert("type".to_string(), "ip_neigh".into());
+            config.insert("scanner".to_string(), scanner_config.into());
+            ScannerConfig::IpNeigh { binary: None }.build()?
         }
-
-        let mut is_connected = true;
-
-        // stay connected
-        for _ in 0..HISTORY_SIZE {
-            is_connected = history.is_connected(is_connected);
-            assert!(is_connected);
-            history.update(true);
+    };
+    println!("Scanning the network...");
+    let mut devices = scanner.scan()?.unwrap_or_default();
+    devices.sort_by_key(|d| d.ip);
+    println!(
+        "Found {} devices, name the ones to get notified about and leave the others empty",
+        devices.len()
+    );
+
+    let mut input = io::stdin().lock();
+    let mut tracked = toml::Table::new();
+    for device in &devices {
+        let hostname = device
+            .hostname
+            .as_ref()
+            .map(|hostname| format!(" ({hostname})"))
+            .unwrap_or_default();
+        let question = format!(
+            "{:<15} {} {}{}",
+            device.ip,
+            device.mac.as_str(),
+            device.vendor,
+            hostname
+        );
+        let name = prompt(&mut input, &question)?;
+        if !name.is_empty() {
+            tracked.insert(device.mac.as_str().to_string(), name.into());
         }
     }
-
-    #[test]
-    fn test_connected_sleeping() {
-        let mut history = ScanHistory::new();
-        for i in 0..HISTORY_SIZE {
-            history.update(i % 10 == 0); // 10% activity
-        }
-
-        let mut is_connected = true;
-        is_connected = history.is_connected(is_connected);
-        assert!(is_connected);
-
-        // stays connected the entire time
-        for _ in 0..HISTORY_SIZE {
-            history.update(false);
-            assert!(is_connected);
-        }
-
-        is_connected = history.is_connected(is_connected);
-        assert!(!is_connected);
+    let ntfy_url = prompt(
+        &mut input,
+        "URL of the ntfy topic to notify, e.g. https://ntfy.sh/my-network",
+    )?;
+    if !ntfy_url.is_empty() {
+        config.insert("ntfy_url".to_string(), ntfy_url.into());
     }
+    let count = tracked.len();
+    config.insert("devices".to_string(), tracked.into());
+
+    let contents = toml::to_string(&config).context("Failed to serialize config")?;
+    Config::parse(&contents).context("Invalid config")?;
+    fs::write(path, contents)
+        .with_context(|| format!("Failed to write config file '{}'", path.display()))?;
+    println!(
+        "Wrote '{}' with {count} devices to track, start lanotify with it",
+        path.display()
+    );
+    Ok(())
+}
 
-    #[test]
-    fn test_connected_intermittent() {
-        let mut history = ScanHistory::new();
-        let mut is_connected = true;
-        for i in 0..HISTORY_SIZE {
-            is_connected = history.is_connected(is_connected);
-            assert!(is_connected);
-            history.update(i % 2 == 0); // 50% activity
-        }
-        for _ in 0..OFFLINE_THRESHOLD {
-            history.update(false);
-        }
-        for _ in 0..HISTORY_SIZE {
-            is_connected = history.is_connected(is_connected);
-            assert!(!is_connected);
-            history.update(false);
-        }
+/// Asks a question on stdout, returning the trimmed answer
+fn prompt(input: &mut impl BufRead, question: &str) -> Result<String> {
+    print!("{question}: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        bail!("Setup aborted");
     }
+    Ok(answer.trim().to_string())
+}
 
-    #[test]
-    fn test_connected_new_sleeping() {
-        let mut is_connected = true;
-        let mut history = ScanHistory::new();
-        for i in 0..(HISTORY_SIZE * 2) {
-            is_connected = history.is_connected(is_connected);
-            assert!(is_connected);
-            history.update(i % 20 == 0); // very low activity
-        }
-    }
+/// Command line arguments
+#[derive(Debug)]
+struct Args {
+    /// Config file or directory, `-` to read it from stdin
+    config_path: PathBuf,
+    /// Config profile to apply instead of the `default_profile` of the config
+    profile: Option<String>,
+    /// Scan a single time and exit
+    once: bool,
+    /// Disable colored output
+    no_color: bool,
+    /// Always print the status table
+    verbose: bool,
+    /// Number of `-v` flags, raising the log level to debug or trace
+    verbosity: usize,
+    /// Redraw the status table in place after every scan
+    watch: bool,
+    /// Command to send to a running daemon over the control socket
+    command: Vec<String>,
+    /// Send a test notification through each notifier and exit
+    test_notify: bool,
+    /// Record the scans to a file for a duration, to replay them with the fixture scanner
+    capture: Option<(PathBuf, Duration)>,
+    /// Write a starter config from a scan and answers to questions
+    init: bool,
+}
 
-    #[test]
-    fn test_connected_intervals() {
-        // on and off in intervals
-        for x in 1..RECENT_WINDOW {
-            let mut is_connected = true;
-            let mut history = ScanHistory::new();
-            for y in 0..HISTORY_SIZE {
-                for z in 0..OFFLINE_THRESHOLD {
-                    // offset of z
-                    for _ in 0..z {
-                        history.update(false);
-                    }
-                    // x times on
-                    for _ in 0..x {
-                        is_connected = history.is_connected(is_connected);
-                        assert!(is_connected, "x={x}, y={y}, z={z}, {history}");
-                        history.update(true);
-                    }
-                    // x times off
-                    for _ in 0..x {
-                        is_connected = history.is_connected(is_connected);
-                        assert!(is_connected, "x={x}, y={y}, z={z} {history}");
-                        history.update(false);
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = Args {
+            config_path: PathBuf::from("config.toml"),
+            profile: None,
+            once: false,
+            no_color: false,
+            verbose: false,
+            verbosity: 0,
+            watch: false,
+            command: Vec::new(),
+            test_notify: false,
+            capture: None,
+            init: false,
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--once" => args.once = true,
+                "--no-color" => args.no_color = true,
+                "--verbose" => args.verbose = true,
+                _ if arg.len() > 1
+                    && arg.starts_with('-')
+                    && arg[1..].chars().all(|c| c == 'v') =>
+                {
+                    args.verbosity += arg.len() - 1
+                }
+                "--watch" => args.watch = true,
+                "-c" | "--config" => {
+                    args.config_path = iter.next().context("Missing config path")?.into()
+                }
+                "--profile" => args.profile = Some(iter.next().context("Missing profile name")?),
+                "--config-dir" => {
+                    let dir: PathBuf = iter.next().context("Missing config directory")?.into();
+                    if !dir.is_dir() {
+                        bail!("Config directory '{}' is not a directory", dir.display());
                     }
+                    args.config_path = dir;
+                }
+                "-" if args.command.is_empty() => args.config_path = PathBuf::from(arg),
+                // options of a control command, such as `status --json`
+                _ if arg.starts_with('-') && args.command.is_empty() => {
+                    bail!("Unknown option '{arg}'")
+                }
+                "test-notify" if args.command.is_empty() => args.test_notify = true,
+                "init" if args.command.is_empty() => args.init = true,
+                "capture" if args.command.is_empty() => {
+                    let path = iter.next().context("Missing capture file")?;
+                    let duration = iter.next().context("Missing capture duration")?;
+                    args.capture = Some((path.into(), parse_duration(&duration)?));
+                }
+                _ if !args.command.is_empty() || Command::is_command(&arg) => {
+                    args.command.push(arg)
                 }
+                _ => args.config_path = PathBuf::from(arg),
             }
         }
+        Ok(args)
     }
 
-    #[test]
-    fn test_regression() {
-        let patterns = &[
-            "-OO----O-O-OO-OOO-O-OOOOOOOO--",
-            "--O--OOOOOOOOOOOOOOOOOOOOOOOOO",
-            "-OOOO--O-OO----OOO--O--OOOOOOO",
-        ];
-        let mut history = ScanHistory::new();
-        let mut is_connected = true;
-        for p in patterns {
-            for (i, c) in p.chars().rev().enumerate() {
-                match c {
-                    'O' => {
-                        history.update(true);
-                    }
-                    '-' => {
-                        history.update(false);
-                    }
-                    _ => panic!(),
-                }
-                is_connected = history.is_connected(is_connected);
-                assert!(is_connected, "{history} i={i}");
-            }
-        }
+    /// Log level set by `-v` flags, never below the configured one
+    fn log_level(&self, configured: log::LevelFilter) -> Option<log::LevelFilter> {
+        let level = match self.verbosity {
+            0 => return None,
+            1 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        };
+        Some(level.max(configured))
+    }
+
+    fn config_from_stdin(&self) -> bool {
+        self.config_path.as_os_str() == "-"
     }
 }
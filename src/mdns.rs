@@ -0,0 +1,155 @@
+use crate::Device;
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+const MDNS_PORT: u16 = 5353;
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+// requests a unicast response, see RFC 6762 section 5.4
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Looks up the mDNS hostnames of the devices, waiting at most `timeout` in total
+///
+/// A reverse (PTR) query is sent directly to each device with an IPv4 address, devices that don't
+/// answer in time keep their current hostname.
+pub fn resolve_hostnames(devices: &mut [Device], timeout: Duration) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).context("Failed to bind socket")?;
+    let mut queried = 0;
+    for device in devices.iter() {
+        let IpAddr::V4(ip) = device.ip else {
+            continue;
+        };
+        let query = encode_query(&reverse_name(ip));
+        if let Err(e) = socket.send_to(&query, (ip, MDNS_PORT)) {
+            log::debug!("Failed to send mDNS query to {}: {}", ip, e);
+        }
+        queried += 1;
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut hostnames = HashMap::new();
+    let mut buf = [0u8; 1500];
+    while hostnames.len() < queried {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let Ok((len, addr)) = socket.recv_from(&mut buf) else {
+            break; // timed out
+        };
+        let IpAddr::V4(ip) = addr.ip() else {
+            continue;
+        };
+        if let Some(hostname) = parse_ptr_response(&buf[..len]) {
+            hostnames.insert(ip, hostname);
+        }
+    }
+    log::debug!(
+        "Resolved {} of {} hostnames using mDNS",
+        hostnames.len(),
+        queried
+    );
+
+    for device in devices.iter_mut() {
+        if let IpAddr::V4(ip) = device.ip
+            && let Some(hostname) = hostnames.remove(&ip)
+        {
+            device.hostname = Some(hostname);
+        }
+    }
+    Ok(())
+}
+
+fn reverse_name(ip: Ipv4Addr) -> String {
+    let [a, b, c, d] = ip.octets();
+    format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+}
+
+fn encode_query(name: &str) -> Vec<u8> {
+    // header: id, flags, 1 question, no answer/authority/additional records
+    let mut msg = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    msg.extend_from_slice(&(CLASS_IN | CLASS_UNICAST_RESPONSE).to_be_bytes());
+    msg
+}
+
+/// Returns the target of the first PTR record in the answers of a DNS response
+fn parse_ptr_response(msg: &[u8]) -> Option<String> {
+    let read_u16 = |pos: usize| Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]));
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos)?.1 + 4; // skip type and class
+    }
+    for _ in 0..answers {
+        pos = read_name(msg, pos)?.1;
+        let record_type = read_u16(pos)?;
+        let data_len = read_u16(pos + 8)? as usize;
+        let data_pos = pos + 10;
+        if record_type == TYPE_PTR {
+            let (name, _) = read_name(msg, data_pos)?;
+            return Some(name);
+        }
+        pos = data_pos + data_len;
+    }
+    None
+}
+
+/// Reads a (possibly compressed) domain name, returns the name and the position after it
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // limit the number of jumps to guard against pointer loops
+    for _ in 0..128 {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        } else if len & 0xC0 == 0xC0 {
+            let pointer = (len & 0x3F) << 8 | *msg.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = pointer;
+        } else {
+            let label = msg.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ptr_response() {
+        let name = reverse_name(Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(name, "2.1.168.192.in-addr.arpa");
+
+        // response: the query with an answer pointing to the question name
+        let mut msg = encode_query(&name);
+        msg[2] = 0x84; // response, authoritative
+        msg[7] = 1; // 1 answer
+        msg.extend_from_slice(&[0xC0, 12]); // pointer to the question name
+        msg.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&120u32.to_be_bytes());
+        let target = b"\x06laptop\x05local\x00";
+        msg.extend_from_slice(&(target.len() as u16).to_be_bytes());
+        msg.extend_from_slice(target);
+
+        assert_eq!(parse_ptr_response(&msg).as_deref(), Some("laptop.local"));
+        assert_eq!(parse_ptr_response(&msg[..msg.len() - 3]), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::Error;
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+// status page polling `/api/devices`, without external resources to work on isolated networks
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Serves the latest rendered metrics in the Prometheus text format, the devices as JSON on
+/// `/api/devices` and optionally a status page on `/dashboard`
+pub struct Exporter {
+    metrics: Arc<Mutex<String>>,
+    devices: Arc<Mutex<String>>,
+}
+
+impl Exporter {
+    /// Listens for scrapes in a background thread
+    pub fn listen(address: SocketAddr, dashboard: bool) -> Result<Self, Error> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("Failed to bind metrics address {address}"))
+            .map_err(Error::Service)?;
+        log::info!("serving metrics on http://{address}/metrics");
+        if dashboard {
+            log::info!("serving dashboard on http://{address}/dashboard");
+        }
+
+        let metrics = Arc::new(Mutex::new(String::new()));
+        let devices = Arc::new(Mutex::new("[]".to_string()));
+        let shared = (metrics.clone(), devices.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .context("Failed to accept connection")
+                    .and_then(|stream| handle_connection(stream, &shared.0, &shared.1, dashboard));
+                if let Err(e) = result {
+                    log::warn!("Metrics connection failed: {:#}", e);
+                }
+            }
+        });
+        Ok(Self { metrics, devices })
+    }
+
+    pub fn update(&self, metrics: String) {
+        *self.metrics.lock().unwrap() = metrics;
+    }
+
+    pub fn update_devices(&self, devices: String) {
+        *self.devices.lock().unwrap() = devices;
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    metrics: &Mutex<String>,
+    devices: &Mutex<String>,
+    dashboard: bool,
+) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    // any other path returns the metrics
+    let path = line.split_whitespace().nth(1).unwrap_or("/");
+    let (content_type, body) = if path == "/api/devices" {
+        ("application/json", devices.lock().unwrap().clone())
+    } else if dashboard && path == "/dashboard" {
+        ("text/html; charset=utf-8", DASHBOARD.to_string())
+    } else {
+        ("text/plain; version=0.0.4", metrics.lock().unwrap().clone())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// Builder for metrics in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new metric family
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.text, "# HELP {name} {help}").unwrap();
+        writeln!(self.text, "# TYPE {name} {kind}").unwrap();
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape(value)))
+                .collect();
+            write!(self.text, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.text, " {value}").unwrap();
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::Ipv4Addr};
+
+    fn get(path: &str, dashboard: bool) -> String {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        write!(client, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let metrics = Mutex::new("metrics".to_string());
+        let devices = Mutex::new("[]".to_string());
+        handle_connection(stream, &metrics, &devices, dashboard).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_dashboard() {
+        assert!(get("/dashboard", true).contains("Content-Type: text/html"));
+        assert!(get("/dashboard", false).ends_with("\r\n\r\nmetrics"));
+        assert!(get("/api/devices", true).ends_with("\r\n\r\n[]"));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::new();
+        metrics.family("lanotify_transitions_total", "counter", "Transitions");
+        metrics.sample(
+            "lanotify_transitions_total",
+            &[("name", "Bob's \"phone\""), ("direction", "connect")],
+            2.0,
+        );
+        assert_eq!(
+            metrics.finish(),
+            "# HELP lanotify_transitions_total Transitions\n\
+             # TYPE lanotify_transitions_total counter\n\
+             lanotify_transitions_total{name=\"Bob's \\\"phone\\\"\",direction=\"connect\"} 2\n"
+        );
+    }
+}
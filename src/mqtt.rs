@@ -0,0 +1,278 @@
+use crate::MacAddr;
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const CLIENT_ID: &str = "lanotify";
+
+/// MQTT broker to publish the presence of the configured devices to
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic prefix Home Assistant listens to for discovery configs
+    pub discovery_prefix: String,
+    /// Prefix of the state topics, `<prefix>/<mac>/state`
+    pub topic_prefix: String,
+}
+
+/// A configured device as published to Home Assistant
+pub struct Presence<'a> {
+    pub mac: &'a MacAddr,
+    pub name: String,
+    pub connected: bool,
+}
+
+/// Publishes discovery configs once and the state of devices when it changes, as retained
+/// messages so Home Assistant picks them up whenever it (re)connects
+pub struct Publisher {
+    config: MqttConfig,
+    stream: Option<TcpStream>,
+    discovered: bool,
+    // state last published per device
+    published: HashMap<MacAddr, bool>,
+}
+
+impl Publisher {
+    pub fn new(config: MqttConfig) -> Self {
+        Self {
+            config,
+            stream: None,
+            discovered: false,
+            published: HashMap::new(),
+        }
+    }
+
+    /// Publishes the states that changed since the last successful publish, and the discovery
+    /// configs on the first one
+    pub fn publish(&mut self, devices: &[Presence]) -> Result<()> {
+        if !self.discovered {
+            for device in devices {
+                let (topic, payload) = self.discovery(device);
+                self.send(&topic, payload.as_bytes())?;
+            }
+            self.discovered = true;
+        }
+        for device in devices {
+            if self.published.get(device.mac) == Some(&device.connected) {
+                continue;
+            }
+            let state = if device.connected { "home" } else { "not_home" };
+            self.send(&self.state_topic(device.mac), state.as_bytes())?;
+            self.published.insert(device.mac.clone(), device.connected);
+        }
+        Ok(())
+    }
+
+    fn state_topic(&self, mac: &MacAddr) -> String {
+        format!("{}/{}/state", self.config.topic_prefix, object_id(mac))
+    }
+
+    /// Topic and payload of the `device_tracker` discovery config of a device
+    fn discovery(&self, device: &Presence) -> (String, String) {
+        let id = format!("lanotify_{}", object_id(device.mac));
+        let topic = format!(
+            "{}/device_tracker/{}/config",
+            self.config.discovery_prefix, id
+        );
+        let payload = json!({
+            "name": device.name,
+            "unique_id": id,
+            "state_topic": self.state_topic(device.mac),
+            "payload_home": "home",
+            "payload_not_home": "not_home",
+            "source_type": "router",
+            "device": {
+                "identifiers": [id],
+                "name": device.name,
+                "connections": [["mac", device.mac.as_str()]],
+            },
+        });
+        (topic, payload.to_string())
+    }
+
+    /// Publishes a retained message, connecting first if needed
+    fn send(&mut self, topic: &str, payload: &[u8]) -> Result<()> {
+        if self.stream.is_none() {
+            self.stream = Some(self.connect()?);
+        }
+        let packet = publish_packet(topic, payload)?;
+        let result = self.stream.as_mut().unwrap().write_all(&packet);
+        if result.is_err() {
+            // reconnect on the next publish
+            self.stream = None;
+        }
+        result.with_context(|| format!("Failed to publish to MQTT topic '{topic}'"))
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let address = (self.config.host.as_str(), self.config.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve MQTT broker '{}'", self.config.host))?
+            .next()
+            .with_context(|| format!("No address for MQTT broker '{}'", self.config.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .with_context(|| format!("Failed to connect to MQTT broker {address}"))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.write_all(&connect_packet(&self.config)?)?;
+        let mut connack = [0; 4];
+        stream
+            .read_exact(&mut connack)
+            .context("No CONNACK from MQTT broker")?;
+        if connack[0] != 0x20 {
+            bail!("Unexpected response from MQTT broker");
+        }
+        if connack[3] != 0 {
+            bail!(
+                "MQTT broker refused the connection with code {}",
+                connack[3]
+            );
+        }
+        log::info!("Connected to MQTT broker {address}");
+        Ok(stream)
+    }
+}
+
+/// MAC address without separators, as allowed in topics and Home Assistant object ids
+fn object_id(mac: &MacAddr) -> String {
+    mac.as_str().replace(':', "")
+}
+
+/// Builds an MQTT 3.1.1 CONNECT packet with a clean session and keep alive disabled
+fn connect_packet(config: &MqttConfig) -> Result<Vec<u8>> {
+    let mut flags = 0x02;
+    let mut payload = Vec::new();
+    push_string(&mut payload, CLIENT_ID)?;
+    if let Some(username) = &config.username {
+        flags |= 0x80;
+        push_string(&mut payload, username).context("Invalid MQTT username")?;
+    }
+    if let Some(password) = &config.password {
+        flags |= 0x40;
+        push_string(&mut payload, password).context("Invalid MQTT password")?;
+    }
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT")?;
+    body.extend_from_slice(&[4, flags, 0, 0]);
+    body.extend(payload);
+    Ok(packet(0x10, body))
+}
+
+/// Builds a retained PUBLISH packet with QoS 0
+fn publish_packet(topic: &str, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    push_string(&mut body, topic).with_context(|| format!("Invalid MQTT topic '{topic}'"))?;
+    body.extend_from_slice(payload);
+    Ok(packet(0x31, body))
+}
+
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![header];
+    // the remaining length is encoded in 7 bits per byte, least significant first
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+/// Appends a string prefixed with its length, which has to fit in 16 bits
+fn push_string(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    let Ok(len) = u16::try_from(s.len()) else {
+        bail!("{} bytes is longer than MQTT allows", s.len());
+    };
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn config(port: u16) -> MqttConfig {
+        MqttConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            discovery_prefix: "homeassistant".to_string(),
+            topic_prefix: "lanotify".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(
+            publish_packet("a/b", b"home").unwrap(),
+            [0x31, 9, 0, 3, b'a', b'/', b'b', b'h', b'o', b'm', b'e']
+        );
+        let long = packet(0x30, vec![0; 200]);
+        assert_eq!(&long[..3], &[0x30, 0xC8, 0x01]);
+        let connect = connect_packet(&config(1883)).unwrap();
+        assert_eq!(&connect[2..10], &[0, 4, b'M', b'Q', b'T', b'T', 4, 0xC2]);
+        // the length of a string is encoded in 16 bits
+        assert!(publish_packet(&"a".repeat(70000), b"home").is_err());
+    }
+
+    #[test]
+    fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut connect = vec![0; header[1] as usize];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01");
+        let mut publisher = Publisher::new(config(port));
+        let device = |connected| Presence {
+            mac: &mac,
+            name: "Laptop".to_string(),
+            connected,
+        };
+        publisher.publish(&[device(true)]).unwrap();
+        publisher.publish(&[device(true)]).unwrap();
+        publisher.publish(&[device(false)]).unwrap();
+        drop(publisher);
+
+        let received = String::from_utf8_lossy(&broker.join().unwrap()).into_owned();
+        assert!(received.contains("homeassistant/device_tracker/lanotify_aabbccddee01/config"));
+        assert!(received.contains("\"state_topic\":\"lanotify/aabbccddee01/state\""));
+        assert_eq!(
+            received.matches("lanotify/aabbccddee01/statehome").count(),
+            1
+        );
+        assert_eq!(
+            received
+                .matches("lanotify/aabbccddee01/statenot_home")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,1173 @@
+use crate::Error;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Priority of a notification, as defined by ntfy
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Min,
+    Low,
+    #[default]
+    Default,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Min => "min",
+            Priority::Low => "low",
+            Priority::Default => "default",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
+/// A notification message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+    pub priority: Priority,
+    /// Overrides the URL of the ntfy notifier named by `notifier`, or else of the first ntfy
+    /// notifier
+    pub ntfy_url: Option<String>,
+    /// Name of the only notifier to send with, all notifiers if `None`
+    pub notifier: Option<String>,
+    /// URL to open when the notification is clicked
+    pub click: Option<String>,
+    pub actions: Vec<Action>,
+    /// ntfy tags, emoji shortcodes are shown in front of the title
+    pub tags: Vec<String>,
+    /// Sent regardless of the notification rate limit
+    pub critical: bool,
+    /// The connection change notified about, if any
+    pub transition: Option<Transition>,
+}
+
+/// A device that connected or disconnected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub mac: String,
+    pub connected: bool,
+}
+
+impl Transition {
+    /// Whether this change undoes `other`, so together they are no change at all
+    pub fn reverts(&self, other: &Transition) -> bool {
+        self.mac == other.mac && self.connected != other.connected
+    }
+}
+
+/// A button on a notification, see <https://docs.ntfy.sh/publish/#action-buttons>
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    pub action: ActionKind,
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    /// Opens the URL
+    View,
+    /// Sends a POST request to the URL
+    Http,
+}
+
+impl Action {
+    fn header(&self) -> String {
+        let kind = match self.action {
+            ActionKind::View => "view",
+            ActionKind::Http => "http",
+        };
+        format!("{kind}, {}, {}", quote(&self.label), quote(&self.url))
+    }
+}
+
+/// Quotes a value of the `Actions` header if it contains a separator or quote
+///
+/// ntfy has no escapes within quotes, so a value with both kinds of quotes loses its double ones.
+fn quote(value: &str) -> Cow<'_, str> {
+    if !value.contains([',', ';', '"', '\'']) && value.trim() == value {
+        Cow::Borrowed(value)
+    } else if !value.contains('"') {
+        Cow::Owned(format!("\"{value}\""))
+    } else if !value.contains('\'') {
+        Cow::Owned(format!("'{value}'"))
+    } else {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "")))
+    }
+}
+
+/// Something that delivers notification messages
+pub trait Notifier: Send + Sync {
+    /// Sends a message, returning a short description of the response such as the HTTP status
+    fn send(&self, message: &Message) -> Result<String, Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum NotifierConfig {
+    Ntfy {
+        /// Name to send only specific notifications with this notifier, such as escalations
+        #[serde(default)]
+        name: Option<String>,
+        url: String,
+        /// PEM file with the CA certificates to trust instead of the system ones
+        #[serde(default)]
+        ca_cert: Option<PathBuf>,
+        /// Accept any certificate, see `ntfy_insecure_skip_verify` in the config
+        #[serde(default)]
+        insecure_skip_verify: bool,
+    },
+    /// Sends messages to a Telegram chat with the Bot API, titles of high priority messages are
+    /// bold and low priority messages are sent silently
+    Telegram {
+        #[serde(default)]
+        name: Option<String>,
+        /// Token of the bot as given by BotFather
+        bot_token: String,
+        /// ID of the chat to send to, or `@username` of a channel
+        chat_id: String,
+        /// URL of the Bot API server, for a self-hosted one
+        #[serde(default = "default_telegram_api_url")]
+        api_url: String,
+    },
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+impl NotifierConfig {
+    pub fn build(&self) -> Result<Box<dyn Notifier>, Error> {
+        Ok(match self {
+            NotifierConfig::Ntfy {
+                url,
+                ca_cert,
+                insecure_skip_verify,
+                ..
+            } => Box::new(NtfyNotifier::new(
+                url.clone(),
+                ca_cert.as_ref(),
+                *insecure_skip_verify,
+            )?),
+            NotifierConfig::Telegram {
+                bot_token,
+                chat_id,
+                api_url,
+                ..
+            } => Box::new(TelegramNotifier::new(api_url, bot_token, chat_id.clone())),
+        })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            NotifierConfig::Ntfy { name, .. } | NotifierConfig::Telegram { name, .. } => {
+                name.as_deref()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for NotifierConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifierConfig::Ntfy { url, .. } => write!(f, "ntfy ({url})"),
+            // without the URL, which contains the bot token
+            NotifierConfig::Telegram { chat_id, .. } => write!(f, "telegram ({chat_id})"),
+        }
+    }
+}
+
+/// Where a notifier comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifierSource {
+    Config(NotifierConfig),
+    /// Added through the library API with `Daemon::add_notifier`, such as a [`Recorder`] in
+    /// tests, kept when the config is reloaded
+    Custom {
+        name: Option<String>,
+    },
+}
+
+impl NotifierSource {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            NotifierSource::Config(config) => config.name(),
+            NotifierSource::Custom { name } => name.as_deref(),
+        }
+    }
+
+    fn is_ntfy(&self) -> bool {
+        matches!(self, NotifierSource::Config(NotifierConfig::Ntfy { .. }))
+    }
+}
+
+impl std::fmt::Display for NotifierSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifierSource::Config(config) => config.fmt(f),
+            NotifierSource::Custom { name } => {
+                write!(f, "custom ({})", name.as_deref().unwrap_or("unnamed"))
+            }
+        }
+    }
+}
+
+/// The notifiers, shared with the queue worker so they can be replaced on reload
+pub type Notifiers = Arc<Mutex<Vec<(NotifierSource, Arc<dyn Notifier>)>>>;
+
+/// What to do when a message is pushed onto a full queue
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Wait until the worker has room for the message
+    #[default]
+    Block,
+    /// Discard the oldest queued message
+    DropOldest,
+}
+
+/// What to do with notifications over the rate limit
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAction {
+    /// Discard the notification, the number of discarded notifications is logged
+    #[default]
+    Drop,
+    /// Send the notification once the rate limit allows it
+    Defer,
+}
+
+/// Token bucket allowing bursts of up to `per_minute` notifications, refilled over a minute
+pub struct RateLimiter {
+    per_minute: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            tokens: per_minute as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one is available at `now`
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        let capacity = self.per_minute as f64;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * capacity / 60.0).min(capacity);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A bounded queue of messages that are sent by a worker thread
+///
+/// Remaining messages are sent before the queue is dropped, for at most [`DRAIN_TIMEOUT`] unless
+/// it is closed with another timeout first.
+pub struct Queue {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<Message>,
+    closed: bool,
+    // set by the worker once it sent the last message
+    stopped: bool,
+}
+
+/// Time a dropped [`Queue`] waits for the remaining messages to be sent
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the worker retries failed sends, and when it stops trying a notifier that keeps failing
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries of a failed send, after `retry_delay` doubled on every retry plus a random jitter
+    pub retries: u32,
+    pub retry_delay: Duration,
+    /// Consecutive failed sends after which the circuit of a notifier opens, never if 0
+    ///
+    /// While open, notifications for the notifier are held instead of sent. After `cooldown` the
+    /// oldest one is sent as a probe, which closes the circuit and sends the rest on success.
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Sending state of a notifier
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    // when the circuit opened or the last probe failed
+    opened: Option<Instant>,
+    held: VecDeque<Message>,
+}
+
+/// Sends the messages of the queue, keeping a circuit per notifier
+struct Worker {
+    notifiers: Notifiers,
+    policy: RetryPolicy,
+    capacity: usize,
+    circuits: Vec<(NotifierSource, Circuit)>,
+}
+
+impl Worker {
+    fn send(&mut self, message: Message) {
+        // not locked while sending, so reloading the notifiers doesn't wait for retries
+        let notifiers = self.notifiers.lock().unwrap().clone();
+        // forget the circuits of notifiers removed on reload
+        self.circuits
+            .retain(|(config, _)| notifiers.iter().any(|(c, _)| c == config));
+        // the URL override is for the notifier a message is routed to, or else the first ntfy one
+        let first_ntfy = notifiers.iter().position(|(config, _)| config.is_ntfy());
+        for (i, (config, notifier)) in notifiers.iter().enumerate() {
+            if message
+                .notifier
+                .as_deref()
+                .is_some_and(|name| config.name() != Some(name))
+            {
+                continue;
+            }
+            let mut message = message.clone();
+            if message.notifier.is_none() && first_ntfy != Some(i) {
+                message.ntfy_url = None;
+            }
+            let circuit = match self.circuits.iter().position(|(c, _)| c == config) {
+                Some(i) => &mut self.circuits[i].1,
+                None => {
+                    self.circuits.push((config.clone(), Circuit::default()));
+                    &mut self.circuits.last_mut().unwrap().1
+                }
+            };
+            if circuit.opened.is_some() {
+                hold(circuit, message, self.capacity, config);
+                continue;
+            }
+            match send_with_retries(notifier.as_ref(), &message, &self.policy) {
+                Ok(_) => circuit.failures = 0,
+                Err(e) => {
+                    log::error!("Failed to send notification with {}: {:#}", config, e);
+                    circuit.failures += 1;
+                    if self.policy.failure_threshold > 0
+                        && circuit.failures >= self.policy.failure_threshold
+                    {
+                        log::warn!(
+                            "Holding notifications for {} after {} failed sends, retrying in {}s",
+                            config,
+                            circuit.failures,
+                            self.policy.cooldown.as_secs()
+                        );
+                        circuit.opened = Some(Instant::now());
+                        hold(circuit, message, self.capacity, config);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the oldest held message of the circuits whose cooldown passed, and the others if
+    /// that succeeds
+    fn probe(&mut self) {
+        let notifiers = self.notifiers.lock().unwrap().clone();
+        for (config, circuit) in &mut self.circuits {
+            let Some(opened) = circuit.opened else {
+                continue;
+            };
+            if opened.elapsed() < self.policy.cooldown {
+                continue;
+            }
+            let Some((_, notifier)) = notifiers.iter().find(|(c, _)| c == config) else {
+                continue;
+            };
+            while let Some(message) = circuit.held.front() {
+                if let Err(e) = notifier.send(message) {
+                    log::warn!("Notifier {} is still failing: {:#}", config, e);
+                    circuit.opened = Some(Instant::now());
+                    break;
+                }
+                circuit.held.pop_front();
+            }
+            if circuit.held.is_empty() {
+                log::info!("Notifier {} works again", config);
+                circuit.opened = None;
+                circuit.failures = 0;
+            }
+        }
+    }
+
+    /// Time until the next circuit can be probed, `None` if all are closed
+    fn until_probe(&self) -> Option<Duration> {
+        self.circuits
+            .iter()
+            .filter_map(|(_, circuit)| circuit.opened)
+            .map(|opened| self.policy.cooldown.saturating_sub(opened.elapsed()))
+            .min()
+    }
+}
+
+fn hold(circuit: &mut Circuit, message: Message, capacity: usize, config: &NotifierSource) {
+    if circuit.held.len() >= capacity
+        && let Some(dropped) = circuit.held.pop_front()
+    {
+        log::warn!(
+            "Too many notifications held for {config}, dropping '{}'",
+            dropped.title
+        );
+    }
+    circuit.held.push_back(message);
+}
+
+fn send_with_retries(
+    notifier: &dyn Notifier,
+    message: &Message,
+    policy: &RetryPolicy,
+) -> Result<String, Error> {
+    let mut delay = policy.retry_delay;
+    let mut attempt = 0;
+    loop {
+        match notifier.send(message) {
+            Err(e) if attempt < policy.retries => {
+                log::debug!("Failed to send notification, retrying: {:#}", e);
+                // jitter so several instances don't retry in lockstep
+                thread::sleep(delay + crate::jitter(delay));
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Queue {
+    pub fn new(
+        notifiers: Notifiers,
+        capacity: usize,
+        overflow: Overflow,
+        policy: RetryPolicy,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow,
+        });
+        let worker = {
+            let shared = shared.clone();
+            let mut worker = Worker {
+                notifiers,
+                policy,
+                capacity: capacity.max(1),
+                circuits: Vec::new(),
+            };
+            thread::spawn(move || {
+                loop {
+                    match shared.pop(worker.until_probe()) {
+                        Some(message) => worker.send(message),
+                        None if shared.is_finished() => break,
+                        None => {}
+                    }
+                    worker.probe();
+                }
+                for (config, circuit) in &worker.circuits {
+                    if !circuit.held.is_empty() {
+                        log::warn!(
+                            "Dropping {} notifications held for {}",
+                            circuit.held.len(),
+                            config
+                        );
+                    }
+                }
+                shared.state.lock().unwrap().stopped = true;
+                shared.changed.notify_all();
+            })
+        };
+        Self {
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    pub fn push(&self, message: Message) {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.messages.len() >= self.shared.capacity {
+            match self.shared.overflow {
+                Overflow::Block => state = self.shared.changed.wait(state).unwrap(),
+                Overflow::DropOldest => {
+                    if let Some(dropped) = state.messages.pop_front() {
+                        log::warn!("Notification queue full, dropping '{}'", dropped.title);
+                    }
+                }
+            }
+        }
+        state.messages.push_back(message);
+        self.shared.changed.notify_all();
+    }
+
+    /// Removes the queued message about a change that `transition` undoes, returns whether there
+    /// was one
+    pub fn cancel(&self, transition: &Transition) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        let Some(i) = state.messages.iter().position(|message| {
+            message
+                .transition
+                .as_ref()
+                .is_some_and(|t| transition.reverts(t))
+        }) else {
+            return false;
+        };
+        state.messages.remove(i);
+        self.shared.changed.notify_all();
+        true
+    }
+
+    /// Stops the worker once the queued messages are sent, waiting at most `timeout` before
+    /// dropping the ones left
+    pub fn close(&mut self, timeout: Duration) {
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        self.shared.changed.notify_all();
+        if !state.messages.is_empty() {
+            log::info!(
+                "Sending {} queued notifications before exiting",
+                state.messages.len()
+            );
+        }
+        while !state.stopped {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        if !state.stopped {
+            // a send blocked on a slow server would otherwise keep lanotify from exiting
+            log::warn!(
+                "Dropping {} queued notifications that weren't sent within {:?}",
+                state.messages.len(),
+                timeout
+            );
+            state.messages.clear();
+            return;
+        }
+        drop(state);
+        let _ = worker.join();
+    }
+}
+
+impl Shared {
+    /// Takes the next message, waiting at most `timeout`, `None` if it passed or once the queue
+    /// is closed and empty
+    fn pop(&self, timeout: Option<Duration>) -> Option<Message> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                self.changed.notify_all();
+                return Some(message);
+            }
+            if state.closed {
+                return None;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.changed.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.changed.wait(state).unwrap(),
+            };
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.closed && state.messages.is_empty()
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        self.close(DRAIN_TIMEOUT);
+    }
+}
+
+pub struct NtfyNotifier {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl NtfyNotifier {
+    pub fn new(
+        url: String,
+        ca_cert: Option<&PathBuf>,
+        insecure_skip_verify: bool,
+    ) -> Result<Self, Error> {
+        let tls = tls_config(&url, ca_cert, insecure_skip_verify).map_err(Error::Notify)?;
+        let agent = ureq::Agent::config_builder()
+            .tls_config(tls)
+            .build()
+            .new_agent();
+        Ok(Self { url, agent })
+    }
+}
+
+/// Builds the TLS config, trusting the certificates of `ca_cert` instead of the system ones if set
+fn tls_config(
+    url: &str,
+    ca_cert: Option<&PathBuf>,
+    insecure_skip_verify: bool,
+) -> Result<ureq::tls::TlsConfig> {
+    let mut tls = ureq::tls::TlsConfig::builder();
+    if let Some(path) = ca_cert {
+        let pem = fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate '{}'", path.display()))?;
+        let certs = ureq::tls::parse_pem(&pem)
+            .filter_map(|item| match item {
+                Ok(ureq::tls::PemItem::Certificate(cert)) => Some(Ok(cert)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid CA certificate '{}'", path.display()))?;
+        if certs.is_empty() {
+            bail!("No certificates in '{}'", path.display());
+        }
+        tls = tls.root_certs(ureq::tls::RootCerts::new_with_certs(&certs));
+    }
+    if insecure_skip_verify {
+        log::warn!("TLS certificate verification is disabled for {url}");
+        tls = tls.disable_verification(true);
+    }
+    Ok(tls.build())
+}
+
+impl Notifier for NtfyNotifier {
+    fn send(&self, message: &Message) -> Result<String, Error> {
+        let url = message.ntfy_url.as_ref().unwrap_or(&self.url);
+        let mut request = self
+            .agent
+            .post(url)
+            .header("Title", &message.title)
+            .header("X-Priority", message.priority.as_str());
+        if let Some(click) = &message.click {
+            request = request.header("Click", click);
+        }
+        if !message.actions.is_empty() {
+            let actions: Vec<String> = message.actions.iter().map(Action::header).collect();
+            request = request.header("Actions", actions.join("; "));
+        }
+        if !message.tags.is_empty() {
+            request = request.header("Tags", message.tags.join(","));
+        }
+        let resp = request
+            .send(&message.body)
+            .map_err(|e| Error::Notify(e.into()))?;
+        log::debug!("Notification sent to {}: {}", url, resp.status());
+        Ok(format!("HTTP {}", resp.status()))
+    }
+}
+
+/// Keeps the messages instead of sending them, to assert on the notifications in tests
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    messages: Arc<Mutex<Vec<Message>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The messages sent so far, oldest first
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+impl Notifier for Recorder {
+    fn send(&self, message: &Message) -> Result<String, Error> {
+        self.messages.lock().unwrap().push(message.clone());
+        Ok("recorded".to_string())
+    }
+}
+
+/// Waits with every send until released, to keep messages in the queue in tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Stalled(Arc<(Mutex<bool>, Condvar)>);
+
+#[cfg(test)]
+impl Stalled {
+    pub fn release(&self) {
+        *self.0.0.lock().unwrap() = true;
+        self.0.1.notify_all();
+    }
+}
+
+#[cfg(test)]
+impl Notifier for Stalled {
+    fn send(&self, _: &Message) -> Result<String, Error> {
+        let (released, changed) = &*self.0;
+        let _released = changed
+            .wait_while(released.lock().unwrap(), |released| !*released)
+            .unwrap();
+        Ok("released".to_string())
+    }
+}
+
+pub struct TelegramNotifier {
+    /// `sendMessage` endpoint of the bot
+    url: String,
+    chat_id: String,
+    agent: ureq::Agent,
+}
+
+impl TelegramNotifier {
+    pub fn new(api_url: &str, bot_token: &str, chat_id: String) -> Self {
+        Self {
+            url: format!(
+                "{}/bot{}/sendMessage",
+                api_url.trim_end_matches('/'),
+                bot_token
+            ),
+            chat_id,
+            agent: ureq::Agent::new_with_defaults(),
+        }
+    }
+}
+
+/// Formats a message as Telegram HTML, with a bold title for high priority messages
+fn telegram_text(message: &Message) -> String {
+    let title = escape_html(&message.title);
+    let title = if message.priority >= Priority::High {
+        format!("<b>{title}</b>")
+    } else {
+        title
+    };
+    let mut text = format!("{title}\n{}", escape_html(&message.body));
+    if let Some(click) = &message.click {
+        text += &format!("\n{}", escape_html(click));
+    }
+    text
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Notifier for TelegramNotifier {
+    fn send(&self, message: &Message) -> Result<String, Error> {
+        let body = serde_json::json!({
+            "chat_id": self.chat_id,
+            "text": telegram_text(message),
+            "parse_mode": "HTML",
+            "disable_notification": message.priority <= Priority::Low,
+        });
+        let resp = self
+            .agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(&body.to_string())
+            .map_err(|e| Error::Notify(e.into()))?;
+        log::debug!("Notification sent to Telegram: {}", resp.status());
+        Ok(format!("HTTP {}", resp.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::mpsc::{Sender, channel},
+        time::Duration,
+    };
+
+    struct ChannelNotifier(Mutex<Sender<String>>);
+
+    impl Notifier for ChannelNotifier {
+        fn send(&self, message: &Message) -> Result<String, Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .send(message.title.clone())
+                .map_err(|e| Error::Notify(e.into()))?;
+            Ok("sent".to_string())
+        }
+    }
+
+    fn message(title: &str) -> Message {
+        Message {
+            title: title.to_string(),
+            body: String::new(),
+            priority: Priority::Default,
+            ntfy_url: None,
+            notifier: None,
+            click: None,
+            actions: Vec::new(),
+            tags: Vec::new(),
+            critical: false,
+            ..Default::default()
+        }
+    }
+
+    fn queue(overflow: Overflow) -> (Queue, Notifiers, std::sync::mpsc::Receiver<String>) {
+        let (sender, receiver) = channel();
+        let notifier: Arc<dyn Notifier> = Arc::new(ChannelNotifier(Mutex::new(sender)));
+        let config = NotifierSource::Custom { name: None };
+        let notifiers = Arc::new(Mutex::new(vec![(config, notifier)]));
+        (
+            Queue::new(notifiers.clone(), 2, overflow, RetryPolicy::default()),
+            notifiers,
+            receiver,
+        )
+    }
+
+    /// Adds a [`Stalled`] notifier in front of the others
+    fn stall(notifiers: &Notifiers) -> Stalled {
+        let stalled = Stalled::default();
+        let config = NotifierSource::Custom { name: None };
+        notifiers
+            .lock()
+            .unwrap()
+            .insert(0, (config, Arc::new(stalled.clone())));
+        stalled
+    }
+
+    #[test]
+    fn test_action_header() {
+        let action = Action {
+            action: ActionKind::Http,
+            label: "Wake".to_string(),
+            url: "http://lanotify/wake/aa:bb:cc:dd:ee:01".to_string(),
+        };
+        assert_eq!(
+            action.header(),
+            "http, Wake, http://lanotify/wake/aa:bb:cc:dd:ee:01"
+        );
+
+        // separators in a value
+        let action = Action {
+            action: ActionKind::View,
+            label: "Open \"Router; admin\"".to_string(),
+            url: "http://router/?a=1,2".to_string(),
+        };
+        assert_eq!(
+            action.header(),
+            "view, 'Open \"Router; admin\"', \"http://router/?a=1,2\""
+        );
+        assert_eq!(quote(" padded"), "\" padded\"");
+        assert_eq!(quote("it's \"x\""), "\"it's x\"");
+    }
+
+    #[test]
+    fn test_ntfy_ca_cert() {
+        let path = std::env::temp_dir().join(format!("lanotify-test-{}.pem", std::process::id()));
+        fs::write(&path, "not a certificate").unwrap();
+        assert!(NtfyNotifier::new(String::new(), Some(&path), false).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(NtfyNotifier::new(String::new(), Some(&path), false).is_err());
+        assert!(NtfyNotifier::new(String::new(), None, true).is_ok());
+    }
+
+    #[test]
+    fn test_telegram() {
+        use std::{io::Write, net::TcpListener};
+        let config: NotifierConfig =
+            toml::from_str("type = \"telegram\"\nbot_token = \"123:abc\"\nchat_id = \"-100200\"")
+                .unwrap();
+        assert_eq!(config.to_string(), "telegram (-100200)");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = crate::test_http::read_json_request(&mut stream);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n{\"ok\":true}")
+                .unwrap();
+            request
+        });
+        let notifier = TelegramNotifier::new(&api_url, "123:abc", "-100200".to_string());
+        let mut message = message("Device <Laptop> disconnected");
+        message.priority = Priority::High;
+        message.body = "Gone & away".to_string();
+        assert_eq!(notifier.send(&message).unwrap(), "HTTP 200 OK");
+
+        let (request, body) = server.join().unwrap();
+        assert!(request.starts_with("POST /bot123:abc/sendMessage HTTP/1.1"));
+        assert_eq!(body["chat_id"], "-100200");
+        assert_eq!(
+            body["text"],
+            "<b>Device &lt;Laptop&gt; disconnected</b>\nGone &amp; away"
+        );
+        assert_eq!(body["disable_notification"], false);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(2);
+        limiter.last_refill = start;
+        assert!(limiter.try_acquire(start));
+        assert!(limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start));
+        // one token is refilled every 30 seconds
+        assert!(!limiter.try_acquire(start + Duration::from_secs(20)));
+        assert!(limiter.try_acquire(start + Duration::from_secs(30)));
+        assert!(!limiter.try_acquire(start + Duration::from_secs(30)));
+        // the bucket doesn't fill beyond its capacity
+        let later = start + Duration::from_secs(600);
+        assert!(limiter.try_acquire(later));
+        assert!(limiter.try_acquire(later));
+        assert!(!limiter.try_acquire(later));
+    }
+
+    #[test]
+    fn test_queue_flushes_on_drop() {
+        let (queue, _, receiver) = queue(Overflow::Block);
+        for title in ["a", "b", "c", "d"] {
+            queue.push(message(title));
+        }
+        drop(queue);
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_ntfy_url_override() {
+        let ntfy = |name: &str| {
+            NotifierSource::Config(NotifierConfig::Ntfy {
+                name: Some(name.to_string()),
+                url: format!("https://ntfy.sh/{name}"),
+                ca_cert: None,
+                insecure_skip_verify: false,
+            })
+        };
+        let (first, second) = (Recorder::new(), Recorder::new());
+        let notifiers: Notifiers = Arc::new(Mutex::new(vec![
+            (
+                NotifierSource::Custom { name: None },
+                Arc::new(Recorder::new()),
+            ),
+            (ntfy("first"), Arc::new(first.clone())),
+            (ntfy("second"), Arc::new(second.clone())),
+        ]));
+        let queue = Queue::new(notifiers, 2, Overflow::Block, RetryPolicy::default());
+        let mut device = message("device");
+        device.ntfy_url = Some("https://ntfy.sh/device".to_string());
+        queue.push(device.clone());
+        device.notifier = Some("second".to_string());
+        queue.push(device);
+        drop(queue);
+        let urls = |recorder: &Recorder| -> Vec<Option<String>> {
+            recorder
+                .messages()
+                .into_iter()
+                .map(|m| m.ntfy_url)
+                .collect()
+        };
+        let url = Some("https://ntfy.sh/device".to_string());
+        assert_eq!(urls(&second), [None, url.clone()]);
+        assert_eq!(urls(&first), [url]);
+    }
+
+    #[test]
+    fn test_queue_close_timeout() {
+        let (mut queue, notifiers, receiver) = queue(Overflow::Block);
+        let stalled = stall(&notifiers);
+        queue.push(message("a"));
+        queue.push(message("b"));
+        let start = Instant::now();
+        queue.close(Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the remaining messages were dropped instead of sent once the worker continues
+        stalled.release();
+        thread::sleep(Duration::from_millis(50));
+        assert!(receiver.try_iter().all(|title| title == "a"));
+    }
+
+    #[test]
+    fn test_queue_drop_oldest() {
+        let (queue, notifiers, receiver) = queue(Overflow::DropOldest);
+        // the worker can't make progress
+        let stalled = stall(&notifiers);
+        for title in ["a", "b", "c", "d", "e"] {
+            queue.push(message(title));
+        }
+        stalled.release();
+        drop(queue);
+        let sent: Vec<String> = receiver.try_iter().collect();
+        // the worker may have taken the first message before the queue filled up
+        assert!(sent.ends_with(&["d".to_string(), "e".to_string()]));
+        assert!(sent.len() <= 3);
+    }
+
+    #[test]
+    fn test_send_without_lock() {
+        let (queue, notifiers, receiver) = queue(Overflow::Block);
+        let stalled = stall(&notifiers);
+        queue.push(message("slow"));
+        // the worker is busy sending, while the notifiers can still be replaced
+        thread::sleep(Duration::from_millis(50));
+        assert!(notifiers.try_lock().is_ok());
+        stalled.release();
+        drop(queue);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["slow"]);
+    }
+
+    #[test]
+    fn test_queue_cancel() {
+        let (queue, notifiers, receiver) = queue(Overflow::Block);
+        let stalled = stall(&notifiers);
+        let transition = |connected| Transition {
+            mac: "aa:bb:cc:dd:ee:01".to_string(),
+            connected,
+        };
+        // the worker may take the first message and wait until released
+        queue.push(message("first"));
+        let mut disconnected = message("disconnected");
+        disconnected.transition = Some(transition(false));
+        queue.push(disconnected);
+        assert!(!queue.cancel(&transition(false)));
+        let other = Transition {
+            mac: "aa:bb:cc:dd:ee:02".to_string(),
+            connected: true,
+        };
+        assert!(!queue.cancel(&other));
+        assert!(queue.cancel(&transition(true)));
+        assert!(!queue.cancel(&transition(true)));
+        stalled.release();
+        drop(queue);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["first"]);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        struct FlakyNotifier {
+            up: Arc<AtomicBool>,
+            attempts: Arc<Mutex<Vec<String>>>,
+            sent: Mutex<Sender<String>>,
+        }
+
+        impl Notifier for FlakyNotifier {
+            fn send(&self, message: &Message) -> Result<String, Error> {
+                self.attempts.lock().unwrap().push(message.title.clone());
+                if !self.up.load(Ordering::SeqCst) {
+                    return Err(Error::Notify(anyhow::anyhow!("connection refused")));
+                }
+                self.sent
+                    .lock()
+                    .unwrap()
+                    .send(message.title.clone())
+                    .unwrap();
+                Ok("sent".to_string())
+            }
+        }
+
+        let up = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let (sender, receiver) = channel();
+        let notifier: Arc<dyn Notifier> = Arc::new(FlakyNotifier {
+            up: up.clone(),
+            attempts: attempts.clone(),
+            sent: Mutex::new(sender),
+        });
+        let config = NotifierSource::Custom { name: None };
+        let policy = RetryPolicy {
+            retries: 1,
+            retry_delay: Duration::from_millis(1),
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(300),
+        };
+        let queue = Queue::new(
+            Arc::new(Mutex::new(vec![(config, notifier)])),
+            10,
+            Overflow::Block,
+            policy,
+        );
+        for title in ["a", "b", "c"] {
+            queue.push(message(title));
+        }
+        // until the circuit opened after the retried sends of "a" and "b"
+        while attempts.lock().unwrap().len() < 4 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(*attempts.lock().unwrap(), ["a", "a", "b", "b"]);
+        up.store(true, Ordering::SeqCst);
+
+        // the probe sends the held messages in order, "a" was given up on
+        let sent: Vec<String> = (0..2)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert_eq!(sent, ["b", "c"]);
+        queue.push(message("d"));
+        drop(queue);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["d"]);
+        assert_eq!(
+            *attempts.lock().unwrap(),
+            ["a", "a", "b", "b", "b", "c", "d"]
+        );
+    }
+}
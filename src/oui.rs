@@ -0,0 +1,73 @@
+use crate::MacAddr;
+use anyhow::{Context, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Vendors by the first three octets of MAC addresses, from the IEEE `oui.txt`
+#[derive(Debug, Default)]
+pub struct Vendors {
+    vendors: HashMap<[u8; 3], String>,
+}
+
+impl Vendors {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OUI file '{}'", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parses the `XX-XX-XX (hex) Vendor` lines, ignoring everything else
+    pub fn parse(contents: &str) -> Self {
+        let vendors = contents
+            .lines()
+            .filter_map(|line| {
+                let (prefix, vendor) = line.split_once("(hex)")?;
+                let mut octets = [0; 3];
+                let mut parts = prefix.trim().split('-');
+                for octet in &mut octets {
+                    *octet = u8::from_str_radix(parts.next()?, 16).ok()?;
+                }
+                let vendor = vendor.trim();
+                (parts.next().is_none() && !vendor.is_empty()).then(|| (octets, vendor.to_string()))
+            })
+            .collect();
+        Self { vendors }
+    }
+
+    pub fn get(&self, mac: &MacAddr) -> Option<&str> {
+        let octets = mac.octets().ok()?;
+        self.vendors
+            .get(&[octets[0], octets[1], octets[2]])
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let vendors = Vendors::parse(
+            "OUI/MA-L                                                    Organization\n\
+             company_id                                                  Organization\n\
+                                                                         Address\n\
+             \n\
+             28-6F-B9   (hex)\t\tNokia Shanghai Bell Co., Ltd.\n\
+             286FB9     (base 16)\t\tNokia Shanghai Bell Co., Ltd.\n\
+             \t\t\t\tNo.388 Ning Qiao Road,Jin Qiao Pudong Shanghai\n\
+             \n\
+             08-EA-44   (hex)\t\tExtreme Networks Headquarters\n\
+             08EA44     (base 16)\t\tExtreme Networks Headquarters\n",
+        );
+        assert_eq!(vendors.vendors.len(), 2);
+        assert_eq!(
+            vendors.get(&"28:6f:b9:01:02:03".parse().unwrap()),
+            Some("Nokia Shanghai Bell Co., Ltd.")
+        );
+        assert_eq!(
+            vendors.get(&"08:EA:44:aa:bb:cc".parse().unwrap()),
+            Some("Extreme Networks Headquarters")
+        );
+        assert_eq!(vendors.get(&"aa:bb:cc:dd:ee:01".parse().unwrap()), None);
+    }
+}
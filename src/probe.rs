@@ -0,0 +1,31 @@
+use std::{
+    net::{IpAddr, SocketAddr, TcpStream},
+    time::Duration,
+};
+
+/// Checks whether a device accepts TCP connections on a port, such as a printer on 9100
+pub fn tcp(ip: IpAddr, port: u16, timeout: Duration) -> bool {
+    match TcpStream::connect_timeout(&SocketAddr::from((ip, port)), timeout) {
+        Ok(_) => true,
+        Err(e) => {
+            log::debug!("TCP probe of {ip}:{port} failed: {e}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let timeout = Duration::from_millis(500);
+        assert!(tcp(Ipv4Addr::LOCALHOST.into(), port, timeout));
+        drop(listener);
+        assert!(!tcp(Ipv4Addr::LOCALHOST.into(), port, timeout));
+    }
+}
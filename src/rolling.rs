@@ -0,0 +1,80 @@
+/// Fraction of the samples that are `true`, `None` without samples
+pub fn rate(samples: impl IntoIterator<Item = bool>) -> Option<f64> {
+    mean(samples.into_iter().map(f64::from))
+}
+
+/// Exponentially weighted fraction of the samples that are `true`, for samples ordered newest
+/// first each older one has `decay` times the weight of the next
+pub fn weighted_rate(samples: impl IntoIterator<Item = bool>, decay: f64) -> Option<f64> {
+    weighted_mean(samples.into_iter().map(f64::from), decay)
+}
+
+fn mean(values: impl IntoIterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values
+        .into_iter()
+        .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Mean in which each value has `decay` times the weight of the one before it
+pub fn weighted_mean(values: impl IntoIterator<Item = f64>, decay: f64) -> Option<f64> {
+    let (mut sum, mut total, mut weight) = (0.0, 0.0, 1.0);
+    for v in values {
+        sum += v * weight;
+        total += weight;
+        weight *= decay;
+    }
+    (total > 0.0).then(|| sum / total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, to check properties on many sequences without extra dependencies
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn test_batch() {
+        assert_eq!(rate([]), None);
+        assert_eq!(rate([true, false, true, true]), Some(0.75));
+        assert_eq!(weighted_rate([true, false], 0.5), Some(1.0 / 1.5));
+        assert_eq!(mean([2.0, 4.0]), Some(3.0));
+        assert_eq!(mean([]), None);
+    }
+
+    #[test]
+    fn test_rate_properties() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..100 {
+            let samples: Vec<bool> = (0..=(random.next() % 40))
+                .map(|_| random.next().is_multiple_of(3))
+                .collect();
+            let flat = rate(samples.iter().copied()).unwrap();
+            // without decay every sample weighs the same
+            assert!(close(
+                weighted_rate(samples.iter().copied(), 1.0).unwrap(),
+                flat
+            ));
+            let weighted = weighted_rate(samples.iter().copied(), 0.8).unwrap();
+            assert!((0.0..=1.0).contains(&weighted));
+            // a new sighting never lowers the rate
+            let mut newer = samples.clone();
+            newer.insert(0, true);
+            assert!(weighted_rate(newer, 0.8).unwrap() >= weighted);
+        }
+    }
+}
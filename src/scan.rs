@@ -0,0 +1,1818 @@
+use crate::{Device, Error, MacAddr};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_with::serde_as;
+use std::{
+    collections::{HashSet, VecDeque},
+    env,
+    ffi::CString,
+    fs,
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+/// Time after which a scan command is killed, unless changed with [`Scanner::set_timeout`]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+// how often a running scan command is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// capability to open raw sockets, as needed by arp-scan
+const CAP_NET_RAW: u32 = 13;
+
+// accepted ranges of the arp-scan tuning options
+const MAX_SCAN_RATE: u32 = 100_000;
+const MAX_SCAN_RETRIES: u32 = 10;
+const MIN_SCAN_TIMEOUT: Duration = Duration::from_millis(10);
+const MAX_SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A source of network scans
+pub trait Scanner {
+    /// Performs a single scan, returns `None` when the source is exhausted
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error>;
+
+    /// Whether [`Scanner::scan_targets`] scans only the given addresses
+    fn supports_targets(&self) -> bool {
+        false
+    }
+
+    /// Scans only the given addresses, scanners that can't do so perform a full scan
+    fn scan_targets(&mut self, _targets: &[IpAddr]) -> Result<Option<Vec<Device>>, Error> {
+        self.scan()
+    }
+
+    /// Sets the time after which a hanging scan command is killed and the scan fails, for
+    /// scanners that run one
+    fn set_timeout(&mut self, _timeout: Duration) {}
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScannerConfig {
+    ArpScan {
+        /// Interfaces to scan, recording which interface a device is on
+        #[serde(default)]
+        interfaces: Vec<String>,
+        /// Path of the arp-scan binary, looked up in `PATH` by default
+        #[serde(default)]
+        binary: Option<PathBuf>,
+        /// Packets sent per second, up to 100000
+        ///
+        /// A higher rate keeps scans short on fast networks, a lower rate avoids losing replies
+        /// on congested or wireless networks. Defaults to the 256 kbit/s bandwidth of arp-scan.
+        #[serde(default)]
+        rate: Option<u32>,
+        /// Extra requests to hosts that didn't reply, up to 10, 1 by default
+        ///
+        /// More retries reduce false misses of devices that drop packets but make scans of
+        /// networks with many unused addresses slower.
+        #[serde(default)]
+        retries: Option<u32>,
+        /// Time to wait for a reply before retrying, in milliseconds, 500 by default
+        #[serde_as(as = "Option<serde_with::DurationMilliSeconds<u64>>")]
+        #[serde(default)]
+        timeout: Option<Duration>,
+        /// Columns of the plain output of arp-scan, for versions without the `--format` option
+        ///
+        /// When set, arp-scan is run without `--format` and `--rtt` and its output is read with
+        /// these columns.
+        #[serde(default)]
+        format: Option<ScanFormat>,
+    },
+    /// Reads the neighbour table of the kernel with `ip neigh`, which doesn't need privileges
+    ///
+    /// Only devices the host recently communicated with are listed, and without their vendor.
+    IpNeigh {
+        /// Path of the `ip` binary, looked up in `PATH` by default
+        #[serde(default)]
+        binary: Option<PathBuf>,
+    },
+    /// Reads the IPv6 neighbour table of the kernel with `ip -6 neigh`, as discovered by NDP,
+    /// for devices that only use IPv6
+    ///
+    /// Like with `ip_neigh`, only devices the host recently communicated with are listed. A device
+    /// with several addresses is listed with its link-local address.
+    Ndp {
+        /// Path of the `ip` binary, looked up in `PATH` by default
+        #[serde(default)]
+        binary: Option<PathBuf>,
+    },
+    /// Replays scans from a fixture file, see [`FixtureScanner`]
+    Fixture { path: PathBuf },
+    /// Runs an external command that outputs devices in the tab separated `ip mac vendor` format
+    Command {
+        command: Vec<String>,
+        /// Columns of the output, if they differ from the default format
+        #[serde(default)]
+        format: ScanFormat,
+    },
+    /// Runs a scan command on another host over SSH, such as a router on the network to scan
+    ///
+    /// The command is interpreted by the remote shell and has to output the same format as for
+    /// the `command` scanner. Only key based authentication is supported.
+    Ssh {
+        host: String,
+        user: Option<String>,
+        port: Option<u16>,
+        /// Private key to authenticate with, defaults to the keys of the SSH client config
+        key: Option<PathBuf>,
+        command: Vec<String>,
+        /// Path of the `ssh` binary, looked up in `PATH` by default
+        #[serde(default)]
+        binary: Option<PathBuf>,
+        /// Columns of the output, if they differ from the default format
+        #[serde(default)]
+        format: ScanFormat,
+    },
+    /// Lists the wifi clients associated with an OpenWrt router through its ubus JSON-RPC API,
+    /// which doesn't need privileges on this host
+    ///
+    /// Devices are present while they are associated, with the wifi interface and signal strength
+    /// they are associated with. Their IP address and hostname are taken from the DHCP leases of
+    /// the router. The user needs rpcd ACLs for `iwinfo` and `luci-rpc`.
+    #[serde(rename = "openwrt")]
+    OpenWrt {
+        /// URL of the ubus endpoint, e.g. `http://192.168.1.1/ubus`
+        url: String,
+        username: String,
+        password: String,
+    },
+    /// Runs several scanners at the same time and merges what they found, a device found by any
+    /// of them is present
+    ///
+    /// Where scanners report the same device, the fields of the first one in the list win and the
+    /// others fill in what it left out. The scan only fails when all scanners fail. Interfaces of
+    /// arp-scan scanners in the list are not checked for being down, such a scanner fails on its
+    /// own while the others go on.
+    Parallel {
+        scanners: Vec<ScannerConfig>,
+        /// Time in seconds after which a hanging scanner is given up on, defaults to
+        /// `scan_hard_timeout`
+        #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+        #[serde(default)]
+        timeout: Option<Duration>,
+    },
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        ScannerConfig::ArpScan {
+            interfaces: Vec::new(),
+            binary: None,
+            rate: None,
+            retries: None,
+            timeout: None,
+            format: None,
+        }
+    }
+}
+
+impl ScannerConfig {
+    /// Local interfaces that are scanned, empty for the default interface and remote scans
+    pub fn interfaces(&self) -> &[String] {
+        match self {
+            ScannerConfig::ArpScan { interfaces, .. } => interfaces,
+            _ => &[],
+        }
+    }
+
+    pub fn build(&self) -> Result<Box<dyn Scanner + Send>, Error> {
+        Ok(match self {
+            ScannerConfig::ArpScan {
+                interfaces,
+                binary,
+                rate,
+                retries,
+                timeout,
+                format,
+            } => {
+                let options = arp_scan_options(*rate, *retries, *timeout).map_err(Error::Scan)?;
+                Box::new(ArpScanner {
+                    program: check_arp_scan(binary.as_ref()).map_err(Error::Scan)?,
+                    interfaces: interfaces.clone(),
+                    options,
+                    format: format.clone(),
+                    timeout: DEFAULT_TIMEOUT,
+                })
+            }
+            ScannerConfig::IpNeigh { binary } | ScannerConfig::Ndp { binary } => {
+                let ipv6 = matches!(self, ScannerConfig::Ndp { .. });
+                let program = match binary {
+                    Some(path) => check_binary(path).map_err(Error::Scan)?,
+                    None => find_executable("ip").ok_or_else(|| {
+                        Error::Scan(anyhow!(
+                            "'ip' command not found, install iproute2 to use the {} scanner",
+                            if ipv6 { "ndp" } else { "ip_neigh" }
+                        ))
+                    })?,
+                };
+                Box::new(IpNeighScanner {
+                    program,
+                    ipv6,
+                    timeout: DEFAULT_TIMEOUT,
+                })
+            }
+            ScannerConfig::Fixture { path } => Box::new(FixtureScanner::load(path)?),
+            ScannerConfig::Command { command, format } => {
+                Box::new(CommandScanner::new(command.clone(), format.clone())?)
+            }
+            ScannerConfig::Ssh {
+                host,
+                user,
+                port,
+                key,
+                command,
+                binary,
+                format,
+            } => {
+                if command.is_empty() {
+                    return Err(Error::Scan(anyhow!("Remote scan command is empty")));
+                }
+                let program = match binary {
+                    Some(path) => check_binary(path).map_err(Error::Scan)?,
+                    None => PathBuf::from("ssh"),
+                };
+                Box::new(CommandScanner::new(
+                    ssh_command(
+                        &program.display().to_string(),
+                        host,
+                        user.as_deref(),
+                        *port,
+                        key.as_ref(),
+                        command,
+                    ),
+                    format.clone(),
+                )?)
+            }
+            ScannerConfig::OpenWrt {
+                url,
+                username,
+                password,
+            } => Box::new(OpenWrtScanner::new(
+                url.clone(),
+                username.clone(),
+                password.clone(),
+            )),
+            ScannerConfig::Parallel { scanners, timeout } => {
+                if scanners.is_empty() {
+                    return Err(Error::Scan(anyhow!("No scanners to run in parallel")));
+                }
+                Box::new(ParallelScanner {
+                    scanners: scanners
+                        .iter()
+                        .map(ScannerConfig::build)
+                        .collect::<Result<_, _>>()?,
+                    timeout: *timeout,
+                })
+            }
+        })
+    }
+}
+
+/// Checks the operational state of a network interface in `/sys/class/net` or another `root`
+///
+/// Interfaces that report an unknown state are considered up, as are all interfaces when there is
+/// no such directory, e.g. on other platforms than Linux.
+pub fn is_interface_up(root: &Path, interface: &str) -> bool {
+    match fs::read_to_string(root.join(interface).join("operstate")) {
+        Ok(state) => !matches!(
+            state.trim(),
+            "down" | "lowerlayerdown" | "notpresent" | "dormant"
+        ),
+        Err(_) => !root.is_dir(),
+    }
+}
+
+/// The scanning host itself and its default gateway, which show up in most scans
+#[derive(Debug, Default)]
+pub struct LocalAddrs {
+    /// MAC addresses of the network interfaces of this host
+    pub macs: HashSet<MacAddr>,
+    /// IPv4 address of the default gateway
+    pub gateway: Option<Ipv4Addr>,
+    /// Network interface of the default route
+    pub interface: Option<String>,
+}
+
+impl LocalAddrs {
+    /// Reads the interfaces from `/sys/class/net` or another `root` and the default route from
+    /// `/proc/net/route` or another `route` file, both are empty on other platforms than Linux
+    pub fn detect(root: &Path, route: &Path) -> Self {
+        let macs = fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("address")).ok())
+            .filter_map(|address| address.trim().parse::<MacAddr>().ok())
+            // loopback interfaces
+            .filter(|mac| mac.as_str() != "00:00:00:00:00:00")
+            .collect();
+        let (interface, gateway) = match fs::read_to_string(route) {
+            Ok(contents) => match parse_default_route(&contents) {
+                Some((interface, gateway)) => (Some(interface.to_string()), gateway),
+                None => (None, None),
+            },
+            Err(_) => (None, None),
+        };
+        Self {
+            macs,
+            gateway,
+            interface,
+        }
+    }
+
+    pub fn contains(&self, device: &Device) -> bool {
+        self.macs.contains(&device.mac) || self.gateway.is_some_and(|gateway| device.ip == gateway)
+    }
+}
+
+/// Parses the interface and gateway of the default route from the `Iface Destination Gateway ...`
+/// lines of `/proc/net/route`, where addresses are hexadecimal in the byte order of the host
+///
+/// The gateway is `None` for a route without one, such as of a point-to-point link.
+fn parse_default_route(contents: &str) -> Option<(&str, Option<Ipv4Addr>)> {
+    contents.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        if fields.next()? != "00000000" {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let gateway = (gateway != 0).then(|| Ipv4Addr::from(gateway.to_ne_bytes()));
+        Some((interface, gateway))
+    })
+}
+
+/// Checks whether arp-scan is installed and allowed to scan, returning the path to run
+fn check_arp_scan(binary: Option<&PathBuf>) -> Result<PathBuf> {
+    let program = match binary {
+        Some(path) => check_binary(path)?,
+        None => find_executable("arp-scan").context(
+            "arp-scan not found, install it, set `binary` to its path or use another scanner such \
+             as type = \"ip_neigh\"",
+        )?,
+    };
+    if !can_open_raw_sockets(&program) {
+        bail!(
+            "lanotify needs CAP_NET_RAW or to run as root for arp-scan, consider using \
+             type = \"ip_neigh\" as scanner which works unprivileged"
+        );
+    }
+    Ok(program)
+}
+
+/// Validates the tuning options of the arp-scan scanner and translates them into its arguments
+fn arp_scan_options(
+    rate: Option<u32>,
+    retries: Option<u32>,
+    timeout: Option<Duration>,
+) -> Result<Vec<String>> {
+    let mut options = Vec::new();
+    if let Some(rate) = rate {
+        if !(1..=MAX_SCAN_RATE).contains(&rate) {
+            bail!("Scan rate must be between 1 and {MAX_SCAN_RATE} packets per second");
+        }
+        // the interval between packets in microseconds
+        options.push(format!("--interval={}u", 1_000_000 / rate));
+    }
+    if let Some(retries) = retries {
+        if retries > MAX_SCAN_RETRIES {
+            bail!("Scan retries must be at most {MAX_SCAN_RETRIES}");
+        }
+        // arp-scan counts the first request as well
+        options.push(format!("--retry={}", retries + 1));
+    }
+    if let Some(timeout) = timeout {
+        if !(MIN_SCAN_TIMEOUT..=MAX_SCAN_TIMEOUT).contains(&timeout) {
+            bail!(
+                "Scan timeout must be between {} ms and {} s",
+                MIN_SCAN_TIMEOUT.as_millis(),
+                MAX_SCAN_TIMEOUT.as_secs()
+            );
+        }
+        options.push(format!("--timeout={}", timeout.as_millis()));
+    }
+    Ok(options)
+}
+
+/// Checks that a configured binary is an executable file
+fn check_binary(path: &Path) -> Result<PathBuf> {
+    if !is_executable_file(path) {
+        bail!("'{}' is not an executable file", path.display());
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Builds the `ssh` command to run `command` on `host`
+fn ssh_command(
+    program: &str,
+    host: &str,
+    user: Option<&str>,
+    port: Option<u16>,
+    key: Option<&PathBuf>,
+    command: &[String],
+) -> Vec<String> {
+    // fail instead of prompting for a password or host key confirmation
+    let mut args: Vec<String> = [program, "-o", "BatchMode=yes", "-o", "ConnectTimeout=10"]
+        .map(String::from)
+        .into();
+    if let Some(port) = port {
+        args.extend(["-p".to_string(), port.to_string()]);
+    }
+    if let Some(key) = key {
+        args.extend(["-i".to_string(), key.display().to_string()]);
+    }
+    args.push(match user {
+        Some(user) => format!("{user}@{host}"),
+        None => host.to_string(),
+    });
+    args.push("--".to_string());
+    args.extend(command.iter().cloned());
+    args
+}
+
+pub struct ArpScanner {
+    program: PathBuf,
+    /// Interfaces to scan, the default interface if empty
+    interfaces: Vec<String>,
+    /// Rate, retry and timeout arguments
+    options: Vec<String>,
+    /// Columns of the plain output, instead of the columns of the default `--format`
+    format: Option<ScanFormat>,
+    timeout: Duration,
+}
+
+impl ArpScanner {
+    /// The arp-scan command to scan the local network, or only the `targets` if given
+    fn command(&self, interface: Option<&str>, targets: Option<&[IpAddr]>) -> Command {
+        let mut command = Command::new(&self.program);
+        if targets.is_none() {
+            command.arg("--localnet");
+        }
+        command.arg("--plain");
+        if self.format.is_none() {
+            command.args(["--rtt", "--format=${ip}\\t${mac}\\t${vendor}\\t${rtt}"]);
+        }
+        command.args(&self.options);
+        if let Some(interface) = interface {
+            command.arg(format!("--interface={interface}"));
+        }
+        // ARP only reaches IPv4 addresses
+        let targets = targets.unwrap_or_default().iter().filter(|ip| ip.is_ipv4());
+        command.args(targets.map(IpAddr::to_string));
+        command
+    }
+
+    fn scan_interface(
+        &self,
+        interface: Option<&str>,
+        targets: Option<&[IpAddr]>,
+    ) -> Result<Vec<Device>> {
+        log::debug!(
+            "starting {} on {}",
+            if targets.is_some() {
+                "targeted scan"
+            } else {
+                "network scan"
+            },
+            interface.unwrap_or("default interface")
+        );
+        let output = output_within(&mut self.command(interface, targets), self.timeout)
+            .context("Failed to execute 'arp-scan' command")?;
+        if !output.status.success() {
+            bail!(
+                "arp-scan failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        let mut devices = match &self.format {
+            Some(format) => format.parse(&output),
+            None => parse_scan(&output),
+        };
+        for device in &mut devices {
+            device.interface = interface.map(str::to_string);
+        }
+        Ok(devices)
+    }
+
+    fn scan_interfaces(&self, targets: Option<&[IpAddr]>) -> Result<Option<Vec<Device>>, Error> {
+        if self.interfaces.is_empty() {
+            return Ok(Some(
+                self.scan_interface(None, targets).map_err(Error::Scan)?,
+            ));
+        }
+        let mut devices: Vec<Device> = Vec::new();
+        for interface in &self.interfaces {
+            for device in self
+                .scan_interface(Some(interface), targets)
+                .map_err(Error::Scan)?
+            {
+                // a device reachable on multiple interfaces is kept on the first one
+                if !devices.iter().any(|d| d.mac == device.mac) {
+                    devices.push(device);
+                }
+            }
+        }
+        Ok(Some(devices))
+    }
+}
+
+impl Scanner for ArpScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.scan_interfaces(None)
+    }
+
+    fn supports_targets(&self) -> bool {
+        true
+    }
+
+    fn scan_targets(&mut self, targets: &[IpAddr]) -> Result<Option<Vec<Device>>, Error> {
+        self.scan_interfaces(Some(targets))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+pub struct CommandScanner {
+    program: String,
+    args: Vec<String>,
+    format: ScanFormat,
+    timeout: Duration,
+}
+
+impl CommandScanner {
+    pub fn new(command: Vec<String>, format: ScanFormat) -> Result<Self, Error> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(Error::Scan(anyhow!("Scan command is empty")));
+        };
+        if !is_executable(program) {
+            return Err(Error::Scan(anyhow!("Scan command '{program}' not found")));
+        }
+        Ok(Self {
+            program: program.clone(),
+            args: args.to_vec(),
+            format,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    fn run(&self) -> Result<Vec<Device>> {
+        log::debug!("running scan command '{}'", self.program);
+        let output = output_within(Command::new(&self.program).args(&self.args), self.timeout)
+            .with_context(|| format!("Failed to execute scan command '{}'", self.program))?;
+        if !output.status.success() {
+            bail!(
+                "Scan command '{}' failed with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(self.format.parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+impl Scanner for CommandScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.run().map(Some).map_err(Error::Scan)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+/// Reads the kernel neighbour table, see [`ScannerConfig::IpNeigh`] and [`ScannerConfig::Ndp`]
+pub struct IpNeighScanner {
+    program: PathBuf,
+    ipv6: bool,
+    timeout: Duration,
+}
+
+impl IpNeighScanner {
+    fn run(&self) -> Result<Vec<Device>> {
+        let family = if self.ipv6 { "-6" } else { "-4" };
+        let output = output_within(
+            Command::new(&self.program).args([family, "neigh", "show"]),
+            self.timeout,
+        )
+        .context("Failed to execute 'ip neigh' command")?;
+        if !output.status.success() {
+            bail!(
+                "'ip neigh' failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let mut devices = parse_neigh(&String::from_utf8_lossy(&output.stdout));
+        if self.ipv6 {
+            prefer_link_local(&mut devices);
+        }
+        Ok(devices)
+    }
+}
+
+impl Scanner for IpNeighScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.run().map(Some).map_err(Error::Scan)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+/// Lists the wifi clients of an OpenWrt router, see [`ScannerConfig::OpenWrt`]
+pub struct OpenWrtScanner {
+    url: String,
+    username: String,
+    password: String,
+    timeout: Duration,
+    // logged in session, renewed when it expires
+    session: Option<String>,
+}
+
+// session to log in with
+const UBUS_NULL_SESSION: &str = "00000000000000000000000000000000";
+
+// ubus status of a call the session isn't allowed to make, which is also returned once it expired
+const UBUS_PERMISSION_DENIED: u64 = 6;
+
+impl OpenWrtScanner {
+    pub fn new(url: String, username: String, password: String) -> Self {
+        Self {
+            url,
+            username,
+            password,
+            timeout: DEFAULT_TIMEOUT,
+            session: None,
+        }
+    }
+
+    /// Calls a ubus method, returning its status and result
+    fn request(
+        &self,
+        session: &str,
+        object: &str,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<(u64, serde_json::Value)> {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(self.timeout))
+            .build()
+            .new_agent();
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "call",
+            "params": [session, object, method, args],
+        });
+        let body = agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(request.to_string())
+            .and_then(|mut resp| resp.body_mut().read_to_string())
+            .with_context(|| format!("Failed to call '{object} {method}' on '{}'", self.url))?;
+        let response: serde_json::Value = serde_json::from_str(&body)
+            .with_context(|| format!("Invalid response to '{object} {method}'"))?;
+        if let Some(error) = response.get("error") {
+            bail!("'{object} {method}' failed: {}", error["message"]);
+        }
+        let result = &response["result"];
+        let status = result[0]
+            .as_u64()
+            .with_context(|| format!("Invalid response to '{object} {method}'"))?;
+        Ok((status, result[1].clone()))
+    }
+
+    fn login(&self) -> Result<String> {
+        let args = serde_json::json!({ "username": self.username, "password": self.password });
+        let (status, result) = self.request(UBUS_NULL_SESSION, "session", "login", args)?;
+        match result["ubus_rpc_session"].as_str() {
+            Some(session) if status == 0 => Ok(session.to_string()),
+            _ => bail!(
+                "Failed to log in to '{}' as '{}', check the credentials",
+                self.url,
+                self.username
+            ),
+        }
+    }
+
+    /// Calls a ubus method, logging in first and again when the session expired
+    fn call(
+        &mut self,
+        object: &str,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let session = match &self.session {
+            Some(session) => session.clone(),
+            None => self.session.insert(self.login()?).clone(),
+        };
+        let (mut status, mut result) = self.request(&session, object, method, args.clone())?;
+        if status == UBUS_PERMISSION_DENIED {
+            log::debug!("ubus session expired, logging in again");
+            let session = self.session.insert(self.login()?).clone();
+            (status, result) = self.request(&session, object, method, args)?;
+        }
+        if status != 0 {
+            bail!("'{object} {method}' failed with ubus status {status}");
+        }
+        Ok(result)
+    }
+
+    fn run(&mut self) -> Result<Vec<Device>> {
+        let leases = self.call("luci-rpc", "getDHCPLeases", serde_json::json!({}))?;
+        let leases = parse_ubus_leases(&leases);
+        let interfaces = self.call("iwinfo", "devices", serde_json::json!({}))?;
+        let mut devices = Vec::new();
+        for interface in interfaces["devices"].as_array().into_iter().flatten() {
+            let Some(interface) = interface.as_str() else {
+                continue;
+            };
+            let clients = self.call(
+                "iwinfo",
+                "assoclist",
+                serde_json::json!({ "device": interface }),
+            )?;
+            devices.extend(parse_assoclist(&clients, interface, &leases));
+        }
+        Ok(devices)
+    }
+}
+
+impl Scanner for OpenWrtScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.run().map(Some).map_err(Error::Scan)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+/// Runs several scanners at the same time, see [`ScannerConfig::Parallel`]
+pub struct ParallelScanner {
+    scanners: Vec<Box<dyn Scanner + Send>>,
+    timeout: Option<Duration>,
+}
+
+impl ParallelScanner {
+    fn run(&mut self, targets: Option<&[IpAddr]>) -> Result<Option<Vec<Device>>, Error> {
+        let results: Vec<Result<Option<Vec<Device>>, Error>> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .scanners
+                .iter_mut()
+                .map(|scanner| {
+                    scope.spawn(move || match targets {
+                        Some(targets) => scanner.scan_targets(targets),
+                        None => scanner.scan(),
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(Error::Scan(anyhow!("Scanner panicked"))))
+                })
+                .collect()
+        });
+        let mut devices: Vec<Device> = Vec::new();
+        let mut error = None;
+        let mut exhausted = true;
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(Some(found)) => {
+                    exhausted = false;
+                    for device in found {
+                        match devices.iter_mut().find(|d| d.mac == device.mac) {
+                            Some(existing) => fill_in(existing, device),
+                            None => devices.push(device),
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("Scanner {} of the parallel scan failed: {}", i + 1, e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if exhausted => Err(e),
+            _ if exhausted => Ok(None),
+            _ => Ok(Some(devices)),
+        }
+    }
+}
+
+impl Scanner for ParallelScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        self.run(None)
+    }
+
+    fn supports_targets(&self) -> bool {
+        self.scanners.iter().any(|s| s.supports_targets())
+    }
+
+    fn scan_targets(&mut self, targets: &[IpAddr]) -> Result<Option<Vec<Device>>, Error> {
+        self.run(Some(targets))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        for scanner in &mut self.scanners {
+            scanner.set_timeout(self.timeout.unwrap_or(timeout));
+        }
+    }
+}
+
+/// Fills in the fields of a device that another scanner left out
+fn fill_in(device: &mut Device, other: Device) {
+    if !crate::has_vendor(&device.vendor) && crate::has_vendor(&other.vendor) {
+        device.vendor = other.vendor;
+    }
+    if device.ip.is_unspecified() {
+        device.ip = other.ip;
+    }
+    device.hostname = device.hostname.take().or(other.hostname);
+    device.latency = device.latency.or(other.latency);
+    device.interface = device.interface.take().or(other.interface);
+    device.rssi = device.rssi.or(other.rssi);
+}
+
+/// IP address and hostname by MAC address from the result of `luci-rpc getDHCPLeases`
+fn parse_ubus_leases(result: &serde_json::Value) -> Vec<(MacAddr, IpAddr, Option<String>)> {
+    result["dhcp_leases"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|lease| {
+            let mac = lease["macaddr"].as_str()?.parse().ok()?;
+            let ip = lease["ipaddr"].as_str()?.parse().ok()?;
+            let hostname = lease["hostname"].as_str().map(String::from);
+            Some((mac, ip, hostname))
+        })
+        .collect()
+}
+
+/// Devices from the result of `iwinfo assoclist` for a wifi interface
+///
+/// Clients without a DHCP lease get the unspecified address, which doesn't replace a known one.
+fn parse_assoclist(
+    result: &serde_json::Value,
+    interface: &str,
+    leases: &[(MacAddr, IpAddr, Option<String>)],
+) -> Vec<Device> {
+    result["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|client| {
+            let mac: MacAddr = client["mac"].as_str()?.parse().ok()?;
+            let lease = leases.iter().find(|(m, _, _)| *m == mac);
+            Some(Device {
+                ip: lease.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |(_, ip, _)| *ip),
+                vendor: String::new(),
+                hostname: lease.and_then(|(_, _, hostname)| hostname.clone()),
+                latency: None,
+                interface: Some(interface.to_string()),
+                rssi: client["signal"]
+                    .as_i64()
+                    .and_then(|s| i32::try_from(s).ok()),
+                node: None,
+                found_as: Vec::new(),
+                mac,
+            })
+        })
+        .collect()
+}
+
+/// Runs a command to completion like [`Command::output`], but kills it once it runs longer than
+/// `timeout`, e.g. when a wedged interface makes it hang
+fn output_within(command: &mut Command, timeout: Duration) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // read while it runs, a child blocked on a full pipe would never exit
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            // the readers are left behind, in case a child of the command keeps the pipes open
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Timed out after {}s, killed it", timeout.as_secs_f64());
+        }
+        sleep(POLL_INTERVAL.min(deadline - now));
+    };
+    let join = |reader: thread::JoinHandle<Vec<u8>>| reader.join().unwrap_or_default();
+    Ok(Output {
+        status,
+        stdout: join(stdout),
+        stderr: join(stderr),
+    })
+}
+
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Parses `ip neigh` output such as `192.168.1.2 dev eth0 lladdr aa:bb:cc:dd:ee:01 REACHABLE`
+///
+/// Entries without a MAC address or that failed to resolve are skipped.
+fn parse_neigh(output: &str) -> Vec<Device> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields.first()?.parse::<IpAddr>().ok()?;
+            let after = |key| {
+                let i = fields.iter().position(|f| *f == key)?;
+                fields.get(i + 1).copied()
+            };
+            if matches!(fields.last(), Some(&"FAILED" | &"INCOMPLETE")) {
+                return None;
+            }
+            Some(Device {
+                mac: after("lladdr")?.parse::<MacAddr>().ok()?,
+                ip,
+                vendor: "(Unknown)".to_string(),
+                hostname: None,
+                latency: None,
+                interface: after("dev").map(str::to_string),
+                rssi: None,
+                node: None,
+                found_as: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Keeps one entry per MAC address, the one with a link-local address if there is one
+///
+/// Unlike global addresses, which change with privacy extensions, the link-local address of a
+/// device is stable.
+fn prefer_link_local(devices: &mut Vec<Device>) {
+    let is_link_local = |d: &Device| matches!(d.ip, IpAddr::V6(ip) if ip.is_unicast_link_local());
+    devices.sort_by_key(|d| !is_link_local(d));
+    let mut seen = std::collections::HashSet::new();
+    devices.retain(|d| seen.insert(d.mac.clone()));
+}
+
+/// Checks whether a program exists, either as a path or in `PATH`
+fn is_executable(program: &str) -> bool {
+    find_executable(program).is_some()
+}
+
+fn find_executable(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| is_executable_file(path));
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable_file(path))
+}
+
+fn is_executable_file(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.mode() & 0o111 != 0)
+}
+
+/// Checks whether `program` can open raw sockets, by running as root or with `CAP_NET_RAW`, or
+/// by the program being setuid root or having the capability itself
+fn can_open_raw_sockets(program: &Path) -> bool {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } == 0 {
+        return true;
+    }
+    let has_capability = fs::read_to_string("/proc/self/status").is_ok_and(|status| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .is_some_and(|caps| caps & (1 << CAP_NET_RAW) != 0)
+    });
+    if has_capability {
+        return true;
+    }
+    if fs::metadata(program).is_ok_and(|m| m.uid() == 0 && m.mode() & 0o4000 != 0) {
+        return true;
+    }
+    file_capabilities(program).is_some_and(|permitted| permitted & (1 << CAP_NET_RAW) != 0)
+}
+
+/// Reads the permitted file capabilities of a program, as set by `setcap`
+fn file_capabilities(program: &Path) -> Option<u32> {
+    let path = CString::new(program.as_os_str().as_bytes()).ok()?;
+    // the version and flags followed by the permitted and inheritable capabilities
+    let mut data = [0u8; 20];
+    // SAFETY: both strings are NUL terminated and the size is that of the buffer
+    let size = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            c"security.capability".as_ptr(),
+            data.as_mut_ptr().cast(),
+            data.len(),
+        )
+    };
+    if size < 8 {
+        return None;
+    }
+    Some(u32::from_le_bytes(data[4..8].try_into().unwrap()))
+}
+
+/// Replays a sequence of scans, for testing and reproducing bug reports
+///
+/// Scans are separated by a line containing `---`. Each scan contains one device per line in the
+/// same tab separated `ip mac vendor` format as the arp-scan output. Lines starting with `#` are
+/// ignored.
+pub struct FixtureScanner {
+    scans: VecDeque<Vec<Device>>,
+}
+
+impl FixtureScanner {
+    pub fn load(path: &PathBuf) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scan fixture '{}'", path.display()))
+            .map_err(Error::Scan)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let mut scans = VecDeque::new();
+        let mut block = String::new();
+        for line in contents.lines() {
+            if line.trim() == "---" {
+                scans.push_back(parse_scan(&block));
+                block.clear();
+            } else if !line.starts_with('#') && !line.trim().is_empty() {
+                block.push_str(line);
+                block.push('\n');
+            }
+        }
+        scans.push_back(parse_scan(&block));
+        Ok(Self { scans })
+    }
+}
+
+impl Scanner for FixtureScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        Ok(self.scans.pop_front())
+    }
+}
+
+/// Records the results of scans during `duration` to a file in the format of [`FixtureScanner`],
+/// each preceded by a comment with the time of the scan
+///
+/// Returns the number of scans recorded.
+pub fn capture(
+    scanner: &mut dyn Scanner,
+    path: &Path,
+    duration: Duration,
+    interval: Duration,
+) -> Result<usize, Error> {
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("Failed to create capture file '{}'", path.display()))
+        .map_err(Error::Scan)?;
+    let start = Instant::now();
+    let mut scans = 0;
+    loop {
+        let devices = match scanner.scan() {
+            Ok(Some(devices)) => devices,
+            Ok(None) => break,
+            // e.g. a timeout, which shouldn't end a capture meant to reproduce such issues
+            Err(e) => {
+                log::warn!("Skipping failed scan: {:#}", e);
+                if start.elapsed() + interval > duration {
+                    break;
+                }
+                sleep(interval);
+                continue;
+            }
+        };
+        let time = chrono::Local::now().to_rfc3339();
+        let separator = if scans > 0 { "---\n" } else { "" };
+        write!(file, "{separator}# {time}\n{}", format_scan(&devices))
+            .with_context(|| format!("Failed to write capture file '{}'", path.display()))
+            .map_err(Error::Scan)?;
+        scans += 1;
+        log::info!("Captured scan {} with {} devices", scans, devices.len());
+        if start.elapsed() + interval > duration {
+            break;
+        }
+        sleep(interval);
+    }
+    Ok(scans)
+}
+
+/// Formats devices in the format read by [`parse_scan`]
+pub fn format_scan(devices: &[Device]) -> String {
+    devices
+        .iter()
+        .map(|device| {
+            let optional = format!(
+                "\t{}\t{}\t{}",
+                device
+                    .latency
+                    .map(|latency| format!("{:.3}", latency.as_secs_f64() * 1000.0))
+                    .unwrap_or_default(),
+                device.interface.as_deref().unwrap_or_default(),
+                device.rssi.map(|rssi| rssi.to_string()).unwrap_or_default()
+            );
+            format!(
+                "{}\t{}\t{}{}\n",
+                device.ip,
+                device.mac.as_str(),
+                device.vendor,
+                // the optional columns that are left out
+                optional.trim_end_matches('\t')
+            )
+        })
+        .collect()
+}
+
+/// A column of tab separated scan output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Ip,
+    Mac,
+    Vendor,
+    /// Round trip time in milliseconds
+    Rtt,
+    Interface,
+    /// Signal strength in dBm
+    Rssi,
+    /// A column that is ignored
+    Skip,
+}
+
+/// Order of the columns of tab separated scan output, such as `["ip", "mac", "vendor"]`
+///
+/// The `ip` and `mac` columns are required. A device line has to contain the `vendor` column if
+/// it is part of the format, the other columns may be missing or empty.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<Field>")]
+pub struct ScanFormat(Vec<Field>);
+
+impl Default for ScanFormat {
+    fn default() -> Self {
+        Self(vec![
+            Field::Ip,
+            Field::Mac,
+            Field::Vendor,
+            Field::Rtt,
+            Field::Interface,
+            Field::Rssi,
+        ])
+    }
+}
+
+impl TryFrom<Vec<Field>> for ScanFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: Vec<Field>) -> Result<Self> {
+        for required in [Field::Ip, Field::Mac] {
+            if !fields.contains(&required) {
+                bail!(
+                    "Scan format is missing the '{}' column",
+                    format!("{required:?}").to_lowercase()
+                );
+            }
+        }
+        for (i, field) in fields.iter().enumerate() {
+            if *field != Field::Skip && fields[..i].contains(field) {
+                bail!(
+                    "Scan format contains the '{}' column more than once",
+                    format!("{field:?}").to_lowercase()
+                );
+            }
+        }
+        Ok(Self(fields))
+    }
+}
+
+impl ScanFormat {
+    fn index(&self, field: Field) -> Option<usize> {
+        self.0.iter().position(|f| *f == field)
+    }
+
+    /// Parses scan output with the columns of this format
+    ///
+    /// Lines without an IP address in the `ip` column, such as banners and summaries, are
+    /// skipped. Malformed device lines are skipped with a warning.
+    pub fn parse(&self, output: &str) -> Vec<Device> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split('\t').collect();
+                let ip = self
+                    .index(Field::Ip)
+                    .and_then(|i| columns.get(i))
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+                let Some(ip) = ip else {
+                    if !line.trim().is_empty() {
+                        log::debug!("Skipping non-device line in scan output: '{line}'");
+                    }
+                    return None;
+                };
+                match self.parse_device(ip, &columns) {
+                    Ok(device) => Some(device),
+                    Err(e) => {
+                        log::warn!("Skipping malformed device line '{line}': {e:#}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn parse_device(&self, ip: IpAddr, columns: &[&str]) -> Result<Device> {
+        let column = |field| self.index(field).and_then(|i| columns.get(i).copied());
+        // optional columns that are empty count as missing
+        let optional = |field| column(field).filter(|value: &&str| !value.trim().is_empty());
+        let mac = column(Field::Mac).context("missing MAC address")?;
+        let vendor = match column(Field::Vendor) {
+            Some(vendor) => vendor,
+            None if self.index(Field::Vendor).is_some() => bail!("missing vendor"),
+            None => "",
+        };
+        Ok(Device {
+            mac: mac.parse::<MacAddr>()?,
+            ip,
+            vendor: vendor.to_string(),
+            hostname: None,
+            latency: optional(Field::Rtt).map(parse_rtt).transpose()?,
+            interface: optional(Field::Interface).map(|i| i.trim().to_string()),
+            rssi: optional(Field::Rssi).map(parse_rssi).transpose()?,
+            node: None,
+            found_as: Vec::new(),
+        })
+    }
+}
+
+/// Parses scan output in the default tab separated `ip mac vendor [rtt] [interface] [rssi]`
+/// format, see [`ScanFormat::parse`]
+pub fn parse_scan(output: &str) -> Vec<Device> {
+    ScanFormat::default().parse(output)
+}
+
+/// Parses a round trip time in milliseconds, such as `1.234` or `1.234 ms`
+fn parse_rtt(rtt: &str) -> Result<Duration> {
+    let ms = rtt.trim().trim_end_matches("ms").trim();
+    let ms: f64 = ms
+        .parse()
+        .with_context(|| format!("invalid round trip time '{rtt}'"))?;
+    Ok(Duration::from_secs_f64(ms / 1000.0))
+}
+
+/// Parses a signal strength in dBm, such as `-67` or `-67 dBm`
+fn parse_rssi(rssi: &str) -> Result<i32> {
+    let dbm = rssi.trim().trim_end_matches("dBm").trim();
+    dbm.parse()
+        .with_context(|| format!("invalid signal strength '{rssi}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_fixture_parse() {
+        let mut scanner = FixtureScanner::parse(
+            "# first scan\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\n\
+             ---\n\
+             ---\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+        )
+        .unwrap();
+        assert_eq!(scanner.scan().unwrap().unwrap().len(), 2);
+        assert_eq!(scanner.scan().unwrap().unwrap().len(), 0);
+        let last = scanner.scan().unwrap().unwrap();
+        assert_eq!(last[0].mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert!(scanner.scan().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_openwrt_scan() {
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ubus", listener.local_addr().unwrap());
+        let router = thread::spawn(move || {
+            let mut logins = 0;
+            let mut calls = Vec::new();
+            for stream in listener.incoming().take(6) {
+                let mut stream = stream.unwrap();
+                let (_, body) = crate::test_http::read_json_request(&mut stream);
+                let params = &body["params"];
+                let (session, method) = (params[0].as_str().unwrap(), params[2].as_str().unwrap());
+                calls.push(format!("{} {}", params[1].as_str().unwrap(), method));
+                let result = match method {
+                    "login" => {
+                        logins += 1;
+                        serde_json::json!([0, { "ubus_rpc_session": format!("s{logins}") }])
+                    }
+                    // the first session expired
+                    _ if session == "s1" => serde_json::json!([6]),
+                    "getDHCPLeases" => serde_json::json!([0, { "dhcp_leases": [
+                        { "macaddr": "AA:BB:CC:DD:EE:01", "ipaddr": "192.168.1.10", "hostname": "phone" },
+                        { "macaddr": "AA:BB:CC:DD:EE:03", "ipaddr": "192.168.1.12" },
+                    ] }]),
+                    "devices" => serde_json::json!([0, { "devices": ["phy0-ap0"] }]),
+                    "assoclist" => serde_json::json!([0, { "results": [
+                        { "mac": "AA:BB:CC:DD:EE:01", "signal": -52 },
+                        { "mac": "AA:BB:CC:DD:EE:02", "signal": -70 },
+                    ] }]),
+                    _ => serde_json::json!([3]),
+                };
+                let response =
+                    serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+            calls
+        });
+        let mut scanner = OpenWrtScanner::new(url, "root".to_string(), "secret".to_string());
+        let devices = scanner.scan().unwrap().unwrap();
+        assert_eq!(
+            router.join().unwrap(),
+            [
+                "session login",
+                "luci-rpc getDHCPLeases",
+                "session login",
+                "luci-rpc getDHCPLeases",
+                "iwinfo devices",
+                "iwinfo assoclist"
+            ]
+        );
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 10));
+        assert_eq!(devices[0].hostname.as_deref(), Some("phone"));
+        assert_eq!(devices[0].interface.as_deref(), Some("phy0-ap0"));
+        assert_eq!(devices[0].rssi, Some(-52));
+        // associated without a lease
+        assert!(devices[1].ip.is_unspecified());
+        assert_eq!(devices[1].hostname, None);
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let config: ScannerConfig = toml::from_str(
+            "type = \"parallel\"\ntimeout = 20\n\
+             [[scanners]]\ntype = \"ip_neigh\"\n\
+             [[scanners]]\ntype = \"openwrt\"\nurl = \"http://192.168.1.1/ubus\"\n\
+             username = \"root\"\npassword = \"secret\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            config,
+            ScannerConfig::Parallel { scanners, timeout: Some(timeout) }
+                if scanners.len() == 2 && timeout == Duration::from_secs(20)
+        ));
+
+        struct Slow(Result<Vec<Device>, ()>);
+        impl Scanner for Slow {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                sleep(Duration::from_millis(300));
+                match &self.0 {
+                    Ok(devices) => Ok(Some(devices.clone())),
+                    Err(()) => Err(Error::Scan(anyhow!("unreachable"))),
+                }
+            }
+        }
+        let mut wifi =
+            parse_scan("0.0.0.0\taa:bb:cc:dd:ee:01\t\n192.168.1.3\taa:bb:cc:dd:ee:02\t\n");
+        wifi[0].rssi = Some(-60);
+        let mut scanner = ParallelScanner {
+            scanners: vec![
+                Box::new(Slow(Ok(parse_scan(
+                    "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n",
+                )))),
+                Box::new(Slow(Ok(wifi))),
+                Box::new(Slow(Err(()))),
+            ],
+            timeout: None,
+        };
+        let start = Instant::now();
+        let devices = scanner.scan().unwrap().unwrap();
+        // the scanners ran at the same time
+        assert!(start.elapsed() < Duration::from_millis(800));
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(devices[0].vendor, "Vendor A");
+        assert_eq!(devices[0].rssi, Some(-60));
+        assert_eq!(devices[1].mac, MacAddr::new("aa:bb:cc:dd:ee:02"));
+
+        // fails only when all scanners fail
+        let mut scanner = ParallelScanner {
+            scanners: vec![Box::new(Slow(Err(()))), Box::new(Slow(Err(())))],
+            timeout: None,
+        };
+        assert!(scanner.scan().is_err());
+    }
+
+    #[test]
+    fn test_capture_replay() {
+        let mut scanner = FixtureScanner::parse(
+            "192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t1.5\teth0\n\
+             192.168.1.3\taa:bb:cc:dd:ee:02\tVendor B\t\twlan0\t-60\n\
+             ---\n\
+             ---\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\t\n",
+        )
+        .unwrap();
+        let path = env::temp_dir().join(format!("lanotify-capture-{}.txt", std::process::id()));
+        let scans = capture(
+            &mut scanner,
+            &path,
+            Duration::from_secs(3600),
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(scans, 3);
+
+        let mut replay = FixtureScanner::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let first = replay.scan().unwrap().unwrap();
+        assert_eq!(first[0].latency, Some(Duration::from_micros(1500)));
+        assert_eq!(first[0].interface.as_deref(), Some("eth0"));
+        assert_eq!(first[1].rssi, Some(-60));
+        assert!(replay.scan().unwrap().unwrap().is_empty());
+        assert_eq!(replay.scan().unwrap().unwrap().len(), 1);
+        assert!(replay.scan().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_capture_failed_scan() {
+        struct Flaky(VecDeque<Result<Option<Vec<Device>>, Error>>);
+        impl Scanner for Flaky {
+            fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+                self.0.pop_front().unwrap_or(Ok(None))
+            }
+        }
+        let devices = parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n");
+        let mut scanner = Flaky(VecDeque::from([
+            Ok(Some(devices.clone())),
+            Err(Error::Scan(anyhow!("timed out"))),
+            Ok(Some(devices)),
+        ]));
+        let path = env::temp_dir().join(format!("lanotify-flaky-{}.txt", std::process::id()));
+        let scans = capture(
+            &mut scanner,
+            &path,
+            Duration::from_secs(3600),
+            Duration::ZERO,
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(scans, 2);
+    }
+
+    #[test]
+    fn test_parse_scan_skips_banners() {
+        let output = "Interface: eth0, type: EN10MB, MAC: 00:11:22:33:44:55\n\
+                      192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+                      192.168.1.3\tnot-a-mac\tVendor B\n\
+                      192.168.1.4\taa:bb:cc:dd:ee:03\n\
+                      \n\
+                      3 packets received by filter, 0 packets dropped by kernel\n\
+                      Ending arp-scan 1.10.0: 256 hosts scanned in 1.9 seconds. 1 responded\n";
+        let devices = parse_scan(output);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert_eq!(devices[0].latency, None);
+    }
+
+    #[test]
+    fn test_scanner_config() {
+        let config: ScannerConfig = toml::from_str("type = \"arp_scan\"").unwrap();
+        assert!(
+            matches!(config, ScannerConfig::ArpScan { interfaces, binary: None, rate: None, .. } if interfaces.is_empty())
+        );
+        let config: ScannerConfig =
+            toml::from_str("type = \"arp_scan\"\ninterfaces = [\"eth0\", \"wlan0\"]").unwrap();
+        assert!(
+            matches!(config, ScannerConfig::ArpScan { interfaces, .. } if interfaces.len() == 2)
+        );
+
+        let config: ScannerConfig =
+            toml::from_str("type = \"arp_scan\"\nrate = 0\nbinary = \"/\"").unwrap();
+        assert!(
+            config
+                .build()
+                .is_err_and(|e| e.to_string().contains("Scan rate must be between"))
+        );
+
+        let config: ScannerConfig = toml::from_str("type = \"ip_neigh\"\nbinary = \"/\"").unwrap();
+        assert!(
+            config
+                .build()
+                .is_err_and(|e| e.to_string().contains("is not an executable file"))
+        );
+        let config: ScannerConfig = toml::from_str("type = \"ndp\"\nbinary = \"/\"").unwrap();
+        assert!(
+            config
+                .build()
+                .is_err_and(|e| e.to_string().contains("is not an executable file"))
+        );
+    }
+
+    #[test]
+    fn test_command_timeout() {
+        let mut scanner = CommandScanner::new(
+            vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "echo started; sleep 10".to_string(),
+            ],
+            ScanFormat::default(),
+        )
+        .unwrap();
+        scanner.set_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        let err = scanner.scan().unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(format!("{err:#}").contains("Timed out after 0.2s"));
+
+        let output = output_within(
+            Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn test_arp_scan_targets() {
+        let scanner = ArpScanner {
+            program: PathBuf::from("arp-scan"),
+            interfaces: Vec::new(),
+            options: Vec::new(),
+            format: Some(ScanFormat::default()),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let args = |command: Command| {
+            let args: Vec<String> = command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            args.join(" ")
+        };
+        assert_eq!(args(scanner.command(None, None)), "--localnet --plain");
+        let targets = [
+            IpAddr::from([192, 168, 1, 2]),
+            "fe80::1".parse().unwrap(),
+            IpAddr::from([192, 168, 1, 3]),
+        ];
+        assert_eq!(
+            args(scanner.command(Some("eth0"), Some(&targets))),
+            "--plain --interface=eth0 192.168.1.2 192.168.1.3"
+        );
+    }
+
+    #[test]
+    fn test_arp_scan_options() {
+        assert!(arp_scan_options(None, None, None).unwrap().is_empty());
+        assert_eq!(
+            arp_scan_options(Some(500), Some(3), Some(Duration::from_millis(200))).unwrap(),
+            ["--interval=2000u", "--retry=4", "--timeout=200"]
+        );
+        assert!(arp_scan_options(Some(MAX_SCAN_RATE + 1), None, None).is_err());
+        assert!(arp_scan_options(None, Some(11), None).is_err());
+        assert!(arp_scan_options(None, None, Some(Duration::from_millis(5))).is_err());
+        let config: ScannerConfig =
+            toml::from_str("type = \"arp_scan\"\nrate = 500\nretries = 3\ntimeout = 200").unwrap();
+        assert!(matches!(
+            config,
+            ScannerConfig::ArpScan { rate: Some(500), retries: Some(3), timeout: Some(t), .. }
+                if t == Duration::from_millis(200)
+        ));
+    }
+
+    #[test]
+    fn test_interface_state() {
+        let root = env::temp_dir().join(format!("lanotify-net-{}", std::process::id()));
+        for (interface, state) in [("eth0", "up\n"), ("wlan0", "down\n"), ("lo", "unknown\n")] {
+            fs::create_dir_all(root.join(interface)).unwrap();
+            fs::write(root.join(interface).join("operstate"), state).unwrap();
+        }
+        assert!(is_interface_up(&root, "eth0"));
+        assert!(!is_interface_up(&root, "wlan0"));
+        assert!(is_interface_up(&root, "lo"));
+        assert!(!is_interface_up(&root, "eth1"));
+        fs::remove_dir_all(&root).unwrap();
+        assert!(is_interface_up(&root, "eth1"));
+    }
+
+    #[test]
+    fn test_local_addrs() {
+        let root = env::temp_dir().join(format!("lanotify-local-{}", std::process::id()));
+        for (interface, address) in [
+            ("eth0", "aa:bb:cc:dd:ee:01\n"),
+            ("lo", "00:00:00:00:00:00\n"),
+        ] {
+            fs::create_dir_all(root.join(interface)).unwrap();
+            fs::write(root.join(interface).join("address"), address).unwrap();
+        }
+        let route = root.join("route");
+        fs::write(
+            &route,
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+             eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+             eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n",
+        )
+        .unwrap();
+        let local = LocalAddrs::detect(&root, &route);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            local.macs,
+            HashSet::from([MacAddr::new("aa:bb:cc:dd:ee:01")])
+        );
+        if cfg!(target_endian = "little") {
+            assert_eq!(local.gateway, Some(Ipv4Addr::new(192, 168, 1, 1)));
+        }
+        assert_eq!(local.interface.as_deref(), Some("eth0"));
+        let devices = parse_scan(
+            "192.168.1.1\taa:bb:cc:dd:ee:02\tRouter\n\
+             192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n\
+             192.168.1.3\taa:bb:cc:dd:ee:03\tVendor B\n",
+        );
+        let local: Vec<bool> = devices.iter().map(|d| local.contains(d)).collect();
+        assert_eq!(local, [cfg!(target_endian = "little"), true, false]);
+
+        let empty = LocalAddrs::detect(&root, &route);
+        assert!(empty.macs.is_empty() && empty.gateway.is_none() && empty.interface.is_none());
+    }
+
+    #[test]
+    fn test_scan_format() {
+        let config: ScannerConfig = toml::from_str(
+            "type = \"command\"\ncommand = [\"true\"]\nformat = [\"mac\", \"skip\", \"ip\"]",
+        )
+        .unwrap();
+        let ScannerConfig::Command { format, .. } = config else {
+            panic!("expected a command scanner");
+        };
+        let devices = format.parse(
+            "MAC\tState\tIP\n\
+             aa:bb:cc:dd:ee:01\treachable\t192.168.1.2\n\
+             aa:bb:cc:dd:ee:02\tstale\n",
+        );
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].mac, MacAddr::new("aa:bb:cc:dd:ee:01"));
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(devices[0].vendor, "");
+
+        let error = toml::from_str::<ScannerConfig>(
+            "type = \"command\"\ncommand = [\"true\"]\nformat = [\"ip\", \"vendor\"]",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("missing the 'mac' column"));
+        assert!(ScanFormat::try_from(vec![Field::Ip, Field::Mac, Field::Ip]).is_err());
+        assert!(
+            ScanFormat::try_from(vec![Field::Skip, Field::Ip, Field::Skip, Field::Mac]).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_ssh_command() {
+        let config: ScannerConfig = toml::from_str(
+            "type = \"ssh\"\nhost = \"router\"\nuser = \"root\"\nkey = \"/etc/lanotify/id_ed25519\"\n\
+             command = [\"arp-scan\", \"--localnet\", \"--plain\"]",
+        )
+        .unwrap();
+        let ScannerConfig::Ssh {
+            host,
+            user,
+            port,
+            key,
+            command,
+            ..
+        } = config
+        else {
+            panic!("expected an ssh scanner");
+        };
+        assert_eq!(
+            ssh_command("ssh", &host, user.as_deref(), port, key.as_ref(), &command).join(" "),
+            "ssh -o BatchMode=yes -o ConnectTimeout=10 -i /etc/lanotify/id_ed25519 root@router \
+             -- arp-scan --localnet --plain"
+        );
+        assert_eq!(
+            ssh_command("ssh", "router", None, Some(2222), None, &command)[5..8],
+            ["-p", "2222", "router"]
+        );
+    }
+
+    #[test]
+    fn test_command_scanner() {
+        let mut scanner = CommandScanner::new(
+            vec![
+                "printf".to_string(),
+                "192.168.1.2\\taa:bb:cc:dd:ee:01\\tVendor A\\n".to_string(),
+            ],
+            ScanFormat::default(),
+        )
+        .unwrap();
+        let devices = scanner.scan().unwrap().unwrap();
+        assert_eq!(devices[0].vendor, "Vendor A");
+
+        assert!(CommandScanner::new(vec![], ScanFormat::default()).is_err());
+        assert!(
+            CommandScanner::new(
+                vec!["lanotify-does-not-exist".to_string()],
+                ScanFormat::default()
+            )
+            .is_err()
+        );
+        assert!(
+            CommandScanner::new(vec!["false".to_string()], ScanFormat::default())
+                .unwrap()
+                .scan()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_scan_latency() {
+        let devices = parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t1.500 ms\n");
+        assert_eq!(devices[0].latency, Some(Duration::from_micros(1500)));
+        assert_eq!(devices[0].interface, None);
+
+        let devices = parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t\twlan0\n");
+        assert_eq!(devices[0].latency, None);
+        assert_eq!(devices[0].interface.as_deref(), Some("wlan0"));
+    }
+
+    #[test]
+    fn test_parse_neigh() {
+        let devices = parse_neigh(
+            "192.168.1.1 dev eth0 lladdr aa:bb:cc:dd:ee:01 REACHABLE\n\
+             192.168.1.2 dev eth0 lladdr aa:bb:cc:dd:ee:02 STALE\n\
+             192.168.1.3 dev eth0  FAILED\n\
+             192.168.1.4 dev wlan0 lladdr aa:bb:cc:dd:ee:04 INCOMPLETE\n",
+        );
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].mac, MacAddr::new("aa:bb:cc:dd:ee:02"));
+        assert_eq!(devices[1].ip, Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(devices[1].interface.as_deref(), Some("eth0"));
+    }
+
+    #[test]
+    fn test_parse_ndp() {
+        let mut devices = parse_neigh(
+            "2001:db8::5e1 dev eth0 lladdr aa:bb:cc:dd:ee:01 STALE\n\
+             fe80::a8bb:ccff:fedd:ee01 dev eth0 lladdr aa:bb:cc:dd:ee:01 REACHABLE\n\
+             fe80::1 dev eth0 lladdr aa:bb:cc:dd:ee:02 router DELAY\n\
+             fe80::3 dev wlan0  FAILED\n\
+             2001:db8::4 dev eth0 lladdr aa:bb:cc:dd:ee:04 PROBE\n",
+        );
+        prefer_link_local(&mut devices);
+        let ips: Vec<String> = devices.iter().map(|d| d.ip.to_string()).collect();
+        assert_eq!(ips, ["fe80::a8bb:ccff:fedd:ee01", "fe80::1", "2001:db8::4"]);
+        assert_eq!(devices[1].mac, MacAddr::new("aa:bb:cc:dd:ee:02"));
+        assert_eq!(devices[1].interface.as_deref(), Some("eth0"));
+    }
+
+    #[test]
+    fn test_parse_scan_rssi() {
+        let devices = parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t\twlan0\t-67 dBm\n");
+        assert_eq!(devices[0].rssi, Some(-67));
+
+        let devices = parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\t\twlan0\tstrong\n");
+        assert!(devices.is_empty());
+    }
+}
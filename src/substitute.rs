@@ -0,0 +1,242 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+
+/// Keys of secrets that can be read from a file given as `<key>_file` instead, e.g. for Docker
+/// secrets and systemd credentials
+const SECRET_KEYS: &[&str] = &[
+    "ntfy_url",
+    "url",
+    "bot_token",
+    "password",
+    "influx_token",
+    "mqtt_password",
+    "home_assistant_token",
+];
+
+/// Replaces `${VAR}` references with the value of the environment variable `VAR`
+///
+/// `$$` is replaced by a single `$`, so `$${VAR}` results in a literal `${VAR}`.
+pub fn substitute(value: &str) -> Result<String> {
+    substitute_with(value, |name| std::env::var(name).ok())
+}
+
+fn substitute_with(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .with_context(|| format!("Unterminated variable reference in '{value}'"))?;
+            let name = &after[..end];
+            if name.is_empty() {
+                bail!("Empty variable reference in '{value}'");
+            }
+            let Some(var) = lookup(name) else {
+                bail!("Environment variable '{name}' is not set");
+            };
+            result.push_str(&var);
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Substitutes environment variables in all strings of a TOML value
+pub fn substitute_toml(value: &mut toml::Value) -> Result<()> {
+    match value {
+        toml::Value::String(s) => *s = substitute(s)?,
+        toml::Value::Array(array) => {
+            for value in array {
+                substitute_toml(value)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                substitute_toml(value).with_context(|| format!("Invalid value for '{key}'"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Substitutes environment variables in all strings of a JSON value
+pub fn substitute_json(value: &mut serde_json::Value) -> Result<()> {
+    match value {
+        serde_json::Value::String(s) => *s = substitute(s)?,
+        serde_json::Value::Array(array) => {
+            for value in array {
+                substitute_json(value)?;
+            }
+        }
+        serde_json::Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                substitute_json(value).with_context(|| format!("Invalid value for '{key}'"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads the file of a `<key>_file` entry, with surrounding whitespace trimmed
+fn read_secret(key: &str, path: &str) -> Result<String> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read `{key}_file` '{path}'"))?;
+    Ok(contents.trim().to_string())
+}
+
+/// The secret key a key names the file of, e.g. `ntfy_url` for `ntfy_url_file`
+fn secret_key(key: &str) -> Option<&'static str> {
+    let key = key.strip_suffix("_file")?;
+    SECRET_KEYS.iter().copied().find(|k| *k == key)
+}
+
+/// Replaces the `<key>_file` entries of secrets in all tables of a TOML value by `<key>` with the
+/// contents of the file
+pub fn read_secret_files_toml(value: &mut toml::Value) -> Result<()> {
+    match value {
+        toml::Value::Array(array) => {
+            for value in array {
+                read_secret_files_toml(value)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            let files: Vec<String> = table
+                .keys()
+                .filter(|k| secret_key(k).is_some())
+                .cloned()
+                .collect();
+            for file_key in files {
+                let key = secret_key(&file_key).unwrap();
+                if table.contains_key(key) {
+                    bail!("Only one of `{key}` and `{file_key}` can be set");
+                }
+                let Some(toml::Value::String(path)) = table.remove(&file_key) else {
+                    bail!("`{file_key}` has to be a path");
+                };
+                table.insert(
+                    key.to_string(),
+                    toml::Value::String(read_secret(key, &path)?),
+                );
+            }
+            for (key, value) in table.iter_mut() {
+                read_secret_files_toml(value)
+                    .with_context(|| format!("Invalid value for '{key}'"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces the `<key>_file` entries of secrets in all objects of a JSON value, like
+/// [`read_secret_files_toml`]
+pub fn read_secret_files_json(value: &mut serde_json::Value) -> Result<()> {
+    match value {
+        serde_json::Value::Array(array) => {
+            for value in array {
+                read_secret_files_json(value)?;
+            }
+        }
+        serde_json::Value::Object(object) => {
+            let files: Vec<String> = object
+                .keys()
+                .filter(|k| secret_key(k).is_some())
+                .cloned()
+                .collect();
+            for file_key in files {
+                let key = secret_key(&file_key).unwrap();
+                if object.contains_key(key) {
+                    bail!("Only one of `{key}` and `{file_key}` can be set");
+                }
+                let Some(serde_json::Value::String(path)) = object.remove(&file_key) else {
+                    bail!("`{file_key}` has to be a path");
+                };
+                object.insert(
+                    key.to_string(),
+                    serde_json::Value::String(read_secret(key, &path)?),
+                );
+            }
+            for (key, value) in object.iter_mut() {
+                read_secret_files_json(value)
+                    .with_context(|| format!("Invalid value for '{key}'"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        let lookup = |name: &str| (name == "TOKEN").then(|| "secret".to_string());
+        let sub = |value| substitute_with(value, lookup);
+        assert_eq!(sub("Bearer ${TOKEN}").unwrap(), "Bearer secret");
+        assert_eq!(sub("${TOKEN}${TOKEN}").unwrap(), "secretsecret");
+        assert_eq!(sub("$$ and $${TOKEN}").unwrap(), "$ and ${TOKEN}");
+        assert_eq!(sub("costs $5").unwrap(), "costs $5");
+        assert_eq!(
+            sub("${MISSING}").unwrap_err().to_string(),
+            "Environment variable 'MISSING' is not set"
+        );
+        assert!(sub("${TOKEN").is_err());
+        assert!(sub("${}").is_err());
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let dir = std::env::temp_dir().join(format!("lanotify-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let url = dir.join("ntfy_url");
+        fs::write(&url, "http://ntfy/secret-topic\n").unwrap();
+        let token = dir.join("bot_token");
+        fs::write(&token, "  123:abc  ").unwrap();
+
+        let mut value: toml::Value = toml::from_str(&format!(
+            "ntfy_url_file = '{}'\noui_file = 'oui.txt'\n\
+             [[notifier]]\ntype = 'telegram'\nbot_token_file = '{}'\nchat_id = '1'\n",
+            url.display(),
+            token.display()
+        ))
+        .unwrap();
+        read_secret_files_toml(&mut value).unwrap();
+        assert_eq!(value["ntfy_url"].as_str(), Some("http://ntfy/secret-topic"));
+        assert!(value.get("ntfy_url_file").is_none());
+        // other files are not secrets
+        assert_eq!(value["oui_file"].as_str(), Some("oui.txt"));
+        assert_eq!(value["notifier"][0]["bot_token"].as_str(), Some("123:abc"));
+
+        let mut value = serde_json::json!({ "mqtt_password_file": token.display().to_string() });
+        read_secret_files_json(&mut value).unwrap();
+        assert_eq!(value["mqtt_password"], "123:abc");
+
+        let mut both: toml::Value =
+            toml::from_str("ntfy_url = 'http://ntfy/a'\nntfy_url_file = '/dev/null'").unwrap();
+        assert_eq!(
+            read_secret_files_toml(&mut both).unwrap_err().to_string(),
+            "Only one of `ntfy_url` and `ntfy_url_file` can be set"
+        );
+        let missing = dir.join("missing");
+        let mut value: toml::Value =
+            toml::from_str(&format!("influx_token_file = '{}'", missing.display())).unwrap();
+        assert_eq!(
+            read_secret_files_toml(&mut value).unwrap_err().to_string(),
+            format!("Failed to read `influx_token_file` '{}'", missing.display())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
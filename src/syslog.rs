@@ -0,0 +1,86 @@
+use crate::Error;
+use anyhow::Context;
+use env_filter::Filter;
+use log::{Level, Log, Metadata, Record};
+use std::ffi::CString;
+
+/// Logger that sends records to the local syslog daemon with the daemon facility
+struct Syslog {
+    filter: Filter,
+}
+
+/// Installs the syslog logger as the global logger, logging the records that pass `filter`
+pub fn init(filter: Filter) -> Result<(), Error> {
+    // SAFETY: the identifier is a static string, openlog keeps a pointer to it
+    unsafe { libc::openlog(c"lanotify".as_ptr(), libc::LOG_PID, libc::LOG_DAEMON) };
+    let level = filter.filter();
+    log::set_logger(Box::leak(Box::new(Syslog { filter })))
+        .context("Failed to set logger")
+        .map_err(Error::Service)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+fn severity(level: Level) -> libc::c_int {
+    match level {
+        Level::Error => libc::LOG_ERR,
+        Level::Warn => libc::LOG_WARNING,
+        Level::Info => libc::LOG_INFO,
+        Level::Debug | Level::Trace => libc::LOG_DEBUG,
+    }
+}
+
+impl Log for Syslog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        // a NUL byte would end the message early
+        let message = record.args().to_string().replace('\0', "");
+        let Ok(message) = CString::new(message) else {
+            return;
+        };
+        // SAFETY: the format string takes exactly the one string argument that is passed
+        unsafe { libc::syslog(severity(record.level()), c"%s".as_ptr(), message.as_ptr()) };
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity() {
+        assert_eq!(severity(Level::Error), libc::LOG_ERR);
+        assert_eq!(severity(Level::Warn), libc::LOG_WARNING);
+        assert_eq!(severity(Level::Trace), libc::LOG_DEBUG);
+        let logger = Syslog {
+            filter: env_filter::Builder::new()
+                .filter_level(log::LevelFilter::Info)
+                .build(),
+        };
+        assert!(logger.enabled(&Metadata::builder().level(Level::Warn).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Debug).build()));
+    }
+
+    #[test]
+    fn test_filter() {
+        // RUST_LOG style directives per module, on top of the configured level
+        let logger = Syslog {
+            filter: env_filter::Builder::new()
+                .filter_level(log::LevelFilter::Warn)
+                .parse("lanotify::scan=debug")
+                .build(),
+        };
+        let metadata = |level, target| Metadata::builder().level(level).target(target).build();
+        assert!(logger.enabled(&metadata(Level::Debug, "lanotify::scan")));
+        assert!(!logger.enabled(&metadata(Level::Debug, "lanotify::notify")));
+        assert!(logger.enabled(&metadata(Level::Warn, "lanotify::notify")));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    env,
+    ffi::OsStr,
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    time::Duration,
+};
+
+/// Connection to systemd for a `Type=notify` service, see sd_notify(3)
+pub struct Notify {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    /// Interval within which systemd expects `WATCHDOG=1`, if `WatchdogSec` is set
+    pub watchdog: Option<Duration>,
+}
+
+impl Notify {
+    /// Connects to the socket in `NOTIFY_SOCKET`, `None` if lanotify wasn't started by systemd as
+    /// a notify service
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let watchdog = watchdog(
+            env::var("WATCHDOG_USEC").ok().as_deref(),
+            env::var("WATCHDOG_PID").ok().as_deref(),
+            std::process::id(),
+        );
+        match Self::new(&path, watchdog) {
+            Ok(notify) => Some(notify),
+            Err(e) => {
+                log::warn!("Invalid systemd notify socket {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn new(path: &OsStr, watchdog: Option<Duration>) -> io::Result<Self> {
+        // a leading `@` is a socket in the abstract namespace
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            addr,
+            watchdog,
+        })
+    }
+
+    /// Sends newline separated `KEY=value` assignments such as `READY=1`, failures are only
+    /// logged
+    pub fn send(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            log::warn!("Failed to notify systemd: {}", e);
+        }
+    }
+}
+
+/// The watchdog interval in `WATCHDOG_USEC`, if `WATCHDOG_PID` is unset or the process id `pid`
+fn watchdog(usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<Duration> {
+    if watchdog_pid.is_some_and(|watchdog_pid| watchdog_pid.parse() != Ok(pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        assert_eq!(watchdog(None, None, 10), None);
+        assert_eq!(
+            watchdog(Some("30000000"), None, 10),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            watchdog(Some("30000000"), Some("10"), 10),
+            Some(Duration::from_secs(30))
+        );
+        // meant for another process
+        assert_eq!(watchdog(Some("30000000"), Some("11"), 10), None);
+        assert_eq!(watchdog(Some("0"), None, 10), None);
+        assert_eq!(watchdog(Some("soon"), None, 10), None);
+    }
+
+    #[test]
+    fn test_send() {
+        let path = env::temp_dir().join(format!("lanotify-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let notify = Notify::new(path.as_os_str(), None).unwrap();
+        notify.send("READY=1\nSTATUS=Monitoring 2 devices\n");
+        let mut buf = [0; 128];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=Monitoring 2 devices\n");
+        std::fs::remove_file(&path).unwrap();
+
+        let name = format!("lanotify-notify-{}", std::process::id());
+        let listener =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(name.as_bytes()).unwrap())
+                .unwrap();
+        let notify = Notify::new(OsStr::new(&format!("@{name}")), None).unwrap();
+        notify.send("WATCHDOG=1\n");
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"WATCHDOG=1\n");
+    }
+}
@@ -0,0 +1,154 @@
+/// ANSI terminal colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+}
+
+impl Color {
+    fn code(&self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+        }
+    }
+}
+
+/// A table cell with an optional color
+#[derive(Debug, Clone)]
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub fn colored(text: String, color: Color) -> Self {
+        Self {
+            text,
+            color: Some(color),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Self { text, color: None }
+    }
+}
+
+/// A simple text table that aligns its columns based on the widest cell
+#[derive(Debug, Default)]
+pub struct Table {
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_row(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    fn column_widths(&self) -> Vec<usize> {
+        let mut widths = Vec::new();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                let width = cell.text.chars().count();
+                if i >= widths.len() {
+                    widths.push(width);
+                } else {
+                    widths[i] = widths[i].max(width);
+                }
+            }
+        }
+        widths
+    }
+
+    /// Renders the table into lines, truncating each line to `max_width` characters if set
+    ///
+    /// Cells are colored using ANSI escape codes if `color` is set.
+    pub fn render(&self, max_width: Option<usize>, color: bool) -> Vec<String> {
+        let widths = self.column_widths();
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut line = String::new();
+                let mut remaining = max_width.unwrap_or(usize::MAX);
+                for (i, cell) in row.iter().enumerate() {
+                    let mut text = if i > 0 {
+                        format!("  {}", cell.text)
+                    } else {
+                        cell.text.clone()
+                    };
+                    // don't pad the last column to avoid trailing whitespace
+                    if i + 1 < row.len() {
+                        let padding = widths[i] - cell.text.chars().count();
+                        text.extend(std::iter::repeat_n(' ', padding));
+                    }
+                    let len = text.chars().count();
+                    if len > remaining {
+                        text = text.chars().take(remaining).collect();
+                    }
+                    remaining -= len.min(remaining);
+                    match cell.color {
+                        Some(c) if color => {
+                            line.push_str(&format!("\x1b[{}m{text}\x1b[0m", c.code()))
+                        }
+                        _ => line.push_str(&text),
+                    }
+                    if remaining == 0 {
+                        break;
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(cells: &[&str]) -> Vec<Cell> {
+        cells.iter().map(|c| Cell::from(c.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render_aligns_columns() {
+        let mut table = Table::new();
+        table.push_row(row(&["a", "long", "x"]));
+        table.push_row(row(&["bbb", "s", "y"]));
+        assert_eq!(
+            table.render(None, false),
+            vec!["a    long  x", "bbb  s     y"]
+        );
+    }
+
+    #[test]
+    fn test_render_truncates() {
+        let mut table = Table::new();
+        table.push_row(row(&["abc", "def"]));
+        assert_eq!(table.render(Some(4), false), vec!["abc "]);
+    }
+
+    #[test]
+    fn test_render_color() {
+        let mut table = Table::new();
+        table.push_row(vec![
+            Cell::colored("ok".to_string(), Color::Green),
+            Cell::from("x".to_string()),
+        ]);
+        assert_eq!(table.render(None, true), vec!["\x1b[32mok\x1b[0m  x"]);
+        assert_eq!(table.render(None, false), vec!["ok  x"]);
+    }
+}
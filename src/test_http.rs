@@ -0,0 +1,17 @@
+use std::io::Read;
+
+/// Reads a request with a JSON body from a test server connection, returning the request line
+/// with the headers and the parsed body
+pub fn read_json_request(stream: &mut impl Read) -> (String, serde_json::Value) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    // until the whole JSON body arrived
+    while !request.ends_with(b"}") {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed before the body arrived");
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8(request).unwrap();
+    let (head, body) = request.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), serde_json::from_str(body).unwrap())
+}
@@ -0,0 +1,72 @@
+use crate::{Config, Daemon, Device, Error, Event, MacAddr, scan::Scanner};
+
+/// Tracks the connection states of devices from scans of another source
+///
+/// Unlike [`Daemon`] it doesn't notify about the events, it only returns them.
+pub struct Tracker {
+    daemon: Daemon,
+}
+
+// the tracker is fed scans instead of scanning itself
+struct NoScanner;
+
+impl Scanner for NoScanner {
+    fn scan(&mut self) -> Result<Option<Vec<Device>>, Error> {
+        Ok(None)
+    }
+}
+
+impl Tracker {
+    pub fn new(config: Config) -> Self {
+        Self {
+            daemon: Daemon::new(config, Box::new(NoScanner)),
+        }
+    }
+
+    /// Updates the states with the devices found by a scan, returning what happened
+    ///
+    /// The devices of the first scan are considered connected without any events.
+    pub fn feed_scan(&mut self, devices: Vec<Device>) -> Vec<Event> {
+        self.daemon.track_scan(devices)
+    }
+
+    /// The devices seen so far, with whether they are connected
+    pub fn devices(&self) -> impl Iterator<Item = (&Device, bool)> {
+        self.daemon
+            .state
+            .values()
+            .map(|state| (&state.device, state.is_connected))
+    }
+
+    pub fn is_connected(&self, mac: &MacAddr) -> Option<bool> {
+        self.daemon.state.get(mac).map(|state| state.is_connected)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.daemon.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HISTORY_SIZE, scan};
+
+    #[test]
+    fn test_feed_scan() {
+        let config = Config::parse("[devices]\n\"aa:bb:cc:dd:ee:01\" = \"Laptop\"\n").unwrap();
+        let mut tracker = Tracker::new(config);
+        let scan = || scan::parse_scan("192.168.1.2\taa:bb:cc:dd:ee:01\tVendor A\n");
+        assert!(tracker.feed_scan(scan()).is_empty());
+        let mac = "aa:bb:cc:dd:ee:01".parse().unwrap();
+        assert_eq!(tracker.is_connected(&mac), Some(true));
+        assert_eq!(tracker.config().name(&mac).unwrap(), "Laptop");
+
+        let events: Vec<Event> = (0..HISTORY_SIZE)
+            .flat_map(|_| tracker.feed_scan(Vec::new()))
+            .collect();
+        assert!(matches!(events[..], [Event::Disconnected(ref m)] if *m == mac));
+        assert_eq!(tracker.devices().count(), 1);
+        assert_eq!(tracker.is_connected(&mac), Some(false));
+    }
+}
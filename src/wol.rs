@@ -0,0 +1,39 @@
+use crate::MacAddr;
+use anyhow::{Context, Result};
+use std::net::{Ipv4Addr, UdpSocket};
+
+const WOL_PORT: u16 = 9;
+
+/// Builds a magic packet: 6 bytes of `0xFF` followed by the MAC address repeated 16 times
+fn magic_packet(mac: [u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    packet
+}
+
+/// Sends a wake-on-LAN magic packet for the device to the broadcast address
+pub fn wake(mac: &MacAddr, broadcast: Ipv4Addr) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).context("Failed to bind socket")?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&magic_packet(mac.octets()?), (broadcast, WOL_PORT))
+        .context("Failed to send magic packet")?;
+    log::info!("Sent wake-on-LAN packet to {} via {}", mac.0, broadcast);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = MacAddr::new("aa:bb:cc:dd:ee:01").octets().unwrap();
+        let packet = magic_packet(mac);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(&packet[..6], &[0xFF; 6]);
+        assert_eq!(&packet[96..], &[0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0x01]);
+    }
+}